@@ -0,0 +1,34 @@
+//! Precompiles syntect's default syntaxes together with the definitions
+//! bundled in `assets/syntaxes`, so the highlighter loads them as a dump
+//! instead of linking every syntax at startup. With the `extra-syntaxes`
+//! feature the extended set from `two-face` replaces syntect's defaults.
+
+use std::path::Path;
+use syntect::parsing::SyntaxSet;
+
+fn main() {
+    println!("cargo:rerun-if-changed=assets/syntaxes");
+
+    let mut builder = base_syntax_set().into_builder();
+    builder
+        .add_from_folder("assets/syntaxes", true)
+        .expect("bundled syntax definitions should load");
+    let syntax_set = builder.build();
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    syntect::dumps::dump_to_file(&syntax_set, Path::new(&out_dir).join("syntaxes.packdump"))
+        .expect("failed to write syntax dump");
+}
+
+#[cfg(not(feature = "extra-syntaxes"))]
+fn base_syntax_set() -> SyntaxSet {
+    SyntaxSet::load_defaults_newlines()
+}
+
+#[cfg(feature = "extra-syntaxes")]
+fn base_syntax_set() -> SyntaxSet {
+    // Record the size of the default dump for comparison in verbose output
+    let default_dump = syntect::dumps::dump_binary(&SyntaxSet::load_defaults_newlines());
+    println!("cargo:rustc-env=SCROLLCAST_DEFAULT_SYNTAX_BYTES={}", default_dump.len());
+    two_face::syntax::extra_newlines()
+}
@@ -0,0 +1,173 @@
+//! Anchors of the document's sections, each assigned once so the table of
+//! contents, the headings the generator writes and every renderer's ids,
+//! file names and link targets agree on them.
+//!
+//! Anchors are ASCII letters, digits, `-` and `_`, starting with a letter
+//! or `_`, so they're valid as HTML and XHTML ids, PDF names and file
+//! names alike.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Anchors of the sections ahead of the files, which no file may take
+pub const TABLE_OF_CONTENTS: &str = "table-of-contents";
+pub const FILE_STRUCTURE: &str = "file-structure";
+pub const REVIEW_CHECKLIST: &str = "review-checklist";
+pub const FILE_CONTENTS: &str = "file-contents";
+/// The anchor of the one section after the files, with `--toc-appendix`
+pub const FILE_INDEX: &str = "file-index";
+pub const SECTION_ANCHORS: [&str; 5] = [TABLE_OF_CONTENTS, FILE_STRUCTURE, REVIEW_CHECKLIST, FILE_CONTENTS, FILE_INDEX];
+
+/// The anchor each file's section gets, by path, unique among themselves
+/// and the section anchors
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, String>", into = "BTreeMap<String, String>")]
+pub struct AnchorRegistry {
+    anchors: BTreeMap<String, String>,
+    taken: HashSet<String>,
+}
+
+impl Default for AnchorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnchorRegistry {
+    /// A registry with the section anchors already taken
+    pub fn new() -> Self {
+        Self::reserving(&SECTION_ANCHORS)
+    }
+
+    /// A registry with only `reserved` taken, for names outside the
+    /// document's sections
+    pub fn reserving(reserved: &[&str]) -> Self {
+        Self {
+            anchors: BTreeMap::new(),
+            taken: reserved.iter().map(|anchor| anchor.to_string()).collect(),
+        }
+    }
+
+    /// The anchor of `key`, such as a file's path, assigning one the first
+    /// time it's asked for
+    pub fn assign(&mut self, key: &str) -> String {
+        if let Some(anchor) = self.anchors.get(key) {
+            return anchor.clone();
+        }
+        let anchor = self.claim(&slugify(key));
+        self.anchors.insert(key.to_string(), anchor.clone());
+        anchor
+    }
+
+    /// The anchor `assign` gave `key`, if any
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.anchors.get(key).map(String::as_str)
+    }
+
+    /// `slug`, or the first of `slug-2`, `slug-3` and so on that's still
+    /// free, taking it
+    pub fn claim(&mut self, slug: &str) -> String {
+        let mut candidate = slug.to_string();
+        let mut suffix = 1;
+        while self.taken.contains(&candidate) {
+            suffix += 1;
+            candidate = format!("{}-{}", slug, suffix);
+        }
+        self.taken.insert(candidate.clone());
+        candidate
+    }
+}
+
+impl From<BTreeMap<String, String>> for AnchorRegistry {
+    fn from(anchors: BTreeMap<String, String>) -> Self {
+        let mut registry = Self::new();
+        registry.taken.extend(anchors.values().cloned());
+        registry.anchors = anchors;
+        registry
+    }
+}
+
+impl From<AnchorRegistry> for BTreeMap<String, String> {
+    fn from(registry: AnchorRegistry) -> Self {
+        registry.anchors
+    }
+}
+
+/// `text` as an anchor: ASCII letters, digits, `-` and `_` kept, other
+/// ASCII characters turned into `-` and anything else written as `u` and
+/// its code point in hex. Anchors not starting with a letter or `_` get an
+/// `f` in front.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '-' => slug.push(c),
+            c if c.is_ascii() => slug.push('-'),
+            c => slug.push_str(&format!("u{:04x}", c as u32)),
+        }
+    }
+    if !slug.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        slug.insert(0, 'f');
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("src/main.rs"), "src-main-rs");
+        assert_eq!(slugify("src\\lib.rs"), "src-lib-rs");
+        assert_eq!(slugify("docs/my notes {draft}.md"), "docs-my-notes--draft--md");
+        assert_eq!(slugify("snake_case-name"), "snake_case-name");
+        assert_eq!(slugify(".env"), "f-env");
+        assert_eq!(slugify("1.rs"), "f1-rs");
+        assert_eq!(slugify("_private.py"), "_private-py");
+        assert_eq!(slugify(""), "f");
+        assert_eq!(slugify("café.rs"), "cafu00e9-rs");
+        assert_eq!(slugify("文档.md"), "u6587u6863-md");
+        assert_eq!(slugify("🚀.txt"), "u1f680-txt");
+    }
+
+    #[test]
+    fn test_collisions_get_numbered() {
+        let mut registry = AnchorRegistry::new();
+        assert_eq!(registry.assign("a.b"), "a-b");
+        assert_eq!(registry.assign("a/b"), "a-b-2");
+        assert_eq!(registry.assign("a-b"), "a-b-3");
+        assert_eq!(registry.assign("a-b-2"), "a-b-2-2");
+        // Asking again gives the same anchor
+        assert_eq!(registry.assign("a/b"), "a-b-2");
+        assert_eq!(registry.get("a-b"), Some("a-b-3"));
+        assert_eq!(registry.get("a_b"), None);
+    }
+
+    #[test]
+    fn test_section_anchors_are_reserved() {
+        let mut registry = AnchorRegistry::new();
+        for section in SECTION_ANCHORS {
+            assert_eq!(registry.assign(section), format!("{}-2", section));
+        }
+        let mut names = AnchorRegistry::reserving(&["nav"]);
+        assert_eq!(names.claim("nav"), "nav-2");
+        assert_eq!(names.claim("table-of-contents"), "table-of-contents");
+        assert_eq!(names.claim("table-of-contents"), "table-of-contents-2");
+    }
+
+    #[test]
+    fn test_round_trips_through_json() {
+        let mut registry = AnchorRegistry::new();
+        registry.assign("src/main.rs");
+        registry.assign("src-main.rs");
+        let json = serde_json::to_string(&registry).unwrap();
+        assert_eq!(json, r#"{"src-main.rs":"src-main-rs-2","src/main.rs":"src-main-rs"}"#);
+
+        let mut restored: AnchorRegistry = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, registry);
+        // Anchors taken before are still taken
+        assert_eq!(restored.assign("src.main.rs"), "src-main-rs-3");
+        assert_eq!(restored.assign(FILE_CONTENTS), "file-contents-2");
+    }
+}
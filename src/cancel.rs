@@ -0,0 +1,108 @@
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Asks a conversion to stop early. Clones share one flag, so a service
+/// can keep a clone and cancel from another thread or task. File
+/// discovery checks it between files, markdown generation between file
+/// sections and the renderers between code blocks and pages; each then
+/// returns a `Cancelled` error.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// `Err(Cancelled)` once the token has been cancelled
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// The error a cancelled conversion stops with, found under any context
+/// with `error.is::<Cancelled>()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        let error = token.check().context("Failed to render").unwrap_err();
+        assert!(error.is::<Cancelled>());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_cancelling_a_conversion_stops_it_promptly() {
+        use crate::file_processor::FileProcessor;
+        use crate::markdown_generator::MarkdownGenerator;
+        use crate::renderer::{pdf::PdfRenderer, DocumentMetadata, DocumentRenderer};
+        use std::time::{Duration, Instant};
+
+        let repo = tempfile::tempdir().unwrap();
+        let function: String = (0..100).map(|i| format!("    let value_{i} = compute({i}) * {i};\n")).collect();
+        for i in 0..2000 {
+            let dir = repo.path().join(format!("module_{}", i / 100));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(format!("file_{i}.rs")), format!("fn f{i}() {{\n{function}}}\n")).unwrap();
+        }
+        let output_dir = tempfile::tempdir().unwrap();
+        let output = output_dir.path().join("repo.pdf");
+
+        let token = CancellationToken::new();
+        let conversion = {
+            let token = token.clone();
+            let root = repo.path().to_path_buf();
+            let output = output.clone();
+            std::thread::spawn(move || -> Result<()> {
+                let files = FileProcessor::new()
+                    .with_cancellation(token.clone())
+                    .process_directory(&root)?;
+                let markdown = MarkdownGenerator::new(true, true)
+                    .with_cancellation(token.clone())
+                    .generate_markdown(&files, "repo")?;
+                let metadata = DocumentMetadata { cancellation: token, ..Default::default() };
+                PdfRenderer::with_options(Default::default()).save_to_file(&markdown, &metadata, &output)
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(100));
+        token.cancel();
+        let cancelled_at = Instant::now();
+        let result = conversion.join().unwrap();
+        assert!(cancelled_at.elapsed() < Duration::from_secs(2), "took {:?} to stop", cancelled_at.elapsed());
+        assert!(result.unwrap_err().is::<Cancelled>());
+        assert!(!output.exists());
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use git2::{DiffFormat, DiffOptions, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// How a line differs from the base revision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    /// Replaces a removed line
+    Modified,
+}
+
+impl LineChange {
+    /// CSS class given to the line in HTML output
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            LineChange::Added => "line-added",
+            LineChange::Modified => "line-modified",
+        }
+    }
+
+    fn attribute(&self) -> &'static str {
+        match self {
+            LineChange::Added => "added",
+            LineChange::Modified => "modified",
+        }
+    }
+}
+
+/// Changed lines of one file, by 1-based line number. Lines not listed
+/// are unchanged context.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ChangedLines", into = "ChangedLines")]
+pub struct LineChanges {
+    lines: BTreeMap<usize, LineChange>,
+}
+
+/// How `LineChanges` looks in JSON: the line numbers of each kind of change
+#[derive(Serialize, Deserialize)]
+struct ChangedLines {
+    #[serde(default)]
+    added: Vec<usize>,
+    #[serde(default)]
+    modified: Vec<usize>,
+}
+
+impl From<ChangedLines> for LineChanges {
+    fn from(changed: ChangedLines) -> Self {
+        let added = changed.added.into_iter().map(|line| (line, LineChange::Added));
+        let modified = changed.modified.into_iter().map(|line| (line, LineChange::Modified));
+        LineChanges { lines: added.chain(modified).collect() }
+    }
+}
+
+impl From<LineChanges> for ChangedLines {
+    fn from(changes: LineChanges) -> Self {
+        let lines = |kind| changes.lines.iter().filter(|(_, change)| **change == kind).map(|(line, _)| *line).collect();
+        ChangedLines { added: lines(LineChange::Added), modified: lines(LineChange::Modified) }
+    }
+}
+
+impl LineChanges {
+    pub fn insert(&mut self, line: usize, change: LineChange) {
+        self.lines.insert(line, change);
+    }
+
+    pub fn get(&self, line: usize) -> Option<LineChange> {
+        self.lines.get(&line).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The same changes after each source line has been split into the
+    /// number of lines given by `pieces`, in order
+    pub fn rewrapped(&self, pieces: impl IntoIterator<Item = usize>) -> LineChanges {
+        let mut rewrapped = LineChanges::default();
+        let mut next = 1;
+        for (index, count) in pieces.into_iter().enumerate() {
+            if let Some(change) = self.get(index + 1) {
+                for line in next..next + count {
+                    rewrapped.insert(line, change);
+                }
+            }
+            next += count;
+        }
+        rewrapped
+    }
+
+    /// Attributes recording these changes in a fence info string, such as
+    /// `added=3,7-9 modified=5`
+    pub fn to_fence_attributes(&self) -> String {
+        [LineChange::Added, LineChange::Modified].iter()
+            .filter_map(|kind| {
+                let lines: Vec<usize> = self.lines.iter()
+                    .filter(|(_, change)| *change == kind)
+                    .map(|(line, _)| *line)
+                    .collect();
+                (!lines.is_empty()).then(|| format!("{}={}", kind.attribute(), line_ranges(&lines)))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Changes recorded in a fence info string by `to_fence_attributes`
+    pub fn from_fence_info(info: &str) -> Option<LineChanges> {
+        let mut changes = LineChanges::default();
+        for word in info.split_whitespace() {
+            let Some((name, ranges)) = word.split_once('=') else { continue };
+            let change = match name {
+                "added" => LineChange::Added,
+                "modified" => LineChange::Modified,
+                _ => continue,
+            };
+            for range in ranges.split(',') {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    for line in start..=end {
+                        changes.insert(line, change);
+                    }
+                }
+            }
+        }
+        (!changes.is_empty()).then_some(changes)
+    }
+}
+
+/// Sorted line numbers written as comma-separated runs, e.g. `3,7-9`
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges.iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Fence info string for a code block in `language`, carrying any line changes
+pub fn fence_info(language: Option<&str>, changes: Option<&LineChanges>) -> String {
+    let attributes = changes.map(LineChanges::to_fence_attributes).unwrap_or_default();
+    match (language, attributes.is_empty()) {
+        (Some(language), true) => language.to_string(),
+        (Some(language), false) => format!("{} {}", language, attributes),
+        (None, _) => attributes,
+    }
+}
+
+/// Files under `root` that differ from the `base` revision, including
+/// untracked ones, with their changed lines. Paths are relative to `root`,
+/// like `FileInfo::path`. A removed line directly followed by an added one
+/// counts as a modification.
+pub fn changed_lines(root: &Path, base: &str) -> Result<HashMap<String, LineChanges>> {
+    let repo = Repository::discover(root)
+        .with_context(|| format!("{} is not inside a git repository", root.display()))?;
+    let workdir = repo.workdir()
+        .context("Cannot compare changes in a bare repository")?
+        .canonicalize()?;
+    let root = root.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", root.display()))?;
+    let prefix = root.strip_prefix(&workdir).unwrap_or(Path::new("")).to_path_buf();
+
+    let tree = repo.revparse_single(base)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("Unknown git revision: {}", base))?;
+    let mut options = DiffOptions::new();
+    options.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))
+        .context("Failed to diff against the base revision")?;
+
+    let mut files: HashMap<String, LineChanges> = HashMap::new();
+    let mut pending_removals = 0;
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        let Some(path) = delta.new_file().path().and_then(|path| path.strip_prefix(&prefix).ok()) else {
+            return true;
+        };
+        let changes = files.entry(path.to_string_lossy().to_string()).or_default();
+        match line.origin() {
+            '-' => pending_removals += 1,
+            '+' => {
+                let change = if pending_removals > 0 {
+                    pending_removals -= 1;
+                    LineChange::Modified
+                } else {
+                    LineChange::Added
+                };
+                if let Some(number) = line.new_lineno() {
+                    changes.insert(number as usize, change);
+                }
+            }
+            _ => pending_removals = 0,
+        }
+        true
+    }).context("Failed to read the diff against the base revision")?;
+
+    Ok(files)
+}
+
+/// The short id of the last commit touching each of `paths`, which are
+/// relative to `root` like `FileInfo::path`. Paths no commit touched, such
+/// as untracked files, are left out.
+pub fn last_commits(root: &Path, paths: &[&str]) -> Result<HashMap<String, String>> {
+    let repo = Repository::discover(root)
+        .with_context(|| format!("{} is not inside a git repository", root.display()))?;
+    let workdir = repo.workdir()
+        .context("Cannot look up commits in a bare repository")?
+        .canonicalize()?;
+    let root = root.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", root.display()))?;
+    let prefix = root.strip_prefix(&workdir).unwrap_or(Path::new("")).to_path_buf();
+
+    let mut wanted: HashSet<&str> = paths.iter().copied().collect();
+    let mut commits = HashMap::new();
+    let mut walk = repo.revwalk().context("Failed to walk the history")?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    if walk.push_head().is_err() {
+        // No commits yet
+        return Ok(commits);
+    }
+    // Newest first, so the first commit found touching a path is its last
+    for oid in walk {
+        if wanted.is_empty() {
+            break;
+        }
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        let short_id = commit.id().to_string()[..7].to_string();
+        for delta in diff.deltas() {
+            let Some(path) = delta.new_file().path().and_then(|path| path.strip_prefix(&prefix).ok()) else {
+                continue;
+            };
+            let path = path.to_string_lossy();
+            if wanted.remove(path.as_ref()) {
+                commits.insert(path.into_owned(), short_id.clone());
+            }
+        }
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "html")]
+    use crate::markdown_generator::{FileInfo, MarkdownGenerator};
+    #[cfg(feature = "html")]
+    use crate::renderer::{html::HtmlRenderer, DocumentMetadata, DocumentRenderer};
+    use std::fs;
+
+    fn commit_all(repo: &Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap();
+    }
+
+    #[test]
+    fn test_last_commit_of_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(last_commits(dir.path(), &["a.rs"]).unwrap().is_empty());
+
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/a.rs"), "fn a() {}\n").unwrap();
+        fs::write(dir.path().join("src/b.rs"), "fn b() {}\n").unwrap();
+        commit_all(&repo, "initial");
+        let first = repo.head().unwrap().peel_to_commit().unwrap().id().to_string();
+        fs::write(dir.path().join("src/b.rs"), "fn b() { todo!() }\n").unwrap();
+        commit_all(&repo, "change b");
+        let second = repo.head().unwrap().peel_to_commit().unwrap().id().to_string();
+        fs::write(dir.path().join("src/new.rs"), "\n").unwrap();
+
+        let commits = last_commits(&dir.path().join("src"), &["a.rs", "b.rs", "new.rs"]).unwrap();
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits["a.rs"], first[..7]);
+        assert_eq!(commits["b.rs"], second[..7]);
+    }
+
+    #[test]
+    fn test_fence_attributes_round_trip() {
+        let mut changes = LineChanges::default();
+        for line in [3, 7, 8, 9] {
+            changes.insert(line, LineChange::Added);
+        }
+        changes.insert(5, LineChange::Modified);
+        let info = fence_info(Some("rust"), Some(&changes));
+        assert_eq!(info, "rust added=3,7-9 modified=5");
+        assert_eq!(LineChanges::from_fence_info(&info), Some(changes.clone()));
+        assert_eq!(LineChanges::from_fence_info("rust,ignore"), None);
+
+        let rewrapped = changes.rewrapped([1, 1, 2, 1, 1]);
+        assert_eq!(rewrapped.get(2), None);
+        assert_eq!(rewrapped.get(3), Some(LineChange::Added));
+        assert_eq!(rewrapped.get(4), Some(LineChange::Added));
+        assert_eq!(rewrapped.get(5), None);
+        assert_eq!(rewrapped.get(6), Some(LineChange::Modified));
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_changed_lines_marked_in_html() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "fn one() {}\nfn two() {}\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "untouched\n").unwrap();
+        commit_all(&repo, "initial");
+
+        fs::write(dir.path().join("src/lib.rs"), "fn one() {}\nfn deux() {}\nfn three() {}\n").unwrap();
+        fs::write(dir.path().join("new.py"), "print('hi')\n").unwrap();
+
+        let changes = changed_lines(&dir.path().join("src"), "HEAD").unwrap();
+        assert_eq!(changes.keys().collect::<Vec<_>>(), vec!["lib.rs"]);
+        let lib = &changes["lib.rs"];
+        assert_eq!(lib.get(1), None);
+        assert_eq!(lib.get(2), Some(LineChange::Modified));
+        assert_eq!(lib.get(3), Some(LineChange::Added));
+
+        let all = changed_lines(dir.path(), "HEAD").unwrap();
+        assert_eq!(all["new.py"].get(1), Some(LineChange::Added));
+        assert!(!all.contains_key("notes.txt"));
+
+        let content = fs::read_to_string(dir.path().join("src/lib.rs")).unwrap();
+        let file = FileInfo {
+            path: "lib.rs".to_string(),
+            size: content.len(),
+            language: MarkdownGenerator::detect_language("lib.rs"),
+            content: content.into(),
+            changes: Some(lib.clone()),
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        };
+        let markdown = MarkdownGenerator::new(false, false).generate_markdown(&[file], "demo").unwrap();
+        let html = HtmlRenderer::new().render_markdown(&markdown, &DocumentMetadata::default()).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        let added = html.find(r#"<span class="line-added">"#).expect("added line");
+        assert!(html[added..].split("</span>\n").next().unwrap().contains("three"), "{}", html);
+        assert!(html.contains(r#"<span class="line-modified">"#));
+        assert_eq!(html.matches(r#"<span class="line-"#).count(), 2);
+    }
+}
@@ -0,0 +1,774 @@
+use clap::builder::PossibleValuesParser;
+use clap::{Arg, ArgAction, Command};
+use clap_complete::Shell;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::config;
+use crate::syntax::highlighter::pandoc_theme_names;
+use crate::theme::builtin_theme_names;
+
+/// Values `--theme` accepts: the Pandoc style names, then the built-in themes
+fn theme_names() -> Vec<&'static str> {
+    pandoc_theme_names().into_iter().chain(builtin_theme_names()).collect()
+}
+
+/// Names that select a subcommand as the first argument; anything else
+/// is taken for `convert`
+const SUBCOMMANDS: [&str; 8] = ["convert", "list", "stats", "diff", "init", "test-project", "completions", "help"];
+
+/// Top-level flags that don't run a conversion
+const TOP_LEVEL_FLAGS: [&str; 5] = ["-h", "--help", "-V", "--version", "--generate-man"];
+
+/// `args` with `convert` inserted when they don't name a subcommand, so
+/// `scrollcast ./repo -o out.pdf` runs as it did before there were
+/// subcommands. The first argument that isn't an option decides.
+pub fn with_default_command(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let Some(first) = args.get(1).and_then(|arg| arg.to_str()) else {
+        return args;
+    };
+    if TOP_LEVEL_FLAGS.contains(&first) {
+        return args;
+    }
+    let named = args.iter().skip(1)
+        .filter_map(|arg| arg.to_str())
+        .find(|arg| !arg.starts_with('-'))
+        .is_some_and(|arg| SUBCOMMANDS.contains(&arg));
+    if !named {
+        args.insert(1, OsString::from("convert"));
+    }
+    args
+}
+
+/// The command line definition, shared by argument parsing and the
+/// completion and man page generators
+pub fn build_cli() -> Command {
+    Command::new("scrollcast")
+        .version("0.1.0")
+        .author("heartcode <0xheartcode@gmail.com>")
+        .about("Convert Git repositories to beautifully formatted documents")
+        .after_help("Without a subcommand, the arguments are for convert: scrollcast ./repo -o out.pdf")
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("no-gitignore")
+                .long("no-gitignore")
+                .help("Ignore .gitignore files and process all files")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            Arg::new("ignore")
+                .long("ignore")
+                .help("Ignore specific directories (can be used multiple times)")
+                .action(ArgAction::Append)
+                .value_name("DIR")
+                .global(true)
+        )
+        .arg(
+            Arg::new("syntax-dir")
+                .long("syntax-dir")
+                .help("Directory of extra .sublime-syntax definitions (in addition to ~/.local/share/scrollcast/syntaxes)")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_name("DIR")
+                .global(true)
+        )
+        .arg(
+            Arg::new("generate-man")
+                .long("generate-man")
+                .help("Print a man page in roff format")
+                .action(ArgAction::SetTrue)
+        )
+        .subcommand(convert_command())
+        .subcommand(
+            Command::new("list")
+                .about("List the available themes or supported languages")
+                .subcommand_required(true)
+                .subcommand(Command::new("themes").about("List syntax highlighting themes"))
+                .subcommand(Command::new("languages").about("List supported programming languages"))
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Print file, line and language counts for a repository without rendering it")
+                .arg(
+                    Arg::new("input")
+                        .help("Input directory (git repository or regular folder)")
+                        .required(true)
+                        .index(1)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the report as JSON")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(todo_index_arg())
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Report the files added, removed and modified since a document saved with --emit-ast")
+                .arg(
+                    Arg::new("old")
+                        .help("Document model JSON written by convert --emit-ast")
+                        .required(true)
+                        .index(1)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("Input directory (git repository or regular folder)")
+                        .required(true)
+                        .index(2)
+                        .value_parser(clap::value_parser!(PathBuf))
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("Write to this file instead of stdout")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .value_name("PATH")
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .help("Output format")
+                        .value_parser(["markdown", "html"])
+                        .default_value("markdown")
+                )
+                .arg(
+                    Arg::new("annotate")
+                        .long("annotate")
+                        .help("Write the whole document, with \"new\" and \"modified\" badges next to the changed files' headings")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Write a scrollcast.toml with every option at its default into the current directory")
+                .arg(
+                    Arg::new("global")
+                        .long("global")
+                        .help("Write ~/.scrollcast.toml instead")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("with-ignore-file")
+                        .long("with-ignore-file")
+                        .help("Also write a starter .scrollcastignore into the current directory")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Overwrite existing files")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("test-project")
+                .about("Generate the test project in every output format (cleans testfiles/output_test)")
+                .arg(
+                    Arg::new("include-experimental")
+                        .long("include-experimental")
+                        .help("Include experimental formats (EPUB)")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script")
+                .arg(
+                    Arg::new("shell")
+                        .help("Shell to complete for")
+                        .required(true)
+                        .index(1)
+                        .value_parser(clap::value_parser!(Shell))
+                )
+        )
+}
+
+/// The `convert` subcommand: every option for generating a document, plus
+/// the hidden flags that ran other commands before there were subcommands
+/// `--todo-index`, shared by `convert` and `stats`
+fn todo_index_arg() -> Arg {
+    Arg::new("todo-index")
+        .long("todo-index")
+        .help("List TODO, FIXME, HACK and XXX comments, or the comma-separated MARKERS, in a review checklist")
+        .value_name("MARKERS")
+        .num_args(0..=1)
+        .require_equals(true)
+        .default_missing_value("")
+}
+
+fn convert_command() -> Command {
+    Command::new("convert")
+        .about("Generate a document from a repository (the default command)")
+        .arg(
+            Arg::new("input")
+                .help("Input directory (git repository or regular folder)")
+                .required_unless_present_any(["list-themes", "list-languages", "test-project", "preview", "init"])
+                .index(1)
+                .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Output file path (or set SCROLLCAST_OUTPUT, or output.filename in the config)")
+                .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Output format, or several separated by commas (epub is experimental)")
+                .value_parser(config::FORMATS)
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .default_value("pdf")
+        )
+        .arg(
+            Arg::new("theme")
+                .short('t')
+                .long("theme")
+                .help("Syntax highlighting theme, or a built-in theme that also colors PDF output")
+                .value_parser(PossibleValuesParser::new(theme_names()))
+                .default_value("kate")
+        )
+        .arg(
+            Arg::new("no-toc")
+                .long("no-toc")
+                .help("Don't include table of contents")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("toc-limit")
+                .long("toc-limit")
+                .help("List directories with file counts in the table of contents once there are more than N files")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .value_name("N")
+        )
+        .arg(
+            Arg::new("toc-depth")
+                .long("toc-depth")
+                .help("How many directories deep a table of contents collapsed by --toc-limit goes")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .value_name("D")
+                .default_value("1")
+        )
+        .arg(
+            Arg::new("toc-appendix")
+                .long("toc-appendix")
+                .help("List every file after the file contents when --toc-limit collapses the table of contents")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Skip confirmation prompts")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no-input")
+                .long("no-input")
+                .help("Fail instead of prompting (prompts are skipped anyway without a terminal)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Overwrite existing output files without asking")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Enable verbose logging")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .help("After generating, regenerate whenever an input file changes (Ctrl-C to stop)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Find the files and estimate the document's size and PDF page count, without writing anything")
+                .conflicts_with_all(["watch", "open"])
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("open")
+                .long("open")
+                .help("Open the document when done, the FORMAT one when several are written (with --watch, only after the first build)")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("")
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Only print errors and the output path")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+        )
+        .arg(
+            Arg::new("interactive")
+                .short('i')
+                .long("interactive")
+                .help("Pick the files to include from a checklist after discovery")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["watch", "no-input"])
+        )
+        .arg(todo_index_arg())
+        .arg(
+            Arg::new("snapshot")
+                .long("snapshot")
+                .help("Copy the files to a temporary directory before reading them, for a consistent view of a working copy that's changing")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("files-from")
+                .long("files-from")
+                .value_name("PATH")
+                .help("Only include the files listed in PATH, one per line relative to the input")
+                .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("save-selection")
+                .long("save-selection")
+                .value_name("PATH")
+                .help("Save the list of included files to PATH for use with --files-from")
+                .value_parser(clap::value_parser!(PathBuf))
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("PATH")
+                .help("Write a JSON summary of the run to PATH, or to stdout with -")
+        )
+        .arg(
+            Arg::new("emit-ast")
+                .long("emit-ast")
+                .value_name("PATH")
+                .help("Also write the document's structure and file contents to PATH as JSON, for other tools")
+        )
+        .arg(
+            Arg::new("warnings-in-document")
+                .long("warnings-in-document")
+                .help("End the document with a Generation Warnings section listing what didn't come out as it should have")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("keep-temp")
+                .long("keep-temp")
+                .help("Keep the intermediate markdown and list its path, instead of removing it")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("chunk-size")
+                .long("chunk-size")
+                .help("Process files in chunks of this size to reduce memory usage")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("20")
+        )
+        .arg(
+            Arg::new("memory-limit")
+                .long("memory-limit")
+                .help("Maximum memory usage in MB (default: 80% of available RAM)")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("max-file-size")
+                .long("max-file-size")
+                .help("Maximum file size to process in MB (default: 50MB, files larger will be truncated)")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("50")
+        )
+        .arg(
+            Arg::new("pdf-columns")
+                .long("pdf-columns")
+                .help("Number of text columns per PDF page (two columns use a smaller code font)")
+                .value_parser(clap::value_parser!(u8).range(1..=2))
+                .default_value("1")
+        )
+        .arg(
+            Arg::new("landscape")
+                .long("landscape")
+                .help("Use landscape pages for PDF output")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("pdfa")
+                .long("pdfa")
+                .help("Produce archival PDF/A-2b output with embedded fonts")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("pdf-no-compress")
+                .long("pdf-no-compress")
+                .help("Write uncompressed PDF content streams (useful for debugging)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max-pages")
+                .long("max-pages")
+                .help("Stop PDF output at this many pages, ending with a page that counts the files left out")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .value_name("N")
+        )
+        .arg(
+            Arg::new("epub-group-dirs")
+                .long("epub-group-dirs")
+                .help("Create one EPUB chapter per directory instead of one per file")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("cover")
+                .long("cover")
+                .help("Cover image for EPUB output (png, jpg, gif, svg or webp)")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_name("IMAGE")
+        )
+        .arg(
+            Arg::new("no-cover")
+                .long("no-cover")
+                .help("Don't generate a cover for EPUB output when no image is given")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("epub-compact")
+                .long("epub-compact")
+                .help("Use very small EPUB text to fit more code on each screen")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("epub-no-font")
+                .long("epub-no-font")
+                .help("Don't embed a monospace font for code in EPUB output (smaller file)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("theme-file")
+                .long("theme-file")
+                .help("Highlight with a .tmTheme color theme instead of --theme")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::new("font-size")
+                .long("font-size")
+                .help("Body text size in points for PDF, HTML and EPUB; code is set slightly smaller")
+                .value_parser(clap::value_parser!(f32))
+        )
+        .arg(
+            Arg::new("line-height")
+                .long("line-height")
+                .help("Line spacing as a multiple of the text size")
+                .value_parser(clap::value_parser!(f32))
+        )
+        .arg(
+            Arg::new("enforce-contrast")
+                .long("enforce-contrast")
+                .help("Adjust code colors to at least 4.5:1 contrast with the background; HTML also gets larger text and underlined links")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("strip-comments")
+                .long("strip-comments")
+                .help("Remove comments from source files")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("keep-doc-comments")
+                .long("keep-doc-comments")
+                .help("With --strip-comments, keep doc comments such as /// and Python docstrings")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("remove-empty-lines")
+                .long("remove-empty-lines")
+                .help("Remove blank lines from source files, or with =squeeze collapse runs of them into one")
+                .value_name("MODE")
+                .value_parser(["all", "squeeze"])
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("all")
+        )
+        .arg(
+            Arg::new("csv-tables")
+                .long("csv-tables")
+                .help("Show small CSV and TSV files as tables instead of code")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("csv-max-rows")
+                .long("csv-max-rows")
+                .help("With --csv-tables, files with more rows stay code")
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("200")
+        )
+        .arg(
+            Arg::new("csv-max-size")
+                .long("csv-max-size")
+                .help("With --csv-tables, files larger than this many KB stay code")
+                .value_name("KB")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("100")
+        )
+        .arg(
+            Arg::new("file-header-fields")
+                .long("file-header-fields")
+                .help("Rows of the table heading each file: any of size, language, lines, modified, commit, hash and truncated, comma-separated")
+                .value_name("FIELDS")
+                .default_value("size")
+        )
+        .arg(
+            Arg::new("cache-dir")
+                .long("cache-dir")
+                .help("Reuse highlighted code and file sections from earlier runs, kept in DIR or the platform cache directory")
+                .value_name("DIR")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("")
+        )
+        .arg(
+            Arg::new("no-highlight-cache")
+                .long("no-highlight-cache")
+                .help("Highlight every code block afresh instead of reusing results for identical blocks")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("highlight-timeout")
+                .long("highlight-timeout")
+                .help("Seconds allowed for highlighting one file before the rest is shown as plain text")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("5")
+                .value_name("SECS")
+        )
+        .arg(
+            Arg::new("highlight-max-size")
+                .long("highlight-max-size")
+                .help("Leave files larger than this many KB unhighlighted")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1024")
+                .value_name("KB")
+        )
+        .arg(
+            Arg::new("description")
+                .long("description")
+                .help("One-line summary of the repository for document metadata")
+                .value_name("TEXT")
+        )
+        .arg(
+            Arg::new("keywords")
+                .long("keywords")
+                .help("Comma-separated keywords for document metadata")
+                .value_delimiter(',')
+                .value_name("LIST")
+        )
+        .arg(
+            Arg::new("title")
+                .long("title")
+                .help("Document title (default: the input directory name)")
+                .value_name("TEXT")
+        )
+        .arg(
+            Arg::new("author")
+                .long("author")
+                .help("Author recorded in the document metadata")
+                .value_name("NAME")
+        )
+        .arg(
+            Arg::new("publisher")
+                .long("publisher")
+                .help("Publisher recorded in the EPUB package")
+                .value_name("NAME")
+        )
+        .arg(
+            Arg::new("doc-language")
+                .long("doc-language")
+                .help("Language of the document text, such as en or pt-BR; headings are translated for en, de, fr, es, pt, ja and zh [default: en]")
+                .value_name("TAG")
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .help("Only include files changed since this git revision (committed, staged, unstaged or untracked)")
+                .value_name("REF")
+        )
+        .arg(
+            Arg::new("annotate-changes")
+                .long("annotate-changes")
+                .help("Shade lines added or modified since --since (HTML backgrounds, PDF gutter bars)")
+                .requires("since")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("preview")
+                .long("preview")
+                .help("Print a file with the selected theme's colors and its detected language, then exit (--format html prints the highlighted HTML instead)")
+                .value_parser(clap::value_parser!(PathBuf))
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::new("include-experimental")
+                .long("include-experimental")
+                .help("Include experimental formats (EPUB) with --test-project")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("list-themes")
+                .long("list-themes")
+                .help("List available syntax highlighting themes")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("list-languages")
+                .long("list-languages")
+                .help("List supported programming languages")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("init")
+                .long("init")
+                .help("Write a scrollcast.toml with every option at its default into the current directory, then exit")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("global")
+                .long("global")
+                .help("With --init, write ~/.scrollcast.toml instead")
+                .requires("init")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("with-ignore-file")
+                .long("with-ignore-file")
+                .help("With --init, also write a starter .scrollcastignore into the current directory")
+                .requires("init")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("test-project")
+                .long("test-project")
+                .help("Generate test project and all output formats (cleans output_test folder)")
+                .action(ArgAction::SetTrue)
+                .hide(true)
+        )
+}
+
+/// Write the completion script for `shell` to `out`
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut build_cli(), "scrollcast", out);
+}
+
+/// Write the man page to `out`
+pub fn write_man_page(out: &mut dyn Write) -> io::Result<()> {
+    clap_mangen::Man::new(build_cli()).render(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every visible `--long` flag of the command and its subcommands
+    fn long_flags(command: &Command) -> Vec<String> {
+        let mut flags: Vec<String> = command.get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(Arg::get_long)
+            .map(|long| format!("--{}", long))
+            .collect();
+        for subcommand in command.get_subcommands() {
+            flags.extend(long_flags(subcommand));
+        }
+        flags
+    }
+
+    #[test]
+    fn test_cli_definition_is_consistent() {
+        build_cli().debug_assert();
+    }
+
+    #[test]
+    fn test_completions_cover_every_flag() {
+        let flags = long_flags(&build_cli());
+        assert!(flags.contains(&"--max-file-size".to_string()));
+        assert!(flags.contains(&"--json".to_string()));
+        assert!(!flags.contains(&"--list-themes".to_string()));
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut script = Vec::new();
+            write_completions(shell, &mut script);
+            let script = String::from_utf8(script).unwrap();
+            for flag in &flags {
+                // Fish spells --output as -l output
+                let spelling = match shell {
+                    Shell::Fish => flag.replacen("--", "-l ", 1),
+                    _ => flag.clone(),
+                };
+                assert!(script.contains(&spelling), "{} completions lack {}", shell, flag);
+            }
+        }
+
+        let mut bash = Vec::new();
+        write_completions(Shell::Bash, &mut bash);
+        let bash = String::from_utf8(bash).unwrap();
+        assert!(bash.contains("solarized-dark") && bash.contains("breezedark"));
+    }
+
+    #[test]
+    fn test_man_page_covers_every_flag() {
+        let mut page = Vec::new();
+        write_man_page(&mut page).unwrap();
+        // roff escapes hyphens
+        let page = String::from_utf8(page).unwrap().replace("\\-", "-");
+        assert!(page.contains(".TH scrollcast 1"), "{}", page);
+        // Subcommands are listed by name; their flags have no section of their own
+        let command = build_cli();
+        let flags = command.get_arguments()
+            .filter_map(Arg::get_long)
+            .map(|long| format!("--{}", long));
+        for flag in flags {
+            assert!(page.contains(flag.as_str()), "man page lacks {}", flag);
+        }
+    }
+    #[test]
+    fn test_arguments_without_a_subcommand_are_for_convert() {
+        let args = |line: &str| -> Vec<String> {
+            with_default_command(line.split_whitespace().map(OsString::from))
+                .into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        };
+        assert_eq!(args("scrollcast ./repo -o out.pdf"), ["scrollcast", "convert", "./repo", "-o", "out.pdf"]);
+        assert_eq!(args("scrollcast -y ./repo"), ["scrollcast", "convert", "-y", "./repo"]);
+        assert_eq!(args("scrollcast --list-themes"), ["scrollcast", "convert", "--list-themes"]);
+        assert_eq!(args("scrollcast --no-gitignore stats ./repo"), ["scrollcast", "--no-gitignore", "stats", "./repo"]);
+        assert_eq!(args("scrollcast list themes"), ["scrollcast", "list", "themes"]);
+        assert_eq!(args("scrollcast --version"), ["scrollcast", "--version"]);
+        assert_eq!(args("scrollcast"), ["scrollcast"]);
+
+        let matches = build_cli().get_matches_from(args("scrollcast ./repo -f html"));
+        let (name, convert) = matches.subcommand().unwrap();
+        assert_eq!(name, "convert");
+        assert_eq!(convert.get_one::<String>("format").map(String::as_str), Some("html"));
+    }
+}
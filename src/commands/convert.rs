@@ -0,0 +1,1129 @@
+use anyhow::{Context, Result};
+use clap::{parser::ValueSource, ArgMatches};
+use colorful::{Colorful, Color};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+use std::sync::Mutex;
+use sysinfo::{ProcessesToUpdate, System};
+
+use crate::cancel::{Cancelled, CancellationToken};
+use crate::changes::{changed_lines, last_commits};
+use crate::comments::{self, EmptyLineMode};
+use crate::config::{expand_filename_template, CliSettings, Config, FilenameContext, Settings};
+use crate::document_model::DocumentModel;
+use crate::estimate::SizeEstimate;
+use crate::file_header::{HeaderField, HeaderOptions};
+use crate::fragment_cache::{fragment_stats, FragmentCache, FragmentStats};
+use crate::i18n;
+use crate::markdown_generator::{front_matter, ChunkEvent, ChunkOptions, FileInfo, MarkdownGenerator, MemoryLimit, TocOptions, LOW_MEMORY_RATIO};
+use crate::opener;
+use crate::output_path;
+use crate::progress::{Progress, ProgressMode, Stage};
+use crate::prompt;
+use crate::renderer::{self, OutputFormat, create_renderer_with_options, DocumentMetadata, RenderOptions};
+use crate::renderer::pdf::{code_capacity, PdfOptions};
+use crate::renderer::epub::EpubOptions;
+use crate::report::{self, Failure, FailureContext, RunReport};
+use crate::selection;
+use crate::tables::{self, TableLimits};
+use crate::temp_files::TempFiles;
+use crate::syntax::cache::shared_cache_stats;
+use crate::syntax::highlighter::{embedded_syntaxes, SyntaxHighlighter};
+use crate::syntax::policy::HighlightPolicy;
+use crate::syntax::preview::preview;
+use crate::theme::{Theme, ThemeMode};
+use crate::todos;
+use crate::warnings::Warnings;
+use crate::watch;
+
+use super::{file_processor, init, list, test_project};
+
+/// Rough peak memory of one render as a multiple of the markdown size:
+/// parsed events, highlighted code and the output buffer
+const RENDER_MEMORY_FACTOR: u64 = 16;
+
+/// Generate the document once, then again on every change with `--watch`.
+/// The flags that came before the subcommands, such as `--list-themes` and
+/// `--init`, still run those commands.
+pub async fn run(matches: &ArgMatches) -> Result<()> {
+    let syntax_dir = matches.get_one::<PathBuf>("syntax-dir").map(PathBuf::as_path);
+    if matches.get_flag("list-themes") {
+        return list::themes(syntax_dir);
+    }
+    if matches.get_flag("list-languages") {
+        return list::languages(syntax_dir);
+    }
+    if matches.get_flag("init") {
+        return init::run(matches);
+    }
+    if matches.get_flag("test-project") {
+        return test_project::run(matches).await;
+    }
+
+    if let Some(file) = matches.get_one::<PathBuf>("preview") {
+        let metadata = DocumentMetadata {
+            syntax_theme: matches.get_one::<String>("theme").unwrap().clone(),
+            syntax_dir: syntax_dir.map(Path::to_path_buf),
+            theme_file: matches.get_one::<PathBuf>("theme-file").cloned(),
+            ..Default::default()
+        };
+        let html = matches.get_many::<String>("format").is_some_and(|mut formats| formats.any(|format| format == "html"));
+        return preview_file(file, &metadata, html);
+    }
+
+    let input_path = matches.get_one::<PathBuf>("input").unwrap();
+    let cancellation = CancellationToken::new();
+    tokio::spawn(cancel_on_ctrl_c(cancellation.clone()));
+    let mut report = RunReport::new(input_path);
+    let warnings = Warnings::new();
+    let result = generate(matches, false, &cancellation, &warnings, &mut report).await;
+    report.generation_warnings = warnings.entries();
+    report.finish(&result);
+    if let Some(target) = matches.get_one::<String>("report") {
+        report.write_to(target)?;
+    }
+    let output = match result {
+        Ok(output) => output,
+        Err(error) => {
+            if error.is::<Cancelled>() {
+                eprintln!("⛔ Cancelled");
+            } else {
+                eprintln!("Error: {:?}", error);
+            }
+            std::process::exit(report::exit_code(&error));
+        }
+    };
+
+    if matches.get_flag("watch") {
+        let config = Config::load_default()
+            .context("Failed to load configuration")?;
+        let settings = Settings::resolve(&config, std::env::vars(), &cli_settings(matches)?)?;
+        let file_processor = file_processor(matches, &config, settings.respect_gitignore);
+        println!("\n👀 Watching {} for changes (Ctrl-C to stop)", input_path.display());
+        let stop = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        let cancellation = &cancellation;
+        let rebuild = move || async move {
+            generate(matches, true, cancellation, &Warnings::new(), &mut RunReport::new(input_path)).await
+        };
+        watch::watch(input_path, &file_processor, output, rebuild, stop).await?;
+    }
+
+    Ok(())
+}
+
+/// Cancel `token` on Ctrl-C, so the run stops at the next file, code block
+/// or page and cleans up its temp files and partial output. A second Ctrl-C
+/// exits at once, for when the run is somewhere that doesn't check, such
+/// as a prompt.
+async fn cancel_on_ctrl_c(token: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        token.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(report::CANCELLED_EXIT_CODE);
+        }
+    }
+}
+
+/// The options given on the command line that can also come from the
+/// config file or environment
+fn cli_settings(matches: &ArgMatches) -> Result<CliSettings> {
+    // Values clap filled in from defaults leave the choice to the config
+    // file and environment
+    let explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    Ok(CliSettings {
+        format: matches.get_many::<String>("format")
+            .filter(|_| explicit("format"))
+            .map(|formats| formats.map(String::as_str).collect::<Vec<_>>().join(",")),
+        theme: matches.get_one::<String>("theme").filter(|_| explicit("theme")).cloned(),
+        output: matches.get_one::<PathBuf>("output").cloned(),
+        no_gitignore: matches.get_flag("no-gitignore"),
+        no_toc: matches.get_flag("no-toc"),
+        yes: matches.get_flag("yes"),
+        verbose: matches.get_flag("verbose"),
+        chunk_size: matches.get_one::<usize>("chunk-size").filter(|_| explicit("chunk-size")).copied(),
+        memory_limit_mb: matches.get_one::<u64>("memory-limit").copied(),
+        max_file_size_mb: matches.get_one::<u64>("max-file-size").filter(|_| explicit("max-file-size")).copied(),
+        enforce_contrast: matches.get_flag("enforce-contrast"),
+        strip_comments: matches.get_flag("strip-comments"),
+        keep_doc_comments: matches.get_flag("keep-doc-comments"),
+        remove_empty_lines: matches.get_one::<String>("remove-empty-lines")
+            .map(|mode| mode.parse())
+            .transpose()?,
+        font_size: matches.get_one::<f32>("font-size").copied(),
+        line_height: matches.get_one::<f32>("line-height").copied(),
+        title: matches.get_one::<String>("title").cloned(),
+        author: matches.get_one::<String>("author").cloned(),
+        publisher: matches.get_one::<String>("publisher").cloned(),
+        language: matches.get_one::<String>("doc-language").cloned(),
+    })
+}
+
+/// Discover the files, generate markdown and render it once in every
+/// format, recording the run in `report`. Rebuilds in watch mode skip the
+/// confirmation and only report errors unless verbose. Returns the paths
+/// written, none if the user declined; `cancellation` stops the run with a
+/// `Cancelled` error. What didn't come out right along the way is collected
+/// in `warnings` and summed up at the end.
+async fn generate(
+    matches: &ArgMatches,
+    rebuilding: bool,
+    cancellation: &CancellationToken,
+    warnings: &Warnings,
+    report: &mut RunReport,
+) -> Result<Vec<PathBuf>> {
+    let mut run = Run::new(matches, rebuilding, cancellation, warnings, report)?;
+    if !run.confirm_overwrite()? {
+        return Ok(Vec::new());
+    }
+    let Some(files) = run.discover()? else {
+        return Ok(Vec::new());
+    };
+    let Some(markdown) = run.build_markdown(&files)? else {
+        return Ok(Vec::new());
+    };
+    let written = run.render(&markdown, files.len())?;
+    run.finish(&written);
+    Ok(written)
+}
+
+/// One run of `generate`: the resolved settings and command line options,
+/// and what the steps share as they go
+struct Run<'a> {
+    matches: &'a ArgMatches,
+    input_path: &'a PathBuf,
+    rebuilding: bool,
+    cancellation: &'a CancellationToken,
+    warnings: &'a Warnings,
+    report: &'a mut RunReport,
+    run_start: Instant,
+    config: Config,
+    settings: Settings,
+    targets: Vec<Target>,
+    /// The document to open at the end
+    open: Option<PathBuf>,
+    progress: Progress,
+    verbose: bool,
+    /// A report on stdout must be the only thing there
+    report_to_stdout: bool,
+    interactive: bool,
+    memory_limit: u64,
+    toc_options: TocOptions,
+    file_header: HeaderOptions,
+    fragment_cache: Option<FragmentCache>,
+    fragments_before: FragmentStats,
+    /// Removed when the run ends, however it ends
+    temp_files: TempFiles,
+}
+
+impl<'a> Run<'a> {
+    /// Resolve the settings and options, print what the run will do and
+    /// check the input exists
+    fn new(
+        matches: &'a ArgMatches,
+        rebuilding: bool,
+        cancellation: &'a CancellationToken,
+        warnings: &'a Warnings,
+        report: &'a mut RunReport,
+    ) -> Result<Self> {
+        let run_start = Instant::now();
+        let input_path = matches.get_one::<PathBuf>("input").unwrap();
+        let config = Config::load_default()
+            .context("Failed to load configuration")
+            .failure(Failure::InvalidArguments)?;
+        let cli = cli_settings(matches).failure(Failure::InvalidArguments)?;
+        let settings = Settings::resolve(&config, std::env::vars(), &cli)
+            .failure(Failure::InvalidArguments)?;
+        let formats = settings.formats();
+        report.formats = formats.iter().map(|format| format.to_string()).collect();
+        let output_template = settings.output
+            .as_ref()
+            .context("No output path: pass -o, set SCROLLCAST_OUTPUT, or set output.filename in the config")
+            .failure(Failure::InvalidArguments)?;
+        let targets = output_targets(input_path, &output_template.to_string_lossy(), &formats)
+            .failure(Failure::InvalidArguments)?;
+        // The document to open at the end: the one asked for, or the first
+        let open = match matches.get_one::<String>("open").map(String::as_str) {
+            Some("") => targets.first(),
+            Some(format) => Some(targets.iter()
+                .find(|target| target.name == format)
+                .with_context(|| format!("--open={} names a format that isn't being written ({})", format, formats.join(", ")))
+                .failure(Failure::InvalidArguments)?),
+            None => None,
+        };
+        let open = open.map(|target| target.path.clone());
+        let report_to_stdout = matches.get_one::<String>("report").is_some_and(|target| target == "-");
+        let quiet = matches.get_flag("quiet") || report_to_stdout;
+        let verbose = settings.verbose && !quiet;
+        // Verbose output is a log of its own, so bars would only get in its way;
+        // watch mode reports each rebuild in one line instead
+        let progress = Progress::new(match (quiet, verbose) {
+            (false, true) => ProgressMode::Log,
+            (false, false) if !rebuilding => ProgressMode::Bars,
+            _ => ProgressMode::Quiet,
+        });
+        let toc_options = TocOptions {
+            limit: matches.get_one::<u64>("toc-limit").map(|limit| *limit as usize),
+            depth: *matches.get_one::<u64>("toc-depth").unwrap() as usize,
+            appendix: matches.get_flag("toc-appendix"),
+        };
+        let file_header = HeaderOptions::parse(matches.get_one::<String>("file-header-fields").map_or("size", String::as_str))
+            .failure(Failure::InvalidArguments)?;
+        let temp_files = TempFiles::new(matches.get_flag("keep-temp"));
+        let fragment_cache = match matches.get_one::<String>("cache-dir").map(String::as_str) {
+            Some("") => Some(FragmentCache::new(FragmentCache::default_dir()
+                .context("No cache directory on this platform: pass --cache-dir=<DIR>")
+                .failure(Failure::InvalidArguments)?)),
+            Some(dir) => Some(FragmentCache::new(dir)),
+            None => None,
+        };
+        let fragments_before = fragment_stats();
+        let interactive = matches.get_flag("interactive") && !rebuilding;
+
+        if interactive && !prompt::is_interactive() {
+            return Err(anyhow::anyhow!("--interactive needs a terminal to show the file list on; use --files-from in scripts")
+                .failure(Failure::InvalidArguments));
+        }
+
+        // Initialize system info for memory monitoring
+        let mut sys = System::new();
+        sys.refresh_memory();
+        
+        let total_memory_mb = sys.total_memory() / 1024 / 1024;
+        let memory_limit = settings.memory_limit_mb.unwrap_or(total_memory_mb * 80 / 100); // 80% of total RAM by default
+
+        // Print startup information
+        progress.status("🎨 Scrollcast Document Converter".color(Color::Blue).bold().to_string());
+        progress.status(format!("📂 Input: {}", input_path.display()));
+        for target in &targets {
+            progress.status(format!("📄 Output: {}", target.path.display()));
+        }
+        progress.status(format!("🎯 Format: {}", formats.join(", ").color(Color::Green)));
+        progress.status(format!("🎨 Theme: {}", settings.theme.clone().color(Color::Yellow)));
+        progress.status(format!("📁 Respect .gitignore: {}", if settings.respect_gitignore { "Yes".color(Color::Green) } else { "No".color(Color::Red) }));
+        if verbose {
+            println!("🔍 Verbose mode: {}", "Enabled".color(Color::Green));
+            println!("📦 Chunk size: {} files per chunk", settings.chunk_size);
+            println!("🧠 Memory limit: {} MB ({} MB total)", memory_limit, total_memory_mb);
+            let syntaxes = embedded_syntaxes();
+            if syntaxes.extended {
+                println!("🔤 Extended syntax set: {} syntaxes, {} embedded ({} for syntect's default set)",
+                    syntaxes.syntaxes, MarkdownGenerator::format_file_size(syntaxes.bytes), MarkdownGenerator::format_file_size(syntaxes.default_bytes));
+            } else {
+                println!("🔤 Default syntax set: {} syntaxes, {} embedded (build with --features extra-syntaxes for more)",
+                    syntaxes.syntaxes, MarkdownGenerator::format_file_size(syntaxes.bytes));
+            }
+        }
+
+        // Validate input path
+        if !input_path.exists() {
+            return Err(anyhow::anyhow!("Input path does not exist: {}", input_path.display()).failure(Failure::Input));
+        }
+
+        Ok(Self {
+            matches,
+            input_path,
+            rebuilding,
+            cancellation,
+            warnings,
+            report,
+            run_start,
+            config,
+            settings,
+            targets,
+            open,
+            progress,
+            verbose,
+            report_to_stdout,
+            interactive,
+            memory_limit,
+            toc_options,
+            file_header,
+            fragment_cache,
+            fragments_before,
+            temp_files,
+        })
+    }
+
+    /// Check the outputs can be written before spending any time on them,
+    /// and ask before overwriting any that exist. False if the user declined.
+    fn confirm_overwrite(&self) -> Result<bool> {
+        // A dry run doesn't write the outputs, so it doesn't create their
+        // directories
+        let dry_run = self.matches.get_flag("dry-run");
+        for target in self.targets.iter().filter(|_| !dry_run) {
+            output_path::ensure_writable(&target.path).failure(Failure::InvalidArguments)?;
+        }
+        let existing: Vec<String> = self.targets.iter()
+            .filter(|target| !dry_run && target.path.exists())
+            .map(|target| target.path.display().to_string())
+            .collect();
+        // A rebuild in watch mode overwrites what the first run wrote
+        if !existing.is_empty() && !self.matches.get_flag("force") && !self.rebuilding {
+            let existing = existing.join(", ");
+            if self.matches.get_flag("no-input") {
+                return Err(anyhow::anyhow!("{} already exists; pass --force to overwrite it", existing)
+                    .failure(Failure::InvalidArguments));
+            }
+            self.progress.status(format!("⚠️  {} already exists", existing).color(Color::Yellow).to_string());
+            if !prompt::confirm(&format!("Overwrite {}?", existing), false).failure(Failure::InvalidArguments)? {
+                println!("Operation cancelled by user.");
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Find and read the files to include, narrowed by `--since`,
+    /// `--files-from` and `--interactive` and with the comment and blank
+    /// line options applied. None if there's nothing to convert.
+    fn discover(&mut self) -> Result<Option<Vec<FileInfo>>> {
+        let matches = self.matches;
+        let input_path = self.input_path;
+        let verbose = self.verbose;
+        let annotate_changes = matches.get_flag("annotate-changes");
+        self.progress.status("");
+        let discovery_start = Instant::now();
+        // Settings, and in verbose mode loading the syntax set for the summary
+        self.report.phases.record("setup", discovery_start - self.run_start, None);
+        let discovery = self.progress.counter(&"📖 Processing files...".color(Color::Cyan).to_string(), "files found");
+        let mut file_processor = file_processor(matches, &self.config, self.settings.respect_gitignore)
+            .with_cancellation(self.cancellation.clone())
+            .with_warnings(self.warnings.clone());
+        if matches.get_flag("snapshot") {
+            let staging = self.temp_files.create_dir("scrollcast-snapshot-")
+                .failure(Failure::Input)?;
+            file_processor = file_processor.with_snapshot(staging);
+        }
+
+        let mut read_time = Duration::ZERO;
+        let mut read_bytes = 0;
+        let mut files = file_processor
+            .process_directory_with(input_path, |file, elapsed| {
+                read_time += elapsed;
+                read_bytes += file.size as u64;
+                discovery.inc(file.path.as_str());
+            })
+            .context("Failed to process input directory")
+            .failure(Failure::Input)?;
+        let found = files.len();
+
+        if let Some(base) = matches.get_one::<String>("since") {
+            let mut changed = changed_lines(input_path, base)
+                .context("Failed to find changed files")
+                .failure(Failure::Input)?;
+            files.retain(|file| changed.contains_key(&file.path));
+            if annotate_changes {
+                for file in &mut files {
+                    file.changes = changed.remove(&file.path);
+                }
+            }
+            discovery.println(format!("🔀 Changed since {}: {} files", base, files.len()));
+        }
+
+        if let Some(list) = matches.get_one::<PathBuf>("files-from") {
+            let listed = selection::read_manifest(list).failure(Failure::InvalidArguments)?;
+            for path in selection::retain_listed(&mut files, &listed) {
+                let warning = format!("{} lists {}, which wasn't found or is ignored", list.display(), path);
+                discovery.println(format!("⚠️  {}", warning));
+                self.report.warn(warning);
+            }
+            discovery.println(format!("📋 Listed in {}: {} files", list.display(), files.len()));
+        }
+
+        let strip_comments = self.settings.strip_comments;
+        let empty_lines = self.settings.empty_lines;
+        if strip_comments || empty_lines != EmptyLineMode::Keep {
+            // Removing lines shifts the rest, so diff line numbers would no longer match
+            if annotate_changes {
+                return Err(anyhow::anyhow!("--annotate-changes can't be combined with comment or blank line removal")
+                    .failure(Failure::InvalidArguments));
+            }
+            for file in &mut files {
+                if let (true, Some(language)) = (strip_comments, &file.language) {
+                    if verbose && !comments::supports_language(language) {
+                        discovery.println(format!("💬 Keeping comments in {}: unknown comment syntax for {}", file.path, language));
+                    }
+                    file.content = comments::strip_comments(&file.content, language, self.settings.keep_doc_comments).into();
+                }
+                if empty_lines != EmptyLineMode::Keep {
+                    file.content = comments::remove_empty_lines(&file.content, file.language.as_deref(), empty_lines).into();
+                }
+            }
+        }
+
+        self.report.files.included = files.len();
+        self.report.files.skipped = found - files.len();
+        self.report.phases.record("discovery", discovery_start.elapsed().saturating_sub(read_time), None);
+        self.report.phases.record("read", read_time, Some(read_bytes));
+
+        if files.is_empty() {
+            self.report.warn("No files found to process");
+            discovery.finish("⚠️  No files found to process".color(Color::Yellow).to_string());
+            return Ok(None);
+        }
+
+        discovery.finish(format!("✅ Found {} files to process", files.len()));
+
+        if self.interactive {
+            let selected = selection::select_files(&files)?;
+            let mut selected = selected.into_iter();
+            files.retain(|_| selected.next().unwrap_or(false));
+            self.report.files.included = files.len();
+            self.report.files.skipped = found - files.len();
+            if files.is_empty() {
+                println!("No files selected.");
+                return Ok(None);
+            }
+            self.progress.status(format!("✅ Selected {} files", files.len()));
+        }
+
+        if let Some(path) = matches.get_one::<PathBuf>("save-selection") {
+            selection::write_manifest(path, &files)?;
+            self.progress.status(format!("💾 Saved the file list to {}", path.display()));
+        }
+        
+        if verbose {
+            println!("📋 Files to process:");
+            for (i, file) in files.iter().enumerate() {
+                println!("   {}. {} ({})", i + 1, file.path, MarkdownGenerator::format_file_size(file.size));
+            }
+            
+            // Languages without a grammar of their own are highlighted with a stand-in
+            let highlighter = SyntaxHighlighter::with_syntax_dir(matches.get_one::<PathBuf>("syntax-dir").map(PathBuf::as_path))?;
+            let mut languages: Vec<&str> = files.iter().filter_map(|f| f.language.as_deref()).collect();
+            languages.sort_unstable();
+            languages.dedup();
+            for language in languages {
+                if let Some(syntax) = highlighter.fallback_for(language) {
+                    println!("🔤 No {} grammar; highlighting as {}", language, syntax);
+                }
+            }
+            let highlight_policy = self.highlight_policy();
+            for file in &files {
+                if let Some(reason) = highlight_policy.skip_reason(file.language.as_deref(), &file.content) {
+                    println!("📏 Not highlighting {}: {}", file.path, reason);
+                }
+            }
+        }
+
+        if self.file_header.includes(HeaderField::Modified) {
+            for file in &mut files {
+                file.modified = fs::metadata(input_path.join(&file.path))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .map(chrono::DateTime::from);
+            }
+        }
+        if self.file_header.includes(HeaderField::Commit) {
+            let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+            match last_commits(input_path, &paths) {
+                Ok(mut commits) => {
+                    for file in &mut files {
+                        file.commit = commits.remove(&file.path);
+                    }
+                }
+                Err(error) => {
+                    let warning = format!("No commits in file headers: {:#}", error);
+                    self.progress.status(format!("⚠️  {}", warning));
+                    self.report.warn(warning);
+                }
+            }
+        }
+        Ok(Some(files))
+    }
+
+    /// Size up the document and, once confirmed, write its markdown to a
+    /// temp file, a chunk at a time if it's large. Returns the markdown;
+    /// none for a dry run or if the user declined.
+    fn build_markdown(&mut self, files: &[FileInfo]) -> Result<Option<String>> {
+        let matches = self.matches;
+        let verbose = self.verbose;
+        let settings = &self.settings;
+        let title = self.title();
+        let include_toc = settings.include_toc;
+        let mut estimate = SizeEstimate::new(files, &title, include_toc);
+        if settings.formats().contains(&"pdf") {
+            let options = PdfOptions {
+                columns: *matches.get_one::<u8>("pdf-columns").unwrap(),
+                landscape: matches.get_flag("landscape"),
+                ..Default::default()
+            };
+            let capacity = code_capacity(&options, settings.font_size, settings.line_height);
+            estimate = estimate.with_pdf_pages(files, capacity);
+        }
+        for warning in estimate.warnings() {
+            self.progress.status(format!("⚠️  Warning: {}", warning));
+            self.report.warn(warning);
+        }
+        if i18n::translation(&settings.language).is_none() {
+            let warning = format!("No translation for {}; headings and labels stay in English", settings.language);
+            self.progress.status(format!("⚠️  Warning: {}", warning));
+            self.report.warn(warning);
+        }
+
+        let effective_chunk_size = estimate.chunk_size(settings.chunk_size);
+        let needs_chunking = files.len() > effective_chunk_size;
+        let max_file_size_mb = settings.max_file_size_mb;
+        // Only the chunked path shortens files over the size limit
+        if needs_chunking {
+            let max_file_size_bytes = max_file_size_mb * 1024 * 1024;
+            self.report.files.truncated = files.iter().filter(|f| f.size > max_file_size_bytes as usize).count();
+            if self.report.files.truncated > 0 {
+                self.report.warn(format!("Truncated {} files larger than {} MB", self.report.files.truncated, max_file_size_mb));
+            }
+        }
+        if needs_chunking {
+            if effective_chunk_size == 1 {
+                self.progress.status(format!("📄 Processing {} files one-by-one for optimal memory usage", files.len()));
+            } else {
+                self.progress.status(format!("📦 Processing {} files in chunks of {} to reduce memory usage", files.len(), effective_chunk_size));
+            }
+            
+            if verbose {
+                println!("📊 Repository stats: {} files, {} total, avg {} per file, {} large files (>50KB)", 
+                    files.len(), MarkdownGenerator::format_file_size(estimate.content_bytes), MarkdownGenerator::format_file_size(estimate.average_file_size()), estimate.large_files);
+                if estimate.huge_files > 0 {
+                    println!("📊 Large file stats: {} files >10MB, largest: {}", 
+                        estimate.huge_files, MarkdownGenerator::format_file_size(estimate.largest_file));
+                }
+            }
+        }
+
+        if matches.get_flag("dry-run") {
+            if estimate.long_lines > 0 {
+                self.report.warn(format!("{} lines over 100 characters will be broken up", estimate.long_lines));
+            }
+            if !self.report_to_stdout {
+                print_estimate(&estimate, &self.report.warnings);
+            }
+            self.report.estimate = Some(estimate);
+            return Ok(None);
+        }
+
+        // Ask for confirmation unless -y flag is used
+        if !settings.skip_confirmation && !self.rebuilding {
+            let proceed = prompt::confirm("Do you want to proceed with processing these files?", matches.get_flag("no-input"))
+                .failure(Failure::InvalidArguments)?;
+            
+            if !proceed {
+                println!("Operation cancelled by user.");
+                return Ok(None);
+            }
+        }
+
+        // Generate markdown
+        let markdown_start = Instant::now();
+        let generation = self.progress.bar(&"📝 Generating markdown...".color(Color::Cyan).to_string(), files.len() as u64);
+
+        let temp_markdown = self.temp_files.create(&format!("scrollcast-{}-", self.repo_name()), ".md")
+            .failure(Failure::Render)?;
+
+        let mut markdown_generator = MarkdownGenerator::new(include_toc, true)
+            .with_cancellation(self.cancellation.clone())
+            .with_warnings(self.warnings.clone())
+            .with_language(&settings.language)
+            .with_file_header(self.file_header.clone())
+            .with_toc_options(self.toc_options.clone());
+        if let Some(markers) = matches.get_one::<String>("todo-index") {
+            markdown_generator = markdown_generator.with_todo_index(todos::parse_markers(markers));
+        }
+        if matches.get_flag("csv-tables") {
+            let limits = TableLimits {
+                max_bytes: matches.get_one::<usize>("csv-max-size").copied().unwrap_or(100) * 1024,
+                max_rows: matches.get_one::<usize>("csv-max-rows").copied().unwrap_or(200),
+                ..Default::default()
+            };
+            if verbose {
+                for file in files {
+                    let Some(delimiter) = tables::delimiter(&file.path) else { continue };
+                    if let Err(reason) = tables::parse(&file.content, delimiter, &limits) {
+                        println!("📊 {} stays code: {}", file.path, reason);
+                    }
+                }
+            }
+            markdown_generator = markdown_generator.with_tables(limits);
+        }
+        if !needs_chunking {
+            if let Some(cache) = &self.fragment_cache {
+                markdown_generator = markdown_generator.with_fragment_cache(cache.clone());
+            }
+        }
+        // Rendered below, so the saved structure matches the document
+        let model = markdown_generator.build_document_model(files, &title);
+        if let Some(path) = matches.get_one::<String>("emit-ast") {
+            let json = model.to_json()
+                .context("Failed to serialize the document model")
+                .failure(Failure::Render)?;
+            fs::write(path, json + "\n")
+                .with_context(|| format!("Failed to write the document model to {}", path))
+                .failure(Failure::Render)?;
+        }
+        if needs_chunking {
+            let chunked = process_files_in_chunks(&ChunkedWrite {
+                generator: &markdown_generator,
+                model: &model,
+                files,
+                output_path: &temp_markdown,
+                chunk_size: effective_chunk_size,
+                memory_limit_mb: self.memory_limit,
+                max_file_size_mb,
+                generation: &generation,
+                verbose,
+            })
+                .context("Failed to process files in chunks")
+                .failure(Failure::Render)?;
+            if chunked.low_memory {
+                self.report.warn(format!("Memory use passed {:.0}% of the {} MB limit; wrote the remaining files one at a time with shorter previews",
+                    LOW_MEMORY_RATIO * 100.0, self.memory_limit));
+            }
+            if chunked.truncated > self.report.files.truncated {
+                self.report.warn(format!("Truncated {} more files to stay under the memory limit", chunked.truncated - self.report.files.truncated));
+                self.report.files.truncated = chunked.truncated;
+            }
+        } else {
+            let markdown_content = markdown_generator.render_markdown(&model)
+                .context("Failed to generate markdown")
+                .failure(Failure::Render)?;
+            fs::write(&temp_markdown, &markdown_content)
+                .context("Failed to write temporary markdown file")
+                .failure(Failure::Render)?;
+            generation.set_position(files.len() as u64);
+        }
+
+        // Warnings from rendering come too late to list in the document, so
+        // only reading and generating are covered
+        if matches.get_flag("warnings-in-document") && !self.warnings.is_empty() {
+            fs::OpenOptions::new().append(true).open(&temp_markdown)
+                .and_then(|mut file| file.write_all(self.warnings.appendix(i18n::strings_for(&settings.language)).as_bytes()))
+                .context("Failed to write the warnings section")
+                .failure(Failure::Render)?;
+        }
+        generation.finish("✅ Markdown generated");
+        self.report.phases.record("markdown", markdown_start.elapsed(), fs::metadata(&temp_markdown).map(|m| m.len()).ok());
+        
+        if verbose {
+            let markdown_size = fs::metadata(&temp_markdown)?.len();
+            println!("📄 Markdown file size: {} bytes", markdown_size);
+            println!("📂 Temporary markdown file: {}", temp_markdown.display());
+        }
+
+        let markdown_content = fs::read_to_string(&temp_markdown)
+            .context("Failed to read temporary markdown file")
+            .failure(Failure::Render)?;
+        Ok(Some(markdown_content))
+    }
+
+    /// Convert the markdown of `file_count` files to each format. The
+    /// formats that fail don't stop the others; the run then fails with the
+    /// first error, naming them all.
+    fn render(&mut self, markdown_content: &str, file_count: usize) -> Result<Vec<PathBuf>> {
+        let matches = self.matches;
+        let settings = &self.settings;
+        let config = &self.config;
+        let theme = settings.theme.clone();
+        let include_toc = settings.include_toc;
+        let metadata = DocumentMetadata {
+            title: self.title(),
+            author: settings.author.clone(),
+            publisher: settings.publisher.clone(),
+            date: Some(chrono::Utc::now()),
+            language: settings.language.clone(),
+            include_toc,
+            syntax_theme: theme.clone(),
+            description: matches.get_one::<String>("description").cloned(),
+            keywords: matches.get_many::<String>("keywords")
+                .map(|values| values.map(|k| k.trim().to_string()).filter(|k| !k.is_empty()).collect())
+                .unwrap_or_default(),
+            syntax_dir: matches.get_one::<PathBuf>("syntax-dir").cloned(),
+            theme_file: matches.get_one::<PathBuf>("theme-file").cloned(),
+            highlight_cache: !matches.get_flag("no-highlight-cache"),
+            highlight_timeout: Duration::from_secs(*matches.get_one::<u64>("highlight-timeout").unwrap()),
+            highlight_policy: self.highlight_policy(),
+            palette: config.theme.custom_colors.clone(),
+            font_styles: config.theme.font_styles.clone(),
+            enforce_contrast: settings.enforce_contrast,
+            font_size: settings.font_size,
+            line_height: settings.line_height,
+            fragment_cache: self.fragment_cache.clone(),
+            cancellation: self.cancellation.clone(),
+            warnings: self.warnings.clone(),
+            toc_depth: (include_toc && self.toc_options.collapses(file_count)).then_some(self.toc_options.depth),
+        };
+
+        // Custom colors in the config file set the palette for every format.
+        // Otherwise a built-in theme colors the PDF, a dark mode forces the
+        // dark PDF palette, and the PDF renderer follows the syntax theme.
+        let pdf_theme = match (config.custom_theme(), Theme::by_name(&theme), config.get_theme_mode()) {
+            (Some(theme), _, _) | (None, Some(theme), _) => Some(theme),
+            (None, None, ThemeMode::Dark) => Some(Theme::dark()),
+            (None, None, ThemeMode::Light) => None,
+        };
+        let render_options = RenderOptions {
+            pdf: PdfOptions {
+                theme: pdf_theme,
+                columns: *matches.get_one::<u8>("pdf-columns").unwrap(),
+                landscape: matches.get_flag("landscape"),
+                pdfa: matches.get_flag("pdfa"),
+                compress: !matches.get_flag("pdf-no-compress"),
+                max_pages: matches.get_one::<u64>("max-pages").map(|pages| *pages as usize),
+                ..Default::default()
+            },
+            epub: EpubOptions {
+                group_by_directory: matches.get_flag("epub-group-dirs"),
+                cover: matches.get_one::<PathBuf>("cover").cloned(),
+                generate_cover: !matches.get_flag("no-cover"),
+                embed_font: !matches.get_flag("epub-no-font"),
+                image_root: Some(self.input_path.clone()),
+                ..if matches.get_flag("epub-compact") { EpubOptions::compact() } else { EpubOptions::default() }
+            },
+        };
+
+        // Every render holds its own copy of the document, so several only run
+        // at once when together they should fit under the memory limit
+        let targets = &self.targets;
+        let several = targets.len() > 1;
+        let render_memory_mb = markdown_content.len() as u64 * RENDER_MEMORY_FACTOR * targets.len() as u64 / 1024 / 1024;
+        let parallel = several && render_memory_mb <= self.memory_limit;
+        if several && !parallel {
+            self.progress.status(format!("🧠 Rendering one format at a time to stay under the {} MB memory limit", self.memory_limit));
+        }
+
+        let render_start = Instant::now();
+        let highlight_start = renderer::highlight_time();
+        let conversion = self.progress.spinner(&"🔄 Converting to final format...".color(Color::Cyan).to_string());
+        let rendered = render_targets(targets, markdown_content, &metadata, &render_options, self.run_start, parallel, |rendered| {
+            if several {
+                match &rendered.result {
+                    Ok(()) => conversion.println(format!("✅ Converted to {} in {:.2?}", rendered.target.name, rendered.elapsed)),
+                    Err(error) => conversion.println(format!("❌ Converting to {} failed: {:#}", rendered.target.name, error)),
+                }
+            }
+        });
+
+        let mut written = Vec::new();
+        let mut failures = Vec::new();
+        for rendered in rendered {
+            let target = rendered.target;
+            match rendered.result {
+                Ok(()) => {
+                    self.report.output(&target.path);
+                    written.push(target.path.clone());
+                }
+                Err(error) => {
+                    self.report.warn(format!("Converting to {} failed: {:#}", target.name, error));
+                    failures.push((target.name.as_str(), error));
+                }
+            }
+            if several {
+                let bytes = fs::metadata(&target.path).map(|metadata| metadata.len()).ok();
+                self.report.phases.record_at(&format!("render {}", target.name), rendered.started, rendered.elapsed, bytes);
+            }
+        }
+        if !several {
+            let highlighting = renderer::highlight_time() - highlight_start;
+            self.report.phases.record("highlight", highlighting, None);
+            self.report.phases.record("render", render_start.elapsed().saturating_sub(highlighting), self.report.outputs.last().map(|output| output.bytes));
+        }
+
+        if failures.is_empty() {
+            conversion.finish(format!("✅ Converted to {}", settings.formats().join(", ")));
+        } else {
+            conversion.finish(format!("⚠️  Converted to {} of {} formats", written.len(), targets.len()));
+        }
+        if self.verbose {
+            println!("⏱️  Rendered in {:.2?}", render_start.elapsed());
+        }
+        if self.verbose && metadata.highlight_cache {
+            let stats = shared_cache_stats();
+            println!("🎨 Highlight cache: {} hits, {} misses ({} entries, {} bytes)",
+                stats.hits, stats.misses, stats.entries, stats.bytes);
+        }
+        if !failures.is_empty() {
+            let names: Vec<&str> = failures.iter().map(|(name, _)| *name).collect();
+            let (_, error) = failures.remove(0);
+            // The formats that did render are kept; the exit code tells
+            // scripts the run only partly succeeded
+            let failure = if written.is_empty() { Failure::Render } else { Failure::PartialSuccess };
+            return Err(error
+                .context(format!("Failed to render {}", names.join(", ")))
+                .failure(failure));
+        }
+        Ok(written)
+    }
+
+    /// Sum up the run: the documents `written` and their sizes, the cache,
+    /// warnings and time by phase. Then open the document if asked.
+    fn finish(&mut self, written: &[PathBuf]) {
+        let verbose = self.verbose;
+        self.progress.status(format!("\n{} Document generated successfully!", "🎉".color(Color::Green)));
+        for output_path in written {
+            if !self.rebuilding && !self.report_to_stdout {
+                println!("📄 Output: {}", output_path.display().to_string().color(Color::Blue));
+            }
+
+            // Show file size
+            if let Ok(metadata) = fs::metadata(output_path) {
+                let size = metadata.len();
+                let size_str = if size > 1_048_576 {
+                    format!("{:.1} MB", size as f64 / 1_048_576.0)
+                } else if size > 1024 {
+                    format!("{:.1} KB", size as f64 / 1024.0)
+                } else {
+                    format!("{} bytes", size)
+                };
+                self.progress.status(format!("📊 File size: {}", size_str.color(Color::Green)));
+            }
+        }
+
+        if let Some(cache) = &self.fragment_cache {
+            let fragments = fragment_stats();
+            let fragments = FragmentStats {
+                hits: fragments.hits - self.fragments_before.hits,
+                misses: fragments.misses - self.fragments_before.misses,
+            };
+            self.report.cache = Some(fragments);
+            if verbose {
+                println!("🗄️  Fragment cache: {} reused, {} generated ({})", fragments.hits, fragments.misses, cache.dir().display());
+            }
+        }
+
+        if !self.warnings.is_empty() {
+            self.progress.status(self.warnings.summary().trim_end());
+        }
+        if verbose {
+            println!("⏱️  Time by phase ({:.2?} elapsed):", self.run_start.elapsed());
+            print!("{}", self.report.phases);
+        }
+        for path in self.temp_files.kept() {
+            self.progress.status(format!("📝 Kept temporary file: {}", path.display()));
+        }
+        // Watch mode opened the document on the first build; the viewer picks
+        // up rebuilds from there. Not being able to open it isn't a failure.
+        if let Some(path) = self.open.as_ref().filter(|_| !self.rebuilding) {
+            if let Err(error) = opener::open(path, self.settings.opener.as_deref()) {
+                eprintln!("ℹ️  Couldn't open {}: {:#}", path.display(), error);
+            }
+        }
+    }
+
+    /// Name of the input directory, "Repository" if it has none
+    fn repo_name(&self) -> &str {
+        self.input_path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("Repository")
+    }
+
+    /// The configured title, or the input directory's name
+    fn title(&self) -> String {
+        self.settings.title.clone().unwrap_or_else(|| self.repo_name().to_string())
+    }
+
+    fn highlight_policy(&self) -> HighlightPolicy {
+        HighlightPolicy {
+            max_bytes: *self.matches.get_one::<usize>("highlight-max-size").unwrap() * 1024,
+            ..Default::default()
+        }
+    }
+}
+
+/// One document to write: a format and where it goes
+struct Target {
+    name: String,
+    format: OutputFormat,
+    path: PathBuf,
+}
+
+/// How writing one target went, and when, measured from the start of the run
+struct Rendered<'a> {
+    target: &'a Target,
+    started: Duration,
+    elapsed: Duration,
+    result: Result<()>,
+}
+
+/// Summarize what a `--dry-run` found
+fn print_estimate(estimate: &SizeEstimate, warnings: &[String]) {
+    println!();
+    println!("🧪 Dry run, nothing written");
+    println!("📁 Files: {} ({}, {} lines)", estimate.files, MarkdownGenerator::format_file_size(estimate.content_bytes), estimate.lines);
+    println!("📝 Estimated markdown: {}", MarkdownGenerator::format_file_size(estimate.markdown_bytes));
+    if let Some(pages) = estimate.pdf_pages {
+        println!("📄 Estimated PDF pages: {}-{}", pages.min, pages.max);
+    }
+    for warning in warnings {
+        println!("⚠️  {}", warning);
+    }
+}
+
+/// Where each format goes. Several formats sharing an output name without
+/// `{format}` in it each get their own extension, and a directory gets a
+/// file named after the input.
+fn output_targets(input: &Path, template: &str, formats: &[&str]) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for &name in formats {
+        let format = match name {
+            "pdf" => OutputFormat::Pdf,
+            "epub" => OutputFormat::Epub,
+            "html" => OutputFormat::Html,
+            "markdown" => OutputFormat::Markdown,
+            _ => unreachable!(), // Settings::resolve ensures this won't happen
+        };
+        let context = FilenameContext::new(input, name);
+        let mut path = PathBuf::from(expand_filename_template(template, &context)?);
+        if output_path::is_directory(&path) {
+            // `-o out/` writes out/<repo>.<extension>
+            path.push(format!("{}.{}", context.repo, format.extension()));
+        } else if formats.len() > 1 && !template.contains("{format}") {
+            path.set_extension(format.extension());
+        }
+        targets.push(Target { name: name.to_string(), format, path });
+    }
+    Ok(targets)
+}
+
+/// Write every target from the same markdown, each on its own thread when
+/// `parallel` is set. A failure doesn't stop the others. `finished` sees
+/// each target as it completes, on the calling thread, so progress lines
+/// don't interleave. Results come back in the order of `targets`.
+fn render_targets<'a>(
+    targets: &'a [Target],
+    markdown: &str,
+    metadata: &DocumentMetadata,
+    options: &RenderOptions,
+    run_start: Instant,
+    parallel: bool,
+    mut finished: impl FnMut(&Rendered),
+) -> Vec<Rendered<'a>> {
+    let render = |target: &'a Target| {
+        let started = run_start.elapsed();
+        let result = write_target(target, markdown, metadata, options);
+        Rendered { target, started, elapsed: run_start.elapsed() - started, result }
+    };
+    let mut rendered: Vec<Rendered> = if parallel {
+        std::thread::scope(|scope| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            for target in targets {
+                let sender = sender.clone();
+                let render = &render;
+                scope.spawn(move || sender.send(render(target)));
+            }
+            drop(sender);
+            receiver.iter().inspect(&mut finished).collect()
+        })
+    } else {
+        targets.iter().map(render).inspect(&mut finished).collect()
+    };
+    rendered.sort_by_key(|rendered| targets.iter().position(|target| std::ptr::eq(target, rendered.target)));
+    rendered
+}
+
+fn write_target(target: &Target, markdown: &str, metadata: &DocumentMetadata, options: &RenderOptions) -> Result<()> {
+    match target.format {
+        // Markdown output is the intermediate file behind a front matter block
+        OutputFormat::Markdown => fs::write(&target.path, front_matter(metadata) + markdown)
+            .context("Failed to write markdown file"),
+        ref format => create_renderer_with_options(format, options)
+            .context("Failed to create renderer")?
+            .save_to_file(markdown, metadata, &target.path)
+            .context("Failed to render document"),
+    }
+}
+
+/// What happened while writing the markdown a chunk at a time
+struct ChunkedRun {
+    /// Files cut down to a preview
+    truncated: usize,
+    /// Memory ran low, so the rest was written with less of it
+    low_memory: bool,
+}
+
+/// Resident memory of this process in bytes, measured with sysinfo so it
+/// works on every platform
+fn process_memory(sys: &mut System) -> Option<u64> {
+    let pid = sysinfo::get_current_pid().ok()?;
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]));
+    sys.process(pid).map(|process| process.memory())
+}
+
+/// What `process_files_in_chunks` writes, where, and within which limits
+struct ChunkedWrite<'a> {
+    generator: &'a MarkdownGenerator,
+    model: &'a DocumentModel,
+    /// The files `model` was built from
+    files: &'a [FileInfo],
+    output_path: &'a Path,
+    chunk_size: usize,
+    memory_limit_mb: u64,
+    max_file_size_mb: u64,
+    /// Advanced as each file is written
+    generation: &'a Stage,
+    /// Log each chunk and file along with memory use
+    verbose: bool,
+}
+
+/// Write the markdown for `write.model` a chunk at a time, keeping the
+/// process under the memory limit
+fn process_files_in_chunks(write: &ChunkedWrite) -> Result<ChunkedRun> {
+    let ChunkedWrite { generator, model, files, output_path, chunk_size, memory_limit_mb, max_file_size_mb, generation, verbose } = *write;
+    let mut sys = System::new();
+    let mut sink = BufWriter::new(fs::File::create(output_path)
+        .context("Failed to create chunked markdown file")?);
+    let probe = Mutex::new(System::new());
+    let options = ChunkOptions {
+        chunk_size,
+        max_file_size: (max_file_size_mb * 1024 * 1024) as usize,
+        memory_limit: Some(MemoryLimit::new(memory_limit_mb * 1024 * 1024)
+            .with_probe(move || process_memory(&mut *probe.lock().ok()?))),
+    };
+    let mut run = ChunkedRun { truncated: 0, low_memory: false };
+    generator.write_model_chunked(model, files, &mut sink, &options, |event| match event {
+        ChunkEvent::Chunk { index, count, files } => if verbose {
+            println!("📄 Processing chunk {} of {} ({} files)", index + 1, count, files);
+        },
+        ChunkEvent::LowMemory { used, limit } => {
+            run.low_memory = true;
+            generation.println(format!("🧠 Memory use ({} MB) is near the {} MB limit; writing one file at a time with shorter previews",
+                used / 1024 / 1024, limit / 1024 / 1024));
+        }
+        ChunkEvent::File { index, file, truncated } => {
+            generation.inc(file.path.as_str());
+            if truncated {
+                run.truncated += 1;
+            }
+            if !verbose {
+                return;
+            }
+            let used_memory_mb = process_memory(&mut sys).unwrap_or(0) / 1024 / 1024;
+            let file_size_str = if file.size > 10_000_000 {
+                format!("{} ⚠️", MarkdownGenerator::format_file_size(file.size))
+            } else {
+                MarkdownGenerator::format_file_size(file.size)
+            };
+            println!("   📄 Processing file {}/{}: {} ({}) [Memory: {} MB/{} MB]", 
+                index + 1, files.len(), file.path, file_size_str, 
+                used_memory_mb, memory_limit_mb);
+            if truncated {
+                println!("   🔄 File too large ({}), showing a preview + summary", MarkdownGenerator::format_file_size(file.size));
+            }
+            if file.path.ends_with(".md") || file.path.ends_with(".markdown") {
+                println!("   📝 Processing markdown file: {} (no code block)", file.path);
+            } else {
+                println!("   💻 Processing code file: {} (with code block)", file.path);
+            }
+        }
+    }).context("Failed to write chunked markdown file")?;
+    sink.flush().context("Failed to write chunked markdown file")?;
+    Ok(run)
+}
+
+fn preview_file(path: &Path, metadata: &DocumentMetadata, html: bool) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let highlighter = renderer::highlighter_for(metadata)?;
+    print!("{}", preview(&highlighter, &path.to_string_lossy(), &content, html));
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use colorful::{Colorful, Color};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::{CliSettings, Config, Settings};
+use crate::diffing::{self, ChangeKind};
+use crate::document_model::DocumentModel;
+use crate::markdown_generator::{front_matter, MarkdownGenerator};
+use crate::renderer::{create_renderer, DocumentMetadata, OutputFormat};
+
+use super::file_processor;
+
+/// Compare the input with a document model saved by `--emit-ast`, writing
+/// a change report or, with `--annotate`, the document with its changed
+/// files badged
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let old_path = matches.get_one::<PathBuf>("old").unwrap();
+    let input_path = matches.get_one::<PathBuf>("input").unwrap();
+    if !input_path.exists() {
+        anyhow::bail!("Input path does not exist: {}", input_path.display());
+    }
+    let json = fs::read_to_string(old_path)
+        .with_context(|| format!("Failed to read {}", old_path.display()))?;
+    let old = DocumentModel::from_json(&json)
+        .with_context(|| format!("Failed to load the document model in {}", old_path.display()))?;
+
+    let config = Config::load_default()
+        .context("Failed to load configuration")?;
+    let cli = CliSettings {
+        no_gitignore: matches.get_flag("no-gitignore"),
+        ..Default::default()
+    };
+    let settings = Settings::resolve(&config, std::env::vars(), &cli)?;
+    let files = file_processor(matches, &config, settings.respect_gitignore)
+        .process_directory(input_path)
+        .context("Failed to process input directory")?;
+
+    let repo_name = input_path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("Repository");
+    let generator = MarkdownGenerator::new(true, true);
+    let new = generator.build_document_model(&files, repo_name);
+    let changes = diffing::compare(&old, &new)
+        .context("Failed to compare the documents")?;
+
+    let (title, markdown) = if matches.get_flag("annotate") {
+        let markdown = generator.with_change_badges(changes.changes()).render_markdown(&new)?;
+        (repo_name.to_string(), markdown)
+    } else {
+        let title = format!("Changes in {}", repo_name);
+        let markdown = changes.to_markdown(&title, &old);
+        (title, markdown)
+    };
+    let metadata = DocumentMetadata::builder(title).build();
+    let output = match matches.get_one::<String>("format").map(String::as_str) {
+        Some("html") => create_renderer(&OutputFormat::Html)?
+            .render_markdown(&markdown, &metadata)
+            .context("Failed to render the HTML")?,
+        _ => (front_matter(&metadata) + &markdown).into_bytes(),
+    };
+
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => {
+            fs::write(path, output)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "🔀 {} added, {} removed, {} modified, {} unchanged",
+                changes.count(ChangeKind::Added),
+                changes.count(ChangeKind::Removed),
+                changes.count(ChangeKind::Modified),
+                changes.unchanged,
+            );
+            println!("📄 Output: {}", path.display().to_string().color(Color::Blue));
+        }
+        None => std::io::stdout().write_all(&output)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+use clap::ArgMatches;
+use colorful::{Colorful, Color};
+
+use crate::config::Config;
+
+/// Write a sample config, and with `--with-ignore-file` a starter ignore file
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let force = matches.get_flag("force");
+    let config_path = Config::init_path(matches.get_flag("global"))?;
+    Config::create_sample_config(&config_path, force)?;
+    println!("{}", format!("✅ Wrote {}", config_path.display()).color(Color::Green));
+    if matches.get_flag("with-ignore-file") {
+        let ignore_path = Config::create_sample_ignore_file(".", force)?;
+        println!("{}", format!("✅ Wrote {}", ignore_path.display()).color(Color::Green));
+    }
+    Ok(())
+}
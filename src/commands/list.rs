@@ -0,0 +1,49 @@
+use anyhow::Result;
+use clap::ArgMatches;
+use colorful::{Colorful, Color};
+use std::path::{Path, PathBuf};
+
+use crate::syntax::catalog::{language_table, theme_table};
+use crate::syntax::highlighter::{default_syntax_dir, default_theme_dir, SyntaxHighlighter};
+use crate::theme::builtin_theme_names;
+
+/// List the themes or languages named by the subcommand
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let syntax_dir = matches.get_one::<PathBuf>("syntax-dir").map(PathBuf::as_path);
+    match matches.subcommand_name() {
+        Some("themes") => themes(syntax_dir),
+        Some("languages") => languages(syntax_dir),
+        _ => unreachable!("clap requires a list subcommand"),
+    }
+}
+
+pub fn themes(syntax_dir: Option<&Path>) -> Result<()> {
+    println!("{}", "Available syntax highlighting themes:".color(Color::Blue).bold());
+    
+    let highlighter = SyntaxHighlighter::with_syntax_dir(syntax_dir)?;
+    print!("{}", theme_table(&highlighter.themes()));
+
+    println!("\n{}", "Built-in themes (also color PDF output):".color(Color::Blue).bold());
+    for name in builtin_theme_names() {
+        println!("  {}", name);
+    }
+    
+    if let Some(theme_dir) = default_theme_dir() {
+        println!("\n{}", format!("Custom .tmTheme files are loaded from {}", theme_dir.display()).color(Color::Yellow));
+    }
+    
+    Ok(())
+}
+
+pub fn languages(syntax_dir: Option<&Path>) -> Result<()> {
+    println!("{}", "Supported programming languages:".color(Color::Blue).bold());
+    
+    let highlighter = SyntaxHighlighter::with_syntax_dir(syntax_dir)?;
+    print!("{}", language_table(&highlighter.languages()));
+    
+    if let Some(syntax_dir) = default_syntax_dir() {
+        println!("\n{}", format!("Custom .sublime-syntax files are loaded from {}", syntax_dir.display()).color(Color::Yellow));
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! One module per subcommand, each with a `run` taking its matches
+
+use clap::ArgMatches;
+
+use crate::config::Config;
+use crate::file_processor::FileProcessor;
+
+pub mod convert;
+pub mod diff;
+pub mod init;
+pub mod list;
+pub mod stats;
+pub mod test_project;
+
+/// A file processor applying the configured ignore rules
+pub fn file_processor(matches: &ArgMatches, config: &Config, respect_gitignore: bool) -> FileProcessor {
+    let ignored_dirs: Vec<String> = matches
+        .get_many::<String>("ignore")
+        .unwrap_or_default()
+        .map(|s| s.to_string())
+        .collect();
+    FileProcessor::new()
+        .with_gitignore_respect(respect_gitignore)
+        .with_ignore_config(config.ignore.clone())
+        .with_ignored_directories(ignored_dirs)
+}
@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use colorful::{Colorful, Color};
+use std::path::PathBuf;
+
+use crate::config::{CliSettings, Config, Settings};
+use crate::stats::RepoStats;
+use crate::todos;
+
+use super::file_processor;
+
+/// Report counts for the files the document would include
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let input_path = matches.get_one::<PathBuf>("input").unwrap();
+    if !input_path.exists() {
+        anyhow::bail!("Input path does not exist: {}", input_path.display());
+    }
+    let config = Config::load_default()
+        .context("Failed to load configuration")?;
+    let cli = CliSettings {
+        no_gitignore: matches.get_flag("no-gitignore"),
+        ..Default::default()
+    };
+    let settings = Settings::resolve(&config, std::env::vars(), &cli)?;
+    let files = file_processor(matches, &config, settings.respect_gitignore)
+        .process_directory(input_path)
+        .context("Failed to process input directory")?;
+
+    let mut stats = RepoStats::from_files(&files);
+    if let Some(markers) = matches.get_one::<String>("todo-index") {
+        stats = stats.with_todos(&files, &todos::parse_markers(markers));
+    }
+    if matches.get_flag("json") {
+        println!("{}", stats.to_json()?);
+    } else {
+        println!("{}", format!("📊 {}", input_path.display()).color(Color::Blue).bold());
+        print!("{}", stats);
+    }
+    Ok(())
+}
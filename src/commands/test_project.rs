@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use colorful::{Colorful, Color};
+use std::fs;
+use std::path::Path;
+
+/// Render the test project to every format, EPUB only with
+/// `--include-experimental`
+pub async fn run(matches: &ArgMatches) -> Result<()> {
+    let include_experimental = matches.get_flag("include-experimental");
+    use std::process::Command;
+    
+    println!("{}", "🧪 Running Test Project Generation".color(Color::Blue).bold());
+    
+    if include_experimental {
+        println!("{}", "🧪 Including experimental formats (EPUB)".color(Color::Yellow));
+    } else {
+        println!("{}", "📝 Skipping experimental formats (use --include-experimental to include EPUB)".color(Color::Yellow));
+    }
+    
+    // Clean output_test folder
+    println!("{}", "🧹 Cleaning testfiles/output_test folder...".color(Color::Cyan));
+    if Path::new("testfiles/output_test").exists() {
+        fs::remove_dir_all("testfiles/output_test")
+            .context("Failed to remove testfiles/output_test directory")?;
+    }
+    fs::create_dir_all("testfiles/output_test")
+        .context("Failed to create testfiles/output_test directory")?;
+    
+    // Check test_project directory exists
+    println!("{}", "📁 Checking testfiles/test_project...".color(Color::Cyan));
+    if !Path::new("testfiles/test_project").exists() {
+        println!("❌ testfiles/test_project directory not found!");
+        println!("Please create a testfiles/test_project directory with some files to test with.");
+        return Ok(());
+    }
+    
+    // Check if directory has files
+    let test_files = fs::read_dir("testfiles/test_project")
+        .context("Failed to read testfiles/test_project directory")?
+        .count();
+    
+    if test_files == 0 {
+        println!("❌ testfiles/test_project directory is empty!");
+        println!("Please add some files to the testfiles/test_project directory to test with.");
+        return Ok(());
+    }
+    
+    println!("✅ Found testfiles/test_project directory with {} files", test_files);
+    
+    // Generate all formats (exclude EPUB unless experimental flag is set)
+    let formats = if include_experimental {
+        vec!["markdown", "html", "epub", "pdf"]
+    } else {
+        vec!["markdown", "html", "pdf"]
+    };
+    let mut success_count = 0;
+    let mut failed_formats = Vec::new();
+    
+    for format in &formats {
+        println!("{}", format!("📄 Generating {} format...", format).color(Color::Cyan));
+        
+        let output_file = format!("testfiles/output_test/test_project.{}", 
+            match *format {
+                "markdown" => "md",
+                other => other,
+            }
+        );
+        
+        // Run the scrollcast command using the same binary
+        let result = Command::new("cargo")
+            .args([
+                "run", "--",
+                "testfiles/test_project",
+                "--output", &output_file,
+                "--format", format,
+                "--yes"
+            ])
+            .status();
+        
+        match result {
+            Ok(status) if status.success() => {
+                println!("✅ {} generated successfully", format);
+                success_count += 1;
+                
+                // Show file size
+                if let Ok(metadata) = fs::metadata(&output_file) {
+                    let size = metadata.len();
+                    let size_str = if size > 1_048_576 {
+                        format!("{:.1} MB", size as f64 / 1_048_576.0)
+                    } else if size > 1024 {
+                        format!("{:.1} KB", size as f64 / 1024.0)
+                    } else {
+                        format!("{} bytes", size)
+                    };
+                    println!("   📊 File size: {}", size_str.color(Color::Green));
+                }
+            }
+            Ok(_) => {
+                println!("❌ Failed to generate {}", format);
+                failed_formats.push(format);
+            }
+            Err(e) => {
+                println!("❌ Error generating {}: {}", format, e);
+                failed_formats.push(format);
+            }
+        }
+    }
+    
+    // Summary
+    println!("\n{}", "📊 Test Project Generation Summary".color(Color::Blue).bold());
+    println!("✅ Successfully generated: {}/{} formats", success_count, formats.len());
+    
+    if !failed_formats.is_empty() {
+        let failed_list: Vec<String> = failed_formats.iter().map(|f| f.to_string()).collect();
+        println!("❌ Failed formats: {}", failed_list.join(", "));
+    }
+    
+    println!("📁 Output directory: {}", "testfiles/output_test/".color(Color::Blue));
+    
+    // List generated files
+    if let Ok(entries) = fs::read_dir("testfiles/output_test") {
+        println!("\n{}", "📄 Generated files:".color(Color::Cyan));
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                let filename = path.file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("unknown");
+                
+                if let Ok(metadata) = entry.metadata() {
+                    let size = metadata.len();
+                    let size_str = if size > 1_048_576 {
+                        format!("{:.1} MB", size as f64 / 1_048_576.0)
+                    } else if size > 1024 {
+                        format!("{:.1} KB", size as f64 / 1024.0)
+                    } else {
+                        format!("{} bytes", size)
+                    };
+                    println!("  📄 {} ({})", filename.color(Color::Green), size_str);
+                } else {
+                    println!("  📄 {}", filename.color(Color::Green));
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,694 @@
+use anyhow::{bail, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A string or character literal, inside which comment markers are text
+#[derive(Debug, Clone, Copy)]
+struct Quote {
+    open: &'static str,
+    close: &'static str,
+    /// Backslash escapes the next character
+    escapes: bool,
+    /// May span lines; single-line quotes end at an unterminated newline
+    multiline: bool,
+}
+
+const DOUBLE: Quote = Quote { open: "\"", close: "\"", escapes: true, multiline: false };
+const SINGLE: Quote = Quote { open: "'", close: "'", escapes: true, multiline: false };
+const RAW_SINGLE: Quote = Quote { open: "'", close: "'", escapes: false, multiline: false };
+const MULTILINE_DOUBLE: Quote = Quote { open: "\"", close: "\"", escapes: true, multiline: true };
+const BACKTICK: Quote = Quote { open: "`", close: "`", escapes: true, multiline: true };
+const RAW_BACKTICK: Quote = Quote { open: "`", close: "`", escapes: false, multiline: true };
+const TRIPLE_DOUBLE: Quote = Quote { open: "\"\"\"", close: "\"\"\"", escapes: true, multiline: true };
+const TRIPLE_SINGLE: Quote = Quote { open: "'''", close: "'''", escapes: true, multiline: true };
+const LONG_BRACKET: Quote = Quote { open: "[[", close: "]]", escapes: false, multiline: true };
+
+/// How comments and literals are written in one language family
+#[derive(Debug, Clone, Copy)]
+struct CommentSyntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    /// Block comments nest, as in Rust and Haskell
+    nested: bool,
+    /// Line comment prefixes that mark documentation, like `///`
+    doc_line: &'static [&'static str],
+    /// Block comment openers that mark documentation, like `/**`
+    doc_block: &'static [&'static str],
+    /// Longest delimiter first, so `"""` wins over `"`
+    quotes: &'static [Quote],
+    /// `'x'` is a character literal, but a lone `'` (a Rust lifetime or a
+    /// Haskell prime) is not a quote
+    char_literals: bool,
+    /// Rust's `r"..."` and `r#"..."#`
+    raw_strings: bool,
+    /// Line comments only start at the beginning of a word, as in shell
+    /// where `$#` and `a#b` are not comments
+    word_start: bool,
+    /// Quotes only open a string at the beginning of a word, as in YAML
+    /// where `it's` is plain text
+    quote_word_start: bool,
+    /// Triple-quoted strings standing alone as a statement are docstrings
+    docstrings: bool,
+}
+
+const NO_SYNTAX: CommentSyntax = CommentSyntax {
+    line: &[],
+    block: None,
+    nested: false,
+    doc_line: &[],
+    doc_block: &[],
+    quotes: &[],
+    char_literals: false,
+    raw_strings: false,
+    word_start: false,
+    quote_word_start: false,
+    docstrings: false,
+};
+
+const C_LIKE: CommentSyntax = CommentSyntax {
+    line: &["//"],
+    block: Some(("/*", "*/")),
+    doc_line: &["///", "//!"],
+    doc_block: &["/**", "/*!"],
+    quotes: &[DOUBLE],
+    char_literals: true,
+    ..NO_SYNTAX
+};
+
+const HASH: CommentSyntax = CommentSyntax {
+    line: &["#"],
+    quotes: &[DOUBLE, RAW_SINGLE],
+    ..NO_SYNTAX
+};
+
+const MARKUP: CommentSyntax = CommentSyntax {
+    block: Some(("<!--", "-->")),
+    ..NO_SYNTAX
+};
+
+/// JSON has no comments, but its strings hide text all the same
+const JSON: CommentSyntax = CommentSyntax {
+    quotes: &[DOUBLE],
+    ..NO_SYNTAX
+};
+
+impl CommentSyntax {
+    fn for_language(language: &str) -> Option<Self> {
+        let syntax = match language {
+            "rust" => CommentSyntax { nested: true, raw_strings: true, quotes: &[MULTILINE_DOUBLE], ..C_LIKE },
+            "c" | "cpp" | "java" | "solidity" | "protobuf" => C_LIKE,
+            "kotlin" | "swift" => CommentSyntax { nested: true, quotes: &[TRIPLE_DOUBLE, DOUBLE], ..C_LIKE },
+            "go" => CommentSyntax { quotes: &[DOUBLE, RAW_BACKTICK], ..C_LIKE },
+            "javascript" | "typescript" | "jsx" | "tsx" | "dart" | "gradle" => CommentSyntax {
+                quotes: &[TRIPLE_DOUBLE, TRIPLE_SINGLE, DOUBLE, SINGLE, BACKTICK],
+                char_literals: false,
+                ..C_LIKE
+            },
+            "php" => CommentSyntax {
+                line: &["//", "#"],
+                quotes: &[DOUBLE, SINGLE],
+                char_literals: false,
+                ..C_LIKE
+            },
+            "zig" => CommentSyntax { block: None, doc_block: &[], ..C_LIKE },
+            "css" | "scss" => CommentSyntax {
+                line: if language == "scss" { &["//"] } else { &[] },
+                quotes: &[DOUBLE, SINGLE],
+                doc_line: &[],
+                doc_block: &[],
+                char_literals: false,
+                ..C_LIKE
+            },
+            "python" => CommentSyntax {
+                quotes: &[TRIPLE_DOUBLE, TRIPLE_SINGLE, DOUBLE, SINGLE],
+                docstrings: true,
+                ..HASH
+            },
+            "bash" | "zsh" | "fish" | "dockerfile" | "perl" => CommentSyntax { word_start: true, ..HASH },
+            "yaml" => CommentSyntax { word_start: true, quote_word_start: true, ..HASH },
+            "toml" => CommentSyntax {
+                quotes: &[TRIPLE_DOUBLE, TRIPLE_SINGLE, DOUBLE, RAW_SINGLE],
+                ..HASH
+            },
+            "ruby" | "r" | "elixir" | "nim" | "cmake" | "graphql" => CommentSyntax { quotes: &[DOUBLE, SINGLE], ..HASH },
+            "nix" => CommentSyntax { block: Some(("/*", "*/")), quotes: &[MULTILINE_DOUBLE], ..HASH },
+            "terraform" => CommentSyntax { line: &["#", "//"], block: Some(("/*", "*/")), quotes: &[DOUBLE], ..HASH },
+            "powershell" => CommentSyntax { block: Some(("<#", "#>")), ..HASH },
+            "ini" | "properties" => CommentSyntax { line: &["#", ";"], quotes: &[], word_start: true, ..HASH },
+            "html" | "xml" | "markdown" | "svelte" | "vue" => MARKUP,
+            "sql" => CommentSyntax {
+                line: &["--"],
+                block: Some(("/*", "*/")),
+                quotes: &[RAW_SINGLE, Quote { open: "\"", close: "\"", escapes: false, multiline: false }],
+                ..NO_SYNTAX
+            },
+            "haskell" => CommentSyntax {
+                line: &["--"],
+                block: Some(("{-", "-}")),
+                nested: true,
+                doc_line: &["-- |", "-- ^"],
+                doc_block: &["{-|"],
+                quotes: &[DOUBLE],
+                char_literals: true,
+                ..NO_SYNTAX
+            },
+            "lua" => CommentSyntax {
+                line: &["--"],
+                block: Some(("--[[", "]]")),
+                quotes: &[DOUBLE, SINGLE, LONG_BRACKET],
+                ..NO_SYNTAX
+            },
+            "ocaml" => CommentSyntax {
+                block: Some(("(*", "*)")),
+                nested: true,
+                doc_block: &["(**"],
+                quotes: &[DOUBLE],
+                char_literals: true,
+                ..NO_SYNTAX
+            },
+            _ => return None,
+        };
+        Some(syntax)
+    }
+}
+
+/// Whether `language` has comments [`strip_comments`] knows how to remove
+pub fn supports_language(language: &str) -> bool {
+    CommentSyntax::for_language(language).is_some()
+}
+
+/// Remove the comments from `content`, written in `language`. Lines left
+/// empty by the removal are dropped; other blank lines stay. With
+/// `keep_doc_comments`, documentation comments (`///`, `/** */`, Python
+/// docstrings, ...) are kept. Content in a language without known comment
+/// syntax is returned unchanged.
+pub fn strip_comments(content: &str, language: &str, keep_doc_comments: bool) -> String {
+    let Some(syntax) = CommentSyntax::for_language(language) else {
+        return content.to_string();
+    };
+    let segments = Scanner { syntax, content }.segments();
+    let mut stripper = Stripper { keep_doc_comments, output: String::with_capacity(content.len()), stripped_lines: vec![false] };
+    for (kind, range) in segments {
+        let text = &content[range];
+        match kind {
+            Segment::Code | Segment::Literal => stripper.emit(text),
+            Segment::Comment { doc } => stripper.comment(text, doc),
+            Segment::Docstring => stripper.comment(text, true),
+        }
+    }
+    stripper.finish()
+}
+
+/// The stretches of `content`, written in `language`, that aren't string
+/// literals: code and comments. Content in a language whose literals
+/// aren't known is one stretch.
+pub fn outside_literals(content: &str, language: Option<&str>) -> Vec<Range<usize>> {
+    let syntax = match language {
+        Some("json") => Some(JSON),
+        Some(language) => CommentSyntax::for_language(language),
+        None => None,
+    };
+    let Some(syntax) = syntax else {
+        return std::iter::once(0..content.len()).collect();
+    };
+    Scanner { syntax, content }.segments().into_iter()
+        .filter(|(kind, _)| *kind != Segment::Literal)
+        .map(|(_, range)| range)
+        .collect()
+}
+
+/// What `--remove-empty-lines` does with blank lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyLineMode {
+    /// Leave them alone
+    #[default]
+    Keep,
+    /// Remove every blank line
+    All,
+    /// Collapse each run of blank lines into one
+    Squeeze,
+}
+
+impl FromStr for EmptyLineMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "keep" | "false" | "0" | "no" | "off" => Ok(EmptyLineMode::Keep),
+            "all" | "true" | "1" | "yes" | "on" => Ok(EmptyLineMode::All),
+            "squeeze" => Ok(EmptyLineMode::Squeeze),
+            _ => bail!("Invalid empty line mode: {:?} (expected all, squeeze or false)", value),
+        }
+    }
+}
+
+/// `remove_empty_lines` in the config is `true`, `false` or `"squeeze"`
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EmptyLineSetting {
+    Flag(bool),
+    Mode(String),
+}
+
+impl Serialize for EmptyLineMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            EmptyLineMode::Keep => EmptyLineSetting::Flag(false),
+            EmptyLineMode::All => EmptyLineSetting::Flag(true),
+            EmptyLineMode::Squeeze => EmptyLineSetting::Mode("squeeze".to_string()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmptyLineMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match EmptyLineSetting::deserialize(deserializer)? {
+            EmptyLineSetting::Flag(false) => Ok(EmptyLineMode::Keep),
+            EmptyLineSetting::Flag(true) => Ok(EmptyLineMode::All),
+            EmptyLineSetting::Mode(mode) => mode.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Remove or squeeze the blank lines of `content`. Blank lines inside
+/// multi-line string literals are kept when `language` is one whose strings
+/// [`strip_comments`] understands, and markdown is left alone entirely since
+/// its blank lines separate paragraphs.
+pub fn remove_empty_lines(content: &str, language: Option<&str>, mode: EmptyLineMode) -> String {
+    if mode == EmptyLineMode::Keep || language == Some("markdown") {
+        return content.to_string();
+    }
+    let protected = language
+        .and_then(CommentSyntax::for_language)
+        .map(|syntax| literal_lines(content, syntax))
+        .unwrap_or_default();
+
+    let mut output = String::with_capacity(content.len());
+    let mut previous_blank = false;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let blank = line.trim().is_empty() && !protected.get(index).copied().unwrap_or(false);
+        let keep = match mode {
+            EmptyLineMode::All => !blank,
+            _ => !(blank && previous_blank),
+        };
+        if keep {
+            output.push_str(line);
+        }
+        previous_blank = blank;
+    }
+    output
+}
+
+/// Per line, whether it starts inside a string literal that began on an
+/// earlier line
+fn literal_lines(content: &str, syntax: CommentSyntax) -> Vec<bool> {
+    let mut lines = vec![false; content.matches('\n').count() + 1];
+    let mut line = 0;
+    for (kind, range) in (Scanner { syntax, content }).segments() {
+        let text = &content[range];
+        let inside = matches!(kind, Segment::Literal | Segment::Docstring);
+        for (offset, _) in text.match_indices('\n') {
+            line += 1;
+            if inside && offset + 1 < text.len() {
+                lines[line] = true;
+            }
+        }
+    }
+    lines
+}
+
+/// What a stretch of source is, as far as comment removal cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Code,
+    Comment { doc: bool },
+    Literal,
+    Docstring,
+}
+
+/// Splits source into code, comments and literals
+struct Scanner<'a> {
+    syntax: CommentSyntax,
+    content: &'a str,
+}
+
+impl Scanner<'_> {
+    fn segments(&self) -> Vec<(Segment, Range<usize>)> {
+        let content = self.content;
+        let mut segments = Vec::new();
+        let mut code_start = 0;
+        let mut i = 0;
+        // A shebang line is an instruction to the system, not a comment
+        if self.syntax.line.contains(&"#") && content.starts_with("#!") {
+            i = line_end(content, 0);
+        }
+
+        while i < content.len() {
+            let rest = &content[i..];
+            let (segment, end) = if let Some(end) = self.block_comment_end(rest) {
+                (Segment::Comment { doc: self.is_doc_block(rest) }, i + end)
+            } else if self.starts_line_comment(i) {
+                (Segment::Comment { doc: self.is_doc_line(rest) }, line_end(content, i))
+            } else if let Some(end) = self.literal_end(i) {
+                if self.syntax.docstrings && self.is_docstring(i, end) {
+                    (Segment::Docstring, i + end)
+                } else {
+                    (Segment::Literal, i + end)
+                }
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+                continue;
+            };
+            if code_start < i {
+                segments.push((Segment::Code, code_start..i));
+            }
+            segments.push((segment, i..end));
+            i = end;
+            code_start = end;
+        }
+        if code_start < content.len() {
+            segments.push((Segment::Code, code_start..content.len()));
+        }
+        segments
+    }
+
+    /// Length of the block comment at the start of `rest`, if one opens there
+    fn block_comment_end(&self, rest: &str) -> Option<usize> {
+        let (open, close) = self.syntax.block?;
+        if !rest.starts_with(open) {
+            return None;
+        }
+        let mut depth = 0;
+        let mut j = 0;
+        while j < rest.len() {
+            if rest[j..].starts_with(open) && (j == 0 || self.syntax.nested) {
+                depth += 1;
+                j += open.len();
+            } else if rest[j..].starts_with(close) {
+                depth -= 1;
+                j += close.len();
+                if depth == 0 {
+                    return Some(j);
+                }
+            } else {
+                j += rest[j..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        Some(rest.len())
+    }
+
+    fn starts_line_comment(&self, i: usize) -> bool {
+        let rest = &self.content[i..];
+        if !self.syntax.line.iter().any(|prefix| rest.starts_with(prefix)) {
+            return false;
+        }
+        !self.syntax.word_start
+            || self.content[..i].chars().next_back().is_none_or(|c| c.is_whitespace() || c == ';')
+    }
+
+    fn is_doc_line(&self, rest: &str) -> bool {
+        self.syntax.doc_line.iter().any(|prefix| {
+            // `////` is a divider, not a doc comment
+            rest.starts_with(prefix) && !rest[prefix.len()..].starts_with(&prefix[prefix.len() - 1..])
+        })
+    }
+
+    fn is_doc_block(&self, rest: &str) -> bool {
+        let Some((open, close)) = self.syntax.block else {
+            return false;
+        };
+        // `/**/` is empty and `/*****` a banner
+        !rest.starts_with(&format!("{}{}", open, close))
+            && self.syntax.doc_block.iter().any(|prefix| {
+                rest.starts_with(prefix) && !rest[prefix.len()..].starts_with(&prefix[prefix.len() - 1..])
+            })
+    }
+
+    /// Length of the string or character literal starting at `i`, if any
+    fn literal_end(&self, i: usize) -> Option<usize> {
+        let rest = &self.content[i..];
+        if self.syntax.raw_strings {
+            if let Some(end) = self.raw_string_end(i) {
+                return Some(end);
+            }
+        }
+        if self.syntax.char_literals && rest.starts_with('\'') {
+            return char_literal_end(rest);
+        }
+        let quote = self.syntax.quotes.iter().find(|quote| rest.starts_with(quote.open))?;
+        if self.syntax.quote_word_start && self.content[..i].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            return None;
+        }
+        let mut j = quote.open.len();
+        while j < rest.len() {
+            let tail = &rest[j..];
+            if quote.escapes && tail.starts_with('\\') {
+                j += 1 + tail[1..].chars().next().map_or(0, char::len_utf8);
+            } else if tail.starts_with(quote.close) {
+                return Some(j + quote.close.len());
+            } else if !quote.multiline && tail.starts_with('\n') {
+                return Some(j);
+            } else {
+                j += tail.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        Some(rest.len())
+    }
+
+    /// `r"..."`, `r#"..."#` and their `br` forms
+    fn raw_string_end(&self, i: usize) -> Option<usize> {
+        let rest = &self.content[i..];
+        let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+        if self.content[..i].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+        if !after_prefix[hashes..].starts_with('"') {
+            return None;
+        }
+        let body_start = rest.len() - after_prefix.len() + hashes + 1;
+        let close = format!("\"{}", "#".repeat(hashes));
+        Some(rest[body_start..].find(&close).map_or(rest.len(), |end| body_start + end + close.len()))
+    }
+
+    /// A triple-quoted string alone on its lines, as docstrings are written
+    fn is_docstring(&self, start: usize, len: usize) -> bool {
+        let literal = &self.content[start..start + len];
+        if !literal.starts_with("\"\"\"") && !literal.starts_with("'''") {
+            return false;
+        }
+        let line_start = self.content[..start].rfind('\n').map_or(0, |pos| pos + 1);
+        let end = start + len;
+        self.content[line_start..start].trim().is_empty()
+            && self.content[end..line_end(self.content, end)].trim().is_empty()
+    }
+}
+
+/// Builds the output of [`strip_comments`]
+struct Stripper {
+    keep_doc_comments: bool,
+    output: String,
+    /// Per output line, whether a comment was removed from it
+    stripped_lines: Vec<bool>,
+}
+
+impl Stripper {
+    /// Keep `text` when it documents something and docs are kept, otherwise
+    /// drop it and remember its line
+    fn comment(&mut self, text: &str, doc: bool) {
+        if doc && self.keep_doc_comments {
+            self.emit(text);
+        } else {
+            *self.stripped_lines.last_mut().unwrap() = true;
+        }
+    }
+
+    fn emit(&mut self, text: &str) {
+        for _ in text.matches('\n') {
+            self.stripped_lines.push(false);
+        }
+        self.output.push_str(text);
+    }
+
+    /// Drop lines that held nothing but comments and trim the space left
+    /// before removed trailing comments
+    fn finish(self) -> String {
+        let mut lines = Vec::with_capacity(self.stripped_lines.len());
+        for (line, stripped) in self.output.split('\n').zip(&self.stripped_lines) {
+            if !stripped {
+                lines.push(line);
+            } else if !line.trim().is_empty() {
+                lines.push(line.trim_end());
+            }
+        }
+        let mut result = lines.join("\n");
+        if self.output.ends_with('\n') && !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result
+    }
+}
+
+/// Index of the newline ending the line that contains `i`, or the end
+fn line_end(content: &str, i: usize) -> usize {
+    content[i..].find('\n').map_or(content.len(), |pos| i + pos)
+}
+
+/// `'a'` or `'\n'`; a lone `'` is not a literal
+fn char_literal_end(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, first) = chars.next()?;
+    if first == '\\' {
+        let from = 2 + rest[2..].chars().next()?.len_utf8();
+        return rest[from..].find('\'')
+            .filter(|&pos| !rest[from..from + pos].contains('\n'))
+            .map(|pos| from + pos + 1);
+    }
+    match chars.next() {
+        Some((pos, '\'')) if first != '\n' => Some(pos + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_comments() {
+        let source = "//! Crate docs\nuse std::fs; // trailing\n\n/// Docs\nfn main() {\n    /* block\n       comment */\n    let url = \"http://example.com\"; // not the string\n    let c = '/';\n    let s: &'static str = r#\"/* raw */\"#;\n    /* outer /* nested */ still comment */ println!(\"{}\", url);\n}\n";
+        assert_eq!(
+            strip_comments(source, "rust", false),
+            "use std::fs;\n\nfn main() {\n    let url = \"http://example.com\";\n    let c = '/';\n    let s: &'static str = r#\"/* raw */\"#;\n     println!(\"{}\", url);\n}\n"
+        );
+        assert_eq!(
+            strip_comments(source, "rust", true),
+            "//! Crate docs\nuse std::fs;\n\n/// Docs\nfn main() {\n    let url = \"http://example.com\";\n    let c = '/';\n    let s: &'static str = r#\"/* raw */\"#;\n     println!(\"{}\", url);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_javascript_strings_hide_comment_markers() {
+        let source = "const a = '// not a comment'; // comment\nconst b = `/* ${a} */`;\n/** JSDoc */\nfunction f() {}\n/**/\n";
+        assert_eq!(
+            strip_comments(source, "javascript", false),
+            "const a = '// not a comment';\nconst b = `/* ${a} */`;\nfunction f() {}\n"
+        );
+        assert_eq!(
+            strip_comments(source, "javascript", true),
+            "const a = '// not a comment';\nconst b = `/* ${a} */`;\n/** JSDoc */\nfunction f() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_c_escaped_quotes() {
+        let source = "char *s = \"say \\\"hi\\\" // there\"; /* gone */\nchar q = '\\''; // gone\n";
+        assert_eq!(
+            strip_comments(source, "c", false),
+            "char *s = \"say \\\"hi\\\" // there\";\nchar q = '\\'';\n"
+        );
+    }
+
+    #[test]
+    fn test_python_comments_and_docstrings() {
+        let source = "#!/usr/bin/env python3\n# module comment\ndef f():\n    \"\"\"Docstring # kept as text.\"\"\"\n    s = \"# not a comment\"  # comment\n    t = '''inline''' + s\n    return t\n";
+        assert_eq!(
+            strip_comments(source, "python", false),
+            "#!/usr/bin/env python3\ndef f():\n    s = \"# not a comment\"\n    t = '''inline''' + s\n    return t\n"
+        );
+        assert_eq!(
+            strip_comments(source, "python", true),
+            "#!/usr/bin/env python3\ndef f():\n    \"\"\"Docstring # kept as text.\"\"\"\n    s = \"# not a comment\"\n    t = '''inline''' + s\n    return t\n"
+        );
+    }
+
+    #[test]
+    fn test_shell_and_yaml_comments() {
+        let shell = "echo \"$#\" 'a # b' # count\n# whole line\nx=a#b\n";
+        assert_eq!(strip_comments(shell, "bash", false), "echo \"$#\" 'a # b'\nx=a#b\n");
+
+        let yaml = "key: it's here # comment\nurl: \"http://x/#anchor\"\n";
+        assert_eq!(strip_comments(yaml, "yaml", false), "key: it's here\nurl: \"http://x/#anchor\"\n");
+
+        let toml = "name = \"a # b\" # comment\n";
+        assert_eq!(strip_comments(toml, "toml", false), "name = \"a # b\"\n");
+    }
+
+    #[test]
+    fn test_markup_sql_and_haskell_comments() {
+        let html = "<p>text</p><!-- one -->\n<!--\n  several\n  lines\n-->\n<p>more</p>\n";
+        assert_eq!(strip_comments(html, "html", false), "<p>text</p>\n<p>more</p>\n");
+
+        let sql = "SELECT '--not' AS x -- comment\nFROM t; /* block */\n";
+        assert_eq!(strip_comments(sql, "sql", false), "SELECT '--not' AS x\nFROM t;\n");
+
+        let haskell = "-- | Docs\nf x' = \"--\" {- note {- nested -} -}\n-- plain\n";
+        assert_eq!(strip_comments(haskell, "haskell", false), "f x' = \"--\"\n");
+        assert_eq!(strip_comments(haskell, "haskell", true), "-- | Docs\nf x' = \"--\"\n");
+    }
+
+    #[test]
+    fn test_empty_lines_in_python_docstrings_survive() {
+        let source = "def f():\n    \"\"\"Summary.\n\n    Details.\n    \"\"\"\n\n\n    return '''a\n\nb'''\n";
+        assert_eq!(
+            remove_empty_lines(source, Some("python"), EmptyLineMode::All),
+            "def f():\n    \"\"\"Summary.\n\n    Details.\n    \"\"\"\n    return '''a\n\nb'''\n"
+        );
+        assert_eq!(
+            remove_empty_lines(source, Some("python"), EmptyLineMode::Squeeze),
+            "def f():\n    \"\"\"Summary.\n\n    Details.\n    \"\"\"\n\n    return '''a\n\nb'''\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_lines_in_rust() {
+        let source = "use std::fs;\n\n\n\nfn main() {\n    \n    let s = \"one\n\n\ntwo\";\n}\n";
+        assert_eq!(
+            remove_empty_lines(source, Some("rust"), EmptyLineMode::Squeeze),
+            "use std::fs;\n\nfn main() {\n    \n    let s = \"one\n\n\ntwo\";\n}\n"
+        );
+        assert_eq!(
+            remove_empty_lines(source, Some("rust"), EmptyLineMode::All),
+            "use std::fs;\nfn main() {\n    let s = \"one\n\n\ntwo\";\n}\n"
+        );
+        assert_eq!(remove_empty_lines(source, Some("rust"), EmptyLineMode::Keep), source);
+
+        // Stripping comments first doesn't leave gaps behind
+        let commented = "fn a() {}\n\n// one\n\n// two\n\nfn b() {}\n";
+        let stripped = strip_comments(commented, "rust", false);
+        assert_eq!(remove_empty_lines(&stripped, Some("rust"), EmptyLineMode::Squeeze), "fn a() {}\n\nfn b() {}\n");
+    }
+
+    #[test]
+    fn test_markdown_blank_lines_are_kept() {
+        let source = "# Title\n\n\nParagraph\n";
+        assert_eq!(remove_empty_lines(source, Some("markdown"), EmptyLineMode::All), source);
+        assert_eq!(remove_empty_lines(source, None, EmptyLineMode::All), "# Title\nParagraph\n");
+    }
+
+    #[test]
+    fn test_empty_line_mode_settings() -> Result<()> {
+        #[derive(Deserialize, Serialize)]
+        struct Formatting {
+            remove_empty_lines: EmptyLineMode,
+        }
+        for (toml_value, mode) in [("false", EmptyLineMode::Keep), ("true", EmptyLineMode::All), ("\"squeeze\"", EmptyLineMode::Squeeze)] {
+            let parsed: Formatting = toml::from_str(&format!("remove_empty_lines = {}", toml_value))?;
+            assert_eq!(parsed.remove_empty_lines, mode);
+            assert_eq!(toml::to_string(&parsed)?.trim(), format!("remove_empty_lines = {}", toml_value));
+        }
+        assert!(toml::from_str::<Formatting>("remove_empty_lines = \"some\"").is_err());
+        assert_eq!("squeeze".parse::<EmptyLineMode>()?, EmptyLineMode::Squeeze);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_language_is_unchanged() {
+        let source = "# heading\n// text\n";
+        assert_eq!(strip_comments(source, "csv", false), source);
+        assert!(!supports_language("csv"));
+        assert!(supports_language("rust"));
+    }
+}
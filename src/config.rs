@@ -1,54 +1,302 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
-use toml;
 
-use crate::theme::{ThemeMode, ColorScheme};
+use crate::comments::EmptyLineMode;
+use crate::file_processor::{IgnoreConfig, IGNORE_FILE_NAME};
+use crate::renderer::DEFAULT_FONT_SIZE;
+use crate::theme::{builtin_theme_names, ThemeMode, ColorScheme, FontStyle, Theme, TOKEN_STYLE_KEYS};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Name of the per-project configuration file
+pub const CONFIG_FILE_NAME: &str = "scrollcast.toml";
+
+/// Name of the user-wide configuration file in the home directory
+pub const GLOBAL_CONFIG_FILE_NAME: &str = ".scrollcast.toml";
+
+/// Output formats accepted by `--format`, `SCROLLCAST_FORMAT` and `output.format`
+pub const FORMATS: [&str; 4] = ["pdf", "epub", "html", "markdown"];
+
+/// The formats named in a comma-separated list such as "pdf,html", each
+/// once and in the order given
+pub fn split_formats(list: &str) -> Vec<&str> {
+    let mut formats: Vec<&str> = Vec::new();
+    for format in list.split(',').map(str::trim).filter(|format| !format.is_empty()) {
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    formats
+}
+
+/// Environment variables read by [`Settings::resolve`]
+pub const ENV_VARS: [&str; 17] = [
+    "SCROLLCAST_FORMAT",
+    "SCROLLCAST_THEME",
+    "SCROLLCAST_OUTPUT",
+    "SCROLLCAST_NO_GITIGNORE",
+    "SCROLLCAST_NO_TOC",
+    "SCROLLCAST_YES",
+    "SCROLLCAST_VERBOSE",
+    "SCROLLCAST_CHUNK_SIZE",
+    "SCROLLCAST_MEMORY_LIMIT",
+    "SCROLLCAST_MAX_FILE_SIZE",
+    "SCROLLCAST_ENFORCE_CONTRAST",
+    "SCROLLCAST_STRIP_COMMENTS",
+    "SCROLLCAST_KEEP_DOC_COMMENTS",
+    "SCROLLCAST_REMOVE_EMPTY_LINES",
+    "SCROLLCAST_FONT_SIZE",
+    "SCROLLCAST_LINE_HEIGHT",
+    "SCROLLCAST_OPENER",
+];
+
+/// The configuration written by `--init`: every option at its default,
+/// with a comment explaining it
+pub const SAMPLE_CONFIG: &str = r##"# scrollcast configuration
+#
+# Read from ./scrollcast.toml, or from ~/.scrollcast.toml when there is none
+# in the current directory. Every value below is the default.
+
+[output]
+# Directory output is written to
+folder = "output"
+# Output file name inside the folder, instead of passing -o. May use
+# {repo}, {date}, {datetime}, {format} and {commit}, as in
+# "{repo}-{date}.pdf"; -o paths accept the same placeholders.
+# filename = "repository.pdf"
+# Output format when --format is not given: pdf, epub, html or markdown,
+# or several separated by commas, as in "pdf,html"
+# format = "pdf"
+# Create the output directory if it doesn't exist
+create_folder = true
+# Write the whole repository to one document
+single_file = true
+
+[theme]
+# Theme used when --theme is not given: a syntax theme such as "kate", or
+# one of light, dark, solarized-light, solarized-dark, dracula, nord,
+# gruvbox-dark, high-contrast
+# name = "kate"
+# "light", "dark" or a built-in theme name; dark switches PDF output to the
+# dark palette
+mode = "light"
+# Body text size in points and line spacing as a multiple of it, for every
+# format; code is set a little smaller. Each format keeps its own defaults
+# when these are unset.
+# font_size = 10.0
+# line_height = 1.2
+
+# Color code with this palette in every format instead of the syntax theme.
+# All eleven colors are required, as #rgb or #rrggbb.
+# [theme.custom_colors]
+# background = "#ffffff"
+# text = "#1a1a1a"
+# keywords = "#0066cc"
+# strings = "#008000"
+# comments = "#808080"
+# numbers = "#800080"
+# functions = "#cc6600"
+# types = "#008080"
+# operators = "#cc0000"
+# line_numbers = "#666666"
+# header = "#000000"
+
+# Font styles per token kind, used with custom_colors. PDF output sets bold
+# tokens in bold; italic and underline apply to HTML and EPUB.
+# [theme.font_styles]
+# comments = { italic = true }
+# keywords = { bold = true }
+
+[document]
+# Title on the first page and in the document metadata, instead of the
+# input directory name
+# title = "Payments Service"
+# Author recorded in the document metadata
+# author = "Jane Doe"
+# Publisher recorded in the EPUB package
+# publisher = "Acme Corp"
+# Language of the document text, as a tag such as "en" or "pt-BR"
+# language = "en"
+
+[formatting]
+# Number the lines of code listings
+line_numbers = true
+# Number the pages of paginated output
+page_numbers = true
+# Highlight code with the selected theme
+syntax_highlighting = true
+# Strip comments from source files
+remove_comments = false
+# With remove_comments, keep doc comments such as /// and Python docstrings
+keep_doc_comments = false
+# Drop blank lines from source files: true removes them all, "squeeze"
+# collapses runs of them into one. Markdown files are never changed.
+remove_empty_lines = false
+
+[ignore]
+# File name patterns to leave out, e.g. ["*.log", ".env"]
+files = []
+# Extensions to leave out, e.g. [".tmp", ".cache"]
+extensions = []
+# Directory names to leave out, e.g. ["tmp", "logs"]
+directories = []
+"##;
+
+/// Starter `.scrollcastignore` written by `--init --with-ignore-file`
+pub const SAMPLE_IGNORE_FILE: &str = r"# Paths scrollcast leaves out, in .gitignore syntax. Applied even with
+# --no-gitignore.
+
+*.log
+*.tmp
+.env
+node_modules/
+target/
+dist/
+";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub output: OutputConfig,
     #[serde(default)]
     pub theme: ThemeConfig,
     #[serde(default)]
+    pub document: DocumentConfig,
+    #[serde(default)]
     pub formatting: FormattingConfig,
     #[serde(default)]
     pub ignore: IgnoreConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OutputConfig {
     pub folder: String,
     pub filename: Option<String>,
+    /// Format used when `--format` is not given, e.g. "html"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
     pub create_folder: bool,
     pub single_file: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ThemeConfig {
+    /// Theme used when `--theme` is not given, e.g. "dracula"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub mode: String, // "light" or "dark"
-    pub font_size: f32,
-    pub line_height: f32,
+    /// Body text size in points; each format keeps its own default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    /// Line spacing as a multiple of the text size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_height: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom_colors: Option<ColorScheme>,
+    /// Bold, italic and underline per token kind, used with `custom_colors`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub font_styles: BTreeMap<String, FontStyle>,
+}
+
+/// What the document says about itself: title, author and language
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentConfig {
+    /// Title used when `--title` is not given; defaults to the input
+    /// directory name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    /// Language tag such as "en" or "pt-BR"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FormattingConfig {
     pub line_numbers: bool,
     pub page_numbers: bool,
     pub syntax_highlighting: bool,
     pub remove_comments: bool,
-    pub remove_empty_lines: bool,
+    /// Keep `///`, `/** */` and docstrings when removing comments
+    #[serde(default)]
+    pub keep_doc_comments: bool,
+    /// `true`, `false` or `"squeeze"`
+    pub remove_empty_lines: EmptyLineMode,
+}
+
+/// Font sizes, in points, that give a readable page
+const FONT_SIZE_RANGE: std::ops::RangeInclusive<f32> = 4.0..=72.0;
+
+/// Line heights, as a multiple of the font size, that give a readable page
+const LINE_HEIGHT_RANGE: std::ops::RangeInclusive<f32> = 0.8..=3.0;
+
+/// Whether `tag` looks like a language tag: letters, digits and hyphens,
+/// starting with a two or three letter language code, as in "en" or "zh-Hant"
+pub fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Describe `value` if it falls outside `range`, naming it after `key`
+fn out_of_range(key: &str, value: f32, range: &std::ops::RangeInclusive<f32>) -> Option<String> {
+    (!range.contains(&value)).then(|| format!(
+        "{} {} is out of range (expected {} to {})",
+        key, value, range.start(), range.end(),
+    ))
+}
+
+/// A value in the configuration file that can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// The offending key, e.g. "theme.font_size"
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    /// All errors as a bulleted list, one per line
+    pub fn list(errors: &[ConfigError]) -> String {
+        errors.iter().map(|error| format!("  - {}", error)).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A TOML syntax or type error, located as `path:line:column`
+fn parse_error(path: &Path, content: &str, error: &toml::de::Error) -> anyhow::Error {
+    let message = error.message().trim();
+    match error.span() {
+        Some(span) => {
+            let before = &content[..span.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().map_or(0, |text| text.chars().count()) + 1;
+            anyhow!("Failed to parse {}:{}:{}: {}", path.display(), line, column, message)
+        }
+        None => anyhow!("Failed to parse {}: {}", path.display(), message),
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct IgnoreConfig {
-    pub files: Vec<String>,
-    pub extensions: Vec<String>,
-    pub directories: Vec<String>,
+/// Write `content` to `path` unless the file exists and `force` is unset
+fn write_new_file(path: &Path, content: &str, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!("{} already exists (use --force to overwrite it)", path.display());
+    }
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write {}", path.display()))
 }
 
 impl Default for Config {
@@ -56,6 +304,7 @@ impl Default for Config {
         Self {
             output: OutputConfig::default(),
             theme: ThemeConfig::default(),
+            document: DocumentConfig::default(),
             formatting: FormattingConfig::default(),
             ignore: IgnoreConfig::default(),
         }
@@ -67,6 +316,7 @@ impl Default for OutputConfig {
         Self {
             folder: "output".to_string(),
             filename: None,
+            format: None,
             create_folder: true,
             single_file: true,
         }
@@ -76,10 +326,12 @@ impl Default for OutputConfig {
 impl Default for ThemeConfig {
     fn default() -> Self {
         Self {
+            name: None,
             mode: "light".to_string(),
-            font_size: 10.0,
-            line_height: 1.2,
+            font_size: None,
+            line_height: None,
             custom_colors: None,
+            font_styles: BTreeMap::new(),
         }
     }
 }
@@ -91,24 +343,14 @@ impl Default for FormattingConfig {
             page_numbers: true,
             syntax_highlighting: true,
             remove_comments: false,
-            remove_empty_lines: false,
-        }
-    }
-}
-
-impl Default for IgnoreConfig {
-    fn default() -> Self {
-        Self {
-            files: vec![],
-            extensions: vec![],
-            directories: vec![],
+            keep_doc_comments: false,
+            remove_empty_lines: EmptyLineMode::Keep,
         }
     }
 }
 
 impl Config {
     /// Load configuration from a file, falling back to defaults if not found
-    #[allow(dead_code)]
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let config_path = path.as_ref();
         
@@ -116,7 +358,12 @@ impl Config {
             let content = fs::read_to_string(config_path)
                 .context("Failed to read configuration file")?;
             let config: Config = toml::from_str(&content)
-                .context("Failed to parse configuration file")?;
+                .map_err(|error| parse_error(config_path, &content, &error))?;
+            config.validate()
+                .map_err(|errors| anyhow!("Invalid configuration in {}:\n{}", config_path.display(), ConfigError::list(&errors)))?;
+            if let Some(warning) = config.custom_theme().and_then(|theme| theme.contrast_warning()) {
+                eprintln!("Warning: theme.custom_colors: {}", warning);
+            }
             Ok(config)
         } else {
             Ok(Config::default())
@@ -124,17 +371,16 @@ impl Config {
     }
 
     /// Load configuration from the current directory or user's home directory
-    #[allow(dead_code)]
     pub fn load_default() -> Result<Self> {
         // Try to load from current directory first
-        let local_config = Path::new("scrollcast.toml");
+        let local_config = Path::new(CONFIG_FILE_NAME);
         if local_config.exists() {
             return Self::load_from_file(local_config);
         }
 
         // Try to load from home directory
         if let Some(home_dir) = dirs::home_dir() {
-            let global_config = home_dir.join(".scrollcast.toml");
+            let global_config = home_dir.join(GLOBAL_CONFIG_FILE_NAME);
             if global_config.exists() {
                 return Self::load_from_file(global_config);
             }
@@ -145,7 +391,6 @@ impl Config {
     }
 
     /// Save configuration to a file
-    #[allow(dead_code)]
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let content = toml::to_string_pretty(self)
             .context("Failed to serialize configuration")?;
@@ -154,69 +399,110 @@ impl Config {
         Ok(())
     }
 
-    /// Create a sample configuration file
-    #[allow(dead_code)]
-    pub fn create_sample_config<P: AsRef<Path>>(path: P) -> Result<()> {
-        let sample_config = Config {
-            output: OutputConfig {
-                folder: "output".to_string(),
-                filename: Some("repository.pdf".to_string()),
-                create_folder: true,
-                single_file: true,
-            },
-            theme: ThemeConfig {
-                mode: "light".to_string(),
-                font_size: 10.0,
-                line_height: 1.2,
-                custom_colors: None,
-            },
-            formatting: FormattingConfig {
-                line_numbers: true,
-                page_numbers: true,
-                syntax_highlighting: true,
-                remove_comments: false,
-                remove_empty_lines: false,
-            },
-            ignore: IgnoreConfig {
-                files: vec![
-                    "*.tmp".to_string(),
-                    "*.log".to_string(),
-                    ".env".to_string(),
-                ],
-                extensions: vec![
-                    ".tmp".to_string(),
-                    ".log".to_string(),
-                    ".cache".to_string(),
-                ],
-                directories: vec![
-                    "tmp".to_string(),
-                    "temp".to_string(),
-                    "logs".to_string(),
-                ],
-            },
-        };
+    /// Write the commented default configuration to `path`, refusing to
+    /// replace an existing file unless `force` is set
+    pub fn create_sample_config<P: AsRef<Path>>(path: P, force: bool) -> Result<()> {
+        write_new_file(path.as_ref(), SAMPLE_CONFIG, force)
+    }
 
-        sample_config.save_to_file(path)?;
-        Ok(())
+    /// Write a starter `.scrollcastignore` into `dir`
+    pub fn create_sample_ignore_file<P: AsRef<Path>>(dir: P, force: bool) -> Result<PathBuf> {
+        let path = dir.as_ref().join(IGNORE_FILE_NAME);
+        write_new_file(&path, SAMPLE_IGNORE_FILE, force)?;
+        Ok(path)
+    }
+
+    /// Where `--init` writes the configuration: the current directory, or
+    /// the home directory with `global`
+    pub fn init_path(global: bool) -> Result<PathBuf> {
+        if global {
+            let home_dir = dirs::home_dir().context("Could not determine the home directory")?;
+            Ok(home_dir.join(GLOBAL_CONFIG_FILE_NAME))
+        } else {
+            Ok(PathBuf::from(CONFIG_FILE_NAME))
+        }
     }
 
-    /// Get theme mode as enum
-    #[allow(dead_code)]
+    /// Get theme mode as enum. A built-in theme name stands for that
+    /// theme's mode.
     pub fn get_theme_mode(&self) -> ThemeMode {
         match self.theme.mode.as_str() {
             "dark" => ThemeMode::Dark,
-            _ => ThemeMode::Light,
+            "light" => ThemeMode::Light,
+            name => Theme::by_name(name).map_or(ThemeMode::Light, |theme| theme.mode),
         }
     }
 
+    /// Check values that parse but can't be used, reporting every problem
+    pub fn validate(&self) -> std::result::Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut error = |field: &str, message: String| errors.push(ConfigError { field: field.to_string(), message });
+
+        for format in self.output.format.as_deref().map(split_formats).unwrap_or_default() {
+            if !FORMATS.contains(&format) {
+                error("output.format", format!("Unknown output.format: {} (expected one of {})", format, FORMATS.join(", ")));
+            }
+        }
+        let folder = Path::new(&self.output.folder);
+        if folder.is_absolute() && folder.parent().is_none_or(|parent| parent.parent().is_none()) && !folder.is_dir() {
+            let relative = self.output.folder.trim_start_matches('/');
+            error("output.folder", format!(
+                "output.folder {:?} is directly under / (did you mean {:?}?)",
+                self.output.folder,
+                if relative.is_empty() { "output" } else { relative },
+            ));
+        }
+
+        let themes = builtin_theme_names();
+        if !themes.contains(&self.theme.mode.as_str()) {
+            error("theme.mode", format!("Unknown theme.mode: {:?} (expected one of {})", self.theme.mode, themes.join(", ")));
+        }
+        if let Some(message) = self.theme.font_size.and_then(|size| out_of_range("theme.font_size", size, &FONT_SIZE_RANGE)) {
+            error("theme.font_size", message);
+        }
+        if let Some(message) = self.theme.line_height.and_then(|height| out_of_range("theme.line_height", height, &LINE_HEIGHT_RANGE)) {
+            error("theme.line_height", message);
+        }
+        if let Some(language) = self.document.language.as_deref().filter(|language| !is_language_tag(language)) {
+            error("document.language", format!("document.language {:?} is not a language tag such as \"en\" or \"pt-BR\"", language));
+        }
+        for key in self.theme.font_styles.keys().filter(|key| !TOKEN_STYLE_KEYS.contains(&key.as_str())) {
+            error("theme.font_styles", format!("Unknown token kind in theme.font_styles: {} (expected one of {})", key, TOKEN_STYLE_KEYS.join(", ")));
+        }
+        if let Some(Err(theme_errors)) = self.custom_theme().map(|theme| theme.validate()) {
+            for theme_error in theme_errors {
+                error("theme.custom_colors", format!("theme.custom_colors: {}", theme_error));
+            }
+        }
+
+        for extension in self.ignore.extensions.iter().filter(|extension| !extension.starts_with('.')) {
+            error("ignore.extensions", format!("ignore.extensions entry {:?} should start with a dot ({:?})", extension, format!(".{}", extension)));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The theme made from `theme.custom_colors`, if the config defines them
+    pub fn custom_theme(&self) -> Option<Theme> {
+        self.theme.custom_colors.as_ref().map(|colors| Theme {
+            mode: self.get_theme_mode(),
+            colors: colors.clone(),
+            font_size: self.theme.font_size.unwrap_or(DEFAULT_FONT_SIZE),
+            line_height: self.theme.line_height.unwrap_or(1.2),
+            font_styles: self.theme.font_styles.clone(),
+        })
+    }
+
     /// Get output directory path
-    #[allow(dead_code)]
     pub fn get_output_dir(&self) -> PathBuf {
         PathBuf::from(&self.output.folder)
     }
 
     /// Get output filename with fallback
-    #[allow(dead_code)]
     pub fn get_output_filename(&self, fallback: &str) -> String {
         self.output.filename
             .as_ref()
@@ -225,7 +511,6 @@ impl Config {
     }
 
     /// Ensure output directory exists
-    #[allow(dead_code)]
     pub fn ensure_output_dir(&self) -> Result<PathBuf> {
         let output_dir = self.get_output_dir();
         
@@ -239,6 +524,316 @@ impl Config {
     }
 }
 
+/// Placeholders accepted in output file names, such as `{repo}-{date}.pdf`
+pub const FILENAME_PLACEHOLDERS: [&str; 5] = ["repo", "date", "datetime", "format", "commit"];
+
+/// Values substituted into an output file name template
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilenameContext {
+    /// Name of the input directory
+    pub repo: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    /// YYYY-MM-DD_HH-MM-SS, without colons so it's a valid Windows file name
+    pub datetime: String,
+    /// Format name, e.g. "pdf" or "markdown"
+    pub format: String,
+    /// Short hash of HEAD, empty when the input isn't in a git repository
+    pub commit: String,
+}
+
+impl FilenameContext {
+    /// Describe `input` being rendered as `format` now
+    pub fn new(input: &Path, format: &str) -> Self {
+        let now = chrono::Utc::now();
+        let repo = input.canonicalize()
+            .ok()
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .or_else(|| input.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "repository".to_string());
+        let commit = git2::Repository::discover(input)
+            .ok()
+            .and_then(|repo| {
+                let head = repo.head().ok()?.peel_to_commit().ok()?;
+                let short_id = head.as_object().short_id().ok()?;
+                short_id.as_str().map(str::to_string)
+            })
+            .unwrap_or_default();
+        Self {
+            repo,
+            date: now.format("%Y-%m-%d").to_string(),
+            datetime: now.format("%Y-%m-%d_%H-%M-%S").to_string(),
+            format: format.to_string(),
+            commit,
+        }
+    }
+
+    fn value(&self, placeholder: &str) -> Option<&str> {
+        match placeholder {
+            "repo" => Some(&self.repo),
+            "date" => Some(&self.date),
+            "datetime" => Some(&self.datetime),
+            "format" => Some(&self.format),
+            "commit" => Some(&self.commit),
+            _ => None,
+        }
+    }
+}
+
+/// Replace the `{placeholder}`s in an output file name. `{{` and `}}` stand
+/// for literal braces.
+pub fn expand_filename_template(template: &str, context: &FilenameContext) -> Result<String> {
+    let valid = || FILENAME_PLACEHOLDERS.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", ");
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        if let Some(after) = tail.strip_prefix("{{").or_else(|| tail.strip_prefix("}}")) {
+            expanded.push_str(&tail[..1]);
+            rest = after;
+            continue;
+        }
+        let Some(end) = tail.find('}').filter(|_| tail.starts_with('{')) else {
+            bail!("Unmatched brace in output file name {:?} (use {{{{ or }}}} for a literal brace)", template);
+        };
+        let name = &tail[1..end];
+        let value = context.value(name)
+            .ok_or_else(|| anyhow!("Unknown placeholder {{{}}} in output file name {:?} (expected one of {})", name, template, valid()))?;
+        expanded.push_str(value);
+        rest = &tail[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Options that can come from the config file, `SCROLLCAST_*` environment
+/// variables or the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// One format, or several separated by commas; see `formats`
+    pub format: String,
+    pub theme: String,
+    pub output: Option<PathBuf>,
+    pub respect_gitignore: bool,
+    pub include_toc: bool,
+    pub skip_confirmation: bool,
+    pub verbose: bool,
+    pub chunk_size: usize,
+    pub memory_limit_mb: Option<u64>,
+    pub max_file_size_mb: u64,
+    pub enforce_contrast: bool,
+    pub strip_comments: bool,
+    pub keep_doc_comments: bool,
+    pub empty_lines: EmptyLineMode,
+    pub font_size: Option<f32>,
+    pub line_height: Option<f32>,
+    /// Document title; the input directory name when unset
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub publisher: Option<String>,
+    pub language: String,
+    /// Program `--open` runs on the document instead of the platform's
+    /// default, from `SCROLLCAST_OPENER`
+    pub opener: Option<String>,
+}
+
+/// The options given explicitly on the command line. Flags can only switch
+/// a behavior on, so `false` leaves the decision to the lower layers.
+#[derive(Debug, Clone, Default)]
+pub struct CliSettings {
+    pub format: Option<String>,
+    pub theme: Option<String>,
+    pub output: Option<PathBuf>,
+    pub no_gitignore: bool,
+    pub no_toc: bool,
+    pub yes: bool,
+    pub verbose: bool,
+    pub chunk_size: Option<usize>,
+    pub memory_limit_mb: Option<u64>,
+    pub max_file_size_mb: Option<u64>,
+    pub enforce_contrast: bool,
+    pub strip_comments: bool,
+    pub keep_doc_comments: bool,
+    pub remove_empty_lines: Option<EmptyLineMode>,
+    pub font_size: Option<f32>,
+    pub line_height: Option<f32>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub publisher: Option<String>,
+    pub language: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            format: "pdf".to_string(),
+            theme: "kate".to_string(),
+            output: None,
+            respect_gitignore: true,
+            include_toc: true,
+            skip_confirmation: false,
+            verbose: false,
+            chunk_size: 20,
+            memory_limit_mb: None,
+            max_file_size_mb: 50,
+            enforce_contrast: false,
+            strip_comments: false,
+            keep_doc_comments: false,
+            empty_lines: EmptyLineMode::Keep,
+            font_size: None,
+            line_height: None,
+            title: None,
+            author: None,
+            publisher: None,
+            language: "en".to_string(),
+            opener: None,
+        }
+    }
+}
+
+/// Parse a boolean environment variable such as `SCROLLCAST_YES=1`
+fn parse_env_flag(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => bail!("Invalid value for {}: {:?} (expected true or false)", name, value),
+    }
+}
+
+/// Parse a numeric environment variable such as `SCROLLCAST_CHUNK_SIZE=50`
+fn parse_env_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value.trim().parse()
+        .map_err(|_| anyhow!("Invalid value for {}: {:?} (expected a number)", name, value))
+}
+
+/// `SCROLLCAST_*` variables that scrollcast doesn't read, most likely typos
+pub fn unknown_env_vars(env: &BTreeMap<String, String>) -> Vec<&str> {
+    env.keys()
+        .map(String::as_str)
+        .filter(|name| name.starts_with("SCROLLCAST_") && !ENV_VARS.contains(name))
+        .collect()
+}
+
+impl Settings {
+    /// The formats to write, each once
+    pub fn formats(&self) -> Vec<&str> {
+        split_formats(&self.format)
+    }
+
+    /// Layer the config file, then the environment, then explicit command
+    /// line values over the defaults; later layers win
+    pub fn resolve<I>(config: &Config, env: I, cli: &CliSettings) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let env: BTreeMap<String, String> = env.into_iter()
+            .filter(|(name, _)| name.starts_with("SCROLLCAST_"))
+            .collect();
+        for name in unknown_env_vars(&env) {
+            eprintln!("Warning: ignoring unknown environment variable {} (known: {})", name, ENV_VARS.join(", "));
+        }
+
+        let mut settings = Settings::default();
+
+        // Config file
+        if let Some(format) = &config.output.format {
+            settings.format = format.clone();
+        }
+        if let Some(name) = &config.theme.name {
+            settings.theme = name.clone();
+        }
+        if let Some(filename) = &config.output.filename {
+            settings.output = Some(config.get_output_dir().join(filename));
+        }
+        settings.strip_comments = config.formatting.remove_comments;
+        settings.keep_doc_comments = config.formatting.keep_doc_comments;
+        settings.empty_lines = config.formatting.remove_empty_lines;
+        settings.font_size = config.theme.font_size;
+        settings.line_height = config.theme.line_height;
+        settings.title = config.document.title.clone();
+        settings.author = config.document.author.clone();
+        settings.publisher = config.document.publisher.clone();
+        if let Some(language) = &config.document.language {
+            settings.language = language.clone();
+        }
+
+        // Environment
+        for (name, value) in &env {
+            match name.as_str() {
+                "SCROLLCAST_FORMAT" => settings.format = value.trim().to_string(),
+                "SCROLLCAST_THEME" => settings.theme = value.trim().to_string(),
+                "SCROLLCAST_OUTPUT" => settings.output = Some(PathBuf::from(value)),
+                "SCROLLCAST_NO_GITIGNORE" => settings.respect_gitignore = !parse_env_flag(name, value)?,
+                "SCROLLCAST_NO_TOC" => settings.include_toc = !parse_env_flag(name, value)?,
+                "SCROLLCAST_YES" => settings.skip_confirmation = parse_env_flag(name, value)?,
+                "SCROLLCAST_VERBOSE" => settings.verbose = parse_env_flag(name, value)?,
+                "SCROLLCAST_CHUNK_SIZE" => settings.chunk_size = parse_env_number(name, value)?,
+                "SCROLLCAST_MEMORY_LIMIT" => settings.memory_limit_mb = Some(parse_env_number(name, value)?),
+                "SCROLLCAST_MAX_FILE_SIZE" => settings.max_file_size_mb = parse_env_number(name, value)?,
+                "SCROLLCAST_ENFORCE_CONTRAST" => settings.enforce_contrast = parse_env_flag(name, value)?,
+                "SCROLLCAST_STRIP_COMMENTS" => settings.strip_comments = parse_env_flag(name, value)?,
+                "SCROLLCAST_KEEP_DOC_COMMENTS" => settings.keep_doc_comments = parse_env_flag(name, value)?,
+                "SCROLLCAST_REMOVE_EMPTY_LINES" => settings.empty_lines = value.parse()
+                    .with_context(|| format!("Invalid value for {}", name))?,
+                "SCROLLCAST_FONT_SIZE" => settings.font_size = Some(parse_env_number(name, value)?),
+                "SCROLLCAST_LINE_HEIGHT" => settings.line_height = Some(parse_env_number(name, value)?),
+                "SCROLLCAST_OPENER" => settings.opener = Some(value.trim().to_string()).filter(|opener| !opener.is_empty()),
+                _ => {}
+            }
+        }
+
+        // Command line
+        if let Some(format) = &cli.format {
+            settings.format = format.clone();
+        }
+        if let Some(theme) = &cli.theme {
+            settings.theme = theme.clone();
+        }
+        if let Some(output) = &cli.output {
+            settings.output = Some(output.clone());
+        }
+        settings.respect_gitignore &= !cli.no_gitignore;
+        settings.include_toc &= !cli.no_toc;
+        settings.skip_confirmation |= cli.yes;
+        settings.verbose |= cli.verbose;
+        settings.enforce_contrast |= cli.enforce_contrast;
+        settings.strip_comments |= cli.strip_comments;
+        settings.keep_doc_comments |= cli.keep_doc_comments;
+        settings.empty_lines = cli.remove_empty_lines.unwrap_or(settings.empty_lines);
+        settings.chunk_size = cli.chunk_size.unwrap_or(settings.chunk_size);
+        settings.memory_limit_mb = cli.memory_limit_mb.or(settings.memory_limit_mb);
+        settings.max_file_size_mb = cli.max_file_size_mb.unwrap_or(settings.max_file_size_mb);
+        settings.font_size = cli.font_size.or(settings.font_size);
+        settings.line_height = cli.line_height.or(settings.line_height);
+        settings.title = cli.title.clone().or(settings.title);
+        settings.author = cli.author.clone().or(settings.author);
+        settings.publisher = cli.publisher.clone().or(settings.publisher);
+        if let Some(language) = &cli.language {
+            settings.language = language.clone();
+        }
+
+        if settings.formats().is_empty() {
+            bail!("No output format given (expected one or more of {})", FORMATS.join(", "));
+        }
+        if let Some(format) = settings.formats().into_iter().find(|format| !FORMATS.contains(format)) {
+            bail!("Unknown output format: {} (expected one of {})", format, FORMATS.join(", "));
+        }
+        if !is_language_tag(&settings.language) {
+            bail!("Unknown document language: {:?} (expected a tag such as \"en\" or \"pt-BR\")", settings.language);
+        }
+        if settings.chunk_size == 0 {
+            bail!("The chunk size must be at least 1");
+        }
+        if let Some(message) = settings.font_size.and_then(|size| out_of_range("The font size", size, &FONT_SIZE_RANGE)) {
+            bail!(message);
+        }
+        if let Some(message) = settings.line_height.and_then(|height| out_of_range("The line height", height, &LINE_HEIGHT_RANGE)) {
+            bail!(message);
+        }
+        Ok(settings)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -265,6 +860,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sample_config_round_trips_to_defaults() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        Config::create_sample_config(&config_path, false)?;
+        assert_eq!(Config::load_from_file(&config_path)?, Config::default());
+
+        // An existing file is only replaced with force
+        fs::write(&config_path, "[output]\nfolder = \"docs\"\ncreate_folder = true\nsingle_file = true\n")?;
+        let error = Config::create_sample_config(&config_path, false).unwrap_err();
+        assert!(error.to_string().contains("already exists"), "{}", error);
+        assert_eq!(Config::load_from_file(&config_path)?.output.folder, "docs");
+        Config::create_sample_config(&config_path, true)?;
+        assert_eq!(Config::load_from_file(&config_path)?, Config::default());
+
+        let ignore_path = Config::create_sample_ignore_file(temp_dir.path(), false)?;
+        assert!(fs::read_to_string(ignore_path)?.contains("node_modules/"));
+        Ok(())
+    }
+
     #[test]
     fn test_config_file_operations() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -281,6 +896,104 @@ mod tests {
         Ok(())
     }
 
+    const LURID_CONFIG: &str = r##"
+[theme]
+mode = "light"
+font_size = 10.0
+line_height = 1.2
+
+[theme.custom_colors]
+background = "#fffff0"
+text = "#202020"
+keywords = "#ff00ff"
+strings = "#00aa00"
+comments = "#888888"
+numbers = "#0000ff"
+functions = "#aa5500"
+types = "#008888"
+operators = "#cc0000"
+line_numbers = "#999999"
+header = "#000000"
+"##;
+
+    #[test]
+    #[cfg(all(feature = "html", feature = "pdf"))]
+    fn test_custom_colors_reach_html_and_pdf() -> Result<()> {
+        use crate::renderer::{html::HtmlRenderer, pdf::{PdfOptions, PdfRenderer}, DocumentMetadata, DocumentRenderer};
+
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("scrollcast.toml");
+        fs::write(&config_path, LURID_CONFIG)?;
+        let config = Config::load_from_file(&config_path)?;
+
+        let markdown = "```rust
+fn main() {
+    let x = 1;
+}
+```
+";
+        let metadata = DocumentMetadata { palette: config.theme.custom_colors.clone(), ..Default::default() };
+
+        let html = String::from_utf8(HtmlRenderer::new().render_markdown(markdown, &metadata)?)?;
+        assert!(html.contains("--color-keywords: #ff00ff;"), "{}", html);
+        assert!(html.contains(r#"<span style="color:#ff00ff;">fn</span>"#), "{}", html);
+        assert!(html.contains(r#"<pre style="background-color:#fffff0;">"#), "{}", html);
+
+        let options = PdfOptions { theme: config.custom_theme(), compress: false, ..Default::default() };
+        let pdf = PdfRenderer::with_options(options).render_markdown(markdown, &metadata)?;
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.contains("1.000 0.000 1.000 rg\n(fn) Tj"), "{}", pdf);
+        assert!(pdf.contains("1.000 1.000 0.941 rg\n0 0 612 792 re"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "html", feature = "pdf"))]
+    fn test_font_styles_reach_html_and_pdf() -> Result<()> {
+        use crate::renderer::{html::HtmlRenderer, pdf::{PdfOptions, PdfRenderer}, DocumentMetadata, DocumentRenderer};
+
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("scrollcast.toml");
+        let styled = format!("{}\n[theme.font_styles]\ncomments = {{ italic = true }}\nkeywords = {{ bold = true }}\n", LURID_CONFIG);
+        fs::write(&config_path, styled)?;
+        let config = Config::load_from_file(&config_path)?;
+
+        let markdown = "```rust\n// note\nfn main() {}\n```\n";
+        let metadata = DocumentMetadata {
+            palette: config.theme.custom_colors.clone(),
+            font_styles: config.theme.font_styles.clone(),
+            ..Default::default()
+        };
+        let html = String::from_utf8(HtmlRenderer::new().render_markdown(markdown, &metadata)?)?;
+        assert!(html.contains(r#"<span style="color:#888888;font-style:italic;">// note"#), "{}", html);
+        assert!(html.contains(r#"<span style="color:#ff00ff;font-weight:bold;">fn</span>"#), "{}", html);
+
+        let options = PdfOptions { theme: config.custom_theme(), compress: false, ..Default::default() };
+        let pdf = PdfRenderer::with_options(options).render_markdown(markdown, &metadata)?;
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.contains("BT\n/F4 9 Tf\n"), "{}", pdf);
+        assert!(pdf.contains("1.000 0.000 1.000 rg\n(fn) Tj\n/F3 9 Tf\n"), "{}", pdf);
+
+        fs::write(&config_path, "[theme]\nmode = \"light\"\nfont_size = 10.0\nline_height = 1.2\n\n[theme.font_styles]\nkeyword = { bold = true }\n")?;
+        let message = format!("{:#}", Config::load_from_file(&config_path).unwrap_err());
+        assert!(message.contains("Unknown token kind in theme.font_styles: keyword"), "{}", message);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_custom_color_is_rejected() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("scrollcast.toml");
+        fs::write(&config_path, LURID_CONFIG.replace("#ff00ff", "#ff00f"))?;
+
+        let error = Config::load_from_file(&config_path).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("Invalid color for keywords: \"#ff00f\""), "{}", message);
+        assert!(message.contains("theme.custom_colors"), "{}", message);
+        Ok(())
+    }
+
     #[test]
     fn test_output_directory_creation() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -301,4 +1014,231 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_settings_precedence_config_env_cli() -> Result<()> {
+        let mut config = Config::default();
+        config.output.format = Some("html".to_string());
+        config.output.filename = Some("book.html".to_string());
+        config.theme.name = Some("nord".to_string());
+
+        let settings = Settings::resolve(&config, env(&[]), &CliSettings::default())?;
+        assert_eq!(settings.format, "html");
+        assert_eq!(settings.theme, "nord");
+        assert_eq!(settings.output, Some(PathBuf::from("output/book.html")));
+
+        let vars = env(&[
+            ("SCROLLCAST_FORMAT", "epub"),
+            ("SCROLLCAST_THEME", "dracula"),
+            ("SCROLLCAST_OUTPUT", "ci/out.epub"),
+            ("SCROLLCAST_NO_GITIGNORE", "true"),
+            ("SCROLLCAST_OPENER", "firefox"),
+            ("HOME", "/root"),
+        ]);
+        let settings = Settings::resolve(&config, vars.clone(), &CliSettings::default())?;
+        assert_eq!(settings.opener.as_deref(), Some("firefox"));
+        assert_eq!(settings.format, "epub");
+        assert_eq!(settings.theme, "dracula");
+        assert_eq!(settings.output, Some(PathBuf::from("ci/out.epub")));
+        assert!(!settings.respect_gitignore);
+
+        let cli = CliSettings {
+            format: Some("pdf".to_string()),
+            theme: Some("kate".to_string()),
+            output: Some(PathBuf::from("local.pdf")),
+            ..Default::default()
+        };
+        let settings = Settings::resolve(&config, vars, &cli)?;
+        assert_eq!(settings.format, "pdf");
+        assert_eq!(settings.theme, "kate");
+        assert_eq!(settings.output, Some(PathBuf::from("local.pdf")));
+        assert!(!settings.respect_gitignore);
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_read_numeric_environment() -> Result<()> {
+        let vars = env(&[("SCROLLCAST_CHUNK_SIZE", "7"), ("SCROLLCAST_MAX_FILE_SIZE", "3")]);
+        let from_env = Settings::resolve(&Config::default(), vars.clone(), &CliSettings::default())?;
+        assert_eq!((from_env.chunk_size, from_env.max_file_size_mb), (7, 3));
+        let from_cli = Settings::resolve(&Config::default(), vars, &CliSettings {
+            chunk_size: Some(100),
+            ..Default::default()
+        })?;
+        assert_eq!((from_cli.chunk_size, from_cli.max_file_size_mb), (100, 3));
+        assert_eq!(Settings::resolve(&Config::default(), env(&[]), &CliSettings::default())?, Settings::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_document_title_author_and_language() -> Result<()> {
+        let config: Config = toml::from_str("[document]\ntitle = \"Payments\"\nauthor = \"Zoë Ðurić\"\npublisher = \"Acme\"\nlanguage = \"de\"\n")?;
+        let settings = Settings::resolve(&config, env(&[]), &CliSettings::default())?;
+        assert_eq!(settings.title.as_deref(), Some("Payments"));
+        assert_eq!(settings.author.as_deref(), Some("Zoë Ðurić"));
+        assert_eq!(settings.publisher.as_deref(), Some("Acme"));
+        assert_eq!(settings.language, "de");
+
+        let cli = CliSettings {
+            title: Some("Payments Review".to_string()),
+            publisher: Some("Acme Press".to_string()),
+            language: Some("pt-BR".to_string()),
+            ..Default::default()
+        };
+        let settings = Settings::resolve(&config, env(&[]), &cli)?;
+        assert_eq!(settings.title.as_deref(), Some("Payments Review"));
+        assert_eq!(settings.author.as_deref(), Some("Zoë Ðurić"));
+        assert_eq!(settings.publisher.as_deref(), Some("Acme Press"));
+        assert_eq!(settings.language, "pt-BR");
+
+        let cli = CliSettings { language: Some("english".to_string()), ..Default::default() };
+        let error = Settings::resolve(&Config::default(), env(&[]), &cli).unwrap_err();
+        assert!(error.to_string().contains("\"english\""), "{}", error);
+        let config: Config = toml::from_str("[document]\nlanguage = \"en_US\"\n")?;
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors[0].field, "document.language");
+        assert!(is_language_tag("zh-Hant-TW") && !is_language_tag("e") && !is_language_tag("en-"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_font_size_and_line_height() -> Result<()> {
+        let mut config = Config::default();
+        config.theme.font_size = Some(12.0);
+        config.theme.line_height = Some(1.4);
+        let settings = Settings::resolve(&config, env(&[]), &CliSettings::default())?;
+        assert_eq!((settings.font_size, settings.line_height), (Some(12.0), Some(1.4)));
+
+        let vars = env(&[("SCROLLCAST_FONT_SIZE", "11")]);
+        let cli = CliSettings { line_height: Some(1.8), ..Default::default() };
+        let settings = Settings::resolve(&config, vars, &cli)?;
+        assert_eq!((settings.font_size, settings.line_height), (Some(11.0), Some(1.8)));
+
+        let cli = CliSettings { font_size: Some(100.0), ..Default::default() };
+        let error = Settings::resolve(&config, env(&[]), &cli).unwrap_err();
+        assert_eq!(error.to_string(), "The font size 100 is out of range (expected 4 to 72)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_reject_bad_env_values() {
+        let error = Settings::resolve(&Config::default(), env(&[("SCROLLCAST_CHUNK_SIZE", "lots")]), &CliSettings::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("SCROLLCAST_CHUNK_SIZE"), "{}", error);
+        let error = Settings::resolve(&Config::default(), env(&[("SCROLLCAST_FORMAT", "docx")]), &CliSettings::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("docx"), "{}", error);
+        let error = Settings::resolve(&Config::default(), env(&[("SCROLLCAST_FORMAT", "pdf,docx")]), &CliSettings::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("docx"), "{}", error);
+        let settings = Settings::resolve(&Config::default(), env(&[("SCROLLCAST_FORMAT", "html, pdf,html")]), &CliSettings::default())
+            .unwrap();
+        assert_eq!(settings.formats(), vec!["html", "pdf"]);
+
+        let vars: BTreeMap<String, String> = env(&[("SCROLLCAST_FROMAT", "pdf"), ("SCROLLCAST_THEME", "nord")])
+            .into_iter()
+            .collect();
+        assert_eq!(unknown_env_vars(&vars), vec!["SCROLLCAST_FROMAT"]);
+    }
+
+    fn filename_context() -> FilenameContext {
+        FilenameContext {
+            repo: "scrollcast".to_string(),
+            date: "2024-05-01".to_string(),
+            datetime: "2024-05-01_09-30-00".to_string(),
+            format: "pdf".to_string(),
+            commit: "1a2b3c4".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_expand_filename_template() -> Result<()> {
+        let context = filename_context();
+        assert_eq!(expand_filename_template("{repo}-{date}-{format}.pdf", &context)?, "scrollcast-2024-05-01-pdf.pdf");
+        assert_eq!(expand_filename_template("out/{repo}@{commit}_{datetime}", &context)?, "out/scrollcast@1a2b3c4_2024-05-01_09-30-00");
+        assert_eq!(expand_filename_template("plain.html", &context)?, "plain.html");
+        assert_eq!(expand_filename_template("{{literal}}-{repo}", &context)?, "{literal}-scrollcast");
+
+        // Each requested format gets its own expansion
+        let names: Vec<String> = ["html", "epub"].iter()
+            .map(|format| expand_filename_template("{repo}.{format}", &FilenameContext { format: format.to_string(), ..context.clone() }))
+            .collect::<Result<_>>()?;
+        assert_eq!(names, vec!["scrollcast.html", "scrollcast.epub"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filename_template_errors() {
+        let error = expand_filename_template("{repo}-{branch}.pdf", &filename_context()).unwrap_err().to_string();
+        assert!(error.contains("{branch}"), "{}", error);
+        assert!(error.contains("{repo}, {date}, {datetime}, {format}, {commit}"), "{}", error);
+
+        let error = expand_filename_template("{repo.pdf", &filename_context()).unwrap_err().to_string();
+        assert!(error.contains("Unmatched brace"), "{}", error);
+    }
+
+    #[test]
+    fn test_filename_context_reads_commit() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        assert_eq!(FilenameContext::new(temp_dir.path(), "pdf").commit, "");
+
+        let repo = git2::Repository::init(temp_dir.path())?;
+        fs::write(temp_dir.path().join("main.rs"), "fn main() {}\n")?;
+        let mut index = repo.index()?;
+        index.add_path(Path::new("main.rs"))?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let signature = git2::Signature::now("Test", "test@example.com")?;
+        let commit = repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])?;
+
+        let context = FilenameContext::new(temp_dir.path(), "html");
+        assert!(commit.to_string().starts_with(&context.commit) && context.commit.len() >= 7, "{}", context.commit);
+        assert_eq!(&context.repo, temp_dir.path().canonicalize()?.file_name().unwrap().to_str().unwrap());
+        assert_eq!(context.format, "html");
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&config_path, "[output]\nfolder = \"/scrollcast-out\"\ncreate_folder = true\nsingle_file = true\nformat = \"docx\"\n\n[theme]\nmode = \"drak\"\nfont_size = 200.0\nline_height = 0.1\n\n[ignore]\nextensions = [\".tmp\", \"log\"]\n")?;
+
+        let message = format!("{:#}", Config::load_from_file(&config_path).unwrap_err());
+        assert!(message.contains(&config_path.display().to_string()), "{}", message);
+        for expected in [
+            "Unknown output.format: docx",
+            "output.folder \"/scrollcast-out\" is directly under / (did you mean \"scrollcast-out\"?)",
+            "Unknown theme.mode: \"drak\"",
+            "theme.font_size 200 is out of range (expected 4 to 72)",
+            "theme.line_height 0.1 is out of range (expected 0.8 to 3)",
+            "ignore.extensions entry \"log\" should start with a dot (\".log\")",
+        ] {
+            assert!(message.contains(expected), "missing {:?} in {}", expected, message);
+        }
+
+        let mut config = Config::default();
+        config.theme.mode = "dracula".to_string();
+        assert_eq!(config.validate(), Ok(()));
+        assert!(matches!(config.get_theme_mode(), ThemeMode::Dark));
+        config.ignore.extensions.push("cache".to_string());
+        let fields: Vec<String> = config.validate().unwrap_err().into_iter().map(|error| error.field).collect();
+        assert_eq!(fields, vec!["ignore.extensions"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors_name_file_and_position() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&config_path, "[theme]\nmode = \"light\"\nfont_size = \"ten\"\nline_height = 1.2\n")?;
+
+        let message = format!("{:#}", Config::load_from_file(&config_path).unwrap_err());
+        assert!(message.starts_with(&format!("Failed to parse {}:3:13:", config_path.display())), "{}", message);
+        assert!(message.contains("invalid type"), "{}", message);
+        Ok(())
+    }
+}
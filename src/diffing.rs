@@ -0,0 +1,262 @@
+//! What changed between two documents of the same repository: the model a
+//! run saved with `--emit-ast` and one built from the files as they are
+//! now. Files are matched by path alone, so a renamed file shows as one
+//! removed and one added, and a file counts as unchanged only when both
+//! its content hash and its content are the same.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::document_model::{DocumentModel, FileEntry};
+use crate::i18n::Strings;
+use crate::markdown_generator::{escape_markdown, table_cell};
+
+/// How a file differs from the earlier document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+
+    /// The badge its heading gets in an annotated document, if it has one
+    /// there: removed files aren't in it
+    pub fn badge(&self, strings: &Strings) -> Option<&'static str> {
+        match self {
+            ChangeKind::Added => Some(strings.badge_new),
+            ChangeKind::Modified => Some(strings.badge_modified),
+            ChangeKind::Removed => None,
+        }
+    }
+}
+
+/// One file that isn't the same in both documents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub change: ChangeKind,
+    /// Lines gained, all of them for an added file
+    pub lines_added: usize,
+    /// Lines lost, all of them for a removed file
+    pub lines_removed: usize,
+}
+
+/// The files that changed between two documents
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ModelDiff {
+    /// By path
+    pub files: Vec<FileDiff>,
+    /// Files the same in both
+    pub unchanged: usize,
+}
+
+impl ModelDiff {
+    pub fn count(&self, change: ChangeKind) -> usize {
+        self.files.iter().filter(|file| file.change == change).count()
+    }
+
+    /// How each file still in the later document changed, by path
+    pub fn changes(&self) -> BTreeMap<String, ChangeKind> {
+        self.files.iter()
+            .filter(|file| file.change != ChangeKind::Removed)
+            .map(|file| (file.path.clone(), file.change))
+            .collect()
+    }
+
+    /// A markdown report titled `title`: how many files were added,
+    /// removed, modified and left alone, then a table of the changed ones
+    /// with how many lines each gained and lost
+    pub fn to_markdown(&self, title: &str, old: &DocumentModel) -> String {
+        let mut markdown = format!("# {}\n\n", title);
+        markdown.push_str(&format!(
+            "Compared with the document generated on {}.\n\n",
+            old.metadata.generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+        markdown.push_str(&format!(
+            "**Added:** {} · **Removed:** {} · **Modified:** {} · **Unchanged:** {}\n\n",
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.count(ChangeKind::Modified),
+            self.unchanged,
+        ));
+        if self.files.is_empty() {
+            markdown.push_str("No files changed.\n");
+            return markdown;
+        }
+        markdown.push_str("| Change | File | Lines added | Lines removed |\n|---|---|---|---|\n");
+        for file in &self.files {
+            markdown.push_str(&format!(
+                "| {} | {} | +{} | -{} |\n",
+                file.change.name(),
+                escape_markdown(&table_cell(&file.path)),
+                file.lines_added,
+                file.lines_removed,
+            ));
+        }
+        markdown
+    }
+}
+
+/// The files of `new` that were added or modified since `old`, and those of
+/// `old` that are gone
+pub fn compare(old: &DocumentModel, new: &DocumentModel) -> Result<ModelDiff> {
+    let mut paths: BTreeMap<&str, (Option<&FileEntry>, Option<&FileEntry>)> = BTreeMap::new();
+    for file in old.files() {
+        paths.entry(&file.path).or_default().0 = Some(file);
+    }
+    for file in new.files() {
+        paths.entry(&file.path).or_default().1 = Some(file);
+    }
+
+    let mut diff = ModelDiff::default();
+    for (path, files) in paths {
+        let (change, lines_added, lines_removed) = match files {
+            (Some(old), Some(new)) if same_content(old, new) => {
+                diff.unchanged += 1;
+                continue;
+            }
+            (Some(old), Some(new)) => {
+                let (added, removed) = line_delta(&old.content, &new.content)?;
+                (ChangeKind::Modified, added, removed)
+            }
+            (None, Some(new)) => (ChangeKind::Added, new.content.lines().count(), 0),
+            (Some(old), None) => (ChangeKind::Removed, 0, old.content.lines().count()),
+            (None, None) => unreachable!("every path comes from one of the models"),
+        };
+        diff.files.push(FileDiff { path: path.to_string(), change, lines_added, lines_removed });
+    }
+    Ok(diff)
+}
+
+/// Whether `old` and `new` hold the same thing. Matching hashes alone
+/// aren't trusted, so a collision can't hide a change.
+fn same_content(old: &FileEntry, new: &FileEntry) -> bool {
+    old.content_hash == new.content_hash && old.content == new.content
+}
+
+/// Lines added and removed going from `old` to `new`
+fn line_delta(old: &str, new: &str) -> Result<(usize, usize)> {
+    let patch = git2::Patch::from_buffers(old.as_bytes(), None, new.as_bytes(), None, None)?;
+    let (_, added, removed) = patch.line_stats()?;
+    Ok((added, removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::content_hash;
+    use crate::i18n::ENGLISH;
+    use crate::markdown_generator::{FileInfo, MarkdownGenerator};
+
+    fn model(files: &[(&str, &str)]) -> DocumentModel {
+        let files: Vec<FileInfo> = files.iter()
+            .map(|(path, content)| FileInfo {
+                path: path.to_string(),
+                content: (*content).into(),
+                language: MarkdownGenerator::detect_language(path),
+                size: content.len(),
+                changes: None,
+                binary_kind: None,
+                modified: None,
+                commit: None,
+            })
+            .collect();
+        MarkdownGenerator::new(true, true).build_document_model(&files, "repo")
+    }
+
+    fn diff(path: &str, change: ChangeKind, lines_added: usize, lines_removed: usize) -> FileDiff {
+        FileDiff { path: path.to_string(), change, lines_added, lines_removed }
+    }
+
+    #[test]
+    fn test_same_files_are_unchanged() -> Result<()> {
+        let old = model(&[("src/main.rs", "fn main() {}\n"), ("README.md", "# Repo\n")]);
+        let mut new = model(&[("README.md", "# Repo\n"), ("src/main.rs", "fn main() {}\n")]);
+        // Facts around the content don't count
+        if let Some(crate::document_model::Section::Files { files, .. }) = new.sections.last_mut() {
+            files[0].commit = Some("0123abc".to_string());
+        }
+
+        let changes = compare(&old, &new)?;
+        assert_eq!(changes, ModelDiff { files: Vec::new(), unchanged: 2 });
+        assert!(changes.to_markdown("Changes", &old).contains("No files changed."));
+        Ok(())
+    }
+
+    #[test]
+    fn test_added_removed_and_modified_with_line_deltas() -> Result<()> {
+        let old = model(&[("a.rs", "one\ntwo\nthree\n"), ("gone.py", "x = 1\ny = 2\n"), ("same.txt", "same\n")]);
+        let new = model(&[("a.rs", "one\nTWO\nthree\nfour\n"), ("new.js", "let a;\n"), ("same.txt", "same\n")]);
+
+        let changes = compare(&old, &new)?;
+        assert_eq!(changes.files, [
+            diff("a.rs", ChangeKind::Modified, 2, 1),
+            diff("gone.py", ChangeKind::Removed, 0, 2),
+            diff("new.js", ChangeKind::Added, 1, 0),
+        ]);
+        assert_eq!(changes.unchanged, 1);
+        assert_eq!(
+            changes.changes().into_iter().collect::<Vec<_>>(),
+            [("a.rs".to_string(), ChangeKind::Modified), ("new.js".to_string(), ChangeKind::Added)]
+        );
+        assert_eq!(ChangeKind::Added.badge(&ENGLISH), Some("new"));
+        assert_eq!(ChangeKind::Removed.badge(&ENGLISH), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_is_a_removal_and_an_addition() -> Result<()> {
+        let content = "pub fn helper() {}\n";
+        let old = model(&[("src/util.rs", content)]);
+        let new = model(&[("src/helpers.rs", content), ("src/copy.rs", content)]);
+
+        // Identical contents under other paths aren't matched up by hash
+        let changes = compare(&old, &new)?;
+        assert_eq!(changes.files, [
+            diff("src/copy.rs", ChangeKind::Added, 1, 0),
+            diff("src/helpers.rs", ChangeKind::Added, 1, 0),
+            diff("src/util.rs", ChangeKind::Removed, 0, 1),
+        ]);
+        assert_eq!(changes.unchanged, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_hash_with_other_content_is_modified() -> Result<()> {
+        let old = model(&[("lib.rs", "fn a() {}\n")]);
+        let mut new = model(&[("lib.rs", "fn b() {}\n")]);
+        if let Some(crate::document_model::Section::Files { files, .. }) = new.sections.last_mut() {
+            files[0].content_hash = content_hash("fn a() {}\n");
+        }
+
+        let changes = compare(&old, &new)?;
+        assert_eq!(changes.files, [diff("lib.rs", ChangeKind::Modified, 1, 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_report_lists_changed_files() -> Result<()> {
+        let old = model(&[("a_b.rs", "1\n"), ("old.rs", "1\n2\n")]);
+        let new = model(&[("a_b.rs", "1\n2\n")]);
+
+        let report = compare(&old, &new)?.to_markdown("Changes in repo", &old);
+        assert!(report.starts_with("# Changes in repo\n\nCompared with the document generated on "), "{}", report);
+        assert!(report.contains("**Added:** 0 · **Removed:** 1 · **Modified:** 1 · **Unchanged:** 0\n\n"), "{}", report);
+        assert!(report.ends_with(
+            "| Change | File | Lines added | Lines removed |\n|---|---|---|---|\n\
+             | modified | a\\_b.rs | +1 | -0 |\n| removed | old.rs | +0 | -2 |\n"
+        ), "{}", report);
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! The document as data: what `MarkdownGenerator::build_document_model`
+//! puts together before any markdown is written, and what `--emit-ast`
+//! saves as JSON for tools that would otherwise parse the markdown back.
+//! The markdown is rendered from this model, so the two always agree.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::anchors::{self, AnchorRegistry};
+use crate::changes::LineChanges;
+use crate::todos::TodoItem;
+
+/// Bumped whenever a field is renamed or removed, or its meaning changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentModel {
+    pub schema_version: u32,
+    pub metadata: ModelMetadata,
+    /// The anchor of each file's section, by path
+    pub anchors: AnchorRegistry,
+    /// In the order they appear, the file contents last
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    pub title: String,
+    /// Language of the headings and labels, such as `de`
+    pub language: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// A top-level part of the document under its own heading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Section {
+    TableOfContents {
+        heading: String,
+        entries: Vec<TocEntry>,
+        /// In place of `entries` when there were too many files to list
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        groups: Vec<TocGroup>,
+    },
+    FileTree { heading: String, paths: Vec<String> },
+    /// With `--todo-index`
+    ReviewChecklist {
+        heading: String,
+        /// Every marker looked for, found or not
+        counts: BTreeMap<String, usize>,
+        items: Vec<ChecklistItem>,
+    },
+    Files { heading: String, files: Vec<FileEntry> },
+    /// Every file again after the contents, with `--toc-appendix`
+    FileIndex { heading: String, entries: Vec<TocEntry> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub path: String,
+    /// Of the file's section
+    pub anchor: String,
+}
+
+/// A directory listed in a collapsed table of contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TocGroup {
+    /// Such as `src/api/`, or `./` for the files at the top
+    pub directory: String,
+    /// How many files are in it, at any depth
+    pub files: usize,
+    /// Of the section of its first file, where the directory starts
+    pub anchor: String,
+}
+
+/// A review marker, linking to the section of the file it's in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    #[serde(flatten)]
+    pub todo: TodoItem,
+    pub anchor: String,
+}
+
+/// One file's section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub anchor: String,
+    pub language: Option<String>,
+    /// Bytes on disk
+    pub size: usize,
+    /// SHA-1 of `content`, in hex
+    pub content_hash: String,
+    pub binary_kind: Option<String>,
+    pub modified: Option<DateTime<Utc>>,
+    /// Short id of the last commit touching the file
+    pub commit: Option<String>,
+    /// Lines changed since a base revision
+    pub changes: Option<LineChanges>,
+    /// Shared with the `FileInfo` it was built from
+    pub content: Arc<str>,
+}
+
+impl Section {
+    /// The anchor of the section's heading
+    pub fn anchor(&self) -> &'static str {
+        match self {
+            Section::TableOfContents { .. } => anchors::TABLE_OF_CONTENTS,
+            Section::FileTree { .. } => anchors::FILE_STRUCTURE,
+            Section::ReviewChecklist { .. } => anchors::REVIEW_CHECKLIST,
+            Section::Files { .. } => anchors::FILE_CONTENTS,
+            Section::FileIndex { .. } => anchors::FILE_INDEX,
+        }
+    }
+}
+
+impl DocumentModel {
+    /// The file sections, in order
+    pub fn files(&self) -> &[FileEntry] {
+        self.sections.iter()
+            .find_map(|section| match section {
+                Section::Files { files, .. } => Some(files.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// A model saved by `to_json`, refusing schema versions this build
+    /// doesn't know
+    pub fn from_json(json: &str) -> Result<Self> {
+        let model: DocumentModel = serde_json::from_str(json)?;
+        if model.schema_version != SCHEMA_VERSION {
+            bail!("Unsupported document model schema version {} (expected {})", model.schema_version, SCHEMA_VERSION);
+        }
+        Ok(model)
+    }
+}
+
+/// The hex SHA-1 of `content`, as `FileEntry::content_hash` holds it
+pub fn content_hash(content: &str) -> String {
+    Sha1::digest(content.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
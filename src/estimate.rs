@@ -0,0 +1,200 @@
+use serde::Serialize;
+
+use crate::markdown_generator::{FileInfo, MarkdownGenerator, MAX_LINE_LENGTH};
+#[cfg(feature = "pdf")]
+use crate::renderer::pdf::CodeCapacity;
+
+/// Files over this size count as large when picking a chunk size
+pub const LARGE_FILE_BYTES: usize = 50_000;
+/// Files over this size may need a lot of memory to render
+pub const HUGE_FILE_BYTES: usize = 10_000_000;
+
+/// "Generated on: 2025-01-01 00:00:00 UTC\n\n"
+const GENERATED_LINE_BYTES: usize = 39;
+/// Section headings, the fences around the file tree and the blank lines
+/// between them
+const SECTION_BYTES: usize = "## Table of Contents\n\n\n## File Structure\n\n```\n```\n\n## File Contents\n\n".len();
+/// Page break, header table, code fence and rule around each file, besides
+/// its path, language and size
+const FILE_BYTES: usize = "\n\\newpage\n\n###  {#}\n\n<!-- file-info -->\n| **Size:** |  |\n|---|---|\n\n```\n\n```\n\n---\n\n".len();
+/// Lines taken by a file's heading and size
+#[cfg(feature = "pdf")]
+const FILE_HEADER_LINES: usize = 4;
+
+/// Estimated page count of the PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PageRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// How big a document the files make, worked out from the files alone so
+/// it's known before any markdown is generated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SizeEstimate {
+    pub files: usize,
+    /// Bytes of file content
+    pub content_bytes: usize,
+    /// Projected size of the generated markdown
+    pub markdown_bytes: usize,
+    /// Lines of file content
+    pub lines: usize,
+    /// Files over 50 KB
+    pub large_files: usize,
+    /// Files over 10 MB
+    pub huge_files: usize,
+    pub largest_file: usize,
+    /// Code lines over 100 characters, which get broken up
+    pub long_lines: usize,
+    /// Set by `with_pdf_pages`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf_pages: Option<PageRange>,
+}
+
+impl SizeEstimate {
+    /// Size up the document `files` make under `title`
+    pub fn new(files: &[FileInfo], title: &str, include_toc: bool) -> Self {
+        let mut estimate = Self {
+            files: files.len(),
+            markdown_bytes: "# \n\n".len() + title.len() + GENERATED_LINE_BYTES + SECTION_BYTES,
+            ..Default::default()
+        };
+        for file in files {
+            let path = file.path.len();
+            let language = file.language.as_deref().map_or(0, str::len);
+            let size = MarkdownGenerator::format_file_size(file.size).len();
+            let lines = file.content.lines().count();
+            let long_lines = file.content.lines().filter(|line| line.len() > MAX_LINE_LENGTH).count();
+            // Heading with its anchor, and the path in the file tree
+            estimate.markdown_bytes += FILE_BYTES + 3 * path + 1 + language + size + file.content.len() + long_lines;
+            if include_toc {
+                // "- [path](#anchor)\n"
+                estimate.markdown_bytes += 2 * path + 7;
+            }
+            estimate.content_bytes += file.size;
+            estimate.lines += lines;
+            estimate.long_lines += long_lines;
+            estimate.large_files += usize::from(file.size > LARGE_FILE_BYTES);
+            estimate.huge_files += usize::from(file.size > HUGE_FILE_BYTES);
+            estimate.largest_file = estimate.largest_file.max(file.size);
+        }
+        estimate
+    }
+
+    /// Add the PDF page count for pages holding `capacity`. Each file
+    /// starts a page. The low end assumes no line wraps and the first file
+    /// shares a page with the title, table of contents and file tree; the
+    /// high end wraps every long line, gives those their own page and
+    /// leaves a tenth more for headings and prose.
+    #[cfg(feature = "pdf")]
+    pub fn with_pdf_pages(mut self, files: &[FileInfo], capacity: CodeCapacity) -> Self {
+        let pages = |lines: usize| lines.div_ceil(capacity.lines_per_page);
+        let front = 2 + files.len() * 2;
+        let mut min = 0;
+        let mut max = pages(front);
+        for (index, file) in files.iter().enumerate() {
+            let wrapped: usize = file.content.lines()
+                .map(|line| line.chars().count().div_ceil(capacity.chars_per_line).max(1))
+                .sum();
+            let shared = if index == 0 { front } else { 0 };
+            min += pages(shared + FILE_HEADER_LINES + file.content.lines().count());
+            max += pages((FILE_HEADER_LINES + wrapped) * 11 / 10);
+        }
+        min = min.max(1);
+        self.pdf_pages = Some(PageRange { min, max: max.max(min) });
+        self
+    }
+
+    pub fn average_file_size(&self) -> usize {
+        self.content_bytes.checked_div(self.files).unwrap_or(0)
+    }
+
+    /// Files to generate markdown for at a time: one by one for large
+    /// repositories or files, a few for medium ones, `default` otherwise
+    pub fn chunk_size(&self, default: usize) -> usize {
+        if self.files > 10 || self.content_bytes > 1_000_000 || self.large_files > 5 {
+            1
+        } else if self.files > 5 || self.average_file_size() > 10_000 {
+            3
+        } else {
+            default
+        }
+    }
+
+    /// Warnings about files that may need more memory than is available
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.huge_files > 0 {
+            warnings.push(format!("Found {} files larger than 10MB. Largest file: {}",
+                self.huge_files, MarkdownGenerator::format_file_size(self.largest_file)));
+            if self.largest_file > 50_000_000 {
+                warnings.push("Files over 50MB may cause memory issues. Consider using --ignore to exclude them.".to_string());
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_processor::FileProcessor;
+
+    fn file(path: &str, content: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            content: content.into(),
+            language: MarkdownGenerator::detect_language(path),
+            size: content.len(),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn test_markdown_estimate_is_close_to_the_generated_size() -> anyhow::Result<()> {
+        let files = FileProcessor::new().process_directory(std::path::Path::new("testfiles/test_project"))?;
+        for include_toc in [true, false] {
+            let markdown = MarkdownGenerator::new(include_toc, true).generate_markdown(&files, "test_project")?;
+            let estimate = SizeEstimate::new(&files, "test_project", include_toc);
+            let error = estimate.markdown_bytes.abs_diff(markdown.len()) as f64 / markdown.len() as f64;
+            assert!(error < 0.1, "estimated {} bytes, generated {}", estimate.markdown_bytes, markdown.len());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_counts_and_chunking() {
+        let files = vec![
+            file("src/main.rs", &"fn main() {}\n".repeat(140)),
+            file("src/long.rs", &format!("{}\n", "x".repeat(250)).repeat(10)),
+        ];
+        let estimate = SizeEstimate::new(&files, "repo", true);
+        assert_eq!(estimate.lines, 150);
+        assert_eq!(estimate.long_lines, 10);
+        assert_eq!(estimate.pdf_pages, None);
+
+        assert_eq!(estimate.chunk_size(20), 20);
+        let many: Vec<FileInfo> = (0..11).map(|i| file(&format!("f{}.rs", i), "x")).collect();
+        assert_eq!(SizeEstimate::new(&many, "repo", true).chunk_size(20), 1);
+        assert_eq!(SizeEstimate::new(&many[..6], "repo", true).chunk_size(20), 3);
+        assert!(estimate.warnings().is_empty());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_pdf_page_range() {
+        let files = vec![
+            file("src/main.rs", &"fn main() {}\n".repeat(140)),
+            file("src/long.rs", &format!("{}\n", "x".repeat(250)).repeat(10)),
+        ];
+        let capacity = CodeCapacity { lines_per_page: 50, chars_per_line: 100 };
+        let estimate = SizeEstimate::new(&files, "repo", true).with_pdf_pages(&files, capacity);
+        // 3 pages of main.rs, the first shared with the front matter, and 1
+        // of long.rs, whose lines wrap to 30; at most a front page more and
+        // the room left for headings pushes main.rs onto a fourth
+        assert_eq!(estimate.pdf_pages, Some(PageRange { min: 4, max: 6 }));
+    }
+}
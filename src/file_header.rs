@@ -0,0 +1,228 @@
+//! The table of facts about a file heading its section, such as its size
+//! and line count, with `--file-header-fields` choosing the rows. Both the
+//! whole-document and chunked writers use `render_file_header`, so the
+//! header looks the same either way.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::document_model::FileEntry;
+use crate::i18n::Strings;
+use crate::markdown_generator::{escape_markdown, table_cell, MarkdownGenerator};
+
+/// What comes ahead of the table, so renderers can tell it from the
+/// tables of the file's own contents
+pub const FILE_INFO_MARKER: &str = "<!-- file-info -->";
+
+/// Shown for a fact a file doesn't have, such as the commit of a file git
+/// doesn't track
+const UNKNOWN: &str = "—";
+
+/// One row of the file header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderField {
+    Size,
+    Language,
+    Lines,
+    /// When the file was last modified on disk
+    Modified,
+    /// The last commit that touched the file
+    Commit,
+    /// SHA-1 of the contents
+    Hash,
+    /// Whether the file was cut down to a preview
+    Truncated,
+}
+
+impl HeaderField {
+    pub const ALL: [HeaderField; 7] = [
+        HeaderField::Size,
+        HeaderField::Language,
+        HeaderField::Lines,
+        HeaderField::Modified,
+        HeaderField::Commit,
+        HeaderField::Hash,
+        HeaderField::Truncated,
+    ];
+
+    /// Name of the field in `--file-header-fields`
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeaderField::Size => "size",
+            HeaderField::Language => "language",
+            HeaderField::Lines => "lines",
+            HeaderField::Modified => "modified",
+            HeaderField::Commit => "commit",
+            HeaderField::Hash => "hash",
+            HeaderField::Truncated => "truncated",
+        }
+    }
+
+    fn label(&self, strings: &Strings) -> &'static str {
+        match self {
+            HeaderField::Size => strings.size,
+            HeaderField::Language => strings.code_language,
+            HeaderField::Lines => strings.lines,
+            HeaderField::Modified => strings.modified,
+            HeaderField::Commit => strings.commit,
+            HeaderField::Hash => strings.hash,
+            HeaderField::Truncated => strings.is_truncated,
+        }
+    }
+
+    /// The field's value for `file`, which is cut down if `truncated`
+    fn value(&self, file: &FileEntry, truncated: bool, strings: &Strings) -> String {
+        match self {
+            HeaderField::Size => MarkdownGenerator::format_file_size(file.size),
+            HeaderField::Language => file.binary_kind.as_deref()
+                .or(file.language.as_deref())
+                .unwrap_or(UNKNOWN)
+                .to_string(),
+            HeaderField::Lines if file.binary_kind.is_some() => UNKNOWN.to_string(),
+            HeaderField::Lines => file.content.lines().count().to_string(),
+            HeaderField::Modified => file.modified
+                .map_or_else(|| UNKNOWN.to_string(), |modified| modified.format("%Y-%m-%d %H:%M UTC").to_string()),
+            HeaderField::Commit => file.commit.clone().unwrap_or_else(|| UNKNOWN.to_string()),
+            HeaderField::Hash => file.content_hash.clone(),
+            HeaderField::Truncated => if truncated { strings.yes } else { strings.no }.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HeaderField {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match HeaderField::ALL.iter().find(|field| field.name() == name) {
+            Some(field) => Ok(*field),
+            None => {
+                let names: Vec<&str> = HeaderField::ALL.iter().map(HeaderField::name).collect();
+                bail!("Unknown file header field '{}' (expected {})", name, names.join(", "))
+            }
+        }
+    }
+}
+
+/// Which rows file headers have, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderOptions {
+    pub fields: Vec<HeaderField>,
+}
+
+impl Default for HeaderOptions {
+    /// Only the size, as headers always had
+    fn default() -> Self {
+        Self { fields: vec![HeaderField::Size] }
+    }
+}
+
+impl HeaderOptions {
+    /// Options from a comma-separated list of field names such as
+    /// `size,lines,commit`. Each field shows once, where it's first named;
+    /// an empty list leaves headers out.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut fields = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let field: HeaderField = name.to_ascii_lowercase().parse()?;
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn includes(&self, field: HeaderField) -> bool {
+        self.fields.contains(&field)
+    }
+}
+
+/// The header of `file`'s section, cut down to a preview if `truncated`:
+/// a two-column table of labels and values after `FILE_INFO_MARKER`, or
+/// nothing when `options` has no fields
+pub fn render_file_header(file: &FileEntry, options: &HeaderOptions, strings: &Strings, truncated: bool) -> String {
+    let mut header = String::new();
+    for (i, field) in options.fields.iter().enumerate() {
+        if i == 0 {
+            header.push_str(FILE_INFO_MARKER);
+            header.push('\n');
+        }
+        let value = escape_markdown(&table_cell(&field.value(file, truncated, strings)));
+        header.push_str(&format!("| **{}** | {} |\n", field.label(strings), value));
+        if i == 0 {
+            header.push_str("|---|---|\n");
+        }
+    }
+    if !header.is_empty() {
+        header.push('\n');
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::content_hash;
+    use crate::i18n::{self, ENGLISH};
+    use chrono::TimeZone;
+
+    fn entry() -> FileEntry {
+        FileEntry {
+            path: "src/main.rs".to_string(),
+            anchor: "src-main-rs".to_string(),
+            language: Some("rust".to_string()),
+            size: 2048,
+            content_hash: content_hash("fn main() {}\n\n"),
+            binary_kind: None,
+            changes: None,
+            modified: Some(chrono::Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap()),
+            commit: Some("0123abc".to_string()),
+            content: "fn main() {}\n\n".into(),
+        }
+    }
+
+    #[test]
+    fn test_parse_field_list() {
+        let options = HeaderOptions::parse(" Size, lines,commit,size ,").unwrap();
+        assert_eq!(options.fields, [HeaderField::Size, HeaderField::Lines, HeaderField::Commit]);
+        assert!(HeaderOptions::parse("").unwrap().fields.is_empty());
+        let error = HeaderOptions::parse("size,owner").unwrap_err().to_string();
+        assert!(error.contains("'owner'") && error.contains("modified"), "{}", error);
+    }
+
+    #[test]
+    fn test_every_field_as_a_row() {
+        let options = HeaderOptions { fields: HeaderField::ALL.to_vec() };
+        let header = render_file_header(&entry(), &options, &ENGLISH, false);
+        assert_eq!(header, format!(
+            "<!-- file-info -->\n| **Size:** | 2.0 KB |\n|---|---|\n| **Language:** | rust |\n| **Lines:** | 2 |\n\
+             | **Modified:** | 2024-03-01 09:30 UTC |\n| **Commit:** | 0123abc |\n| **SHA-1:** | {} |\n\
+             | **Truncated:** | no |\n\n",
+            content_hash("fn main() {}\n\n")
+        ));
+    }
+
+    #[test]
+    fn test_missing_facts_and_translations() {
+        let file = FileEntry {
+            binary_kind: Some("SQLite database".to_string()),
+            language: None,
+            modified: None,
+            commit: None,
+            ..entry()
+        };
+        let options = HeaderOptions::parse("language,lines,modified,commit,truncated").unwrap();
+        let header = render_file_header(&file, &options, i18n::strings_for("de"), true);
+        assert!(header.contains("| **Sprache:** | SQLite database |\n"), "{}", header);
+        assert!(header.contains("| **Zeilen:** | — |\n| **Geändert:** | — |\n| **Commit:** | — |\n"), "{}", header);
+        assert!(header.ends_with("| **Gekürzt:** | ja |\n\n"), "{}", header);
+
+        assert_eq!(render_file_header(&file, &HeaderOptions { fields: Vec::new() }, &ENGLISH, false), "");
+    }
+}
@@ -1,32 +1,85 @@
 use anyhow::{Context, Result};
 use content_inspector::{inspect, ContentType};
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use ignore::WalkBuilder;
 
+use crate::cancel::CancellationToken;
+use crate::magic;
 use crate::markdown_generator::{FileInfo, MarkdownGenerator};
+use crate::warnings::{WarningCategory, Warnings};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Per-directory ignore file honored in addition to `.gitignore`
+pub const IGNORE_FILE_NAME: &str = ".scrollcastignore";
+
+/// Files left out of the document, from the `[ignore]` config section,
+/// `scrollcast.ignore` and `--ignore`. The `ignored_*` names are the
+/// spelling older configs used.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IgnoreConfig {
-    pub ignored_files: Vec<String>,
-    pub ignored_extensions: Vec<String>,
+    /// Name fragments; a file whose path contains one is skipped
+    #[serde(alias = "ignored_files")]
+    pub files: Vec<String>,
+    /// Extensions such as `.log`
+    #[serde(alias = "ignored_extensions")]
+    pub extensions: Vec<String>,
+    /// Directory names skipped wherever they occur, or paths like
+    /// `docs/generated` skipped relative to the root
+    #[serde(alias = "ignored_directories")]
+    pub directories: Vec<String>,
 }
 
-impl Default for IgnoreConfig {
-    fn default() -> Self {
-        Self {
-            ignored_files: vec![],
-            ignored_extensions: vec![],
-        }
+impl IgnoreConfig {
+    /// Whether `relative_path` lies inside one of the ignored directories
+    fn in_ignored_directory(&self, relative_path: &Path) -> bool {
+        let parent = relative_path.parent().unwrap_or(Path::new(""));
+        self.directories.iter()
+            .map(|dir| dir.trim_matches('/'))
+            .filter(|dir| !dir.is_empty())
+            .any(|dir| {
+                if dir.contains('/') {
+                    parent.starts_with(dir)
+                } else {
+                    parent.components().any(|component| component.as_os_str() == dir)
+                }
+            })
+    }
+
+    /// Whether the extension of `file_path` is ignored
+    fn has_ignored_extension(&self, file_path: &Path) -> bool {
+        let Some(extension) = file_path.extension() else {
+            return false;
+        };
+        let extension = extension.to_string_lossy();
+        self.extensions.iter().any(|ignored| ignored.trim_start_matches('.') == extension)
     }
 }
 
+/// A file found while walking the input, not read yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredFile {
+    pub path: PathBuf,
+    /// Size when found, checked against what's read later
+    pub size: u64,
+}
+
+#[derive(Clone)]
 pub struct FileProcessor {
     ignore_config: IgnoreConfig,
     universal_excludes: UniversalExcludes,
     respect_gitignore: bool,
-    ignored_directories: Vec<String>,
+    cancellation: CancellationToken,
+    /// The directory `discover_files` walks, when built with `for_directory`
+    root: Option<PathBuf>,
+    /// Files that couldn't be read, or read only in part
+    warnings: Warnings,
+    /// Directory the files are copied to before they're read
+    snapshot: Option<PathBuf>,
 }
 
 impl FileProcessor {
@@ -35,11 +88,28 @@ impl FileProcessor {
             ignore_config: IgnoreConfig::default(),
             universal_excludes: UniversalExcludes::new(),
             respect_gitignore: true,
-            ignored_directories: Vec::new(),
+            cancellation: CancellationToken::new(),
+            root: None,
+            warnings: Warnings::new(),
+            snapshot: None,
+        }
+    }
+
+    /// A processor for the directory at `root`, which must exist, ignoring
+    /// `ignored_dirs` and, if `respect_gitignore` is set, whatever
+    /// `.gitignore` does. Walk it with `discover_files`.
+    pub fn for_directory<P: AsRef<Path>>(root: P, respect_gitignore: bool, ignored_dirs: Vec<String>) -> Result<Self> {
+        let root = root.as_ref();
+        if !root.is_dir() {
+            anyhow::bail!("Input directory does not exist: {}", root.display());
         }
+        let mut processor = Self::new()
+            .with_gitignore_respect(respect_gitignore)
+            .with_ignored_directories(ignored_dirs);
+        processor.root = Some(root.to_path_buf());
+        Ok(processor)
     }
 
-    #[allow(dead_code)]
     pub fn with_ignore_config(mut self, config: IgnoreConfig) -> Self {
         self.ignore_config = config;
         self
@@ -50,12 +120,38 @@ impl FileProcessor {
         self
     }
 
+    /// Ignore these directories in addition to the configured ones
     pub fn with_ignored_directories(mut self, dirs: Vec<String>) -> Self {
-        self.ignored_directories = dirs;
+        self.ignore_config.directories.extend(dirs);
+        self
+    }
+
+    /// Stop walking the directory, with a `Cancelled` error, once `token`
+    /// is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Record files that can't be read, or only in part, in `warnings`
+    pub fn with_warnings(mut self, warnings: Warnings) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Copy the files found into `dir`, which should be empty, and read
+    /// the copies. The copying is quick next to reading and classifying,
+    /// so files edited or deleted during a long read don't leave the
+    /// document with a mix of old and new.
+    pub fn with_snapshot(mut self, dir: PathBuf) -> Self {
+        self.snapshot = Some(dir);
         self
     }
 
-    #[allow(dead_code)]
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+
     pub fn load_ignore_config_from_path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
         let ignore_file_path = path.as_ref().join("scrollcast.ignore");
         if ignore_file_path.exists() {
@@ -69,15 +165,84 @@ impl FileProcessor {
     }
 
     pub fn process_directory<P: AsRef<Path>>(&self, path: P) -> Result<Vec<FileInfo>> {
-        let mut files = Vec::new();
+        self.process_directory_with(path, |_, _| {})
+    }
+
+    /// `process_directory` for the directory given to `for_directory`, run
+    /// on tokio's blocking pool so async callers' worker threads stay free
+    /// during the walk and file reads.
+    ///
+    /// ```
+    /// use scrollcast::FileProcessor;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let repo = tempfile::tempdir()?;
+    ///     std::fs::write(repo.path().join("main.rs"), "fn main() {}\n")?;
+    ///
+    ///     let processor = FileProcessor::for_directory(repo.path(), true, Vec::new())?;
+    ///     let files = processor.discover_files().await?;
+    ///     assert_eq!(files[0].path, "main.rs");
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn discover_files(&self) -> Result<Vec<FileInfo>> {
+        let root = self.root.clone()
+            .context("No directory to discover files in; create the processor with for_directory")?;
+        let processor = self.clone();
+        tokio::task::spawn_blocking(move || processor.process_directory(root))
+            .await
+            .context("File discovery stopped unexpectedly")?
+    }
+
+    /// Like `process_directory`, calling `on_file` as each file is found
+    /// with the time taken to read and classify it
+    pub fn process_directory_with<P, F>(&self, path: P, on_file: F) -> Result<Vec<FileInfo>>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&FileInfo, Duration),
+    {
         let root_path = path.as_ref();
+        let found = self.discover_paths(root_path)?;
+        let mut files = match &self.snapshot {
+            Some(staging) => {
+                let staged = self.stage(root_path, staging, found)?;
+                self.read_discovered(staging, &staged, on_file)?
+            }
+            None => self.read_discovered(root_path, &found, on_file)?,
+        };
+
+        // Show warning for large file counts
+        if files.len() > 50 {
+            eprintln!("⚠️  Warning: Processing {} files. This may take a while and result in a large document.", files.len());
+            
+            // Show top directories by file count
+            let dir_counts = self.get_directory_file_counts(&files);
+            if !dir_counts.is_empty() {
+                eprintln!("   Top directories by file count:");
+                for (dir, count) in dir_counts.iter().take(5) {
+                    eprintln!("     {} - {} files", dir, count);
+                }
+            }
+            
+            eprintln!("   Consider using .gitignore or custom ignore rules to reduce the number of files.");
+        }
+
+        // Sort files by path for consistent output
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
 
-        // Use ignore crate for proper gitignore handling
+    /// Walk `root` for the files to include, without reading them
+    pub fn discover_paths<P: AsRef<Path>>(&self, root: P) -> Result<Vec<DiscoveredFile>> {
+        let root_path = root.as_ref();
         let walker = if self.respect_gitignore {
             WalkBuilder::new(root_path)
                 .git_ignore(true)
                 .git_global(true)
                 .git_exclude(true)
+                .add_custom_ignore_filename(IGNORE_FILE_NAME)
                 .hidden(false)
                 .follow_links(false)
                 .build()
@@ -86,26 +251,22 @@ impl FileProcessor {
                 .git_ignore(false)
                 .git_global(false)
                 .git_exclude(false)
+                .add_custom_ignore_filename(IGNORE_FILE_NAME)
                 .hidden(false)
                 .follow_links(false)
                 .build()
         };
 
+        let mut found = Vec::new();
         for result in walker {
+            self.cancellation.check()?;
             match result {
                 Ok(entry) => {
-                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                        let file_path = entry.path();
-                        
-                        if self.should_process_file_simple(file_path, root_path)? {
-                            match self.process_single_file(file_path, root_path) {
-                                Ok(file_info) => files.push(file_info),
-                                Err(e) => {
-                                    eprintln!("Warning: Failed to process file {}: {}", file_path.display(), e);
-                                    continue;
-                                }
-                            }
-                        }
+                    if entry.file_type().map_or(false, |ft| ft.is_file())
+                        && self.should_process_file_simple(entry.path(), root_path)?
+                    {
+                        let size = entry.metadata().map_or(0, |metadata| metadata.len());
+                        found.push(DiscoveredFile { path: entry.into_path(), size });
                     }
                 }
                 Err(e) => {
@@ -114,28 +275,101 @@ impl FileProcessor {
                 }
             }
         }
+        Ok(found)
+    }
 
-        // Show warning for large file counts
-        if files.len() > 50 {
-            eprintln!("⚠️  Warning: Processing {} files. This may take a while and result in a large document.", files.len());
-            
-            // Show top directories by file count
-            let dir_counts = self.get_directory_file_counts(&files);
-            if !dir_counts.is_empty() {
-                eprintln!("   Top directories by file count:");
-                for (dir, count) in dir_counts.iter().take(5) {
-                    eprintln!("     {} - {} files", dir, count);
+    /// Read the `found` files under `root`, calling `on_file` with the time
+    /// taken to read and classify each. Files deleted since they were found
+    /// are left out and files whose size changed are read as they are now,
+    /// with a warning either way.
+    pub fn read_discovered<F>(&self, root: &Path, found: &[DiscoveredFile], mut on_file: F) -> Result<Vec<FileInfo>>
+    where
+        F: FnMut(&FileInfo, Duration),
+    {
+        let mut files = Vec::new();
+        for file in found {
+            self.cancellation.check()?;
+            let started = Instant::now();
+            match self.process_single_file(&file.path, root) {
+                Ok(file_info) => {
+                    if file_info.size as u64 != file.size {
+                        self.warnings.push(
+                            WarningCategory::Changed,
+                            Some(&file_info.path),
+                            format!(
+                                "Changed from {} to {} after it was found; the newer content is used",
+                                MarkdownGenerator::format_file_size(file.size as usize),
+                                MarkdownGenerator::format_file_size(file_info.size)
+                            ),
+                        );
+                    }
+                    on_file(&file_info, started.elapsed());
+                    files.push(file_info);
                 }
+                Err(e) => self.warn_unreadable(&file.path, root, &e),
             }
-            
-            eprintln!("   Consider using .gitignore or custom ignore rules to reduce the number of files.");
         }
-
-        // Sort files by path for consistent output
-        files.sort_by(|a, b| a.path.cmp(&b.path));
         Ok(files)
     }
 
+    /// Copy the `found` files under `root` into `staging`, keeping their
+    /// relative paths, and return the copies
+    fn stage(&self, root: &Path, staging: &Path, found: Vec<DiscoveredFile>) -> Result<Vec<DiscoveredFile>> {
+        let mut staged = Vec::with_capacity(found.len());
+        for file in found {
+            self.cancellation.check()?;
+            let relative = file.path.strip_prefix(root)
+                .context("Failed to get relative path")?;
+            let copy = staging.join(relative);
+            let copied = copy.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::copy(&file.path, &copy))
+                .with_context(|| format!("Failed to copy {} to the snapshot", file.path.display()));
+            match copied {
+                // Changes up to the copy are in the snapshot, so compare
+                // the copy with the size when found as reading would
+                Ok(_) => staged.push(DiscoveredFile { path: copy, size: file.size }),
+                Err(e) => self.warn_unreadable(&file.path, root, &e),
+            }
+        }
+        Ok(staged)
+    }
+
+    /// Note that `path` was left out because of `error`, telling deleted
+    /// files apart from ones that couldn't be read
+    fn warn_unreadable(&self, path: &Path, root: &Path, error: &anyhow::Error) {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+        let vanished = error.chain()
+            .filter_map(|cause| cause.downcast_ref::<io::Error>())
+            .any(|cause| cause.kind() == io::ErrorKind::NotFound);
+        if vanished {
+            self.warnings.push(WarningCategory::Vanished, Some(&relative), "Deleted after it was found; left out");
+        } else {
+            self.warnings.push(WarningCategory::Unreadable, Some(&relative), format!("Left out: {:#}", error));
+        }
+    }
+
+    /// The `.gitignore` (when respected) and `.scrollcastignore` rules at
+    /// `root`, for checking paths one at a time. Ignore files further down
+    /// the tree only apply during `process_directory`.
+    pub fn root_ignore_rules(&self, root: &Path) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(root);
+        if self.respect_gitignore {
+            builder.add(root.join(".gitignore"));
+        }
+        builder.add(root.join(IGNORE_FILE_NAME));
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    }
+
+    /// Whether a file at `path` would be picked up when processing `root`,
+    /// going by `rules` from `root_ignore_rules` and the configured excludes
+    pub fn would_process(&self, root: &Path, rules: &Gitignore, path: &Path) -> bool {
+        let Ok(relative_path) = path.strip_prefix(root) else {
+            return false;
+        };
+        !rules.matched_path_or_any_parents(relative_path, false).is_ignore()
+            && self.should_process_file_simple(path, root).unwrap_or(false)
+    }
+
     fn should_process_file_simple(&self, file_path: &Path, root_path: &Path) -> Result<bool> {
         // Get relative path for checking
         let relative_path = file_path.strip_prefix(root_path)
@@ -143,11 +377,8 @@ impl FileProcessor {
         let relative_path_str = relative_path.to_string_lossy();
 
         // Check user-specified ignored directories
-        for ignored_dir in &self.ignored_directories {
-            if relative_path_str.starts_with(ignored_dir) || 
-               relative_path_str.starts_with(&format!("{}/", ignored_dir)) {
-                return Ok(false);
-            }
+        if self.ignore_config.in_ignored_directory(relative_path) {
+            return Ok(false);
         }
 
         // Check universal excludes
@@ -156,7 +387,7 @@ impl FileProcessor {
         }
 
         // Check custom ignore configuration
-        if self.ignore_config.ignored_files.iter().any(|ignored| {
+        if self.ignore_config.files.iter().any(|ignored| {
             relative_path_str.contains(ignored) || 
             file_path.file_name().map_or(false, |name| name.to_string_lossy().contains(ignored))
         }) {
@@ -164,11 +395,8 @@ impl FileProcessor {
         }
 
         // Check file extensions
-        if let Some(extension) = file_path.extension() {
-            let ext_str = format!(".{}", extension.to_string_lossy());
-            if self.ignore_config.ignored_extensions.contains(&ext_str) {
-                return Ok(false);
-            }
+        if self.ignore_config.has_ignored_extension(file_path) {
+            return Ok(false);
         }
 
         Ok(true)
@@ -213,39 +441,58 @@ impl FileProcessor {
         let file_size = content.len();
 
         // Check if file is binary
+        let mut binary_kind = None;
         let (text_content, detected_language) = match inspect(&content) {
             ContentType::BINARY => {
                 // For binary files, we'll include a placeholder
-                let placeholder = format!("[Binary file: {} ({} bytes)]", 
+                let kind = magic::binary_kind(&content);
+                let placeholder = format!("[Binary file: {} ({}, {} bytes)]",
                     file_path.file_name().unwrap_or_default().to_string_lossy(),
+                    kind,
                     content.len()
                 );
+                self.warnings.push(WarningCategory::Encoding, Some(&relative_path_str), "Binary content left out");
+                binary_kind = Some(kind.to_string());
                 (placeholder, None)
             }
             ContentType::UTF_8 | ContentType::UTF_8_BOM => {
-                // Convert to string and detect language
-                let text = String::from_utf8_lossy(&content).to_string();
-                let language = MarkdownGenerator::detect_language(&relative_path_str);
+                // Convert to string and detect language, reusing the buffer
+                // when it's valid UTF-8
+                let text = String::from_utf8(content).unwrap_or_else(|error| {
+                    self.warnings.push(WarningCategory::Encoding, Some(&relative_path_str), "Invalid UTF-8 bytes replaced with �");
+                    String::from_utf8_lossy(error.as_bytes()).into_owned()
+                });
+                let language = MarkdownGenerator::detect_language_with_content(&relative_path_str, &text);
                 (text, language)
             }
-            ContentType::UTF_16LE | ContentType::UTF_16BE | 
-            ContentType::UTF_32LE | ContentType::UTF_32BE => {
+            encoding @ (ContentType::UTF_16LE | ContentType::UTF_16BE |
+            ContentType::UTF_32LE | ContentType::UTF_32BE) => {
                 // Handle UTF-16/32 files
+                self.warnings.push(
+                    WarningCategory::Encoding,
+                    Some(&relative_path_str),
+                    format!("{} text isn't decoded and may show garbled", encoding),
+                );
                 let text = String::from_utf8_lossy(&content).to_string();
-                let language = MarkdownGenerator::detect_language(&relative_path_str);
+                let language = MarkdownGenerator::detect_language_with_content(&relative_path_str, &text);
                 (text, language)
             }
         };
 
         Ok(FileInfo {
             path: relative_path_str,
-            content: text_content,
+            content: text_content.into(),
             language: detected_language,
             size: file_size,
+            changes: None,
+            binary_kind,
+            modified: None,
+            commit: None,
         })
     }
 }
 
+#[derive(Clone)]
 pub struct UniversalExcludes {
     excluded_dirs: Vec<String>,
     excluded_files: Vec<String>,
@@ -387,21 +634,70 @@ mod tests {
         assert!(!excludes.should_exclude(Path::new("src/main.rs")));
     }
 
+    #[test]
+    fn test_for_directory_needs_an_existing_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let missing = temp_dir.path().join("missing");
+        let error = FileProcessor::for_directory(&missing, true, Vec::new()).err().unwrap();
+        assert!(error.to_string().contains("does not exist"), "{}", error);
+
+        let processor = FileProcessor::for_directory(temp_dir.path(), false, vec!["build".to_string()])?;
+        assert_eq!(processor.root.as_deref(), Some(temp_dir.path()));
+        assert!(!processor.respect_gitignore);
+        assert_eq!(processor.ignore_config.directories, ["build"]);
+        Ok(())
+    }
+
     #[test]
     fn test_ignore_config() -> Result<()> {
         let config = IgnoreConfig {
-            ignored_files: vec!["test.txt".to_string()],
-            ignored_extensions: vec![".tmp".to_string()],
+            files: vec!["test.txt".to_string()],
+            extensions: vec![".tmp".to_string()],
+            directories: vec!["logs".to_string()],
         };
 
-        let processor = FileProcessor::new().with_ignore_config(config);
+        let processor = FileProcessor::new()
+            .with_ignore_config(config)
+            .with_ignored_directories(vec!["docs/generated".to_string()]);
         
-        // This would require setting up test files to fully test
-        assert!(processor.ignore_config.ignored_files.contains(&"test.txt".to_string()));
+        assert!(processor.ignore_config.files.contains(&"test.txt".to_string()));
+        assert!(processor.ignore_config.has_ignored_extension(Path::new("cache.tmp")));
+        assert!(processor.ignore_config.in_ignored_directory(Path::new("logs/today.txt")));
+        assert!(processor.ignore_config.in_ignored_directory(Path::new("server/logs/today.txt")));
+        assert!(processor.ignore_config.in_ignored_directory(Path::new("docs/generated/api.md")));
+        assert!(!processor.ignore_config.in_ignored_directory(Path::new("docs/guide.md")));
+        assert!(!processor.ignore_config.in_ignored_directory(Path::new("logsearch/main.rs")));
+        assert!(!processor.ignore_config.in_ignored_directory(Path::new("logs")));
+
+        // Configs written before the types were merged keep loading
+        let legacy: IgnoreConfig = serde_json::from_str(r#"{"ignored_files": ["a"], "ignored_extensions": [".b"]}"#)?;
+        assert_eq!(legacy.files, vec!["a"]);
+        assert_eq!(legacy.extensions, vec![".b"]);
+        assert!(legacy.directories.is_empty());
         
         Ok(())
     }
 
+    #[test]
+    fn test_toml_ignore_section_filters_files() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_path = temp_dir.path();
+        fs::create_dir_all(temp_path.join("src"))?;
+        fs::create_dir_all(temp_path.join("fixtures/big"))?;
+        fs::write(temp_path.join("src/main.rs"), "fn main() {}\n")?;
+        fs::write(temp_path.join("src/build.cache"), "cached\n")?;
+        fs::write(temp_path.join("fixtures/big/data.json"), "{}\n")?;
+        fs::write(temp_path.join("scrollcast.toml"), "[ignore]\nextensions = [\".cache\"]\ndirectories = [\"fixtures\"]\n")?;
+
+        let config = crate::config::Config::load_from_file(temp_path.join("scrollcast.toml"))?;
+        let processor = FileProcessor::new().with_ignore_config(config.ignore);
+        let paths: Vec<String> = processor.process_directory(temp_path)?.into_iter().map(|f| f.path).collect();
+        assert!(paths.contains(&"src/main.rs".to_string()), "{:?}", paths);
+        assert!(!paths.iter().any(|path| path.ends_with(".cache")), "{:?}", paths);
+        assert!(!paths.iter().any(|path| path.starts_with("fixtures")), "{:?}", paths);
+        Ok(())
+    }
+
     #[test]
     fn test_file_processing() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -427,6 +723,50 @@ mod tests {
         assert!(files.iter().any(|f| f.path.contains("readme.txt")));
         assert!(!files.iter().any(|f| f.path.contains(".git")));
 
+        let mut found = Vec::new();
+        processor.process_directory_with(temp_path, |file, _| found.push(file.path.clone()))?;
+        found.sort();
+        assert_eq!(found, vec!["readme.txt", "test.rs"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scrollcastignore_is_honored() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_path = temp_dir.path();
+        fs::write(temp_path.join("main.rs"), "fn main() {}\n")?;
+        fs::write(temp_path.join("notes.log"), "noise\n")?;
+        fs::write(temp_path.join(IGNORE_FILE_NAME), "*.log\n")?;
+
+        for respect_gitignore in [true, false] {
+            let processor = FileProcessor::new().with_gitignore_respect(respect_gitignore);
+            let paths: Vec<String> = processor.process_directory(temp_path)?.into_iter().map(|f| f.path).collect();
+            assert!(paths.contains(&"main.rs".to_string()), "{:?}", paths);
+            assert!(!paths.iter().any(|path| path.ends_with(".log")), "{:?}", paths);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_would_process_matches_walk_rules() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        fs::write(root.join(".gitignore"), "generated/\n")?;
+        fs::write(root.join(IGNORE_FILE_NAME), "*.log\n")?;
+
+        let processor = FileProcessor::new();
+        let rules = processor.root_ignore_rules(root);
+        assert!(processor.would_process(root, &rules, &root.join("src/main.rs")));
+        assert!(!processor.would_process(root, &rules, &root.join("notes.log")));
+        assert!(!processor.would_process(root, &rules, &root.join("generated/api.rs")));
+        assert!(!processor.would_process(root, &rules, &root.join("target/debug/build.rs")));
+        assert!(!processor.would_process(root, &rules, Path::new("/elsewhere/main.rs")));
+
+        let processor = FileProcessor::new().with_gitignore_respect(false);
+        let rules = processor.root_ignore_rules(root);
+        assert!(processor.would_process(root, &rules, &root.join("generated/api.rs")));
+        assert!(!processor.would_process(root, &rules, &root.join("notes.log")));
         Ok(())
     }
 
@@ -447,4 +787,69 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_binary_kind_in_placeholder() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("tool"), b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0")?;
+        fs::write(temp_dir.path().join("blob"), [0u8, 1, 2, 3, 0, 0, 0, 0])?;
+
+        let mut files = FileProcessor::new().process_directory(temp_dir.path())?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files[0].binary_kind.as_deref(), Some("unknown binary"));
+        assert_eq!(&*files[1].content, "[Binary file: tool (ELF 64-bit executable, 16 bytes)]");
+        assert_eq!(files[1].binary_kind.as_deref(), Some("ELF 64-bit executable"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_deleted_or_changed_after_discovery() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("gone.rs"), "fn gone() {}\n")?;
+        fs::write(temp_dir.path().join("grown.rs"), "fn a() {}\n")?;
+        fs::write(temp_dir.path().join("same.rs"), "fn same() {}\n")?;
+
+        let warnings = Warnings::new();
+        let processor = FileProcessor::new().with_warnings(warnings.clone());
+        let mut found = processor.discover_paths(temp_dir.path())?;
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[1].size, 10);
+
+        fs::remove_file(temp_dir.path().join("gone.rs"))?;
+        fs::write(temp_dir.path().join("grown.rs"), "fn a() {}\nfn b() {}\n")?;
+        let files = processor.read_discovered(temp_dir.path(), &found, |_, _| {})?;
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["grown.rs", "same.rs"]);
+        assert_eq!(&*files[0].content, "fn a() {}\nfn b() {}\n");
+
+        let entries = warnings.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].category, entries[0].path.as_deref()), (WarningCategory::Vanished, Some("gone.rs")));
+        assert_eq!((entries[1].category, entries[1].path.as_deref()), (WarningCategory::Changed, Some("grown.rs")));
+        assert_eq!(entries[1].message, "Changed from 10 B to 20 B after it was found; the newer content is used");
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_reads_copies() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo = temp_dir.path().join("repo");
+        let staging = temp_dir.path().join("staging");
+        fs::create_dir_all(repo.join("src"))?;
+        fs::create_dir(&staging)?;
+        fs::write(repo.join("src/lib.rs"), "pub fn lib() {}\n")?;
+        fs::write(repo.join("README.md"), "# Repo\n")?;
+
+        let warnings = Warnings::new();
+        let files = FileProcessor::new()
+            .with_warnings(warnings.clone())
+            .with_snapshot(staging.clone())
+            .process_directory(&repo)?;
+        let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["README.md", "src/lib.rs"]);
+        assert_eq!(fs::read_to_string(staging.join("src/lib.rs"))?, "pub fn lib() {}\n");
+        assert!(warnings.is_empty());
+        Ok(())
+    }
+}
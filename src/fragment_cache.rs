@@ -0,0 +1,156 @@
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Part of every key, so fragments from another version are never reused
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+/// Numbers the temporary files of this process, so threads storing the
+/// same entry at once each write their own
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Fragments reused from and generated into the cache by this process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FragmentStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Counters of every fragment cache in the process
+pub fn fragment_stats() -> FragmentStats {
+    FragmentStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
+
+/// Rendered pieces of a document kept on disk between runs, such as the
+/// highlighted HTML of a code block or the markdown section of a file, so
+/// a rerun only redoes the files that changed. Entries are keyed by a hash
+/// of everything that went into them; a cache that can't be read or
+/// written only costs the time to regenerate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentCache {
+    dir: PathBuf,
+}
+
+impl FragmentCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `scrollcast/fragments` under the platform cache directory
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("scrollcast").join("fragments"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The key for a fragment of `kind` made from `parts`: the content, the
+    /// language, the theme and whatever options shape the output
+    pub fn key(kind: &str, parts: &[&str]) -> String {
+        let mut hasher = Sha1::new();
+        for part in [VERSION, kind].iter().chain(parts) {
+            // Length prefixes keep ("ab", "c") and ("a", "bc") apart
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let fragment = fs::read_to_string(self.path(key)).ok();
+        let counter = if fragment.is_some() { &HITS } else { &MISSES };
+        counter.fetch_add(1, Ordering::Relaxed);
+        fragment
+    }
+
+    /// Store `fragment` under `key`. Written to a temporary file first, so
+    /// a concurrent run or thread never reads half an entry.
+    pub fn insert(&self, key: &str, fragment: &str) {
+        let path = self.path(key);
+        let temp = path.with_extension(format!("tmp{}-{}", std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&temp, fragment))
+            .and_then(|()| fs::rename(&temp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+    }
+
+    /// The cached fragment for `key`, or the one `generate` makes, stored
+    /// for next time
+    pub fn get_or_insert_with(&self, key: &str, generate: impl FnOnce() -> String) -> String {
+        if let Some(fragment) = self.get(key) {
+            return fragment;
+        }
+        let fragment = generate();
+        self.insert(key, &fragment);
+        fragment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fragments_survive_between_caches() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let key = FragmentCache::key("markdown", &["src/main.rs", "fn main() {}", "rust"]);
+        assert_eq!(key.len(), 40);
+        assert_ne!(key, FragmentCache::key("markdown", &["src/main.rs", "fn main() {}", "c"]));
+        assert_ne!(FragmentCache::key("x", &["ab", "c"]), FragmentCache::key("x", &["a", "bc"]));
+
+        let cache = FragmentCache::new(temp_dir.path());
+        assert_eq!(cache.get(&key), None);
+        assert_eq!(cache.get_or_insert_with(&key, || "section".to_string()), "section");
+
+        let reopened = FragmentCache::new(temp_dir.path());
+        assert_eq!(reopened.get_or_insert_with(&key, || unreachable!()), "section");
+        let leftovers = fs::read_dir(temp_dir.path().join(&key[..2])).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn test_threads_storing_one_entry_never_mix_it() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = FragmentCache::new(temp_dir.path());
+        let key = FragmentCache::key("html", &["code"]);
+        let versions: Vec<String> = (0..8).map(|i| i.to_string().repeat(256 * 1024)).collect();
+        std::thread::scope(|scope| {
+            for version in &versions {
+                let (cache, key) = (&cache, &key);
+                scope.spawn(move || {
+                    for _ in 0..5 {
+                        cache.insert(key, version);
+                    }
+                });
+            }
+        });
+        assert!(versions.contains(&cache.get(&key).unwrap()));
+        let leftovers = fs::read_dir(temp_dir.path().join(&key[..2])).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn test_unwritable_cache_still_generates() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("not-a-directory");
+        fs::write(&file, "").unwrap();
+        let cache = FragmentCache::new(&file);
+        let key = FragmentCache::key("html", &["code"]);
+        assert_eq!(cache.get_or_insert_with(&key, || "html".to_string()), "html");
+        assert_eq!(cache.get(&key), None);
+    }
+}
@@ -0,0 +1,479 @@
+//! The boilerplate text of generated documents, in each language
+//! `--doc-language` knows. Adding a language means adding one entry to
+//! `LANGUAGES`.
+
+/// Every heading and label a document shows besides the repository's own
+/// text. Labels include their colon, since languages space it differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strings {
+    /// Primary language subtag, such as `de`
+    pub code: &'static str,
+    pub table_of_contents: &'static str,
+    pub file_structure: &'static str,
+    pub file_contents: &'static str,
+    pub size: &'static str,
+    pub generated_on: &'static str,
+    pub generated: &'static str,
+    pub author: &'static str,
+    pub today: &'static str,
+    pub cover: &'static str,
+    /// Description of an EPUB without one, with `{title}` and `{count}`
+    pub source_listing: &'static str,
+    pub review_checklist: &'static str,
+    pub marker: &'static str,
+    pub location: &'static str,
+    pub note: &'static str,
+    pub generation_warnings: &'static str,
+    pub category: &'static str,
+    pub file: &'static str,
+    pub message: &'static str,
+    pub count: &'static str,
+    /// What a truncated file's preview is followed by, with `{size}`
+    pub continues: &'static str,
+    /// Heading of a sample from a truncated file, with `{n}` and `{percent}`
+    pub sample: &'static str,
+    pub file_summary: &'static str,
+    pub total_size: &'static str,
+    /// How much of a truncated file is shown, with `{shown}` and `{total}`
+    pub lines_shown: &'static str,
+    pub truncated: &'static str,
+    /// Labels of the file header's rows
+    pub code_language: &'static str,
+    pub lines: &'static str,
+    pub modified: &'static str,
+    pub commit: &'static str,
+    pub hash: &'static str,
+    pub is_truncated: &'static str,
+    pub yes: &'static str,
+    pub no: &'static str,
+    /// Heading of the page ending a PDF cut short by `--max-pages`
+    pub page_limit: &'static str,
+    /// What that page says, with `{pages}` and `{count}`
+    pub files_left_out: &'static str,
+    /// In place of the contents of a file with nothing in it
+    pub empty_file: &'static str,
+    /// In place of the contents of a file of one blank line
+    pub blank_line: &'static str,
+    /// In place of the contents of a file of `{n}` blank lines
+    pub blank_lines: &'static str,
+    /// Next to the heading of a file added since an earlier document
+    pub badge_new: &'static str,
+    /// Next to the heading of a file modified since an earlier document
+    pub badge_modified: &'static str,
+    /// Heading of the list of every file after the contents
+    pub file_index: &'static str,
+    /// How many files a directory of the table of contents holds
+    pub one_file: &'static str,
+    /// The same for `{n}` files
+    pub files_count: &'static str,
+}
+
+impl Strings {
+    /// The default EPUB description for `title` with `count` files
+    pub fn source_listing(&self, title: &str, count: usize) -> String {
+        self.source_listing.replace("{title}", title).replace("{count}", &count.to_string())
+    }
+
+    /// The line saying `size` more of a file was left out
+    pub fn continues(&self, size: &str) -> String {
+        self.continues.replace("{size}", size)
+    }
+
+    /// The heading of sample `n`, taken from `percent` of the way in
+    pub fn sample(&self, n: usize, percent: usize) -> String {
+        self.sample.replace("{n}", &n.to_string()).replace("{percent}", &percent.to_string())
+    }
+
+    /// What a file of `n` blank lines shows in place of its contents
+    pub fn blank_lines(&self, n: usize) -> String {
+        if n == 1 { self.blank_line.to_string() } else { self.blank_lines.replace("{n}", &n.to_string()) }
+    }
+
+    /// How many files a directory holds, for `n` files
+    pub fn files(&self, n: usize) -> String {
+        if n == 1 { self.one_file.to_string() } else { self.files_count.replace("{n}", &n.to_string()) }
+    }
+
+    /// The line saying `count` files were left out to stay within `pages`
+    pub fn files_left_out(&self, count: usize, pages: usize) -> String {
+        self.files_left_out.replace("{count}", &count.to_string()).replace("{pages}", &pages.to_string())
+    }
+
+    /// The line saying about `shown` of `total` lines are shown
+    pub fn lines_shown(&self, shown: usize, total: usize) -> String {
+        self.lines_shown.replace("{shown}", &shown.to_string()).replace("{total}", &total.to_string())
+    }
+}
+
+pub const ENGLISH: Strings = Strings {
+    code: "en",
+    table_of_contents: "Table of Contents",
+    file_structure: "File Structure",
+    file_contents: "File Contents",
+    size: "Size:",
+    generated_on: "Generated on:",
+    generated: "Generated:",
+    author: "Author:",
+    today: "Today",
+    cover: "Cover",
+    source_listing: "Source listing of {title} ({count} files)",
+    review_checklist: "Review Checklist",
+    marker: "Marker",
+    location: "Location",
+    note: "Note",
+    generation_warnings: "Generation Warnings",
+    category: "Category",
+    file: "File",
+    message: "Message",
+    count: "Count",
+    continues: "... [File continues for {size} more] ...",
+    sample: "Sample {n} (around {percent}%)",
+    file_summary: "File Summary",
+    total_size: "Total size:",
+    lines_shown: "Lines shown: ~{shown} of ~{total}",
+    truncated: "Note: Large file truncated to prevent memory issues.",
+    code_language: "Language:",
+    lines: "Lines:",
+    modified: "Modified:",
+    commit: "Commit:",
+    hash: "SHA-1:",
+    is_truncated: "Truncated:",
+    yes: "yes",
+    no: "no",
+    page_limit: "Page Limit Reached",
+    files_left_out: "Files left out to keep this document within {pages} pages: {count}",
+    empty_file: "(empty file)",
+    blank_line: "(1 blank line)",
+    blank_lines: "({n} blank lines)",
+    badge_new: "new",
+    badge_modified: "modified",
+    file_index: "File Index",
+    one_file: "1 file",
+    files_count: "{n} files",
+};
+
+/// Every language documents can be written in, English first
+pub static LANGUAGES: [Strings; 7] = [
+    ENGLISH,
+    Strings {
+        code: "de",
+        table_of_contents: "Inhaltsverzeichnis",
+        file_structure: "Dateistruktur",
+        file_contents: "Dateiinhalte",
+        size: "Größe:",
+        generated_on: "Erstellt am:",
+        generated: "Erstellt:",
+        author: "Autor:",
+        today: "Heute",
+        cover: "Titelbild",
+        source_listing: "Quelltext von {title} ({count} Dateien)",
+        review_checklist: "Prüfliste",
+        marker: "Markierung",
+        location: "Fundstelle",
+        note: "Notiz",
+        generation_warnings: "Hinweise zur Erstellung",
+        category: "Kategorie",
+        file: "Datei",
+        message: "Meldung",
+        count: "Anzahl",
+        continues: "... [Datei geht noch {size} weiter] ...",
+        sample: "Auszug {n} (bei etwa {percent} %)",
+        file_summary: "Dateiübersicht",
+        total_size: "Gesamtgröße:",
+        lines_shown: "Gezeigte Zeilen: ~{shown} von ~{total}",
+        truncated: "Hinweis: Große Datei gekürzt, um Speicherprobleme zu vermeiden.",
+        code_language: "Sprache:",
+        lines: "Zeilen:",
+        modified: "Geändert:",
+        commit: "Commit:",
+        hash: "SHA-1:",
+        is_truncated: "Gekürzt:",
+        yes: "ja",
+        no: "nein",
+        page_limit: "Seitenlimit erreicht",
+        files_left_out: "Ausgelassene Dateien, um dieses Dokument auf {pages} Seiten zu begrenzen: {count}",
+        empty_file: "(leere Datei)",
+        blank_line: "(1 Leerzeile)",
+        blank_lines: "({n} Leerzeilen)",
+        badge_new: "neu",
+        badge_modified: "geändert",
+        file_index: "Dateiverzeichnis",
+        one_file: "1 Datei",
+        files_count: "{n} Dateien",
+    },
+    Strings {
+        code: "fr",
+        table_of_contents: "Table des matières",
+        file_structure: "Structure des fichiers",
+        file_contents: "Contenu des fichiers",
+        size: "Taille :",
+        generated_on: "Généré le :",
+        generated: "Généré :",
+        author: "Auteur :",
+        today: "Aujourd'hui",
+        cover: "Couverture",
+        source_listing: "Code source de {title} ({count} fichiers)",
+        review_checklist: "Liste de relecture",
+        marker: "Marqueur",
+        location: "Emplacement",
+        note: "Remarque",
+        generation_warnings: "Avertissements de génération",
+        category: "Catégorie",
+        file: "Fichier",
+        message: "Message",
+        count: "Nombre",
+        continues: "... [Le fichier continue sur {size}] ...",
+        sample: "Extrait {n} (vers {percent} %)",
+        file_summary: "Résumé du fichier",
+        total_size: "Taille totale :",
+        lines_shown: "Lignes affichées : ~{shown} sur ~{total}",
+        truncated: "Remarque : fichier volumineux tronqué pour éviter les problèmes de mémoire.",
+        code_language: "Langage :",
+        lines: "Lignes :",
+        modified: "Modifié :",
+        commit: "Commit :",
+        hash: "SHA-1 :",
+        is_truncated: "Tronqué :",
+        yes: "oui",
+        no: "non",
+        page_limit: "Limite de pages atteinte",
+        files_left_out: "Fichiers omis pour limiter ce document à {pages} pages : {count}",
+        empty_file: "(fichier vide)",
+        blank_line: "(1 ligne vide)",
+        blank_lines: "({n} lignes vides)",
+        badge_new: "nouveau",
+        badge_modified: "modifié",
+        file_index: "Index des fichiers",
+        one_file: "1 fichier",
+        files_count: "{n} fichiers",
+    },
+    Strings {
+        code: "es",
+        table_of_contents: "Índice",
+        file_structure: "Estructura de archivos",
+        file_contents: "Contenido de los archivos",
+        size: "Tamaño:",
+        generated_on: "Generado el:",
+        generated: "Generado:",
+        author: "Autor:",
+        today: "Hoy",
+        cover: "Portada",
+        source_listing: "Código fuente de {title} ({count} archivos)",
+        review_checklist: "Lista de revisión",
+        marker: "Marcador",
+        location: "Ubicación",
+        note: "Nota",
+        generation_warnings: "Advertencias de generación",
+        category: "Categoría",
+        file: "Archivo",
+        message: "Mensaje",
+        count: "Cantidad",
+        continues: "... [El archivo continúa {size} más] ...",
+        sample: "Muestra {n} (alrededor del {percent} %)",
+        file_summary: "Resumen del archivo",
+        total_size: "Tamaño total:",
+        lines_shown: "Líneas mostradas: ~{shown} de ~{total}",
+        truncated: "Nota: archivo grande truncado para evitar problemas de memoria.",
+        code_language: "Lenguaje:",
+        lines: "Líneas:",
+        modified: "Modificado:",
+        commit: "Commit:",
+        hash: "SHA-1:",
+        is_truncated: "Truncado:",
+        yes: "sí",
+        no: "no",
+        page_limit: "Límite de páginas alcanzado",
+        files_left_out: "Archivos omitidos para limitar este documento a {pages} páginas: {count}",
+        empty_file: "(archivo vacío)",
+        blank_line: "(1 línea en blanco)",
+        blank_lines: "({n} líneas en blanco)",
+        badge_new: "nuevo",
+        badge_modified: "modificado",
+        file_index: "Índice de archivos",
+        one_file: "1 archivo",
+        files_count: "{n} archivos",
+    },
+    Strings {
+        code: "pt",
+        table_of_contents: "Sumário",
+        file_structure: "Estrutura de arquivos",
+        file_contents: "Conteúdo dos arquivos",
+        size: "Tamanho:",
+        generated_on: "Gerado em:",
+        generated: "Gerado:",
+        author: "Autor:",
+        today: "Hoje",
+        cover: "Capa",
+        source_listing: "Código-fonte de {title} ({count} arquivos)",
+        review_checklist: "Lista de revisão",
+        marker: "Marcador",
+        location: "Local",
+        note: "Nota",
+        generation_warnings: "Avisos de geração",
+        category: "Categoria",
+        file: "Arquivo",
+        message: "Mensagem",
+        count: "Quantidade",
+        continues: "... [O arquivo continua por mais {size}] ...",
+        sample: "Amostra {n} (por volta de {percent}%)",
+        file_summary: "Resumo do arquivo",
+        total_size: "Tamanho total:",
+        lines_shown: "Linhas exibidas: ~{shown} de ~{total}",
+        truncated: "Nota: arquivo grande truncado para evitar problemas de memória.",
+        code_language: "Linguagem:",
+        lines: "Linhas:",
+        modified: "Modificado:",
+        commit: "Commit:",
+        hash: "SHA-1:",
+        is_truncated: "Truncado:",
+        yes: "sim",
+        no: "não",
+        page_limit: "Limite de páginas atingido",
+        files_left_out: "Arquivos omitidos para limitar este documento a {pages} páginas: {count}",
+        empty_file: "(arquivo vazio)",
+        blank_line: "(1 linha em branco)",
+        blank_lines: "({n} linhas em branco)",
+        badge_new: "novo",
+        badge_modified: "modificado",
+        file_index: "Índice de arquivos",
+        one_file: "1 arquivo",
+        files_count: "{n} arquivos",
+    },
+    Strings {
+        code: "ja",
+        table_of_contents: "目次",
+        file_structure: "ファイル構成",
+        file_contents: "ファイルの内容",
+        size: "サイズ:",
+        generated_on: "生成日時:",
+        generated: "生成日:",
+        author: "作成者:",
+        today: "今日",
+        cover: "表紙",
+        source_listing: "{title} のソースコード（{count} ファイル）",
+        review_checklist: "レビューチェックリスト",
+        marker: "マーカー",
+        location: "場所",
+        note: "メモ",
+        generation_warnings: "生成時の警告",
+        category: "分類",
+        file: "ファイル",
+        message: "メッセージ",
+        count: "件数",
+        continues: "... [ファイルはさらに {size} 続きます] ...",
+        sample: "抜粋 {n}（約 {percent}% の位置）",
+        file_summary: "ファイルの概要",
+        total_size: "合計サイズ:",
+        lines_shown: "表示行数: 約 {shown} / 約 {total}",
+        truncated: "注: メモリ不足を防ぐため、大きなファイルを切り詰めました。",
+        code_language: "言語:",
+        lines: "行数:",
+        modified: "更新日時:",
+        commit: "コミット:",
+        hash: "SHA-1:",
+        is_truncated: "切り詰め:",
+        yes: "はい",
+        no: "いいえ",
+        page_limit: "ページ数の上限に達しました",
+        files_left_out: "{pages} ページに収めるために省略したファイル: {count}",
+        empty_file: "(空のファイル)",
+        blank_line: "(空行 1 行)",
+        blank_lines: "(空行 {n} 行)",
+        badge_new: "新規",
+        badge_modified: "変更",
+        file_index: "ファイル索引",
+        one_file: "1 ファイル",
+        files_count: "{n} ファイル",
+    },
+    Strings {
+        code: "zh",
+        table_of_contents: "目录",
+        file_structure: "文件结构",
+        file_contents: "文件内容",
+        size: "大小:",
+        generated_on: "生成时间:",
+        generated: "生成:",
+        author: "作者:",
+        today: "今天",
+        cover: "封面",
+        source_listing: "{title} 的源代码（{count} 个文件）",
+        review_checklist: "审查清单",
+        marker: "标记",
+        location: "位置",
+        note: "备注",
+        generation_warnings: "生成警告",
+        category: "类别",
+        file: "文件",
+        message: "消息",
+        count: "次数",
+        continues: "... [文件还有 {size} 未显示] ...",
+        sample: "片段 {n}（约 {percent}% 处）",
+        file_summary: "文件摘要",
+        total_size: "总大小:",
+        lines_shown: "显示行数: 约 {shown} / 约 {total}",
+        truncated: "注意: 为避免内存问题，已截断大文件。",
+        code_language: "语言:",
+        lines: "行数:",
+        modified: "修改时间:",
+        commit: "提交:",
+        hash: "SHA-1:",
+        is_truncated: "已截断:",
+        yes: "是",
+        no: "否",
+        page_limit: "已达到页数上限",
+        files_left_out: "为将文档限制在 {pages} 页内而省略的文件: {count}",
+        empty_file: "(空文件)",
+        blank_line: "(1 个空行)",
+        blank_lines: "({n} 个空行)",
+        badge_new: "新增",
+        badge_modified: "已修改",
+        file_index: "文件索引",
+        one_file: "1 个文件",
+        files_count: "{n} 个文件",
+    },
+];
+
+/// The strings for `language`, a tag such as `de` or `pt-BR`, going by
+/// its primary subtag; `None` for languages without a translation
+pub fn translation(language: &str) -> Option<&'static Strings> {
+    let primary = language.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+    LANGUAGES.iter().find(|strings| strings.code == primary)
+}
+
+/// The strings for `language`, falling back to English
+pub fn strings_for(language: &str) -> &'static Strings {
+    translation(language).unwrap_or(&LANGUAGES[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_by_primary_subtag() {
+        assert_eq!(strings_for("de").table_of_contents, "Inhaltsverzeichnis");
+        assert_eq!(strings_for("pt-BR").code, "pt");
+        assert_eq!(strings_for("ZH_cn").code, "zh");
+        assert!(translation("xx").is_none());
+        assert_eq!(strings_for("xx"), &ENGLISH);
+        assert_eq!(strings_for("fr").source_listing("repo", 3), "Code source de repo (3 fichiers)");
+    }
+
+    #[test]
+    fn test_every_language_is_translated() {
+        for strings in &LANGUAGES[1..] {
+            assert_ne!(strings.table_of_contents, ENGLISH.table_of_contents, "{}", strings.code);
+            for placeholder in ["{title}", "{count}"] {
+                assert!(strings.source_listing.contains(placeholder), "{}", strings.code);
+            }
+            for placeholder in ["{shown}", "{total}"] {
+                assert!(strings.lines_shown.contains(placeholder), "{}", strings.code);
+            }
+            assert!(strings.continues.contains("{size}"), "{}", strings.code);
+            assert!(strings.blank_lines.contains("{n}"), "{}", strings.code);
+            assert!(strings.files_count.contains("{n}"), "{}", strings.code);
+            assert!(strings.files_left_out.contains("{count}") && strings.files_left_out.contains("{pages}"), "{}", strings.code);
+            assert!(strings.sample.contains("{n}") && strings.sample.contains("{percent}"), "{}", strings.code);
+        }
+    }
+}
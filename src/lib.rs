@@ -12,45 +12,88 @@
 //! ## Example
 //! 
 //! ```rust,no_run
-//! use scrollcast::{FileProcessor, MarkdownGenerator, OutputFormat, create_renderer, DocumentMetadata};
+//! use scrollcast::{create_renderer, DocumentMetadata, FileProcessor, MarkdownGenerator, OutputFormat};
 //! use std::path::Path;
-//! 
-//! #[tokio::main]
-//! async fn main() -> anyhow::Result<()> {
-//!     let input_path = Path::new("./my-repo");
-//!     let output_path = Path::new("./output.pdf");
-//!     
+//!
+//! fn main() -> anyhow::Result<()> {
 //!     // Process files
-//!     let mut processor = FileProcessor::new(input_path, true, Vec::new())?;
-//!     let files = processor.discover_files().await?;
-//!     
+//!     let files = FileProcessor::new().process_directory("./my-repo")?;
+//!
 //!     // Generate markdown
-//!     let generator = MarkdownGenerator::new();
-//!     let markdown = generator.generate_markdown(&files, input_path, false).await?;
-//!     
+//!     let markdown = MarkdownGenerator::new(true, true).generate_markdown(&files, "My Repository")?;
+//!
 //!     // Create renderer and convert
-//!     let metadata = DocumentMetadata {
-//!         title: "My Repository".to_string(),
-//!         author: "Author".to_string(),
-//!         created_at: chrono::Utc::now(),
-//!     };
-//!     
-//!     let mut renderer = create_renderer(OutputFormat::Pdf, "kate".to_string())?;
-//!     renderer.render(&markdown, output_path, &metadata).await?;
-//!     
+//!     let metadata = DocumentMetadata::builder("My Repository")
+//!         .author("Author")
+//!         .created_at(chrono::Utc::now())
+//!         .build();
+//!     create_renderer(&OutputFormat::Pdf)?
+//!         .save_to_file(&markdown, &metadata, Path::new("./output.pdf"))?;
+//!
 //!     Ok(())
 //! }
 //! ```
 
+//! ## Cargo features
+//!
+//! - `cli` (default): the `scrollcast` binary and the modules only it needs
+//!   (`cli`, `commands`, `progress`, `prompt`, `selection` and `watch`),
+//!   with their dependencies. Turns on every format and `async`.
+//! - `pdf`, `epub`, `html`: the renderers for each output format. Without
+//!   one, [`create_renderer`] returns [`renderer::UnsupportedFormat`] for it.
+//! - `async`: [`FileProcessor::discover_files`], which walks the input on
+//!   tokio's blocking pool.
+//!
+//! A library that only writes HTML can depend on scrollcast with
+//! `default-features = false, features = ["html"]`.
+
+pub mod anchors;
+pub mod cancel;
+pub mod changes;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod comments;
+#[cfg(feature = "cli")]
+pub mod commands;
 pub mod config;
+pub mod diffing;
+pub mod document_model;
+pub mod estimate;
+pub mod file_header;
 pub mod file_processor;
+pub mod fragment_cache;
+pub mod i18n;
+pub mod magic;
 pub mod markdown_generator;
+pub mod opener;
+pub mod output_path;
+#[cfg(feature = "cli")]
+pub mod progress;
+#[cfg(feature = "cli")]
+pub mod prompt;
 pub mod renderer;
+pub mod report;
+#[cfg(feature = "cli")]
+pub mod selection;
+pub mod stats;
 pub mod syntax;
+pub mod tables;
+pub mod temp_files;
 pub mod theme;
+pub mod todos;
+pub mod warnings;
+#[cfg(feature = "cli")]
+pub mod watch;
 
-pub use file_processor::FileProcessor;
+pub use file_processor::{DiscoveredFile, FileProcessor};
+pub use document_model::DocumentModel;
 pub use markdown_generator::{FileInfo, MarkdownGenerator};
 pub use renderer::{OutputFormat, create_renderer, DocumentMetadata};
 pub use config::Config;
-pub use theme::Theme;
\ No newline at end of file
+pub use theme::Theme;
+pub use warnings::{Warning, WarningCategory, Warnings};
+
+// Compiles the README's library example along with these doc tests
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
\ No newline at end of file
@@ -0,0 +1,69 @@
+//! What kind of binary a file is, going by the magic bytes it starts with,
+//! so a placeholder can say "SQLite database" rather than only its size.
+
+/// Kind given to binaries whose first bytes match nothing in `SIGNATURES`
+pub const UNKNOWN_BINARY: &str = "unknown binary";
+
+/// Kinds and the bytes their files start with, at an offset. The first
+/// match wins, so longer signatures come before shorter ones they share a
+/// prefix with.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"SQLite format 3\0", "SQLite database"),
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF87a", "GIF image"),
+    (0, b"GIF89a", "GIF image"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"\x1f\x8b", "gzip archive"),
+    (0, b"BZh", "bzip2 archive"),
+    (0, b"\xfd7zXZ\0", "xz archive"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd archive"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (0, b"PK\x03\x04", "zip archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"\0asm", "WebAssembly module"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O 64-bit executable"),
+    (0, b"\xce\xfa\xed\xfe", "Mach-O 32-bit executable"),
+    (0, b"\xca\xfe\xba\xbe", "Java class file or Mach-O universal binary"),
+    (0, b"MZ", "Windows executable"),
+];
+
+/// The kind of binary `content` holds, such as "ELF 64-bit executable", or
+/// `UNKNOWN_BINARY`
+pub fn binary_kind(content: &[u8]) -> &'static str {
+    if let Some(header) = content.strip_prefix(b"\x7fELF") {
+        return match header.first() {
+            Some(1) => "ELF 32-bit executable",
+            Some(2) => "ELF 64-bit executable",
+            _ => "ELF executable",
+        };
+    }
+    SIGNATURES.iter()
+        .find(|(offset, magic, _)| content.get(*offset..).is_some_and(|rest| rest.starts_with(magic)))
+        .map_or(UNKNOWN_BINARY, |(_, _, kind)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crafted_headers() {
+        assert_eq!(binary_kind(b"\x7fELF\x02\x01\x01\0\0\0"), "ELF 64-bit executable");
+        assert_eq!(binary_kind(b"\x7fELF\x01\x01\x01\0"), "ELF 32-bit executable");
+        assert_eq!(binary_kind(b"SQLite format 3\0\x10\0\x01\x01"), "SQLite database");
+        assert_eq!(binary_kind(&[0x1f, 0x8b, 0x08, 0x00, 0x00]), "gzip archive");
+        assert_eq!(binary_kind(&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0]), "PNG image");
+
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(binary_kind(&tar), "tar archive");
+    }
+
+    #[test]
+    fn test_unknown_magic() {
+        assert_eq!(binary_kind(&[0x00, 0x01, 0x02, 0x03]), UNKNOWN_BINARY);
+        assert_eq!(binary_kind(b""), UNKNOWN_BINARY);
+        assert_eq!(binary_kind(b"SQLite"), UNKNOWN_BINARY);
+    }
+}
@@ -1,19 +1,200 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use crate::anchors::{self, AnchorRegistry};
+use crate::cancel::CancellationToken;
+use crate::changes::{fence_info, LineChanges};
+use crate::diffing::ChangeKind;
+use crate::file_header::{render_file_header, HeaderOptions};
+use crate::document_model::{content_hash, ChecklistItem, DocumentModel, FileEntry, ModelMetadata, Section, TocEntry, TocGroup, SCHEMA_VERSION};
+use crate::fragment_cache::FragmentCache;
+use crate::i18n::{self, Strings, ENGLISH};
+use crate::renderer::DocumentMetadata;
+use crate::tables::{self, TableLimits};
+use crate::todos::{count_by_marker, find_todos};
+use crate::warnings::{WarningCategory, Warnings};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
 use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// Longer code lines are broken up, so they don't overflow the page
+pub const MAX_LINE_LENGTH: usize = 100;
+
+/// Share of the memory limit past which chunked generation starts saving
+/// memory
+pub const LOW_MEMORY_RATIO: f64 = 0.8;
+
+/// How much of a large file its preview shows, normally and once memory
+/// runs low
+const PREVIEW_SIZE: usize = 100_000;
+const LOW_MEMORY_PREVIEW_SIZE: usize = 10_000;
+
+/// Once memory runs low, files over this many bytes are cut down too
+const LOW_MEMORY_MAX_FILE_SIZE: usize = 256 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct MarkdownGenerator {
     include_toc: bool,
     include_file_tree: bool,
+    /// Reuses the sections of unchanged files from earlier runs
+    fragments: Option<FragmentCache>,
+    /// Checked before each file's section
+    cancellation: CancellationToken,
+    /// Where truncated files and overlong lines are noted
+    warnings: Warnings,
+    /// Markers listed in a review checklist, none for no checklist
+    todo_markers: Vec<String>,
+    /// Headings and labels, in the document's language
+    strings: &'static Strings,
+    /// Which CSV and TSV files are shown as tables, none for code blocks
+    table_limits: Option<TableLimits>,
+    /// Rows of the table heading each file's section
+    header: HeaderOptions,
+    /// How files changed since an earlier document, by path, shown as
+    /// badges next to their headings
+    change_badges: BTreeMap<String, ChangeKind>,
+    /// How the table of contents lists the files
+    toc: TocOptions,
+}
+
+/// How the table of contents lists the files of a large document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocOptions {
+    /// Past this many files, list directories and how many files each
+    /// holds instead of the files
+    pub limit: Option<usize>,
+    /// How many directories deep those directories go
+    pub depth: usize,
+    /// When the files aren't listed, list them all after the contents
+    pub appendix: bool,
+}
+
+impl Default for TocOptions {
+    fn default() -> Self {
+        Self { limit: None, depth: 1, appendix: false }
+    }
+}
+
+impl TocOptions {
+    /// Whether a document of `files` files lists directories instead
+    pub fn collapses(&self, files: usize) -> bool {
+        self.limit.is_some_and(|limit| files > limit)
+    }
+}
+
+/// How `MarkdownGenerator::write_markdown_chunked` splits up the work
+#[derive(Debug, Clone)]
+pub struct ChunkOptions {
+    /// Files per chunk
+    pub chunk_size: usize,
+    /// Files over this many bytes are cut down to a preview, samples and a
+    /// summary
+    pub max_file_size: usize,
+    /// Checked before each file; see `MemoryLimit`
+    pub memory_limit: Option<MemoryLimit>,
+}
+
+/// Memory that chunked generation may use, in bytes, and how to measure
+/// it. Past `LOW_MEMORY_RATIO` of the limit, generation writes one file
+/// per chunk, flushes after every file and cuts large files down harder.
+/// If usage is still over the limit after that, it stops with a
+/// `MemoryLimitExceeded` error.
+#[derive(Clone)]
+pub struct MemoryLimit {
+    pub bytes: u64,
+    probe: Arc<dyn Fn() -> Option<u64> + Send + Sync>,
+}
+
+impl MemoryLimit {
+    /// A limit on this process's resident memory, as `resident_memory`
+    /// measures it
+    pub fn new(bytes: u64) -> Self {
+        Self { bytes, probe: Arc::new(resident_memory) }
+    }
+
+    /// Measure usage with `probe` instead, which returns `None` when it
+    /// can't tell
+    pub fn with_probe(mut self, probe: impl Fn() -> Option<u64> + Send + Sync + 'static) -> Self {
+        self.probe = Arc::new(probe);
+        self
+    }
+
+    pub fn usage(&self) -> Option<u64> {
+        (self.probe)()
+    }
+
+    fn is_low(&self, used: u64) -> bool {
+        used as f64 >= self.bytes as f64 * LOW_MEMORY_RATIO
+    }
+}
+
+impl fmt::Debug for MemoryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryLimit").field("bytes", &self.bytes).finish_non_exhaustive()
+    }
+}
+
+/// Resident memory of this process in bytes, where `/proc` reports it;
+/// `None` on other platforms, which leaves the limit unchecked
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// The error chunked generation stops with when memory use stays over its
+/// `MemoryLimit`, naming the file it had reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLimitExceeded {
+    pub file: String,
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Memory use ({} MB) is over the {} MB limit at {}",
+            self.used / 1024 / 1024, self.limit / 1024 / 1024, self.file
+        )
+    }
+}
+
+impl std::error::Error for MemoryLimitExceeded {}
+
+/// Progress through `MarkdownGenerator::write_markdown_chunked`
+#[derive(Debug, Clone, Copy)]
+pub enum ChunkEvent<'a> {
+    /// Chunk `index` of `count`, holding `files` files, is starting
+    Chunk { index: usize, count: usize, files: usize },
+    /// File `index` is next, cut down if `truncated`
+    File { index: usize, file: &'a FileInfo, truncated: bool },
+    /// Memory use reached `used` bytes of the `limit`; the rest of the
+    /// files are written one per chunk and cut down harder
+    LowMemory { used: u64, limit: u64 },
 }
 
 #[derive(Debug, Clone)]
 pub struct FileInfo {
     pub path: String,
-    pub content: String,
+    /// Shared, so cloning a file never copies what can be the bulk of the
+    /// repository
+    pub content: Arc<str>,
     pub language: Option<String>,
     pub size: usize,
+    /// Lines changed since a base revision, shaded in the output
+    pub changes: Option<LineChanges>,
+    /// What a binary file holds, such as "SQLite database"; `None` for text
+    pub binary_kind: Option<String>,
+    /// When the file was last modified, if its header shows it
+    pub modified: Option<DateTime<Utc>>,
+    /// Short id of the last commit touching the file, if its header shows it
+    pub commit: Option<String>,
 }
 
 impl MarkdownGenerator {
@@ -21,85 +202,455 @@ impl MarkdownGenerator {
         Self {
             include_toc,
             include_file_tree,
+            fragments: None,
+            cancellation: CancellationToken::new(),
+            warnings: Warnings::new(),
+            todo_markers: Vec::new(),
+            strings: &ENGLISH,
+            table_limits: None,
+            header: HeaderOptions::default(),
+            change_badges: BTreeMap::new(),
+            toc: TocOptions::default(),
         }
     }
 
-    pub fn generate_markdown(&self, files: &[FileInfo], repo_name: &str) -> Result<String> {
-        let mut markdown = String::new();
+    /// Keep each file's section in `cache`, so reruns only regenerate the
+    /// files that changed along with the table of contents and tree
+    pub fn with_fragment_cache(mut self, cache: FragmentCache) -> Self {
+        self.fragments = Some(cache);
+        self
+    }
 
-        // Title and metadata
-        markdown.push_str(&format!("# {}\n\n", repo_name));
-        markdown.push_str(&format!("Generated on: {}\n\n", chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")));
+    /// Stop generating, with a `Cancelled` error, once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
 
-        // Table of contents
-        if self.include_toc {
-            markdown.push_str("## Table of Contents\n\n");
-            for file in files {
-                let sanitized_path = file.path.replace(['/', '\\'], "-").replace('.', "-");
-                let escaped_path = self.escape_markdown_special_chars(&file.path);
-                markdown.push_str(&format!("- [{}](#{sanitized_path})\n", escaped_path));
-            }
-            markdown.push_str("\n");
+    /// Note truncated files and lines too long for the page in `warnings`
+    pub fn with_warnings(mut self, warnings: Warnings) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Write headings and labels in `language`, a tag such as `de` or
+    /// `pt-BR`, or in English when there's no translation for it
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.strings = i18n::strings_for(language);
+        self
+    }
+
+    /// Show CSV and TSV files within `limits` as tables rather than code
+    pub fn with_tables(mut self, limits: TableLimits) -> Self {
+        self.table_limits = Some(limits);
+        self
+    }
+
+    /// Head each file's section with the rows `options` picks
+    pub fn with_file_header(mut self, options: HeaderOptions) -> Self {
+        self.header = options;
+        self
+    }
+
+    /// List the files in the table of contents as `options` says
+    pub fn with_toc_options(mut self, options: TocOptions) -> Self {
+        self.toc = options;
+        self
+    }
+
+    /// Badge the headings of the files in `changes`, such as "new" next to
+    /// a file added since an earlier document
+    pub fn with_change_badges(mut self, changes: BTreeMap<String, ChangeKind>) -> Self {
+        self.change_badges = changes;
+        self
+    }
+
+    /// List where each of `markers`, such as `TODO`, appears in a Review
+    /// Checklist section ahead of the file contents, linking to the files
+    pub fn with_todo_index(mut self, markers: Vec<String>) -> Self {
+        self.todo_markers = markers;
+        self
+    }
+
+    /// Warn about the lines of the file at `path` over `MAX_LINE_LENGTH`, which are
+    /// `handled` one way or another
+    fn warn_long_lines(&self, path: &str, content: &str, handled: &str) {
+        let long_lines = content.lines().filter(|line| line.len() > MAX_LINE_LENGTH).count();
+        if long_lines > 0 {
+            let lines = if long_lines == 1 { "line" } else { "lines" };
+            self.warnings.push(
+                WarningCategory::LongLines,
+                Some(path),
+                format!("{} {} over {} characters {}", long_lines, lines, MAX_LINE_LENGTH, handled),
+            );
+        }
+    }
+
+    pub fn generate_markdown(&self, files: &[FileInfo], title: &str) -> Result<String> {
+        self.render_markdown(&self.build_document_model(files, title))
+    }
+
+    /// The document for `files` as data, with the sections it's going to
+    /// have in order and the anchor each file's section gets. Nothing is
+    /// rendered yet; see `render_markdown`.
+    pub fn build_document_model(&self, files: &[FileInfo], repo_name: &str) -> DocumentModel {
+        let mut anchors = AnchorRegistry::new();
+        for file in files {
+            anchors.assign(&file.path);
         }
+        let anchor = |path: &str| anchors.get(path).map(str::to_string).unwrap_or_default();
 
-        // File tree
+        let entries: Vec<TocEntry> = files.iter()
+            .map(|file| TocEntry { path: file.path.clone(), anchor: anchor(&file.path) })
+            .collect();
+        let collapsed = self.toc.collapses(files.len());
+
+        let mut sections = Vec::new();
+        if self.include_toc {
+            sections.push(Section::TableOfContents {
+                heading: self.strings.table_of_contents.to_string(),
+                entries: if collapsed { Vec::new() } else { entries.clone() },
+                groups: if collapsed { toc_groups(&entries, self.toc.depth) } else { Vec::new() },
+            });
+        }
         if self.include_file_tree {
-            markdown.push_str("## File Structure\n\n");
-            markdown.push_str("```\n");
-            markdown.push_str(&self.generate_file_tree(&files));
-            markdown.push_str("```\n\n");
+            sections.push(Section::FileTree {
+                heading: self.strings.file_structure.to_string(),
+                paths: files.iter().map(|file| file.path.clone()).collect(),
+            });
+        }
+        if !self.todo_markers.is_empty() {
+            let todos: Vec<_> = files.iter().flat_map(|file| find_todos(file, &self.todo_markers)).collect();
+            sections.push(Section::ReviewChecklist {
+                heading: self.strings.review_checklist.to_string(),
+                counts: count_by_marker(&todos, &self.todo_markers),
+                items: todos.into_iter()
+                    .map(|todo| ChecklistItem { anchor: anchor(&todo.path), todo })
+                    .collect(),
+            });
+        }
+        sections.push(Section::Files {
+            heading: self.strings.file_contents.to_string(),
+            files: files.iter()
+                .map(|file| FileEntry {
+                    path: file.path.clone(),
+                    anchor: anchor(&file.path),
+                    language: file.language.clone(),
+                    size: file.size,
+                    content_hash: content_hash(&file.content),
+                    binary_kind: file.binary_kind.clone(),
+                    modified: file.modified,
+                    commit: file.commit.clone(),
+                    changes: file.changes.clone(),
+                    content: file.content.clone(),
+                })
+                .collect(),
+        });
+        if self.include_toc && collapsed && self.toc.appendix {
+            sections.push(Section::FileIndex {
+                heading: self.strings.file_index.to_string(),
+                entries,
+            });
         }
 
-        // File contents
-        markdown.push_str("## File Contents\n\n");
-        
-        let _total_files = files.len();
-        let mut _global_page_number = 1; // Start after title/TOC page
-        
-        for (file_index, file) in files.iter().enumerate() {
-            let _file_counter = file_index + 1;
-            _global_page_number += 1; // Each file gets a new page
+        DocumentModel {
+            schema_version: SCHEMA_VERSION,
+            metadata: ModelMetadata {
+                title: repo_name.to_string(),
+                language: self.strings.code.to_string(),
+                generated_at: Utc::now(),
+            },
+            anchors,
+            sections,
+        }
+    }
+
+    /// The markdown for `model`, with labels in this generator's language.
+    /// Each file starts on a new page, with long code lines broken up.
+    pub fn render_markdown(&self, model: &DocumentModel) -> Result<String> {
+        let mut markdown = self.front_matter_sections(model);
+
+        for (file_index, file) in model.files().iter().enumerate() {
+            self.cancellation.check()?;
             
             // Add page break before each file (except the first one)
             if file_index > 0 {
                 markdown.push_str("\n\\newpage\n\n");
             }
-            let sanitized_path = file.path.replace(['/', '\\'], "-").replace('.', "-");
-            let escaped_path = self.escape_markdown_special_chars(&file.path);
-            markdown.push_str(&format!("### {} {{#{sanitized_path}}}\n\n", escaped_path));
-            markdown.push_str(&format!("**Size:** {}\n\n", MarkdownGenerator::format_file_size(file.size)));
-            
-            // Handle markdown files differently - render them directly without code blocks
-            if file.path.ends_with(".md") || file.path.ends_with(".markdown") {
-                // Process content to prevent LaTeX errors
-                let processed_content = self.process_content_for_latex(&file.content);
-                markdown.push_str(&processed_content);
-                if !processed_content.ends_with('\n') {
-                    markdown.push('\n');
+            self.warn_long_lines(&file.path, &file.content, "broken up");
+            match &self.fragments {
+                Some(cache) => {
+                    let changes = file.changes.as_ref().map(|changes| format!("{:?}", changes)).unwrap_or_default();
+                    let key = FragmentCache::key("markdown", &[
+                        &file.path,
+                        &file.content,
+                        file.language.as_deref().unwrap_or_default(),
+                        &file.size.to_string(),
+                        &changes,
+                        self.strings.code,
+                        &format!("{:?}", self.table_limits),
+                        &format!("{:?} {:?} {:?}", self.header, file.modified, file.commit),
+                        &format!("{:?}", self.change_badges.get(&file.path)),
+                    ]);
+                    markdown.push_str(&cache.get_or_insert_with(&key, || self.file_section(file)));
                 }
-            } else {
-                // For code files, wrap in code blocks with language highlighting
-                if let Some(language) = &file.language {
-                    markdown.push_str(&format!("```{}\n", language));
+                None => markdown.push_str(&self.file_section(file)),
+            }
+        }
+        markdown.push_str(&self.back_matter_sections(model));
+
+        Ok(markdown)
+    }
+
+    /// Write the document for `files` to `sink` a chunk of files at a time,
+    /// holding no more than one file's section in memory. Unlike
+    /// `generate_markdown`, every file starts on a new page, files over
+    /// `max_file_size` are cut down to a preview and long lines are kept
+    /// whole. Memory use is checked against `memory_limit` before each
+    /// file. `on_event` hears about each chunk and file as it's reached.
+    pub fn write_markdown_chunked<W: Write>(
+        &self,
+        files: &[FileInfo],
+        title: &str,
+        sink: &mut W,
+        options: &ChunkOptions,
+        on_event: impl FnMut(ChunkEvent),
+    ) -> Result<()> {
+        self.write_model_chunked(&self.build_document_model(files, title), files, sink, options, on_event)
+    }
+
+    /// `write_markdown_chunked` for a `model` already built from `files`
+    pub fn write_model_chunked<W: Write>(
+        &self,
+        model: &DocumentModel,
+        files: &[FileInfo],
+        sink: &mut W,
+        options: &ChunkOptions,
+        mut on_event: impl FnMut(ChunkEvent),
+    ) -> Result<()> {
+        sink.write_all(self.front_matter_sections(model).as_bytes())?;
+
+        let entries = model.files();
+        let mut chunk_size = options.chunk_size.max(1);
+        let mut max_file_size = options.max_file_size;
+        let mut preview_size = PREVIEW_SIZE;
+        let mut low_memory = false;
+        let mut file_index = 0;
+        let mut chunk_index = 0;
+        while file_index < files.len() {
+            let chunk = &files[file_index..(file_index + chunk_size).min(files.len())];
+            let count = chunk_index + (files.len() - file_index).div_ceil(chunk_size);
+            on_event(ChunkEvent::Chunk { index: chunk_index, count, files: chunk.len() });
+            chunk_index += 1;
+            for file in chunk {
+                self.cancellation.check()?;
+                if let Some(limit) = &options.memory_limit {
+                    let mut used = limit.usage();
+                    if let Some(reading) = used.filter(|&reading| !low_memory && limit.is_low(reading)) {
+                        on_event(ChunkEvent::LowMemory { used: reading, limit: limit.bytes });
+                        low_memory = true;
+                        chunk_size = 1;
+                        max_file_size = max_file_size.min(LOW_MEMORY_MAX_FILE_SIZE);
+                        preview_size = LOW_MEMORY_PREVIEW_SIZE;
+                        sink.flush()?;
+                        used = limit.usage();
+                    }
+                    if let Some(used) = used.filter(|&used| used > limit.bytes) {
+                        return Err(MemoryLimitExceeded { file: file.path.clone(), used, limit: limit.bytes }.into());
+                    }
+                }
+
+                let truncated = file.size > max_file_size;
+                on_event(ChunkEvent::File { index: file_index, file, truncated });
+                let entry = &entries[file_index];
+                file_index += 1;
+
+                let content = if truncated {
+                    self.warnings.push(
+                        WarningCategory::Truncated,
+                        Some(&file.path),
+                        format!("Cut down from {} to a preview", MarkdownGenerator::format_file_size(file.size)),
+                    );
+                    truncate_to_preview(&entry.content, entry.size, preview_size, self.strings)
                 } else {
-                    markdown.push_str("```\n");
+                    Cow::Borrowed(&*entry.content)
+                };
+                self.warn_long_lines(&file.path, &content, "kept whole");
+                sink.write_all(b"\n\\newpage\n\n")?;
+                self.write_section(sink, entry, &content, false, truncated)?;
+                if low_memory {
+                    sink.flush()?;
                 }
-                
-                // Process content to prevent LaTeX errors
-                let processed_content = self.process_content_for_latex(&file.content);
-                markdown.push_str(&processed_content);
-                
-                // Ensure there's always a newline before closing backticks
-                if !processed_content.ends_with('\n') {
+            }
+        }
+        sink.write_all(self.back_matter_sections(model).as_bytes())?;
+        Ok(())
+    }
+
+    /// Title, generation time and every section of `model` ahead of the
+    /// files, up to the heading the file sections go under
+    fn front_matter_sections(&self, model: &DocumentModel) -> String {
+        let mut markdown = String::new();
+
+        // Title and metadata
+        markdown.push_str(&format!("# {}\n\n", model.metadata.title));
+        markdown.push_str(&format!("{} {}\n\n", self.strings.generated_on, model.metadata.generated_at.format("%Y-%m-%d %H:%M:%S UTC")));
+
+        for section in &model.sections {
+            match section {
+                Section::TableOfContents { heading, entries, groups } => {
+                    markdown.push_str(&format!("## {} {{#{}}}\n\n", heading, section.anchor()));
+                    markdown.push_str(&self.file_list(entries));
+                    for group in groups {
+                        let escaped_directory = self.escape_markdown_special_chars(&group.directory);
+                        markdown.push_str(&format!("- [{}](#{}) ({})\n", escaped_directory, group.anchor, self.strings.files(group.files)));
+                    }
                     markdown.push('\n');
                 }
-                
-                markdown.push_str("```\n\n");
+                Section::FileTree { heading, paths } => {
+                    markdown.push_str(&format!("## {} {{#{}}}\n\n", heading, section.anchor()));
+                    markdown.push_str("```\n");
+                    for path in paths {
+                        markdown.push_str(&format!("{}\n", path));
+                    }
+                    markdown.push_str("```\n\n");
+                }
+                Section::ReviewChecklist { heading, counts, items } => {
+                    markdown.push_str(&self.review_checklist(heading, counts, items));
+                }
+                Section::Files { heading, .. } => {
+                    markdown.push_str(&format!("## {} {{#{}}}\n\n", heading, section.anchor()));
+                }
+                Section::FileIndex { .. } => {}
             }
-            markdown.push_str("---\n\n");
         }
+        markdown
+    }
 
-        Ok(markdown)
+    /// The sections of `model` after the files, each on a new page
+    fn back_matter_sections(&self, model: &DocumentModel) -> String {
+        let mut markdown = String::new();
+        for section in &model.sections {
+            if let Section::FileIndex { heading, entries } = section {
+                markdown.push_str(&format!("\n\\newpage\n\n## {} {{#{}}}\n\n", heading, section.anchor()));
+                markdown.push_str(&self.file_list(entries));
+                markdown.push('\n');
+            }
+        }
+        markdown
+    }
+
+    /// A list of links to the sections of the files in `entries`
+    fn file_list(&self, entries: &[TocEntry]) -> String {
+        entries.iter()
+            .map(|entry| format!("- [{}](#{})\n", self.escape_markdown_special_chars(&entry.path), entry.anchor))
+            .collect()
+    }
+
+    /// A table of every review marker in `items`, after a count of each
+    fn review_checklist(&self, heading: &str, counts: &BTreeMap<String, usize>, items: &[ChecklistItem]) -> String {
+        let strings = self.strings;
+        let mut markdown = format!("## {} {{#{}}}\n\n", heading, anchors::REVIEW_CHECKLIST);
+        let counts: Vec<String> = counts.iter()
+            .map(|(marker, count)| format!("**{}:** {}", self.escape_markdown_special_chars(marker), count))
+            .collect();
+        markdown.push_str(&format!("{}\n\n", counts.join(" · ")));
+        if items.is_empty() {
+            return markdown;
+        }
+        markdown.push_str(&format!("| {} | {} | {} |\n|---|---|---|\n", strings.marker, strings.location, strings.note));
+        for ChecklistItem { todo, anchor } in items {
+            markdown.push_str(&format!(
+                "| {} | [{}:{}](#{}) | {} |\n",
+                table_cell(&todo.marker),
+                self.escape_markdown_special_chars(&table_cell(&todo.path)),
+                todo.line,
+                anchor,
+                self.escape_markdown_special_chars(&table_cell(&todo.text)),
+            ));
+        }
+        markdown.push('\n');
+        markdown
+    }
+
+    /// The heading and contents of one file
+    fn file_section(&self, file: &FileEntry) -> String {
+        let mut section = Vec::new();
+        self.write_section(&mut section, file, &file.content, true, false)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(section).expect("sections are built from strings")
+    }
+
+    /// Write the heading, header and `content` of `file` to `sink`, with
+    /// long code lines broken up when `break_lines` is set. `truncated`
+    /// says whether `content` is a preview.
+    fn write_section<W: Write + ?Sized>(&self, sink: &mut W, file: &FileEntry, content: &str, break_lines: bool, truncated: bool) -> std::io::Result<()> {
+        let escaped_path = self.escape_markdown_special_chars(&file.path);
+        let badge = self.change_badges.get(&file.path)
+            .and_then(|change| change.badge(self.strings))
+            .map(|badge| format!(" *({})*", badge))
+            .unwrap_or_default();
+        write!(sink, "### {}{} {{#{}}}\n\n", escaped_path, badge, file.anchor)?;
+        sink.write_all(render_file_header(file, &self.header, self.strings, truncated).as_bytes())?;
+        
+        // Process content to prevent LaTeX errors
+        let processed_content = if break_lines {
+            Cow::Owned(self.process_content_for_latex(content))
+        } else {
+            Cow::Borrowed(content)
+        };
+        // Ensure there's always a newline at the end, before closing backticks
+        let newline: &[u8] = if processed_content.ends_with('\n') { b"" } else { b"\n" };
+
+        let table = match (&self.table_limits, tables::delimiter(&file.path)) {
+            (Some(limits), Some(delimiter)) => tables::parse(content, delimiter, limits).ok(),
+            _ => None,
+        };
+
+        let blank = file.binary_kind.is_none().then(|| blank_line_count(content)).flatten();
+
+        if let Some(lines) = blank {
+            // A note in place of a fence with nothing in it
+            let note = if lines == 0 { self.strings.empty_file.to_string() } else { self.strings.blank_lines(lines) };
+            write!(sink, "*{}*\n\n", note)?;
+        } else if let Some(rows) = table {
+            self.write_table(sink, &rows)?;
+        } else if file.path.ends_with(".md") || file.path.ends_with(".markdown") {
+            // Handle markdown files differently - render them directly without code blocks
+            sink.write_all(processed_content.as_bytes())?;
+            sink.write_all(newline)?;
+        } else {
+            // For code files, wrap in code blocks with language highlighting,
+            // following changed lines onto the lines they're broken into
+            let changes = match &file.changes {
+                Some(changes) if break_lines => Some(Cow::Owned(
+                    changes.rewrapped(content.lines().map(|line| self.break_long_line(line).len())),
+                )),
+                changes => changes.as_ref().map(Cow::Borrowed),
+            };
+            writeln!(sink, "```{}", fence_info(file.language.as_deref(), changes.as_deref()))?;
+            sink.write_all(processed_content.as_bytes())?;
+            sink.write_all(newline)?;
+            sink.write_all(b"```\n\n")?;
+        }
+        sink.write_all(b"---\n\n")
+    }
+
+    /// `rows` as a markdown table under a bold header row
+    fn write_table<W: Write + ?Sized>(&self, sink: &mut W, rows: &[Vec<String>]) -> std::io::Result<()> {
+        let cell = |text: &String| self.escape_markdown_special_chars(&table_cell(text));
+        let Some((header, rows)) = rows.split_first() else {
+            return Ok(());
+        };
+        let header: Vec<String> = header.iter()
+            .map(|text| if text.is_empty() { String::new() } else { format!("**{}**", cell(text)) })
+            .collect();
+        writeln!(sink, "| {} |", header.join(" | "))?;
+        writeln!(sink, "|{}", "---|".repeat(header.len()))?;
+        for row in rows {
+            writeln!(sink, "| {} |", row.iter().map(cell).collect::<Vec<_>>().join(" | "))?;
+        }
+        writeln!(sink)
     }
 
     pub fn format_file_size(size: usize) -> String {
@@ -119,29 +670,6 @@ impl MarkdownGenerator {
         }
     }
 
-    fn generate_file_tree(&self, files: &[FileInfo]) -> String {
-        let mut tree = String::new();
-        let mut dirs: HashMap<String, Vec<&str>> = HashMap::new();
-        
-        // Group files by directory
-        for file in files {
-            let path = Path::new(&file.path);
-            if let Some(parent) = path.parent() {
-                let parent_str = parent.to_string_lossy().to_string();
-                dirs.entry(parent_str).or_default().push(&file.path);
-            } else {
-                dirs.entry(".".to_string()).or_default().push(&file.path);
-            }
-        }
-
-        // Simple tree representation
-        for file in files {
-            tree.push_str(&format!("{}\n", file.path));
-        }
-
-        tree
-    }
-
     pub fn detect_language(file_path: &str) -> Option<String> {
         let path = Path::new(file_path);
         
@@ -196,22 +724,104 @@ impl MarkdownGenerator {
             "sol" => "solidity",
             "vy" => "python",     // Vyper (use python highlighting as fallback)
             "move" => "rust",     // Move language (use rust as fallback)
+            "zig" => "zig",
+            "nim" | "nims" | "nimble" => "nim",
+            "ex" | "exs" => "elixir",
+            "erl" | "hrl" => "erlang",
+            "hs" => "haskell",
+            "ml" | "mli" => "ocaml",
+            "svelte" => "svelte",
+            "vue" => "vue",
+            "graphql" | "gql" => "graphql",
+            "proto" => "protobuf",
+            "tf" | "tfvars" | "hcl" => "terraform",
+            "cue" => "cue",
+            "nix" => "nix",
+            "dart" => "dart",
+            "gradle" => "gradle",
+            "cmake" => "cmake",
+            "ps1" | "psm1" | "psd1" => "powershell",
+            "bat" | "cmd" => "batch",
+            "csv" => "csv",
+            "ini" | "cfg" => "ini",
+            "properties" => "properties",
             _ => return None,
         };
 
         Some(language.to_string())
     }
 
+    /// Detect the language from the path, falling back to the shebang line or
+    /// a vim/emacs modeline for files without a recognized extension
+    pub fn detect_language_with_content(file_path: &str, content: &str) -> Option<String> {
+        Self::detect_language(file_path)
+            .or_else(|| Self::language_from_shebang(content))
+            .or_else(|| Self::language_from_modeline(content))
+    }
+
+    /// `#!/usr/bin/env python3`, `#!/bin/bash`, `#!/usr/bin/env -S node --flag`, ...
+    fn language_from_shebang(content: &str) -> Option<String> {
+        let line = content.lines().next()?.strip_prefix("#!")?;
+        let mut words = line.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            // Skip env's own options and VAR=value assignments
+            program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        }
+        Self::language_from_name(program)
+    }
+
+    /// `# vim: ft=yaml`, `/* vim: set filetype=c : */` or `-*- mode: ruby -*-`
+    /// in the first or last five lines
+    fn language_from_modeline(content: &str) -> Option<String> {
+        static VIM: OnceLock<Regex> = OnceLock::new();
+        static EMACS: OnceLock<Regex> = OnceLock::new();
+        let vim = VIM.get_or_init(|| {
+            Regex::new(r"(?:^|\s)(?:vim?|ex):.*?\b(?:ft|filetype|syn|syntax)=([\w+-]+)").unwrap()
+        });
+        let emacs = EMACS.get_or_init(|| {
+            Regex::new(r"-\*-\s*(?:.*?\bmode:\s*([\w+-]+)|([\w+-]+)\s*-\*-)").unwrap()
+        });
+
+        let lines: Vec<&str> = content.lines().collect();
+        let tail = lines.len().saturating_sub(5).max(5.min(lines.len()));
+        lines[..5.min(lines.len())].iter()
+            .chain(&lines[tail..])
+            .find_map(|line| {
+                let name = vim.captures(line)
+                    .or_else(|| emacs.captures(line))
+                    .and_then(|caps| caps.get(1).or_else(|| caps.get(2)))?;
+                Self::language_from_name(name.as_str())
+            })
+    }
+
+    /// Map an interpreter or editor mode name (with any version suffix, as in
+    /// `python3.11`) to a language
+    fn language_from_name(name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        let base = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let language = match base {
+            "python" | "pypy" => "python",
+            "sh" | "bash" | "dash" | "ksh" | "ash" | "shell-script" => "bash",
+            "zsh" => "zsh",
+            "fish" => "fish",
+            "node" | "nodejs" | "deno" | "bun" | "js" => "javascript",
+            "ts-node" => "typescript",
+            "ruby" => "ruby",
+            "perl" => "perl",
+            "php" => "php",
+            "lua" | "luajit" => "lua",
+            "rscript" => "r",
+            "make" => "makefile",
+            "rust" | "javascript" | "typescript" | "markdown" | "dockerfile" | "makefile" => base,
+            // Editor modes are usually named after an extension
+            _ => return Self::detect_language(&format!("modeline.{}", base)),
+        };
+        Some(language.to_string())
+    }
+
     fn escape_markdown_special_chars(&self, text: &str) -> String {
-        // Escape characters that have special meaning in markdown/LaTeX outside code blocks
-        text.replace('_', "\\_")     // Escape underscores that could be interpreted as emphasis
-            .replace('#', "\\#")     // Escape hash symbols
-            .replace('$', "\\$")     // Escape dollar signs (LaTeX math mode)
-            .replace('%', "\\%")     // Escape percent signs (LaTeX comments)
-            .replace('&', "\\&")     // Escape ampersands
-            .replace('^', "\\^")     // Escape carets
-            .replace('{', "\\{")     // Escape curly braces
-            .replace('}', "\\}")
+        escape_markdown(text)
     }
 
     fn process_content_for_latex(&self, content: &str) -> String {
@@ -220,37 +830,192 @@ impl MarkdownGenerator {
         let mut processed_lines = Vec::new();
         
         for line in lines {
-            if line.len() > 100 {
-                // Break long lines at reasonable breakpoints
-                let mut current_line = String::new();
-                let chars: Vec<char> = line.chars().collect();
-                
-                for (_i, &ch) in chars.iter().enumerate() {
-                    current_line.push(ch);
-                    
-                    // Break at 100 characters or at natural breakpoints
-                    if current_line.len() >= 100 && (ch == ' ' || ch == ',' || ch == ';' || ch == ')' || ch == '}') {
-                        processed_lines.push(current_line.clone());
-                        current_line.clear();
-                    }
-                }
-                
-                // Add remaining characters
-                if !current_line.is_empty() {
-                    processed_lines.push(current_line);
-                }
-            } else {
-                processed_lines.push(line.to_string());
-            }
+            processed_lines.extend(self.break_long_line(line));
         }
         
         processed_lines.join("\n")
     }
+
+    fn break_long_line(&self, line: &str) -> Vec<String> {
+        if line.len() <= MAX_LINE_LENGTH {
+            return vec![line.to_string()];
+        }
+        
+        // Break long lines at reasonable breakpoints
+        let mut pieces = Vec::new();
+        let mut current_line = String::new();
+        
+        for ch in line.chars() {
+            current_line.push(ch);
+            
+            // Break at 100 characters or at natural breakpoints
+            if current_line.len() >= MAX_LINE_LENGTH && (ch == ' ' || ch == ',' || ch == ';' || ch == ')' || ch == '}') {
+                pieces.push(current_line.clone());
+                current_line.clear();
+            }
+        }
+        
+        // Add remaining characters
+        if !current_line.is_empty() {
+            pieces.push(current_line);
+        }
+        pieces
+    }
+}
+
+/// The directory a collapsed table of contents lists a file in `directory`
+/// under: its first `depth` directories, such as `src/` for `src/api` at
+/// depth 1, or `./` at the top
+pub fn toc_directory(directory: &str, depth: usize) -> String {
+    let directories: Vec<&str> = directory.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .take(depth)
+        .collect();
+    if directories.is_empty() {
+        "./".to_string()
+    } else {
+        format!("{}/", directories.join("/"))
+    }
+}
+
+/// The directory of the file at `path`, empty at the top
+pub fn parent_directory(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(directory, _)| directory)
+}
+
+/// `entries` collapsed into the directories `depth` deep that hold them, in
+/// the order the directories first come up, each linking to its first file
+fn toc_groups(entries: &[TocEntry], depth: usize) -> Vec<TocGroup> {
+    let mut groups: Vec<TocGroup> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let directory = toc_directory(parent_directory(&entry.path), depth);
+        match positions.get(&directory) {
+            Some(&position) => groups[position].files += 1,
+            None => {
+                positions.insert(directory.clone(), groups.len());
+                groups.push(TocGroup { directory, files: 1, anchor: entry.anchor.clone() });
+            }
+        }
+    }
+    groups
+}
+
+/// How many lines `content` has if there's nothing in it but whitespace,
+/// counting a byte order mark as whitespace, so 0 for an empty file
+pub(crate) fn blank_line_count(content: &str) -> Option<usize> {
+    let content = content.trim_start_matches('\u{feff}');
+    content.trim().is_empty().then(|| content.lines().count())
+}
+
+/// `text` with the characters that mean something in markdown or LaTeX
+/// outside code blocks escaped
+pub(crate) fn escape_markdown(text: &str) -> String {
+    text.replace('_', "\\_")     // Escape underscores that could be interpreted as emphasis
+        .replace('#', "\\#")     // Escape hash symbols
+        .replace('$', "\\$")     // Escape dollar signs (LaTeX math mode)
+        .replace('%', "\\%")     // Escape percent signs (LaTeX comments)
+        .replace('&', "\\&")     // Escape ampersands
+        .replace('^', "\\^")     // Escape carets
+        .replace('{', "\\{")     // Escape curly braces
+        .replace('}', "\\}")
+}
+
+/// `text` made safe for a markdown table cell
+pub(crate) fn table_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace('\n', " ")
+}
+
+/// A preview of a file too large to include whole: the first 100 KB, a few
+/// samples from further in and a summary of what was left out. Content
+/// that already fits is borrowed as it is.
+pub fn truncate_large_file_content(content: &str, original_size: usize) -> Cow<'_, str> {
+    truncate_to_preview(content, original_size, PREVIEW_SIZE, &ENGLISH)
+}
+
+/// `truncate_large_file_content` with the first `preview_size` bytes,
+/// samples a tenth of that and the notes in between written with `strings`
+fn truncate_to_preview<'a>(content: &'a str, original_size: usize, preview_size: usize, strings: &Strings) -> Cow<'a, str> {
+    let sample_size = preview_size / 10;
+    const MAX_SAMPLES: usize = 5;
+    
+    if content.len() <= preview_size {
+        return Cow::Borrowed(content);
+    }
+    
+    let mut result = String::new();
+    
+    // Add first part
+    let preview_end = std::cmp::min(preview_size, content.len());
+    result.push_str(&content[..preview_end]);
+    result.push_str("\n\n");
+    result.push_str(&strings.continues(&MarkdownGenerator::format_file_size(original_size - preview_end)));
+    result.push_str("\n\n");
+    
+    // Add samples from the middle and end
+    let remaining = content.len() - preview_end;
+    if remaining > sample_size * 2 {
+        for i in 1..=MAX_SAMPLES {
+            let sample_start = preview_end + (remaining * i) / (MAX_SAMPLES + 1);
+            let sample_end = std::cmp::min(sample_start + sample_size, content.len());
+            
+            if sample_start < content.len() {
+                result.push_str(&format!("\n--- {} ---\n", strings.sample(i, (sample_start * 100) / content.len())));
+                result.push_str(&content[sample_start..sample_end]);
+                result.push('\n');
+            }
+        }
+    }
+    
+    // Add summary
+    result.push_str(&format!("\n\n--- {} ---\n", strings.file_summary));
+    result.push_str(&format!("{} {}\n", strings.total_size, MarkdownGenerator::format_file_size(original_size)));
+    let lines_shown = strings.lines_shown(result.lines().count(), content.lines().count());
+    result.push_str(&lines_shown);
+    result.push('\n');
+    result.push_str(strings.truncated);
+    result.push('\n');
+    
+    Cow::Owned(result)
+}
+
+/// YAML front matter naming the document's title, author, language and
+/// date, for markdown output read by static site generators and Pandoc
+pub fn front_matter(metadata: &DocumentMetadata) -> String {
+    let mut yaml = String::from("---\n");
+    yaml.push_str(&format!("title: {}\n", yaml_string(&metadata.title)));
+    if let Some(author) = &metadata.author {
+        yaml.push_str(&format!("author: {}\n", yaml_string(author)));
+    }
+    yaml.push_str(&format!("lang: {}\n", yaml_string(&metadata.language)));
+    if let Some(date) = metadata.date_label() {
+        yaml.push_str(&format!("date: {}\n", yaml_string(&date)));
+    }
+    yaml.push_str("---\n\n");
+    yaml
+}
+
+/// `text` as a double-quoted YAML scalar
+fn yaml_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
 
     #[test]
     fn test_language_detection() {
@@ -260,15 +1025,66 @@ mod tests {
         assert_eq!(MarkdownGenerator::detect_language("unknown.xyz"), None);
     }
 
+    #[test]
+    fn test_language_detection_modern_ecosystems() {
+        let cases = [
+            ("build.zig", "zig"), ("main.nim", "nim"), ("lib/app.ex", "elixir"),
+            ("test/app_test.exs", "elixir"), ("src/server.erl", "erlang"), ("Main.hs", "haskell"),
+            ("parser.ml", "ocaml"), ("App.svelte", "svelte"), ("App.vue", "vue"),
+            ("schema.graphql", "graphql"), ("query.gql", "graphql"), ("api.proto", "protobuf"),
+            ("main.tf", "terraform"), ("config.hcl", "terraform"), ("schema.cue", "cue"),
+            ("flake.nix", "nix"), ("main.dart", "dart"), ("build.gradle", "gradle"),
+            ("deps.cmake", "cmake"), ("Module.psm1", "powershell"), ("build.bat", "batch"),
+            ("data.csv", "csv"), ("setup.ini", "ini"), ("app.properties", "properties"),
+        ];
+        for (path, language) in cases {
+            assert_eq!(MarkdownGenerator::detect_language(path).as_deref(), Some(language), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_language_detection_from_shebang() {
+        let detect = MarkdownGenerator::detect_language_with_content;
+        assert_eq!(detect("bin/deploy", "#!/usr/bin/env python3\nprint('hi')\n"), Some("python".to_string()));
+        assert_eq!(detect("tools/release", "#!/bin/bash\nset -e\n"), Some("bash".to_string()));
+        assert_eq!(detect("bin/serve", "#!/usr/bin/env -S node --no-warnings\n"), Some("javascript".to_string()));
+        assert_eq!(detect("bin/check", "#!/usr/local/bin/python3.11 -u\n"), Some("python".to_string()));
+        assert_eq!(detect("bin/run", "#!/usr/bin/env RUBYOPT=-w ruby\n"), Some("ruby".to_string()));
+        // The extension still wins
+        assert_eq!(detect("build.rs", "#!/usr/bin/env python3\n"), Some("rust".to_string()));
+        assert_eq!(detect("notes", "just some text\n"), None);
+    }
+
+    #[test]
+    fn test_language_detection_from_modeline() {
+        let detect = MarkdownGenerator::detect_language_with_content;
+        let mut config = "name: deploy\nsteps:\n".repeat(10);
+        config.push_str("# vim: ft=yaml\n");
+        assert_eq!(detect("deploy.conf", &config), Some("yaml".to_string()));
+        assert_eq!(detect("Rakefile", "# -*- mode: ruby -*-\ntask :default\n"), Some("ruby".to_string()));
+        assert_eq!(detect("config", "/* vim: set filetype=c : */\nint x;\n"), Some("c".to_string()));
+        assert_eq!(detect("script", "# -*- python -*-\n"), Some("python".to_string()));
+
+        // Modelines in the middle of a long file are ignored
+        let mut long = "line\n".repeat(10);
+        long.push_str("# vim: ft=yaml\n");
+        long.push_str(&"line\n".repeat(10));
+        assert_eq!(detect("middle", &long), None);
+    }
+
     #[test]
     fn test_markdown_generation() {
         let generator = MarkdownGenerator::new(true, true);
         let files = vec![
             FileInfo {
                 path: "main.rs".to_string(),
-                content: "fn main() {\n    println!(\"Hello, world!\");\n}".to_string(),
+                content: "fn main() {\n    println!(\"Hello, world!\");\n}".into(),
                 language: Some("rust".to_string()),
                 size: 44,
+                changes: None,
+                binary_kind: None,
+                modified: None,
+                commit: None,
             }
         ];
 
@@ -279,4 +1095,381 @@ mod tests {
         assert!(markdown.contains("### main.rs"));
         assert!(markdown.contains("```rust"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cached_sections_follow_the_language() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = FragmentCache::new(temp_dir.path());
+        let files = vec![FileInfo {
+            path: "main.rs".to_string(),
+            content: "fn main() {}\n".into(),
+            language: Some("rust".to_string()),
+            size: 13,
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }];
+
+        let generate = |language: &str| MarkdownGenerator::new(false, false)
+            .with_fragment_cache(cache.clone())
+            .with_language(language)
+            .generate_markdown(&files, "demo")
+            .unwrap();
+        assert!(generate("en").contains("| **Size:** | 13 B |"));
+        assert!(generate("de").contains("| **Größe:** | 13 B |"));
+    }
+
+    #[test]
+    fn test_document_model_round_trips() -> Result<()> {
+        let mut files = crate::file_processor::FileProcessor::new().process_directory(Path::new("testfiles/todo_project"))?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut changes = LineChanges::default();
+        changes.insert(2, crate::changes::LineChange::Added);
+        files[0].changes = Some(changes);
+        let generator = MarkdownGenerator::new(true, true).with_todo_index(crate::todos::parse_markers(""));
+
+        let model = generator.build_document_model(&files, "todo_project");
+        let restored = DocumentModel::from_json(&model.to_json()?)?;
+        assert_eq!(restored, model);
+        assert_eq!(generator.render_markdown(&restored)?, generator.render_markdown(&model)?);
+
+        let json: serde_json::Value = serde_json::from_str(&model.to_json()?)?;
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        let kinds: Vec<&str> = json["sections"].as_array().unwrap().iter().map(|section| section["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["table_of_contents", "file_tree", "review_checklist", "files"]);
+        let first = &json["sections"][3]["files"][0];
+        assert_eq!(first["anchor"], crate::anchors::slugify(&files[0].path));
+        assert_eq!(json["anchors"][&files[0].path], first["anchor"]);
+        assert_eq!(first["content_hash"], content_hash(&files[0].content));
+        assert_eq!(first["changes"], serde_json::json!({ "added": [2], "modified": [] }));
+
+        let mut chunked = Vec::new();
+        let options = ChunkOptions { chunk_size: 2, max_file_size: usize::MAX, memory_limit: None };
+        generator.write_model_chunked(&restored, &files, &mut chunked, &options, |_| {})?;
+        assert!(String::from_utf8(chunked)?.starts_with(&generator.front_matter_sections(&model)));
+
+        let future = json.to_string().replacen(&format!("\"schema_version\":{}", SCHEMA_VERSION), "\"schema_version\":99", 1);
+        assert!(DocumentModel::from_json(&future).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_front_matter_quotes_metadata() {
+        let metadata = DocumentMetadata {
+            title: "Payments \"v2\"".to_string(),
+            author: Some("Zoë Ðurić".to_string()),
+            date: Some(chrono::Utc.with_ymd_and_hms(2024, 3, 1, 23, 59, 0).unwrap()),
+            language: "de".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            front_matter(&metadata),
+            "---\ntitle: \"Payments \\\"v2\\\"\"\nauthor: \"Zoë Ðurić\"\nlang: \"de\"\ndate: \"2024-03-01\"\n---\n\n"
+        );
+
+        let metadata = DocumentMetadata { title: "a\\b".to_string(), date: None, ..Default::default() };
+        assert_eq!(front_matter(&metadata), "---\ntitle: \"a\\\\b\"\nlang: \"en\"\n---\n\n");
+    }
+
+    #[test]
+    fn test_chunked_markdown_matches_snapshot() -> Result<()> {
+        let files = crate::file_processor::FileProcessor::new().process_directory(Path::new("testfiles/test_project"))?;
+        let mut events = Vec::new();
+        let mut markdown = Vec::new();
+        let options = ChunkOptions { chunk_size: 2, max_file_size: 50 * 1024 * 1024, memory_limit: None };
+        MarkdownGenerator::new(true, true).write_markdown_chunked(&files, "test_project", &mut markdown, &options, |event| {
+            events.push(match event {
+                ChunkEvent::Chunk { index, count, files } => format!("chunk {}/{} of {}", index + 1, count, files),
+                ChunkEvent::File { index, file, truncated } => format!("file {} {} {}", index, file.path, truncated),
+                ChunkEvent::LowMemory { .. } => unreachable!(),
+            });
+        })?;
+        assert_eq!(events, [
+            "chunk 1/2 of 2", "file 0 README.md false", "file 1 Token.sol false",
+            "chunk 2/2 of 1", "file 2 main.rs false",
+        ]);
+
+        // Written by the binary's own chunked writer before it moved here
+        let generated = Regex::new(r"(?m)^Generated on: .*$").unwrap();
+        let markdown = String::from_utf8(markdown)?;
+        let markdown = generated.replace(&markdown, "Generated on: 2025-01-01 00:00:00 UTC");
+        assert_eq!(markdown, include_str!("../testfiles/snapshots/chunked_test_project.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_and_blank_files_get_a_note_in_both_writers() -> Result<()> {
+        let files = crate::file_processor::FileProcessor::new().process_directory(Path::new("testfiles/empty_project"))?;
+        let generator = MarkdownGenerator::new(true, true);
+        let mut chunked = Vec::new();
+        let options = ChunkOptions { chunk_size: 2, max_file_size: 50 * 1024 * 1024, memory_limit: None };
+        generator.write_markdown_chunked(&files, "empty_project", &mut chunked, &options, |_| {})?;
+
+        let generated = Regex::new(r"(?m)^Generated on: .*$").unwrap();
+        let chunked = String::from_utf8(chunked)?;
+        let chunked = generated.replace(&chunked, "Generated on: 2025-01-01 00:00:00 UTC");
+        assert_eq!(chunked, include_str!("../testfiles/snapshots/chunked_empty_project.md"));
+
+        // The whole-document writer only differs in not breaking the page
+        // before the first file
+        let batch = generator.generate_markdown(&files, "empty_project")?;
+        let batch = generated.replace(&batch, "Generated on: 2025-01-01 00:00:00 UTC");
+        assert_eq!(batch, chunked.replacen("{#file-contents}\n\n\n\\newpage\n\n", "{#file-contents}\n\n", 1));
+        Ok(())
+    }
+
+    /// 5,000 files: `dir0/` to `dir49/`, each with ten subdirectories of
+    /// ten files
+    fn huge_repository() -> Vec<FileInfo> {
+        let mut files = Vec::new();
+        for dir in 0..50 {
+            for subdir in 0..10 {
+                for file in 0..10 {
+                    let path = format!("dir{}/sub{}/file{}.rs", dir, subdir, file);
+                    files.push(FileInfo {
+                        path,
+                        content: "fn f() {}\n".into(),
+                        language: Some("rust".to_string()),
+                        size: 10,
+                        changes: None,
+                        binary_kind: None,
+                        modified: None,
+                        commit: None,
+                    });
+                }
+            }
+        }
+        files
+    }
+
+    fn toc_of(model: &DocumentModel) -> (&[TocEntry], &[TocGroup]) {
+        match &model.sections[0] {
+            Section::TableOfContents { entries, groups, .. } => (entries, groups),
+            section => panic!("expected the table of contents, got {:?}", section),
+        }
+    }
+
+    #[test]
+    fn test_toc_collapses_to_directories_past_the_limit() -> Result<()> {
+        let files = huge_repository();
+        let build = |limit: Option<usize>, depth: usize| {
+            MarkdownGenerator::new(true, false)
+                .with_toc_options(TocOptions { limit, depth, appendix: true })
+                .build_document_model(&files, "huge")
+        };
+
+        for limit in [None, Some(5000), Some(100_000)] {
+            let model = build(limit, 1);
+            assert_eq!(toc_of(&model).0.len(), 5000, "{:?}", limit);
+            assert!(toc_of(&model).1.is_empty());
+            assert!(!model.sections.iter().any(|section| matches!(section, Section::FileIndex { .. })));
+        }
+        for (depth, groups, files_each) in [(1, 50, 100), (2, 500, 10), (3, 500, 10)] {
+            let model = build(Some(4999), depth);
+            let (entries, toc_groups) = toc_of(&model);
+            assert!(entries.is_empty());
+            assert_eq!(toc_groups.len(), groups, "depth {}", depth);
+            assert!(toc_groups.iter().all(|group| group.files == files_each), "depth {}", depth);
+            assert_eq!(toc_groups[1].directory, if depth == 1 { "dir1/" } else { "dir0/sub1/" });
+        }
+
+        // Each group links to the first file under its directory
+        let model = build(Some(1000), 2);
+        let anchors: HashMap<&str, &str> = model.files().iter()
+            .map(|file| (file.anchor.as_str(), file.path.as_str()))
+            .collect();
+        for group in toc_of(&model).1 {
+            let path = anchors.get(group.anchor.as_str()).unwrap_or_else(|| panic!("no file for #{}", group.anchor));
+            assert_eq!(format!("{}file0.rs", group.directory), *path);
+        }
+
+        // And so does every link in the document, the full list included
+        let markdown = MarkdownGenerator::new(true, false)
+            .with_toc_options(TocOptions { limit: Some(1000), depth: 2, appendix: true })
+            .render_markdown(&model)?;
+        let ids: std::collections::HashSet<&str> = Regex::new(r"\{#([^}]+)\}").unwrap()
+            .captures_iter(&markdown)
+            .map(|c| c.get(1).unwrap().as_str())
+            .collect();
+        let links: Vec<&str> = Regex::new(r"\]\(#([^)]+)\)").unwrap()
+            .captures_iter(&markdown)
+            .map(|c| c.get(1).unwrap().as_str())
+            .collect();
+        assert_eq!(links.len(), 500 + 5000);
+        assert!(links.iter().all(|link| ids.contains(link)));
+        assert!(markdown.contains("- [dir0/sub0/](#dir0-sub0-file0-rs) (10 files)\n"));
+        let (_, index) = markdown.split_once("\n\\newpage\n\n## File Index {#file-index}\n\n").unwrap();
+        assert_eq!(index.lines().filter(|line| line.starts_with("- [")).count(), 5000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_toc_directory() {
+        assert_eq!(toc_directory("src/api/v1", 1), "src/");
+        assert_eq!(toc_directory("src/api/v1", 2), "src/api/");
+        assert_eq!(toc_directory("src/api/", 5), "src/api/");
+        assert_eq!(toc_directory("", 1), "./");
+        assert_eq!(toc_directory("./", 1), "./");
+    }
+
+    #[test]
+    fn test_review_checklist_links_markers_to_files() -> Result<()> {
+        let files = crate::file_processor::FileProcessor::new().process_directory(Path::new("testfiles/todo_project"))?;
+        let markers = crate::todos::parse_markers("");
+        let markdown = MarkdownGenerator::new(true, false)
+            .with_todo_index(markers)
+            .generate_markdown(&files, "todo_project")?;
+        let (_, checklist) = markdown.split_once("## Review Checklist {#review-checklist}\n\n").unwrap();
+        let (checklist, _) = checklist.split_once("## File Contents").unwrap();
+        assert_eq!(checklist, concat!(
+            "**FIXME:** 1 · **HACK:** 1 · **TODO:** 1 · **XXX:** 1\n\n",
+            "| Marker | Location | Note |\n|---|---|---|\n",
+            "| XXX | [deploy.py:2](#deploy-py) | XXX: assumes a single target |\n",
+            "| TODO | [src/main.rs:2](#src-main-rs) | TODO: read the port from the environment |\n",
+            "| FIXME | [src/main.rs:5](#src-main-rs) | FIXME: handle a busy port |\n",
+            "| HACK | [src/main.rs:10](#src-main-rs) | HACK: no logging yet |\n\n",
+        ));
+        // The strings in config.json and the quoted ones in the code don't count
+        assert!(!checklist.contains("config.json"));
+        assert!(!MarkdownGenerator::new(true, false).generate_markdown(&files, "todo_project")?.contains("Review Checklist"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_markdown_truncates_large_files() -> Result<()> {
+        let content = "let x = 1;\n".repeat(20_000);
+        let files = vec![FileInfo {
+            path: "big.rs".to_string(),
+            size: content.len(),
+            content: content.into(),
+            language: Some("rust".to_string()),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }];
+        let mut markdown = Vec::new();
+        let options = ChunkOptions { chunk_size: 1, max_file_size: 1024, memory_limit: None };
+        MarkdownGenerator::new(false, false).write_markdown_chunked(&files, "big", &mut markdown, &options, |_| {})?;
+        let markdown = String::from_utf8(markdown)?;
+        assert!(markdown.contains("... [File continues for 117.2 KB more] ..."), "{}", &markdown[markdown.len() - 500..]);
+        assert!(markdown.contains("--- Sample 5 (around "));
+        assert!(markdown.contains("Note: Large file truncated to prevent memory issues.\n```\n\n---\n\n"));
+        assert!(markdown.len() < 200_000);
+
+        // Files that fit aren't copied
+        assert!(matches!(truncate_large_file_content("let x = 1;\n", 11), Cow::Borrowed("let x = 1;\n")));
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "coarse memory measurement, run with --ignored"]
+    fn test_chunked_writing_copies_no_content() {
+        use sysinfo::{ProcessesToUpdate, System};
+
+        fn resident_bytes(sys: &mut System) -> u64 {
+            let pid = sysinfo::get_current_pid().unwrap();
+            sys.refresh_processes(ProcessesToUpdate::Some(&[pid]));
+            sys.process(pid).map(|p| p.memory()).unwrap_or(0)
+        }
+
+        // Synthetic repository of 512 MB of text; SCROLLCAST_SYNTHETIC_MB
+        // scales it, e.g. to 2048
+        let megabytes: usize = std::env::var("SCROLLCAST_SYNTHETIC_MB").ok().and_then(|mb| mb.parse().ok()).unwrap_or(512);
+        let line = "    let value = compute(input, factor) * offset; // keep the columns busy\n";
+        let content: Arc<str> = line.repeat(1024 * 1024 / line.len()).into();
+        let files: Vec<FileInfo> = (0..megabytes)
+            .map(|i| FileInfo {
+                path: format!("src/module_{}/file_{}.rs", i / 64, i),
+                size: content.len(),
+                // Every file shares one buffer, so only copies would show up
+                content: content.clone(),
+                language: Some("rust".to_string()),
+                changes: None,
+                binary_kind: None,
+                modified: None,
+                commit: None,
+            })
+            .collect();
+        let options = ChunkOptions { chunk_size: 20, max_file_size: 50 * 1024 * 1024, memory_limit: None };
+        let mut sys = System::new();
+
+        let before = resident_bytes(&mut sys);
+        let started = std::time::Instant::now();
+        let mut sink = std::io::BufWriter::new(std::io::sink());
+        MarkdownGenerator::new(true, true).write_markdown_chunked(&files, "synthetic", &mut sink, &options, |_| {}).unwrap();
+        let growth = resident_bytes(&mut sys).saturating_sub(before);
+
+        println!("{} MB of text in {:?}, resident memory grew {} KB", megabytes, started.elapsed(), growth / 1024);
+        assert!(growth < 64 * 1024 * 1024, "grew {} KB", growth / 1024);
+    }
+
+    /// The fixture repository and a file big enough to be cut down once
+    /// memory runs low, but not before
+    fn files_with_a_large_one() -> Result<Vec<FileInfo>> {
+        let mut files = crate::file_processor::FileProcessor::new().process_directory(Path::new("testfiles/test_project"))?;
+        let content = "let x = 1;\n".repeat(30_000);
+        files.push(FileInfo {
+            path: "zz_big.rs".to_string(),
+            size: content.len(),
+            content: content.into(),
+            language: Some("rust".to_string()),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        });
+        Ok(files)
+    }
+
+    #[test]
+    fn test_chunked_markdown_saves_memory_near_the_limit() -> Result<()> {
+        let files = files_with_a_large_one()?;
+        // Usage climbs towards a tiny limit, passing 80% at the second file
+        let readings = std::sync::atomic::AtomicU64::new(0);
+        let limit = MemoryLimit::new(1000).with_probe(move || {
+            Some(700 + 100 * readings.fetch_add(1, std::sync::atomic::Ordering::Relaxed).min(1))
+        });
+        let options = ChunkOptions { chunk_size: 3, max_file_size: 50 * 1024 * 1024, memory_limit: Some(limit) };
+        let mut events = Vec::new();
+        let mut markdown = Vec::new();
+        MarkdownGenerator::new(true, true).write_markdown_chunked(&files, "test_project", &mut markdown, &options, |event| {
+            events.push(match event {
+                ChunkEvent::Chunk { index, count, files } => format!("chunk {}/{} of {}", index + 1, count, files),
+                ChunkEvent::File { index, file, truncated } => format!("file {} {} {}", index, file.path, truncated),
+                ChunkEvent::LowMemory { used, limit } => format!("low memory {}/{}", used, limit),
+            });
+        })?;
+        assert_eq!(events, [
+            "chunk 1/2 of 3", "file 0 README.md false", "low memory 800/1000", "file 1 Token.sol false", "file 2 main.rs false",
+            "chunk 2/2 of 1", "file 3 zz_big.rs true",
+        ]);
+        let markdown = String::from_utf8(markdown)?;
+        assert!(markdown.contains("... [File continues for 312.5 KB more] ..."));
+        assert!(markdown.len() < 30_000, "{}", markdown.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_markdown_stops_over_the_limit() -> Result<()> {
+        let files = files_with_a_large_one()?;
+        let readings = std::sync::atomic::AtomicU64::new(0);
+        let limit = MemoryLimit::new(1000).with_probe(move || {
+            Some(500 + 300 * readings.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+        });
+        let options = ChunkOptions { chunk_size: 2, max_file_size: 50 * 1024 * 1024, memory_limit: Some(limit) };
+        let mut markdown = Vec::new();
+        let error = MarkdownGenerator::new(true, true)
+            .write_markdown_chunked(&files, "test_project", &mut markdown, &options, |_| {})
+            .unwrap_err();
+        let exceeded = error.downcast_ref::<MemoryLimitExceeded>().expect("a MemoryLimitExceeded error");
+        assert_eq!(exceeded, &MemoryLimitExceeded { file: "Token.sol".to_string(), used: 1100, limit: 1000 });
+        assert!(error.to_string().ends_with("at Token.sol"), "{}", error);
+
+        // Whatever can't be measured isn't limited
+        let options = ChunkOptions { memory_limit: Some(MemoryLimit::new(1).with_probe(|| None)), ..options };
+        MarkdownGenerator::new(true, true).write_markdown_chunked(&files, "test_project", &mut Vec::new(), &options, |_| {})?;
+        Ok(())
+    }
+}
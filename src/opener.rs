@@ -0,0 +1,57 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// The command that opens `path` in its default application: `opener` when
+/// given, otherwise `open` on macOS, `start` on Windows and `xdg-open`
+/// elsewhere
+pub fn command(path: &Path, opener: Option<&str>) -> Command {
+    let mut command = match opener {
+        Some(opener) => Command::new(opener),
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None if cfg!(windows) => {
+            // The empty argument is the window title `start` expects first
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+    command.arg(path);
+    command
+}
+
+/// Open `path` in its default application and wait for the launcher, which
+/// returns once the application is started
+pub fn open(path: &Path, opener: Option<&str>) -> Result<()> {
+    let mut command = command(path, opener);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launcher_commands() {
+        let path = Path::new("book.html");
+        let custom = command(path, Some("firefox"));
+        assert_eq!(custom.get_program(), "firefox");
+        assert_eq!(custom.get_args().collect::<Vec<_>>(), ["book.html"]);
+
+        let default = command(path, None);
+        assert_eq!(default.get_args().last().unwrap(), "book.html");
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            assert_eq!(default.get_program(), "xdg-open");
+        }
+
+        let error = open(path, Some("/nonexistent/opener")).unwrap_err();
+        assert!(error.to_string().contains("Failed to run /nonexistent/opener"), "{}", error);
+    }
+}
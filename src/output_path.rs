@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Whether `path` names a directory rather than a file: one that exists, or
+/// one written with a trailing separator, like `out/`
+pub fn is_directory(path: &Path) -> bool {
+    path.is_dir() || path.as_os_str().to_string_lossy().ends_with(std::path::is_separator)
+}
+
+/// Create the directory `path` goes in and check a file can be written
+/// there, by creating and removing an empty one. Runs before any work so a
+/// bad `-o` fails at once rather than after the whole document is rendered.
+pub fn ensure_writable(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+    tempfile::Builder::new()
+        .prefix(".scrollcast-")
+        .tempfile_in(dir)
+        .with_context(|| format!("Can't write to the output directory {}; pass -o with a location you can write to", dir.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directories_by_existence_or_trailing_separator() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        assert!(is_directory(temp_dir.path()));
+        assert!(is_directory(Path::new("missing/")));
+        assert!(!is_directory(Path::new("missing")));
+        assert!(!is_directory(&temp_dir.path().join("book.html")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_writable_creates_the_directory_and_leaves_nothing() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let dir = temp_dir.path().join("nested/out");
+        ensure_writable(&dir.join("book.pdf"))?;
+        assert!(dir.is_dir());
+        assert_eq!(fs::read_dir(&dir)?.count(), 0);
+        Ok(())
+    }
+}
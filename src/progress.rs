@@ -0,0 +1,146 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::time::Duration;
+
+/// How much the command line reports while it works
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Progress bars on stderr, with status lines between stages
+    Bars,
+    /// Plain status lines only, so verbose and CI logs stay readable
+    Log,
+    /// Nothing but errors and the final output path
+    Quiet,
+}
+
+/// Status output for a run. Stages draw a bar or spinner in `Bars` mode and
+/// fall back to a line at their start and finish otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    mode: ProgressMode,
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode) -> Self {
+        Self { mode }
+    }
+
+    /// Print a status line unless quiet
+    pub fn status(&self, message: impl AsRef<str>) {
+        if self.mode != ProgressMode::Quiet {
+            println!("{}", message.as_ref());
+        }
+    }
+
+    /// A stage counting up to `total` items
+    pub fn bar(&self, prefix: &str, total: u64) -> Stage {
+        let style = ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} ({eta}) {wide_msg}")
+            .unwrap()
+            .progress_chars("=> ");
+        self.stage(ProgressBar::new(total).with_style(style), prefix)
+    }
+
+    /// A stage counting items whose total isn't known in advance
+    pub fn counter(&self, prefix: &str, unit: &str) -> Stage {
+        let template = format!("{{spinner}} {{prefix}} {{pos}} {} {{wide_msg}}", unit);
+        let style = ProgressStyle::with_template(&template).unwrap();
+        self.stage(ProgressBar::no_length().with_style(style), prefix)
+    }
+
+    /// A stage with no count, shown as a spinner
+    pub fn spinner(&self, prefix: &str) -> Stage {
+        let style = ProgressStyle::with_template("{spinner} {prefix} {elapsed}").unwrap();
+        let stage = self.stage(ProgressBar::new_spinner().with_style(style), prefix);
+        stage.bar.enable_steady_tick(Duration::from_millis(100));
+        stage
+    }
+
+    fn stage(&self, bar: ProgressBar, prefix: &str) -> Stage {
+        let target = match self.mode {
+            ProgressMode::Bars => ProgressDrawTarget::stderr(),
+            ProgressMode::Log | ProgressMode::Quiet => ProgressDrawTarget::hidden(),
+        };
+        bar.set_draw_target(target);
+        bar.set_prefix(prefix.to_string());
+        // Without a terminal to draw on, the prefix stands in for the bar
+        if bar.is_hidden() {
+            self.status(prefix);
+        }
+        Stage { bar, progress: *self }
+    }
+}
+
+/// One stage of the run, such as discovery or rendering
+pub struct Stage {
+    bar: ProgressBar,
+    progress: Progress,
+}
+
+impl Stage {
+    /// Count one item, showing `message` (a file path, say) beside the bar
+    pub fn inc(&self, message: impl Into<String>) {
+        self.bar.set_message(message.into());
+        self.bar.inc(1);
+    }
+
+    /// Jump to `position`, for work done in one step
+    pub fn set_position(&self, position: u64) {
+        self.bar.set_position(position);
+    }
+
+    /// Print a line above the bar without tearing it
+    pub fn println(&self, message: impl AsRef<str>) {
+        if self.bar.is_hidden() {
+            self.progress.status(message);
+        } else {
+            self.bar.println(message.as_ref());
+        }
+    }
+
+    /// End the stage, leaving `message` in place of the bar
+    pub fn finish(self, message: impl Into<String>) {
+        let message = message.into();
+        if self.bar.is_hidden() {
+            self.progress.status(&message);
+        } else {
+            self.bar.set_style(ProgressStyle::with_template("{wide_msg}").unwrap());
+            self.bar.finish_with_message(message);
+        }
+    }
+}
+
+impl Drop for Stage {
+    fn drop(&mut self) {
+        // A stage abandoned by an error shouldn't leave a spinner running
+        if !self.bar.is_finished() {
+            self.bar.abandon();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_stages_still_count() {
+        for mode in [ProgressMode::Log, ProgressMode::Quiet] {
+            let stage = Progress::new(mode).bar("📝 Generating markdown", 3);
+            assert!(stage.bar.is_hidden());
+            stage.inc("src/main.rs");
+            stage.inc("src/lib.rs");
+            assert_eq!(stage.bar.position(), 2);
+            stage.set_position(3);
+            assert_eq!(stage.bar.position(), 3);
+            stage.finish("✅ Markdown generated");
+        }
+    }
+
+    #[test]
+    fn test_counter_has_no_length() {
+        let stage = Progress::new(ProgressMode::Quiet).counter("📖 Processing files", "files found");
+        stage.inc("a.rs");
+        assert_eq!(stage.bar.length(), None);
+        assert_eq!(stage.bar.position(), 1);
+        stage.finish("✅ Found 1 file");
+    }
+}
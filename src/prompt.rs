@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use dialoguer::Confirm;
+use std::io::IsTerminal;
+
+/// Whether there's someone at a terminal to answer a prompt. Prompts read
+/// from stdin and draw on stderr, so both have to be terminals.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Ask a yes/no question, defaulting to yes. Without a terminal the prompt
+/// is skipped and the answer is yes, as with `--yes`; with `no_input` any
+/// prompt is an error instead.
+pub fn confirm(prompt: &str, no_input: bool) -> Result<bool> {
+    confirm_with(prompt, no_input, is_interactive())
+}
+
+fn confirm_with(prompt: &str, no_input: bool, interactive: bool) -> Result<bool> {
+    if no_input {
+        anyhow::bail!("--no-input was given, but scrollcast needs to ask: {} (pass --yes to skip)", prompt);
+    }
+    if !interactive {
+        eprintln!("ℹ️  Not running in a terminal, continuing as with --yes");
+        return Ok(true);
+    }
+    Confirm::new()
+        .with_prompt(prompt)
+        .default(true)
+        .interact()
+        .context("Failed to get user confirmation")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompts_without_a_terminal() -> Result<()> {
+        assert!(confirm_with("Proceed?", false, false)?);
+        let error = confirm_with("Proceed?", true, true).unwrap_err();
+        assert!(error.to_string().contains("needs to ask: Proceed?"), "{}", error);
+        Ok(())
+    }
+}
@@ -1,232 +1,1250 @@
-use anyhow::Result;
-use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
-use pulldown_cmark::{Event, html, Tag, TagEnd, CowStr};
-use crate::renderer::{DocumentRenderer, DocumentMetadata};
+use anyhow::{Context, Result};
+use epub_builder::{EpubBuilder, EpubContent, EpubVersion, ReferenceType, TocElement, ZipLibrary};
+use pulldown_cmark::{Event, html, HeadingLevel, Tag, TagEnd};
+use crate::anchors::{self, slugify, AnchorRegistry};
+use crate::i18n::{self, Strings};
+use crate::markdown_generator::{parent_directory, toc_directory};
+use crate::renderer::{file_info_cards, highlight_code_blocks, FILE_INFO_CARD, highlighter_for, DocumentRenderer, DocumentMetadata, CODE_FONT_RATIO, DEFAULT_FONT_SIZE};
+use crate::renderer::pdf_font::EMBEDDED_FONTS;
 use crate::syntax::highlighter::SyntaxHighlighter;
+use crate::warnings::{WarningCategory, Warnings};
 use regex::Regex;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use uuid::Uuid;
 
-pub struct EpubRenderer;
+/// Location of the package document written by epub-builder
+const PACKAGE_DOCUMENT: &str = "OEBPS/content.opf";
 
-impl EpubRenderer {
-    pub fn new() -> Self {
-        Self
+/// Embedded code font (DejaVu Sans Mono, shared with PDF/A output), relative
+/// to the stylesheet
+const CODE_FONT_PATH: &str = "fonts/DejaVuSansMono.ttf";
+const CODE_FONT_FAMILY: &str = "DejaVu Sans Mono";
+
+/// Options controlling EPUB output
+#[derive(Debug, Clone)]
+pub struct EpubOptions {
+    /// Put all files of a directory into one chapter instead of one per file
+    pub group_by_directory: bool,
+    /// Chapters larger than this many bytes of XHTML are split into parts
+    pub max_chapter_bytes: usize,
+    /// Cover image file (PNG, JPEG, GIF, SVG or WebP)
+    pub cover: Option<PathBuf>,
+    /// Generate a simple SVG cover with the title when no image is given
+    pub generate_cover: bool,
+    /// Body text size as a CSS length; defaults to `1em` so the reader's
+    /// own font scaling applies
+    pub base_font_size: Option<String>,
+    /// Code size as a CSS length relative to the body text; defaults to `0.85em`
+    pub code_font_size: Option<String>,
+    /// Body font stack; defaults to a serif stack
+    pub font_family: Option<String>,
+    /// Embed a monospace font for code, since many readers lack one
+    pub embed_font: bool,
+    /// Repository root that relative image references in markdown files are
+    /// resolved against; images are left untouched when unset
+    pub image_root: Option<PathBuf>,
+    /// Images larger than this are left out, keeping their alt text
+    pub max_image_bytes: u64,
+}
+
+impl Default for EpubOptions {
+    fn default() -> Self {
+        Self {
+            group_by_directory: false,
+            // Many e-readers refuse content documents much above 300 KB
+            max_chapter_bytes: 256 * 1024,
+            cover: None,
+            generate_cover: true,
+            base_font_size: None,
+            code_font_size: None,
+            font_family: None,
+            embed_font: true,
+            image_root: None,
+            max_image_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl EpubOptions {
+    /// Tiny text for people who prefer to fit more code on each screen; code
+    /// ends up at roughly 0.4em of the reader's default size
+    pub fn compact() -> Self {
+        Self {
+            base_font_size: Some("0.45em".to_string()),
+            code_font_size: Some("0.9em".to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+pub struct EpubRenderer {
+    options: EpubOptions,
+}
+
+/// What a chapter holds, which decides where it sits in the navigation
+#[derive(Debug, Clone)]
+enum ChapterKind {
+    /// The title page or a section before the file listings
+    Front,
+    /// A single source file
+    File(String),
+    /// All files directly inside one directory
+    Directory(String),
+}
+
+/// A run of events that becomes one or more XHTML content documents
+struct Chapter<'a> {
+    title: String,
+    kind: ChapterKind,
+    /// Source files in this chapter with their heading anchors
+    files: Vec<(String, String)>,
+    /// READMEs introducing a directory chapter, kept out of `files`
+    introductions: Vec<String>,
+    events: Vec<Event<'a>>,
+}
+
+impl<'a> Chapter<'a> {
+    fn new(title: &str, kind: ChapterKind) -> Self {
+        Self { title: title.to_string(), kind, files: Vec::new(), introductions: Vec::new(), events: Vec::new() }
     }
     
-    fn convert_syntect_to_inline_css(&self, html: &str) -> String {
-        let mut result = html.to_string();
-        
-        // Define color mappings for different syntect classes
-        let class_mappings = vec![
-            (r#"class="[^"]*comment[^"]*""#, r#"style="color: #6a737d; font-style: italic;""#),
-            (r#"class="[^"]*keyword[^"]*""#, r#"style="color: #d73a49; font-weight: bold;""#),
-            (r#"class="[^"]*storage[^"]*""#, r#"style="color: #d73a49; font-weight: bold;""#),
-            (r#"class="[^"]*string[^"]*""#, r#"style="color: #032f62;""#),
-            (r#"class="[^"]*constant[^"]*""#, r#"style="color: #005cc5;""#),
-            (r#"class="[^"]*entity[^"]*""#, r#"style="color: #6f42c1;""#),
-            (r#"class="[^"]*support[^"]*""#, r#"style="color: #005cc5;""#),
-            (r#"class="[^"]*variable[^"]*""#, r#"style="color: #e36209;""#),
-        ];
-        
-        // Apply each mapping
-        for (pattern, replacement) in class_mappings {
-            if let Ok(re) = Regex::new(pattern) {
-                result = re.replace_all(&result, replacement).to_string();
+    /// A section holding nothing but its heading, used as a parent for the
+    /// file listings that follow it
+    fn is_divider(&self) -> bool {
+        matches!(self.kind, ChapterKind::Front)
+            && self.events.iter().all(|event| matches!(event,
+                Event::Start(Tag::Heading { .. }) | Event::End(TagEnd::Heading(_))
+                    | Event::Text(_) | Event::Code(_)))
+    }
+    
+    /// A section made only of same-document links, i.e. the inline table of contents
+    fn is_link_index(&self) -> bool {
+        let mut links = 0;
+        for event in &self.events {
+            match event {
+                Event::Start(Tag::Link { dest_url, .. }) if dest_url.starts_with('#') => links += 1,
+                Event::Start(Tag::Link { .. }) | Event::Start(Tag::CodeBlock(_)) | Event::Html(_) => return false,
+                _ => {}
+            }
+        }
+        links > 0
+    }
+}
+
+/// A rendered content document
+struct ChapterPart {
+    href: String,
+    title: String,
+    html: String,
+    kind: ChapterKind,
+    /// Files listed under this part in the navigation (first part only)
+    files: Vec<(String, String)>,
+    /// READMEs introducing this part's directory (first part only)
+    introductions: usize,
+    part: usize,
+    total: usize,
+}
+
+/// Builds nested navigation entries: directories become branches and
+/// files become leaves, below the front matter sections
+struct NavBuilder {
+    base_level: i32,
+    open_dirs: Vec<String>,
+    /// Titles of the directories the navigation collapses the files into,
+    /// when the table of contents did
+    groups: Option<CollapsedGroups>,
+}
+
+/// The directories a collapsed navigation lists instead of files
+struct CollapsedGroups {
+    depth: usize,
+    /// "src/ (12 files)" and the like, by directory
+    titles: HashMap<String, String>,
+    /// The directories with an entry so far
+    listed: HashSet<String>,
+}
+
+impl CollapsedGroups {
+    /// Titles for the directories `depth` deep holding the files of `parts`
+    fn new(parts: &[ChapterPart], depth: usize, strings: &Strings) -> Self {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for part in parts {
+            if let Some(group) = Self::group(&part.kind, depth) {
+                *counts.entry(group).or_default() += part.files.len() + part.introductions;
             }
         }
+        let titles = counts.into_iter()
+            .map(|(group, count)| {
+                let title = format!("{} ({})", group, strings.files(count));
+                (group, title)
+            })
+            .collect();
+        Self { depth, titles, listed: HashSet::new() }
+    }
+
+    /// The directory a part's files are listed under, the way the
+    /// generator groups them in the table of contents
+    fn group(kind: &ChapterKind, depth: usize) -> Option<String> {
+        match kind {
+            ChapterKind::Front => None,
+            ChapterKind::File(path) => Some(toc_directory(parent_directory(path), depth)),
+            ChapterKind::Directory(dir) => Some(toc_directory(dir, depth)),
+        }
+    }
+}
+
+impl NavBuilder {
+    /// Title, level and nested children for a content document's nav entry.
+    /// A collapsed navigation gives the first part in each directory an
+    /// entry naming it and the rest none, an empty title.
+    fn entry(&mut self, part: &ChapterPart, ids: &HashMap<String, String>) -> (String, i32, Vec<TocElement>) {
+        if let Some(groups) = &mut self.groups {
+            let Some(group) = CollapsedGroups::group(&part.kind, groups.depth) else {
+                return (part.title.clone(), 1, Vec::new());
+            };
+            if !groups.listed.insert(group.clone()) {
+                return (String::new(), self.base_level, Vec::new());
+            }
+            let title = groups.titles.get(&group).cloned().unwrap_or(group);
+            return (title, self.base_level, Vec::new());
+        }
+        let (dirs, leaf_title) = match &part.kind {
+            ChapterKind::Front => return (part.title.clone(), 1, Vec::new()),
+            ChapterKind::File(path) => {
+                let mut dirs = path_components(path);
+                let name = dirs.pop().unwrap_or_else(|| path.clone());
+                (dirs, name)
+            }
+            ChapterKind::Directory(dir) => {
+                let mut dirs = path_components(dir);
+                match dirs.pop() {
+                    Some(name) => (dirs, format!("{}/", name)),
+                    None => (dirs, dir.clone()),
+                }
+            }
+        };
+        let leaf_level = self.base_level + dirs.len() as i32;
+        
+        if part.part > 1 {
+            let title = format!("{} ({}/{})", leaf_title, part.part, part.total);
+            return (title, leaf_level, Vec::new());
+        }
+        let leaf_title = if part.total > 1 {
+            format!("{} (1/{})", leaf_title, part.total)
+        } else {
+            leaf_title
+        };
         
-        // Remove any remaining complex class attributes
-        if let Ok(re) = Regex::new(r#"class="[^"]*""#) {
-            result = re.replace_all(&result, "").to_string();
+        // Files grouped into a directory chapter are listed under it by anchor
+        let file_children: Vec<TocElement> = part.files.iter()
+            .filter(|_| matches!(part.kind, ChapterKind::Directory(_)))
+            .filter_map(|(path, id)| {
+                let href = ids.get(id)?;
+                let name = path_components(path).pop().unwrap_or_else(|| path.clone());
+                Some(TocElement::new(format!("{}#{}", href, id), name))
+            })
+            .collect();
+        
+        // Branches for directories not already open from the previous entry
+        let common = self.open_dirs.iter().zip(&dirs).take_while(|(a, b)| a == b).count();
+        let new_dirs = dirs[common..].to_vec();
+        self.open_dirs = dirs;
+        if let ChapterKind::Directory(_) = part.kind {
+            self.open_dirs.push(leaf_title.trim_end_matches('/').to_string());
         }
         
-        result
+        let Some((outermost, inner_dirs)) = new_dirs.split_first() else {
+            return (leaf_title, leaf_level, file_children);
+        };
+        let leaf = file_children.into_iter().fold(
+            TocElement::new(part.href.as_str(), leaf_title).level(leaf_level),
+            TocElement::child,
+        );
+        let branch = inner_dirs.iter().rev().fold(leaf, |child, dir| {
+            TocElement::new(part.href.as_str(), format!("{}/", dir)).child(child)
+        });
+        (format!("{}/", outermost), self.base_level + common as i32, vec![branch])
     }
 }
 
-impl DocumentRenderer for EpubRenderer {
-    fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
-        // Initialize syntax highlighter
-        let highlighter = SyntaxHighlighter::new()?;
-        
-        // Process events to add syntax highlighting (same as HTML renderer)
-        let mut processed_events = Vec::new();
-        let mut i = 0;
+/// Events in the heading `directory_heading` opens a directory chapter with
+const DIRECTORY_HEADING_EVENTS: usize = 3;
+
+/// The heading a directory chapter starts with, naming the directory
+fn directory_heading<'a>(dir: &str) -> Vec<Event<'a>> {
+    vec![
+        Event::Start(Tag::Heading { level: HeadingLevel::H2, id: None, classes: Vec::new(), attrs: Vec::new() }),
+        Event::Text(dir.to_string().into()),
+        Event::End(TagEnd::Heading(HeadingLevel::H2)),
+    ]
+}
+
+/// Whether `path` is a `README.md`, in any case
+fn is_readme(path: &str) -> bool {
+    Path::new(path).file_name().is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case("README.md"))
+}
+
+/// A README's section as prose: without its file heading, the file header
+/// card after it and the rule closing the section
+fn readme_introduction(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut events: Vec<Event<'_>> = events.into_iter()
+        .skip_while(|event| !matches!(event, Event::End(TagEnd::Heading(_))))
+        .skip(1)
+        .skip_while(|event| match event {
+            Event::Html(html) => html.starts_with(FILE_INFO_CARD),
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => true,
+            _ => false,
+        })
+        .collect();
+    if matches!(events.last(), Some(Event::Rule)) {
+        events.pop();
+    }
+    events
+}
+
+/// Normal path components of a `/`-separated path
+fn path_components(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .map(str::to_string)
+        .collect()
+}
+
+impl EpubRenderer {
+    pub fn new() -> Self {
+        Self::with_options(EpubOptions::default())
+    }
+    
+    pub fn with_options(options: EpubOptions) -> Self {
+        Self { options }
+    }
+    
+    /// Structural checks on a generated EPUB: `mimetype` stored first, a
+    /// resolvable container and package document, manifest items present in
+    /// the archive, spine references to manifest items, and well-formed XHTML.
+    /// All problems found are listed in the error.
+    pub fn validate(epub: &[u8]) -> Result<()> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub))
+            .context("EPUB is not a valid zip archive")?;
+        let mut problems = Vec::new();
         
-        while i < events.len() {
-            match &events[i] {
-                Event::Start(Tag::CodeBlock(kind)) => {
-                    // Extract language from code block
-                    let language = match kind {
-                        pulldown_cmark::CodeBlockKind::Fenced(lang) => {
-                            if lang.is_empty() { None } else { Some(lang.as_ref()) }
-                        }
-                        _ => None,
-                    };
-                    
-                    // Find the corresponding text and end events
-                    i += 1;
-                    let mut code_content = String::new();
-                    while i < events.len() {
-                        match &events[i] {
-                            Event::Text(text) => {
-                                code_content.push_str(text);
-                            }
-                            Event::End(TagEnd::CodeBlock) => {
-                                break;
-                            }
-                            _ => {}
-                        }
-                        i += 1;
+        match archive.by_index(0) {
+            Ok(mut first) => {
+                if first.name() != "mimetype" {
+                    problems.push(format!("first entry is '{}' instead of 'mimetype'", first.name()));
+                } else {
+                    if first.compression() != zip::CompressionMethod::Stored {
+                        problems.push("mimetype is compressed".to_string());
                     }
-                    
-                    // Generate highlighted HTML
-                    if language.is_some() {
-                        let highlighted_html = highlighter.highlight_to_html(&code_content, language);
-                        let wrapped_html = format!("<pre>{}</pre>", highlighted_html);
-                        processed_events.push(Event::Html(CowStr::Boxed(wrapped_html.into_boxed_str())));
-                    } else {
-                        // No language specified, use regular code block
-                        processed_events.push(Event::Start(Tag::CodeBlock(kind.clone())));
-                        processed_events.push(Event::Text(CowStr::Boxed(code_content.into_boxed_str())));
-                        processed_events.push(Event::End(TagEnd::CodeBlock));
+                    let mut mimetype = String::new();
+                    if first.read_to_string(&mut mimetype).is_err() || mimetype != "application/epub+zip" {
+                        problems.push("mimetype is not 'application/epub+zip'".to_string());
                     }
                 }
-                _ => {
-                    processed_events.push(events[i].clone());
+            }
+            Err(_) => problems.push("archive is empty".to_string()),
+        }
+        
+        let package_path = match read_archive_entry(&mut archive, "META-INF/container.xml") {
+            Ok(container) => match roxmltree::Document::parse(&container) {
+                Ok(doc) => {
+                    let path = doc.descendants()
+                        .find(|n| n.has_tag_name("rootfile"))
+                        .and_then(|n| n.attribute("full-path"))
+                        .map(str::to_string);
+                    if path.is_none() {
+                        problems.push("container.xml has no rootfile".to_string());
+                    }
+                    path
+                }
+                Err(e) => {
+                    problems.push(format!("container.xml is not well-formed: {}", e));
+                    None
                 }
+            },
+            Err(e) => {
+                problems.push(e.to_string());
+                None
+            }
+        };
+        
+        if let Some(package_path) = package_path {
+            match read_archive_entry(&mut archive, &package_path) {
+                Ok(opf) => problems.extend(Self::validate_package(&mut archive, &package_path, &opf)),
+                Err(e) => problems.push(e.to_string()),
             }
-            i += 1;
         }
         
-        // Convert processed events to HTML
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, processed_events.into_iter());
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("EPUB validation failed:\n  - {}", problems.join("\n  - "))
+        }
+    }
+    
+    /// Check the manifest and spine of the package document at `package_path`
+    fn validate_package(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, package_path: &str, opf: &str) -> Vec<String> {
+        let doc = match roxmltree::Document::parse(opf) {
+            Ok(doc) => doc,
+            Err(e) => return vec![format!("{} is not well-formed: {}", package_path, e)],
+        };
+        let base = match package_path.rfind('/') {
+            Some(slash) => &package_path[..=slash],
+            None => "",
+        };
+        let xml_options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+        let mut problems = Vec::new();
+        let mut manifest = Vec::new();
         
-        // Convert complex syntect spans to inline CSS for EPUB
-        html_output = self.convert_syntect_to_inline_css(&html_output);
+        for item in doc.descendants().filter(|n| n.has_tag_name("item")) {
+            let (Some(id), Some(href)) = (item.attribute("id"), item.attribute("href")) else {
+                problems.push("manifest item without id or href".to_string());
+                continue;
+            };
+            manifest.push(id);
+            let path = format!("{}{}", base, href);
+            if archive.index_for_name(&path).is_none() {
+                problems.push(format!("manifest item '{}' is missing from the archive", href));
+                continue;
+            }
+            if item.attribute("media-type") == Some("application/xhtml+xml") {
+                let parsed = read_archive_entry(archive, &path).and_then(|content| {
+                    roxmltree::Document::parse_with_options(&content, xml_options)
+                        .map(|_| ())
+                        .map_err(anyhow::Error::from)
+                });
+                if let Err(e) = parsed {
+                    problems.push(format!("{} is not well-formed XHTML: {}", path, e));
+                }
+            }
+        }
         
-        // Replace \newpage with EPUB page break (handle different markdown outputs)
-        html_output = html_output.replace("<p>\\newpage</p>", r#"<div style="page-break-before: always;"></div>"#);
-        html_output = html_output.replace("\\newpage", r#"<div style="page-break-before: always;"></div>"#);
+        for itemref in doc.descendants().filter(|n| n.has_tag_name("itemref")) {
+            match itemref.attribute("idref") {
+                Some(idref) if manifest.contains(&idref) => {}
+                Some(idref) => problems.push(format!("spine item '{}' is not in the manifest", idref)),
+                None => problems.push("spine item without idref".to_string()),
+            }
+        }
+        problems
+    }
+    
+    /// Group events into chapters: the title page and each front matter
+    /// section first, then one chapter per file heading (or per directory when
+    /// grouping is enabled). File headings are the `### path {#id}` headings
+    /// the generator emits; headings inside rendered markdown files carry no
+    /// id and stay in their file's chapter. The file index after the files
+    /// is a section of its own. The inline table of contents is dropped
+    /// unless `include_toc` is set.
+    fn split_chapters<'a>(&self, events: Vec<Event<'a>>, front_title: &str, include_toc: bool) -> Vec<Chapter<'a>> {
+        let mut chapters = vec![Chapter::new(front_title, ChapterKind::Front)];
+        let mut in_files = false;
+        let mut heading: Option<String> = None;
         
-        // Create EPUB
-        let zip_library = ZipLibrary::new()
-            .map_err(|e| anyhow::anyhow!("Failed to create zip library: {}", e))?;
-        let mut builder = EpubBuilder::new(zip_library)
-            .map_err(|e| anyhow::anyhow!("Failed to create EPUB builder: {}", e))?;
+        for event in events {
+            // Front matter sections get anchors so the navigation can link to them
+            let event = match event {
+                Event::Start(Tag::Heading { level: HeadingLevel::H2, id, classes, attrs })
+                    if !in_files || id.as_deref() == Some(anchors::FILE_INDEX) => {
+                    let id = id.unwrap_or_else(|| format!("section-{}", chapters.len()).into());
+                    chapters.push(Chapter::new("", ChapterKind::Front));
+                    heading = Some(String::new());
+                    Event::Start(Tag::Heading { level: HeadingLevel::H2, id: Some(id), classes, attrs })
+                }
+                event => event,
+            };
+            
+            match &event {
+                Event::Start(Tag::Heading { level: HeadingLevel::H3, id: Some(id), .. }) => {
+                    in_files = true;
+                    let mut chapter = Chapter::new("", ChapterKind::File(String::new()));
+                    chapter.files.push((String::new(), id.to_string()));
+                    chapters.push(chapter);
+                    heading = Some(String::new());
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let (Some(title), Some(chapter)) = (heading.take(), chapters.last_mut()) {
+                        let title = title.trim().to_string();
+                        if let ChapterKind::File(path) = &mut chapter.kind {
+                            path.clone_from(&title);
+                            if let Some(file) = chapter.files.first_mut() {
+                                file.0.clone_from(&title);
+                            }
+                        }
+                        chapter.title = title;
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(title) = heading.as_mut() {
+                        title.push_str(text);
+                    }
+                }
+                _ => {}
+            }
+            if let Some(chapter) = chapters.last_mut() {
+                chapter.events.push(event);
+            }
+        }
         
-        // Set metadata
-        builder.metadata("title", &metadata.title)
-            .map_err(|e| anyhow::anyhow!("Failed to set title: {}", e))?;
-        if let Some(author) = &metadata.author {
-            builder.metadata("author", author)
-                .map_err(|e| anyhow::anyhow!("Failed to set author: {}", e))?;
+        chapters.retain(|chapter| {
+            !chapter.events.is_empty() && (include_toc || !chapter.is_link_index())
+        });
+        
+        if !self.options.group_by_directory {
+            return chapters;
         }
-        builder.metadata("lang", &metadata.language)
-            .map_err(|e| anyhow::anyhow!("Failed to set language: {}", e))?;
         
-        // Add CSS for styling with much smaller fonts and more padding for EPUB
-        let css_content = r#"
-            body {
-                font-family: Georgia, serif;
-                font-size: 0.45em;
-                line-height: 1.6;
-                margin: 1.5em 2em;
-                padding: 1em 1.5em;
+        // Merge consecutive file chapters that share a parent directory under
+        // a heading naming it. A README directly inside the directory follows
+        // the heading as its introduction rather than being listed as a file.
+        let mut grouped: Vec<Chapter<'a>> = Vec::new();
+        for chapter in chapters {
+            let ChapterKind::File(path) = &chapter.kind else {
+                grouped.push(chapter);
+                continue;
+            };
+            let dir = Self::directory_of(path);
+            let continues_group = matches!(
+                grouped.last(),
+                Some(last) if matches!(&last.kind, ChapterKind::Directory(d) if *d == dir)
+            );
+            if !continues_group {
+                let mut group = Chapter::new(&dir, ChapterKind::Directory(dir.clone()));
+                group.events = directory_heading(&dir);
+                grouped.push(group);
             }
-            
-            h1 {
-                font-family: Helvetica, Arial, sans-serif;
-                font-size: 0.7em;
-                margin-top: 1.5em;
-                margin-bottom: 0.8em;
+            let Some(group) = grouped.last_mut() else { continue };
+            if is_readme(path) {
+                group.introductions.push(path.clone());
+                let after_heading = DIRECTORY_HEADING_EVENTS;
+                group.events.splice(after_heading..after_heading, readme_introduction(chapter.events));
+            } else {
+                group.files.extend(chapter.files);
+                group.events.extend(chapter.events);
             }
+        }
+        grouped
+    }
+    
+    /// Stylesheet for all content documents, built from the typography options.
+    /// A configured font size is expressed in ems against the default size so
+    /// the reader's own scaling still applies.
+    fn stylesheet(&self, metadata: &DocumentMetadata) -> String {
+        let base_size = match (&self.options.base_font_size, metadata.font_size) {
+            (Some(size), _) => size.clone(),
+            (None, Some(points)) => format!("{}em", points / DEFAULT_FONT_SIZE),
+            (None, None) => "1em".to_string(),
+        };
+        let code_size = match (&self.options.code_font_size, metadata.font_size) {
+            (Some(size), _) => size.clone(),
+            (None, Some(_)) => format!("{}em", CODE_FONT_RATIO),
+            (None, None) => "0.85em".to_string(),
+        };
+        let line_height = metadata.line_height.unwrap_or(1.5);
+        let code_line_height = metadata.line_height.unwrap_or(1.35);
+        let font_family = self.options.font_family.as_deref().unwrap_or("Georgia, serif");
+        let (font_face, code_family) = if self.options.embed_font {
+            (
+                format!(
+                    "@font-face {{ font-family: '{}'; src: url({}); }}",
+                    CODE_FONT_FAMILY, CODE_FONT_PATH
+                ),
+                format!("'{}', 'Courier New', Monaco, monospace", CODE_FONT_FAMILY),
+            )
+        } else {
+            (String::new(), "'Courier New', Monaco, monospace".to_string())
+        };
+        format!(r#"
+            {font_face}
             
-            h2 {
-                font-family: Helvetica, Arial, sans-serif;
-                font-size: 0.6em;
-                margin-top: 1.2em;
-                margin-bottom: 0.6em;
-            }
+            body {{
+                font-family: {font_family};
+                font-size: {base_size};
+                line-height: {line_height};
+                margin: 0 0.5em;
+            }}
             
-            h3 {
+            h1, h2, h3 {{
                 font-family: Helvetica, Arial, sans-serif;
-                font-size: 0.55em;
-                margin-top: 1em;
-                margin-bottom: 0.5em;
-            }
+                line-height: 1.2;
+            }}
+            
+            h1 {{
+                font-size: 1.6em;
+                margin: 1em 0 0.6em;
+            }}
+            
+            h2 {{
+                font-size: 1.3em;
+                margin: 1em 0 0.5em;
+            }}
             
-            pre {
+            h3 {{
+                font-size: 1.1em;
+                margin: 0.8em 0 0.4em;
+            }}
+            
+            pre {{
+                font-family: {code_family};
                 background-color: #f8f8f8;
-                padding: 0.8em;
-                font-size: 0.4em;
-                line-height: 1.4;
-                overflow-x: auto;
+                padding: 0.6em;
+                font-size: {code_size};
+                line-height: {code_line_height};
+                white-space: pre-wrap;
+                word-wrap: break-word;
                 border: 1px solid #e0e0e0;
                 border-radius: 3px;
                 margin: 0.8em 0;
-            }
+            }}
             
-            code {
-                font-family: 'Courier New', Monaco, monospace;
-                font-size: 0.42em;
+            code {{
+                font-family: {code_family};
+                font-size: {code_size};
                 background-color: #f0f0f0;
-                padding: 0.2em 0.3em;
+                padding: 0.1em 0.2em;
                 border-radius: 2px;
-            }
+            }}
             
-            pre code {
+            pre code {{
                 background-color: transparent;
                 padding: 0;
-                font-size: 0.4em;
-            }
+                font-size: 1em;
+            }}
             
-            blockquote {
+            blockquote {{
                 border-left: 3px solid #ccc;
                 margin-left: 0;
                 padding-left: 1em;
                 color: #666;
                 font-style: italic;
-            }
+            }}
             
-            table {
+            table {{
                 width: 100%;
                 border-collapse: collapse;
-                font-size: 0.42em;
+                font-size: 0.9em;
                 margin: 0.8em 0;
-            }
+            }}
             
-            th, td {
+            th, td {{
                 border: 1px solid #ddd;
-                padding: 0.5em;
+                padding: 0.4em;
                 text-align: left;
-            }
+            }}
             
-            th {
+            th {{
                 background-color: #f5f5f5;
                 font-weight: bold;
-            }
+            }}
             
-            p {
+            .file-info {{
+                font-size: 0.85em;
+                color: #666;
+                margin: 0.4em 0 0.8em;
+                padding: 0.3em 0.6em;
+                border-left: 3px solid #ddd;
+            }}
+            
+            .file-info dt {{
+                float: left;
+                clear: left;
+                margin-right: 0.5em;
+                font-weight: bold;
+            }}
+            
+            .file-info dd {{
+                margin: 0;
+            }}
+            
+            p {{
                 margin: 0.8em 0;
-            }
+            }}
             
-            ul, ol {
+            ul, ol {{
                 margin: 0.8em 0;
                 padding-left: 2em;
-            }
+            }}
             
-            li {
+            li {{
                 margin: 0.3em 0;
+            }}
+        "#)
+    }
+    
+    /// The cover image file name, bytes and media type, if any
+    fn cover_image(&self, metadata: &DocumentMetadata, file_count: usize) -> Result<Option<(String, Vec<u8>, &'static str)>> {
+        if let Some(path) = &self.options.cover {
+            let extension = path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let Some(mime) = image_mime(&extension) else {
+                anyhow::bail!(
+                    "Unsupported cover image format '{}' for {} (expected png, jpg, gif, svg or webp)",
+                    extension, path.display()
+                );
+            };
+            let data = std::fs::read(path)
+                .with_context(|| format!("Failed to read cover image: {}", path.display()))?;
+            return Ok(Some((format!("cover.{}", extension), data, mime)));
+        }
+        
+        if !self.options.generate_cover {
+            return Ok(None);
+        }
+        let svg = generated_cover_svg(&metadata.title, metadata.date_label().as_deref(), file_count);
+        Ok(Some(("cover.svg".to_string(), svg.into_bytes(), "image/svg+xml")))
+    }
+    
+    fn directory_of(path: &str) -> String {
+        match Path::new(path).parent().map(|p| p.to_string_lossy().to_string()) {
+            Some(dir) if !dir.is_empty() => format!("{}/", dir),
+            _ => "./".to_string(),
+        }
+    }
+    
+    /// The name of a chapter's content documents, before `.xhtml`: the
+    /// anchor of its file or front matter section, so names follow the
+    /// anchors the generator assigned, or one made from a directory's path
+    fn chapter_name(chapter: &Chapter) -> String {
+        let name = match &chapter.kind {
+            ChapterKind::File(_) => chapter.files.first().map(|(_, id)| id.clone()),
+            ChapterKind::Directory(dir) => match dir.trim_end_matches('/') {
+                "." => Some("dir-root".to_string()),
+                dir => Some(slugify(&format!("dir/{}", dir))),
+            },
+            ChapterKind::Front => chapter.events.iter().find_map(|event| match event {
+                Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+                _ => None,
+            }),
+        };
+        name.unwrap_or_else(|| "title".to_string())
+    }
+    
+    /// Render a chapter to one or more XHTML bodies, splitting between
+    /// top-level blocks once a part exceeds the size limit, each named from
+    /// `chapter_name` and claimed in `names`. Heading ids are recorded
+    /// against the content document they end up in.
+    fn render_chapter(&self, chapter: Chapter, names: &mut AnchorRegistry, ids: &mut HashMap<String, String>) -> Vec<ChapterPart> {
+        let name = Self::chapter_name(&chapter);
+        let mut parts: Vec<(String, Vec<String>)> = vec![(String::new(), Vec::new())];
+        let mut block: Vec<Event> = Vec::new();
+        let mut block_ids: Vec<String> = Vec::new();
+        let mut depth = 0usize;
+        
+        for event in chapter.events {
+            match &event {
+                Event::Start(Tag::Heading { id: Some(id), .. }) => {
+                    block_ids.push(id.to_string());
+                    depth += 1;
+                }
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            block.push(event);
+            if depth > 0 {
+                continue;
+            }
+            
+            let mut block_html = String::new();
+            html::push_html(&mut block_html, block.drain(..));
+            // Generator page breaks are redundant between chapters
+            if block_html.trim() == "<p>\\newpage</p>" {
+                continue;
             }
-        "#;
+            
+            // A single large listing is cut into several blocks between lines
+            for piece in split_preformatted(&block_html, self.options.max_chapter_bytes) {
+                let (body, _) = parts.last().expect("at least one part");
+                if !body.is_empty() && body.len() + piece.len() > self.options.max_chapter_bytes {
+                    parts.push((String::new(), Vec::new()));
+                }
+                let (body, part_ids) = parts.last_mut().expect("at least one part");
+                body.push_str(&piece);
+                part_ids.append(&mut block_ids);
+            }
+        }
         
+        let total = parts.len();
+        let mut files = chapter.files;
+        let mut introductions = chapter.introductions.len();
+        parts.into_iter()
+            .enumerate()
+            .map(|(i, (html, part_ids))| {
+                let href = match i {
+                    0 => format!("{}.xhtml", names.claim(&name)),
+                    i => format!("{}.xhtml", names.claim(&format!("{}-{}", name, i + 1))),
+                };
+                for id in part_ids {
+                    ids.insert(id, href.clone());
+                }
+                let title = if total > 1 {
+                    format!("{} ({}/{})", chapter.title, i + 1, total)
+                } else {
+                    chapter.title.clone()
+                };
+                ChapterPart {
+                    href,
+                    title,
+                    html,
+                    kind: chapter.kind.clone(),
+                    files: std::mem::take(&mut files),
+                    introductions: std::mem::take(&mut introductions),
+                    part: i + 1,
+                    total,
+                }
+            })
+            .collect()
+    }
+    
+    /// Point same-document fragment links at the content document that
+    /// now holds the target heading
+    fn rewrite_fragment_links(html: &str, own_href: &str, ids: &HashMap<String, String>) -> String {
+        static FRAGMENT_LINK: OnceLock<Regex> = OnceLock::new();
+        let re = FRAGMENT_LINK.get_or_init(|| Regex::new(r##"href="#([^"]+)""##).unwrap());
+        re.replace_all(html, |caps: &regex::Captures| {
+            match ids.get(&caps[1]) {
+                Some(href) if href != own_href => format!(r#"href="{}#{}""#, href, &caps[1]),
+                _ => caps[0].to_string(),
+            }
+        }).to_string()
+    }
+}
+
+/// Split a `<pre>` block larger than `limit` bytes into several `<pre>`
+/// blocks at line ends. Elements open at a cut (the `<pre>` itself, `<code>`,
+/// highlighting spans) are closed and reopened so every piece is well-formed;
+/// other blocks are returned unchanged.
+fn split_preformatted(block: &str, limit: usize) -> Vec<String> {
+    if block.len() <= limit || !block.trim_start().starts_with("<pre") {
+        return vec![block.to_string()];
+    }
+    let last_line_end = block.rfind('\n').unwrap_or(0);
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    // (element name, opening tag) for each element open at this point
+    let mut open: Vec<(&str, &str)> = Vec::new();
+    let mut pos = 0;
+    
+    while pos < block.len() {
+        let rest = &block[pos..];
+        if rest.starts_with('<') {
+            let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            let tag = &rest[..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else if !tag.ends_with("/>") && !tag.starts_with("<!") {
+                let name = tag[1..].split(|c: char| c.is_whitespace() || c == '>').next().unwrap_or("");
+                open.push((name, tag));
+            }
+            current.push_str(tag);
+            pos += end;
+            continue;
+        }
+        
+        let end = match rest.find(['<', '\n']) {
+            Some(i) if rest.as_bytes()[i] == b'\n' => i + 1,
+            Some(i) => i,
+            None => rest.len(),
+        };
+        current.push_str(&rest[..end]);
+        pos += end;
+        
+        // Cut after a line end once the piece is large enough, unless only
+        // the final line remains
+        if current.ends_with('\n') && current.len() >= limit && pos <= last_line_end && !open.is_empty() {
+            for (name, _) in open.iter().rev() {
+                current.push_str(&format!("</{}>", name));
+            }
+            current.push('\n');
+            pieces.push(std::mem::take(&mut current));
+            for (_, tag) in &open {
+                current.push_str(tag);
+            }
+        }
+    }
+    pieces.push(current);
+    pieces
+}
+
+/// Media type for an image file extension EPUB readers support
+fn image_mime(extension: &str) -> Option<&'static str> {
+    match extension {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Packages images referenced from rendered markdown under `images/`
+struct ImagePackager<'a> {
+    root: &'a Path,
+    max_bytes: u64,
+    /// Where images kept only as alt text are noted
+    warnings: Warnings,
+    /// Package path for each source image already added
+    packaged: HashMap<PathBuf, String>,
+    resources: Vec<(String, Vec<u8>, &'static str)>,
+}
+
+impl<'a> ImagePackager<'a> {
+    fn new(root: &'a Path, max_bytes: u64, warnings: Warnings) -> Self {
+        Self { root, max_bytes, warnings, packaged: HashMap::new(), resources: Vec::new() }
+    }
+    
+    /// Point `<img>` tags at packaged copies of their images, resolving
+    /// relative sources against `base_dir` in the repository. Images that
+    /// can't be packaged are replaced by their alt text; remote images are
+    /// left alone.
+    fn rewrite(&mut self, html: &str, base_dir: &str) -> String {
+        static IMG_TAG: OnceLock<Regex> = OnceLock::new();
+        static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+        let img_tag = IMG_TAG.get_or_init(|| Regex::new(r"<img\b[^>]*>").unwrap());
+        let attribute = ATTRIBUTE.get_or_init(|| {
+            Regex::new(r#"\b(src|alt)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+        });
+        
+        img_tag.replace_all(html, |caps: &regex::Captures| {
+            let tag = &caps[0];
+            let mut src = None;
+            let mut alt = "";
+            for attr in attribute.captures_iter(tag) {
+                let value = attr.get(2).or_else(|| attr.get(3)).map_or("", |m| m.as_str());
+                match &attr[1] {
+                    "src" => src = Some((attr.get(0).unwrap().as_str(), value)),
+                    _ => alt = value,
+                }
+            }
+            let Some((src_attribute, src)) = src else {
+                return tag.to_string();
+            };
+            if src.contains("://") || src.starts_with("data:") {
+                return tag.to_string();
+            }
+            let Some(href) = self.package(src, base_dir) else {
+                return format!(r#"<span class="image-alt">{}</span>"#, alt);
+            };
+            let mut packaged = tag.replacen(src_attribute, &format!(r#"src="{}""#, href), 1);
+            // Raw HTML images are often not closed, which XHTML requires
+            if !packaged.ends_with("/>") {
+                packaged.pop();
+                packaged.push_str(" />");
+            }
+            packaged
+        }).to_string()
+    }
+    
+    fn package(&mut self, src: &str, base_dir: &str) -> Option<String> {
+        let src = percent_decode(&src.replace("&amp;", "&"));
+        let src = src.split(['#', '?']).next().unwrap_or_default();
+        let relative = match src.strip_prefix('/') {
+            Some(from_root) => PathBuf::from(from_root),
+            None => Path::new(base_dir).join(src),
+        };
+        let root = self.root.canonicalize().ok()?;
+        let Ok(path) = root.join(&relative).canonicalize() else {
+            self.warnings.push(
+                WarningCategory::Image,
+                Some(&relative.display().to_string()),
+                "Not found, keeping its alt text",
+            );
+            return None;
+        };
+        if !path.starts_with(&root) {
+            return None;
+        }
+        if let Some(href) = self.packaged.get(&path) {
+            return Some(href.clone());
+        }
+        
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        let mime = image_mime(&extension)?;
+        let size = std::fs::metadata(&path).ok()?.len();
+        if size > self.max_bytes {
+            self.warnings.push(
+                WarningCategory::Image,
+                Some(&relative.display().to_string()),
+                format!("Larger than {} KB, keeping its alt text", self.max_bytes / 1024),
+            );
+            return None;
+        }
+        let mut data = std::fs::read(&path).ok()?;
+        // SVG content documents must be XML with a declaration
+        if mime == "image/svg+xml" && !data.starts_with(b"<?xml") {
+            let mut declared = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_vec();
+            declared.extend(data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(&data));
+            data = declared;
+        }
+        
+        let href = format!("images/image{}.{}", self.resources.len() + 1, extension);
+        self.packaged.insert(path, href.clone());
+        self.resources.push((href.clone(), data, mime));
+        Some(href)
+    }
+}
+
+/// Decode `%XX` escapes in a URL path, leaving invalid sequences as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A plain cover showing the title, date and file count as SVG text
+fn generated_cover_svg(title: &str, date: Option<&str>, file_count: usize) -> String {
+    // Keep long repository names inside the page
+    let title: String = if title.chars().count() > 28 {
+        title.chars().take(27).chain(std::iter::once('…')).collect()
+    } else {
+        title.to_string()
+    };
+    let files = if file_count == 1 { "1 file".to_string() } else { format!("{} files", file_count) };
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="600" height="800" viewBox="0 0 600 800">
+  <rect width="600" height="800" fill="#1f2933"/>
+  <rect x="40" y="40" width="520" height="720" fill="none" stroke="#52606d" stroke-width="2"/>
+  <text x="300" y="340" font-family="Helvetica, Arial, sans-serif" font-size="40" font-weight="bold" fill="#f5f7fa" text-anchor="middle">{}</text>
+  <text x="300" y="400" font-family="Helvetica, Arial, sans-serif" font-size="22" fill="#cbd2d9" text-anchor="middle">{}</text>
+  <text x="300" y="440" font-family="Helvetica, Arial, sans-serif" font-size="22" fill="#cbd2d9" text-anchor="middle">{}</text>
+</svg>
+"##,
+        escape_html(&title),
+        escape_html(date.unwrap_or_default()),
+        files
+    )
+}
+
+/// Overrides for readers in night mode. Code keeps the syntax theme's own
+/// colors when the theme is dark; otherwise it takes the colors of the dark
+/// theme paired with it.
+/// Readers without media query support ignore the block.
+fn dark_mode_stylesheet(highlighter: &SyntaxHighlighter) -> String {
+    let hex = |(r, g, b): (u8, u8, u8)| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    let (code_background, code_color, token_colors) = match highlighter.night_palette() {
+        // A light theme's code keeps its inline colors by day; at night the
+        // paired dark theme's colors win over them
+        Some(night) => {
+            let (background, foreground) = (hex(night.background), hex(night.foreground));
+            let mut rules = format!(r#"
+                pre[style] {{
+                    background-color: {background} !important;
+                }}
+                
+                pre span[style] {{
+                    color: {foreground} !important;
+                }}
+"#);
+            for (light, dark) in &night.colors {
+                rules.push_str(&format!(
+                    "                pre span[style^=\"color:{};\"] {{ color: {} !important; }}\n",
+                    hex(*light), hex(*dark)
+                ));
+            }
+            (background, foreground, rules)
+        }
+        None => {
+            let theme_color = |color: Option<(u8, u8, u8, u8)>, default: &str| {
+                color.map_or(default.to_string(), |(r, g, b, _)| hex((r, g, b)))
+            };
+            (
+                theme_color(highlighter.get_theme_background(), "#2b2f36"),
+                theme_color(highlighter.get_theme_foreground(), "#d8dce2"),
+                String::new(),
+            )
+        }
+    };
+    format!(r#"
+            @media (prefers-color-scheme: dark) {{
+                body {{
+                    background-color: #1e2126;
+                    color: #d4d7dc;
+                }}
+                
+                pre {{
+                    background-color: {code_background};
+                    color: {code_color};
+                    border-color: #3a3f47;
+                }}
+                {token_colors}
+                code {{
+                    background-color: {code_background};
+                    color: {code_color};
+                }}
+                
+                pre code {{
+                    background-color: transparent;
+                }}
+                
+                blockquote {{
+                    border-left-color: #4a5059;
+                    color: #a3a9b1;
+                }}
+                
+                th, td {{
+                    border-color: #3a3f47;
+                }}
+                
+                th {{
+                    background-color: #2a2e35;
+                }}
+                
+                a {{
+                    color: #8ab4f8;
+                }}
+            }}
+"#)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl DocumentRenderer for EpubRenderer {
+    fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
+        // Initialize syntax highlighter with the selected theme
+        let highlighter = highlighter_for(metadata)?;
+        
+        // Highlight every fenced block, including those from embedded markdown
+        let processed_events = highlight_code_blocks(events, &highlighter, &metadata.highlight_policy, &metadata.cancellation)?;
+        let processed_events = file_info_cards(processed_events);
+        
+        // Split into one content document per file (or directory), with
+        // oversized chapters split further into parts
+        let chapters = self.split_chapters(processed_events, &metadata.title, metadata.include_toc);
+        // File listings nest under a heading-only "File Contents" style divider
+        let base_level = match chapters.iter().take_while(|c| matches!(c.kind, ChapterKind::Front)).last() {
+            Some(last) if last.is_divider() => 2,
+            _ => 1,
+        };
+        let file_count: usize = chapters.iter().map(|c| c.files.len() + c.introductions.len()).sum();
+        let mut parts = Vec::new();
+        let mut ids = HashMap::new();
+        // Names the package already uses for the cover and navigation
+        let mut names = AnchorRegistry::reserving(&["cover", "nav"]);
+        for chapter in chapters {
+            metadata.cancellation.check()?;
+            let rendered = self.render_chapter(chapter, &mut names, &mut ids);
+            parts.extend(rendered);
+        }
+        
+        // Images referenced from markdown files resolve relative to the file
+        let mut images = self.options.image_root.as_deref()
+            .map(|root| ImagePackager::new(root, self.options.max_image_bytes, metadata.warnings.clone()));
+        if let Some(images) = &mut images {
+            for part in &mut parts {
+                let base_dir = match &part.kind {
+                    ChapterKind::File(path) => Self::directory_of(path),
+                    ChapterKind::Directory(dir) => dir.clone(),
+                    ChapterKind::Front => "./".to_string(),
+                };
+                part.html = images.rewrite(&part.html, &base_dir);
+            }
+        }
+        
+        // Create EPUB
+        let zip_library = ZipLibrary::new()
+            .map_err(|e| anyhow::anyhow!("Failed to create zip library: {}", e))?;
+        let mut builder = EpubBuilder::new(zip_library)
+            .map_err(|e| anyhow::anyhow!("Failed to create EPUB builder: {}", e))?;
+        builder.epub_version(EpubVersion::V30);
+        
+        // Set metadata
+        builder.metadata("title", &metadata.title)
+            .map_err(|e| anyhow::anyhow!("Failed to set title: {}", e))?;
+        if let Some(author) = &metadata.author {
+            builder.metadata("author", author)
+                .map_err(|e| anyhow::anyhow!("Failed to set author: {}", e))?;
+        }
+        builder.metadata("lang", &metadata.language)
+            .map_err(|e| anyhow::anyhow!("Failed to set language: {}", e))?;
+        // Re-generating identical content keeps the identifier, so library
+        // software recognizes it as the same book
+        builder.set_uuid(content_uuid(&metadata.title, &parts));
+        builder.set_generator(generator());
+        builder.add_description(metadata.description.clone().unwrap_or_else(|| {
+            i18n::strings_for(&metadata.language).source_listing(&metadata.title, file_count)
+        }));
+        for keyword in &metadata.keywords {
+            builder.add_subject(keyword.as_str());
+        }
+        if let Some(date) = metadata.date {
+            builder.set_publication_date(date);
+        }
+        
+        let mut css_content = self.stylesheet(metadata);
+        css_content.push_str(&dark_mode_stylesheet(&highlighter));
         builder.stylesheet(css_content.as_bytes())
             .map_err(|e| anyhow::anyhow!("Failed to add stylesheet: {}", e))?;
+        for (href, data, mime) in images.map(|images| images.resources).unwrap_or_default() {
+            builder.add_resource(&href, data.as_slice(), mime)
+                .map_err(|e| anyhow::anyhow!("Failed to add image {}: {}", href, e))?;
+        }
+        if self.options.embed_font {
+            builder.add_resource(CODE_FONT_PATH, EMBEDDED_FONTS[2].data, "font/ttf")
+                .map_err(|e| anyhow::anyhow!("Failed to add code font: {}", e))?;
+        }
         
-        // Add the content as a single chapter
-        let chapter_content = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
+        // The cover page comes first in the spine and stays out of the navigation
+        if let Some((file_name, data, mime)) = self.cover_image(metadata, file_count)? {
+            builder.add_cover_image(&file_name, data.as_slice(), mime)
+                .map_err(|e| anyhow::anyhow!("Failed to add cover image: {}", e))?;
+            let cover_page = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+    <title>{}</title>
+    <style>body {{ margin: 0; text-align: center; }} img {{ max-width: 100%; max-height: 100%; }}</style>
+</head>
+<body epub:type="cover">
+<img src="{}" alt="{}"/>
+</body>
+</html>"#,
+                escape_html(&metadata.title),
+                file_name,
+                escape_html(i18n::strings_for(&metadata.language).cover)
+            );
+            builder.add_content(
+                EpubContent::new("cover.xhtml", cover_page.as_bytes())
+                    .reftype(ReferenceType::Cover)
+            ).map_err(|e| anyhow::anyhow!("Failed to add cover page: {}", e))?;
+        }
+        
+        let groups = metadata.toc_depth
+            .map(|depth| CollapsedGroups::new(&parts, depth, i18n::strings_for(&metadata.language)));
+        let mut nav = NavBuilder { base_level, open_dirs: Vec::new(), groups };
+        for part in &parts {
+            let body = Self::rewrite_fragment_links(&part.html, &part.href, &ids);
+            let chapter_content = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE html>
 <html xmlns="http://www.w3.org/1999/xhtml">
 <head>
@@ -237,20 +1255,716 @@ impl DocumentRenderer for EpubRenderer {
 {}
 </body>
 </html>"#,
-            metadata.title,
-            html_output
-        );
-        
-        builder.add_content(
-            EpubContent::new("chapter1.xhtml", chapter_content.as_bytes())
-                .title(&metadata.title)
-        ).map_err(|e| anyhow::anyhow!("Failed to add content: {}", e))?;
+                escape_html(&part.title),
+                body
+            );
+            
+            let (nav_title, level, children) = nav.entry(part, &ids);
+            let content = children.into_iter().fold(
+                EpubContent::new(part.href.as_str(), chapter_content.as_bytes())
+                    .title(nav_title)
+                    .level(level),
+                EpubContent::child,
+            );
+            builder.add_content(content)
+                .map_err(|e| anyhow::anyhow!("Failed to add content: {}", e))?;
+        }
         
         // Generate EPUB
         let mut buffer = Vec::new();
         builder.generate(&mut buffer)
             .map_err(|e| anyhow::anyhow!("Failed to generate EPUB: {}", e))?;
         
-        Ok(buffer)
+        // epub-builder has no publisher field and only puts the generator in
+        // the navigation document, so both are added to the package afterwards
+        let mut extra = String::new();
+        if let Some(publisher) = &metadata.publisher {
+            extra.push_str(&format!("    <dc:publisher>{}</dc:publisher>\n", escape_html(publisher)));
+        }
+        extra.push_str(&format!("    <meta name=\"generator\" content=\"{}\"/>\n", escape_html(&generator())));
+        let epub = add_package_metadata(&buffer, &extra)?;
+        Self::validate(&epub)?;
+        Ok(epub)
+    }
+    
+    /// Render completely (including validation) before touching the output,
+    /// so a failed run leaves an existing file in place
+    fn save_to_file(&self, markdown: &str, metadata: &DocumentMetadata, output_path: &Path) -> Result<()> {
+        let epub = self.render_markdown(markdown, metadata)?;
+        std::fs::write(output_path, epub)
+            .with_context(|| format!("Failed to write output file: {}", output_path.display()))
+    }
+}
+
+/// Generator name recorded in the package and navigation documents
+fn generator() -> String {
+    format!("scrollcast {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Name-based (v5) UUID from the title and a hash of the rendered chapters
+fn content_uuid(title: &str, parts: &[ChapterPart]) -> Uuid {
+    let mut content = Sha1::new();
+    for part in parts {
+        content.update(part.href.as_bytes());
+        content.update(part.html.as_bytes());
     }
-}
\ No newline at end of file
+    let name = format!("scrollcast:{}:{:x}", title, content.finalize());
+
+    let mut hasher = Sha1::new();
+    hasher.update(Uuid::NAMESPACE_URL.as_bytes());
+    hasher.update(name.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hasher.finalize()[..16]);
+    uuid::Builder::from_sha1_bytes(bytes).into_uuid()
+}
+
+/// Read a text entry from the archive
+fn read_archive_entry(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String> {
+    let mut entry = archive.by_name(name)
+        .map_err(|_| anyhow::anyhow!("{} is missing", name))?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)
+        .with_context(|| format!("{} is not valid UTF-8", name))?;
+    Ok(content)
+}
+
+/// Copy the EPUB archive, inserting extra elements at the end of the package metadata
+fn add_package_metadata(epub: &[u8], extra: &str) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(epub))
+        .context("Failed to read generated EPUB")?;
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.name() != PACKAGE_DOCUMENT {
+            writer.raw_copy_file(entry)?;
+            continue;
+        }
+        let mut opf = String::new();
+        entry.read_to_string(&mut opf)?;
+        let opf = opf.replacen("  </metadata>", &format!("{}  </metadata>", extra), 1);
+        writer.start_file(PACKAGE_DOCUMENT, zip::write::SimpleFileOptions::default())?;
+        writer.write_all(opf.as_bytes())?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown_generator::{FileInfo, MarkdownGenerator, TocOptions};
+    use chrono::TimeZone;
+    use std::io::{Cursor, Read};
+
+    fn file(path: &str, content: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            content: content.into(),
+            language: MarkdownGenerator::detect_language(path),
+            size: content.len(),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }
+    }
+
+    fn sample_files() -> Vec<FileInfo> {
+        vec![
+            file("README.md", "# Sample\n\n### Usage\n\nRun it.\n"),
+            file("src/lib.rs", "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n"),
+            file("src/main.rs", "fn main() {\n    println!(\"hi\");\n}\n"),
+            file("tests/cli.rs", "#[test]\nfn runs() {}\n"),
+        ]
+    }
+
+    fn render(files: &[FileInfo], options: EpubOptions) -> Vec<u8> {
+        let markdown = MarkdownGenerator::new(true, true).generate_markdown(files, "sample").unwrap();
+        let metadata = DocumentMetadata { title: "sample".to_string(), ..Default::default() };
+        EpubRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap()
+    }
+
+    /// Whether the archive entry `name` is one of the content documents,
+    /// rather than the cover or navigation
+    fn is_chapter(name: &str) -> bool {
+        name.starts_with("OEBPS/") && name.ends_with(".xhtml") && !["OEBPS/cover.xhtml", "OEBPS/nav.xhtml"].contains(&name)
+    }
+
+    fn read_entry(epub: &[u8], name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let mut entry = archive.by_name(name).unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    /// Locate the OPF through the container and return its contents
+    fn package_document(epub: &[u8]) -> String {
+        let container = read_entry(epub, "META-INF/container.xml");
+        let path = container.split("full-path=\"").nth(1).unwrap().split('"').next().unwrap();
+        read_entry(epub, path)
+    }
+
+    /// Title page, table of contents, file structure and the file contents divider
+    const FRONT_SECTIONS: usize = 4;
+    /// The generated cover page leads the spine but has no nav entry
+    const COVER_PAGE: usize = 1;
+
+    #[test]
+    fn test_one_spine_item_per_file() {
+        let files = sample_files();
+        let epub = render(&files, EpubOptions::default());
+        let opf = package_document(&epub);
+
+        assert_eq!(opf.matches("<itemref ").count(), COVER_PAGE + FRONT_SECTIONS + files.len());
+        let ncx = read_entry(&epub, "OEBPS/toc.ncx");
+        for name in ["README.md", "lib.rs", "main.rs", "cli.rs"] {
+            assert!(ncx.contains(name), "missing nav entry for {}", name);
+        }
+
+        // The README's own headings stay inside its chapter
+        assert!(!ncx.contains("<text>Usage</text>"));
+    }
+
+    #[test]
+    fn test_group_by_directory() {
+        let options = EpubOptions { group_by_directory: true, ..Default::default() };
+        let epub = render(&sample_files(), options);
+        let opf = package_document(&epub);
+
+        // ./, src/ and tests/
+        assert_eq!(opf.matches("<itemref ").count(), COVER_PAGE + FRONT_SECTIONS + 3);
+        let nav = read_entry(&epub, "OEBPS/nav.xhtml");
+        assert!(nav.contains(r#"<a href="dir-src.xhtml#src-main-rs">main.rs</a>"#), "{}", nav);
+    }
+
+    #[test]
+    fn test_readmes_introduce_their_directory_chapters() {
+        let files = crate::file_processor::FileProcessor::new()
+            .process_directory(Path::new("testfiles/packages_project"))
+            .unwrap();
+        let options = EpubOptions { group_by_directory: true, ..Default::default() };
+        let epub = render(&files, options);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let names: Vec<String> = archive.file_names()
+            .filter(|name| is_chapter(name))
+            .map(str::to_string)
+            .collect();
+        let chapters: Vec<String> = names.iter().map(|name| {
+            let mut contents = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+            contents
+        }).collect();
+        let chapter = |heading: &str| chapters.iter()
+            .find(|chapter| chapter.contains(&format!("<h2>{}</h2>", heading)))
+            .unwrap_or_else(|| panic!("no chapter for {}", heading));
+
+        let api = chapter("packages/api/");
+        let heading = api.find("<h2>packages/api/</h2>").unwrap();
+        let introduction = api.find("Serves the public HTTP endpoints").unwrap();
+        let first_file = api.find("id=\"packages-api-handlers-rs\"").unwrap();
+        assert!(heading < introduction && introduction < first_file, "{}", api);
+        assert!(!api.contains("packages/api/README.md"), "{}", api);
+        // The nested README introduces its own directory, not the parent's
+        assert!(!api.contains("Version 2 routes"), "{}", api);
+        assert!(chapter("packages/api/v2/").contains("Version 2 routes"));
+
+        for text in ["A workspace of small packages", "Serves the public HTTP endpoints", "Command line front end"] {
+            let count: usize = chapters.iter().map(|chapter| chapter.matches(text).count()).sum();
+            assert_eq!(count, 1, "{}", text);
+        }
+        let nav = read_entry(&epub, "OEBPS/nav.xhtml");
+        assert!(!nav.to_lowercase().contains("readme.md"), "{}", nav);
+        assert!(nav.contains(">handlers.rs</a>"), "{}", nav);
+    }
+
+    #[test]
+    fn test_oversized_file_split_into_parts() {
+        let body: Vec<String> = (0..400).map(|i| format!("let value_{} = {};", i, i)).collect();
+        let mut files = sample_files();
+        files.push(file("src/big.rs", &body.join("\n\n")));
+        let options = EpubOptions { max_chapter_bytes: 4 * 1024, ..Default::default() };
+        let epub = render(&files, options);
+
+        let ncx = read_entry(&epub, "OEBPS/toc.ncx");
+        assert!(ncx.contains("big.rs (1/"));
+        assert!(ncx.contains("big.rs (2/"));
+    }
+
+    /// Text of every `<pre>` in the spine documents titled `title`, in reading order
+    fn preformatted_text(epub: &[u8], title: &str) -> Vec<String> {
+        let opf = package_document(epub);
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+        let hrefs: HashMap<&str, &str> = doc.descendants()
+            .filter(|n| n.has_tag_name("item"))
+            .map(|n| (n.attribute("id").unwrap(), n.attribute("href").unwrap()))
+            .collect();
+        let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+        doc.descendants()
+            .filter(|n| n.has_tag_name("itemref"))
+            .flat_map(|itemref| {
+                let href = hrefs[itemref.attribute("idref").unwrap()];
+                let xhtml = read_entry(epub, &format!("OEBPS/{}", href));
+                let chapter = roxmltree::Document::parse_with_options(&xhtml, options).unwrap();
+                let heading = chapter.descendants().find(|n| n.has_tag_name("title")).and_then(|n| n.text());
+                if !heading.is_some_and(|heading| heading.starts_with(title)) {
+                    return Vec::new();
+                }
+                chapter.descendants()
+                    .filter(|n| n.has_tag_name("pre"))
+                    .map(|pre| {
+                        let text: String = pre.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect();
+                        // Readers drop a newline directly after <pre>, as HTML does
+                        text.strip_prefix('\n').map(str::to_string).unwrap_or(text)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_large_listing_split_between_lines() {
+        let mut source = String::new();
+        let mut i = 0;
+        while source.len() < 1024 * 1024 {
+            source.push_str(&format!("fn item_{}() -> &'static str {{ \"<value {}>\" }}\n", i, i));
+            i += 1;
+        }
+        let markdown = MarkdownGenerator::new(false, true)
+            .generate_markdown(&[file("src/big.rs", &source)], "big")
+            .unwrap();
+        let options = EpubOptions { generate_cover: false, ..Default::default() };
+        let epub = EpubRenderer::with_options(options)
+            .render_markdown(&markdown, &DocumentMetadata::default())
+            .unwrap();
+
+        let listings = preformatted_text(&epub, "src/big.rs");
+        assert!(listings.len() > 4, "expected several listing pieces, got {}", listings.len());
+        assert_eq!(listings.concat(), source);
+
+        let ncx = read_entry(&epub, "OEBPS/toc.ncx");
+        assert!(ncx.contains("big.rs (2/"));
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index).unwrap();
+            if entry.name().ends_with(".xhtml") {
+                assert!(entry.size() < 300 * 1024, "{} is {} bytes", entry.name(), entry.size());
+            }
+        }
+    }
+
+    #[test]
+    fn test_toc_links_point_at_chapter_files() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        // Chapters are named after the anchors of their sections and files
+        let toc = read_entry(&epub, "OEBPS/table-of-contents.xhtml");
+        assert!(read_entry(&epub, "OEBPS/src-main-rs.xhtml").contains(r#"id="src-main-rs""#));
+        assert!(toc.contains(r#"href="src-main-rs.xhtml#src-main-rs""#), "{}", toc);
+
+        let archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let names: Vec<&str> = archive.file_names().filter(|name| is_chapter(name)).collect();
+        assert_eq!(names.len(), FRONT_SECTIONS + 4, "{:?}", names);
+        assert!(names.iter().all(|name| !read_entry(&epub, name).contains("\\newpage")));
+    }
+
+    #[test]
+    fn test_nav_nests_files_under_directories() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let nav = read_entry(&epub, "OEBPS/nav.xhtml");
+
+        // Four front sections, README.md, src/ with two files, tests/ with one
+        assert_eq!(nav.matches("<a href=").count(), FRONT_SECTIONS + 4 + 2);
+        let nested = Regex::new(
+            r#">src/</a>\s*<ol>\s*<li><a href="[^"]+">lib.rs</a></li>\s*<li><a href="[^"]+">main.rs</a></li>\s*</ol>"#
+        ).unwrap();
+        assert!(nested.is_match(&nav), "{}", nav);
+        let under_divider = Regex::new(r#">File Contents</a>\s*<ol>\s*<li><a href="[^"]+">README.md</a>"#).unwrap();
+        assert!(under_divider.is_match(&nav));
+    }
+
+    #[test]
+    fn test_nav_collapses_to_directories_like_the_toc() {
+        let mut files = sample_files();
+        files.push(file("src/net/tcp.rs", "pub fn connect() {}\n"));
+        let toc = TocOptions { limit: Some(2), depth: 1, appendix: true };
+        let markdown = MarkdownGenerator::new(true, true)
+            .with_toc_options(toc)
+            .generate_markdown(&files, "sample")
+            .unwrap();
+        let metadata = DocumentMetadata { title: "sample".to_string(), toc_depth: Some(1), ..Default::default() };
+
+        let titles = Regex::new(r#"<a href="([^"]+)">([^<]+)</a>"#).unwrap();
+        for options in [EpubOptions::default(), EpubOptions { group_by_directory: true, ..Default::default() }] {
+            let epub = EpubRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap();
+            let nav = read_entry(&epub, "OEBPS/nav.xhtml");
+            let entries: Vec<(String, String)> = titles.captures_iter(&nav)
+                .map(|c| (c[1].to_string(), c[2].to_string()))
+                .collect();
+            let after_divider: Vec<&str> = entries.iter()
+                .skip_while(|(_, title)| title != "File Contents")
+                .skip(1)
+                .map(|(_, title)| title.as_str())
+                .collect();
+            assert_eq!(after_divider, ["./ (1 file)", "src/ (3 files)", "tests/ (1 file)", "File Index"], "{}", nav);
+
+            // Every file still has its chapter, and each entry opens one
+            let archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+            let names: Vec<&str> = archive.file_names().filter(|name| is_chapter(name)).collect();
+            for (href, _) in &entries {
+                assert!(names.contains(&format!("OEBPS/{}", href).as_str()), "{} in {:?}", href, names);
+            }
+            let index = read_entry(&epub, "OEBPS/file-index.xhtml");
+            assert!(index.contains("src-net-tcp-rs"), "{}", index);
+        }
+    }
+
+    #[test]
+    fn test_without_toc_keeps_machine_nav() {
+        let markdown = MarkdownGenerator::new(true, true).generate_markdown(&sample_files(), "sample").unwrap();
+        let metadata = DocumentMetadata { title: "sample".to_string(), include_toc: false, ..Default::default() };
+        let epub = EpubRenderer::new().render_markdown(&markdown, &metadata).unwrap();
+
+        let opf = package_document(&epub);
+        assert_eq!(opf.matches("<itemref ").count(), COVER_PAGE + FRONT_SECTIONS - 1 + 4);
+        let nav = read_entry(&epub, "OEBPS/nav.xhtml");
+        assert!(!nav.contains(">Table of Contents</a>"));
+        assert!(nav.contains(">main.rs</a>"));
+    }
+
+    #[test]
+    fn test_generated_cover_leads_spine() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let opf = package_document(&epub);
+
+        assert!(opf.contains(r#"properties="cover-image""#));
+        assert!(opf.contains(r#"href="cover.svg""#));
+        let spine = opf.split("<spine").nth(1).unwrap();
+        assert!(spine.trim_start_matches(|c| c != '<').starts_with(r#"<itemref idref="cover.xhtml"/>"#));
+
+        let svg = read_entry(&epub, "OEBPS/cover.svg");
+        assert!(svg.contains(">sample</text>"));
+        assert!(svg.contains(">4 files</text>"));
+    }
+
+    #[test]
+    fn test_cover_image_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cover = dir.path().join("cover.png");
+        std::fs::write(&cover, b"\x89PNG\r\n\x1a\n").unwrap();
+        let options = EpubOptions { cover: Some(cover), ..Default::default() };
+        let epub = render(&sample_files(), options);
+        let opf = package_document(&epub);
+
+        assert!(opf.contains(r#"media-type="image/png""#));
+        assert!(opf.contains(r#"properties="cover-image""#));
+        assert!(read_entry(&epub, "OEBPS/cover.xhtml").contains(r#"<img src="cover.png""#));
+    }
+
+    #[test]
+    fn test_unsupported_cover_format_errors() {
+        let options = EpubOptions { cover: Some(PathBuf::from("cover.bmp")), ..Default::default() };
+        let markdown = MarkdownGenerator::new(true, true).generate_markdown(&sample_files(), "sample").unwrap();
+        let err = EpubRenderer::with_options(options)
+            .render_markdown(&markdown, &DocumentMetadata::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("Unsupported cover image format 'bmp'"));
+    }
+
+    #[test]
+    fn test_no_cover_when_disabled() {
+        let options = EpubOptions { generate_cover: false, ..Default::default() };
+        let opf = package_document(&render(&sample_files(), options));
+        assert!(!opf.contains("cover-image"));
+    }
+
+    #[test]
+    fn test_default_stylesheet_uses_readable_sizes() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let css = read_entry(&epub, "OEBPS/stylesheet.css");
+
+        assert!(css.contains("font-size: 1em;"));
+        assert!(css.contains("font-size: 0.85em;"));
+        assert!(css.contains("white-space: pre-wrap;"));
+        assert!(!css.contains("0.45em"));
+        assert!(!css.contains("font-size: 0.4em"));
+    }
+
+    #[test]
+    fn test_stylesheet_uses_configured_typography() {
+        let options = EpubOptions {
+            base_font_size: Some("1.1em".to_string()),
+            code_font_size: Some("0.8em".to_string()),
+            font_family: Some("\"Literata\", serif".to_string()),
+            ..Default::default()
+        };
+        let css = EpubRenderer::with_options(options).stylesheet(&DocumentMetadata::default());
+        assert!(css.contains("font-family: \"Literata\", serif;"));
+        assert!(css.contains("font-size: 1.1em;"));
+        assert!(css.contains("font-size: 0.8em;"));
+
+        let compact = EpubRenderer::with_options(EpubOptions::compact()).stylesheet(&DocumentMetadata::default());
+        assert!(compact.contains("font-size: 0.45em;"));
+
+        let metadata = DocumentMetadata { font_size: Some(12.0), line_height: Some(1.4), ..Default::default() };
+        let css = EpubRenderer::new().stylesheet(&metadata);
+        assert!(css.contains("font-size: 1.2em;"));
+        assert!(css.contains("font-size: 0.9em;"));
+        assert!(css.contains("line-height: 1.4;"));
+        assert!(!css.contains("line-height: 1.5;"));
+    }
+
+    #[test]
+    fn test_embedded_code_font() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let opf = package_document(&epub);
+        let css = read_entry(&epub, "OEBPS/stylesheet.css");
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let font = archive.by_name("OEBPS/fonts/DejaVuSansMono.ttf").unwrap();
+        assert_eq!(font.size() as usize, EMBEDDED_FONTS[2].data.len());
+        let item = opf.lines().find(|line| line.contains(r#"href="fonts/DejaVuSansMono.ttf""#)).unwrap();
+        assert!(item.contains(r#"media-type="font/ttf""#));
+        assert!(css.contains("src: url(fonts/DejaVuSansMono.ttf)"));
+        assert!(css.contains("font-family: 'DejaVu Sans Mono', 'Courier New'"));
+
+        let options = EpubOptions { embed_font: false, ..Default::default() };
+        let epub = render(&sample_files(), options);
+        let css = read_entry(&epub, "OEBPS/stylesheet.css");
+        assert!(zip::ZipArchive::new(Cursor::new(&epub)).unwrap().by_name("OEBPS/fonts/DejaVuSansMono.ttf").is_err());
+        assert!(!package_document(&epub).contains("font/ttf"));
+        assert!(!css.contains("@font-face"));
+        assert!(css.contains("font-family: 'Courier New', Monaco, monospace;"));
+    }
+
+    #[test]
+    fn test_generated_epub_validates() {
+        let options = EpubOptions { group_by_directory: true, ..Default::default() };
+        EpubRenderer::validate(&render(&sample_files(), EpubOptions::default())).unwrap();
+        EpubRenderer::validate(&render(&sample_files(), options)).unwrap();
+    }
+
+    #[test]
+    fn test_validate_lists_each_problem() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).unwrap();
+            let name = entry.name().to_string();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            // Compress the mimetype, drop one chapter, break another and
+            // point the spine at an unknown item
+            let content = match name.as_str() {
+                "OEBPS/src-lib-rs.xhtml" => continue,
+                "OEBPS/src-main-rs.xhtml" => b"<html><body><p>unclosed</body></html>".to_vec(),
+                PACKAGE_DOCUMENT => String::from_utf8(content).unwrap()
+                    .replace("</spine>", "<itemref idref=\"missing\"/></spine>")
+                    .into_bytes(),
+                _ => content,
+            };
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(&content).unwrap();
+        }
+        let broken = writer.finish().unwrap().into_inner();
+
+        let message = EpubRenderer::validate(&broken).unwrap_err().to_string();
+        assert!(message.contains("mimetype is compressed"), "{}", message);
+        assert!(message.contains("'src-lib-rs.xhtml' is missing"), "{}", message);
+        assert!(message.contains("OEBPS/src-main-rs.xhtml is not well-formed"), "{}", message);
+        assert!(message.contains("spine item 'missing' is not in the manifest"), "{}", message);
+    }
+
+    #[test]
+    fn test_failed_render_keeps_existing_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("book.epub");
+        std::fs::write(&output, b"previous").unwrap();
+        let cover = dir.path().join("cover.bmp");
+        std::fs::write(&cover, b"BM").unwrap();
+
+        let options = EpubOptions { cover: Some(cover), ..Default::default() };
+        let result = EpubRenderer::with_options(options)
+            .save_to_file("# Title\n", &DocumentMetadata::default(), &output);
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&output).unwrap(), b"previous");
+    }
+
+    #[test]
+    fn test_dark_mode_overrides() {
+        let epub = render(&sample_files(), EpubOptions::default());
+        let css = read_entry(&epub, "OEBPS/stylesheet.css");
+        let dark = &css[css.find("@media (prefers-color-scheme: dark)").unwrap()..];
+        let pre = &dark[dark.find("pre {").unwrap()..];
+        assert!(pre.contains("background-color: #2b303b;"));
+        for selector in ["body {", "code {", "blockquote {", "th, td {", "pre[style] {", "pre span[style] {"] {
+            assert!(dark.contains(selector), "missing {}", selector);
+        }
+        let values = Regex::new(r": (#[0-9a-f]{6})").unwrap();
+        assert!(values.captures_iter(dark).all(|c| &c[1] != "#000000" && &c[1] != "#ffffff"), "{}", dark);
+
+        // A light theme's code keeps its inline colors, each with a night
+        // counterpart from the paired dark theme
+        let chapter = read_entry(&epub, "OEBPS/src-main-rs.xhtml");
+        let spans = Regex::new(r#"<span style="color:(#[0-9a-f]{6});"#).unwrap();
+        assert!(spans.is_match(&chapter), "{}", chapter);
+        for color in spans.captures_iter(&chapter) {
+            let rule = format!("pre span[style^=\"color:{};\"] {{ color: #", &color[1]);
+            assert!(dark.contains(&rule), "no night color for {} in {}", &color[1], dark);
+        }
+
+        // Dark syntax themes supply the night mode code colors
+        let metadata = DocumentMetadata { syntax_theme: "base16-ocean.dark".to_string(), ..Default::default() };
+        let markdown = MarkdownGenerator::new(true, true).generate_markdown(&sample_files(), "sample").unwrap();
+        let epub = EpubRenderer::new().render_markdown(&markdown, &metadata).unwrap();
+        let css = read_entry(&epub, "OEBPS/stylesheet.css");
+        let dark = &css[css.find("@media (prefers-color-scheme: dark)").unwrap()..];
+        assert!(dark.contains("background-color: #2b303b;"));
+    }
+
+    #[test]
+    fn test_readme_images_are_packaged() {
+        let repo = tempfile::tempdir().unwrap();
+        let png = b"\x89PNG\r\n\x1a\nnot really a picture".to_vec();
+        std::fs::create_dir_all(repo.path().join("docs")).unwrap();
+        std::fs::write(repo.path().join("docs/shot.png"), &png).unwrap();
+        std::fs::write(repo.path().join("docs/logo.svg"), "<svg xmlns=\"http://www.w3.org/2000/svg\"/>").unwrap();
+        std::fs::write(repo.path().join("docs/huge.png"), vec![0u8; 4096]).unwrap();
+        std::fs::write(
+            repo.path().join("README.md"),
+            "# Demo\n\n![Screenshot](docs/shot.png)\n\n<img src=\"docs/logo.svg\" alt=\"Logo\">\n\n\
+             ![Too big](docs/huge.png)\n\n![Gone](docs/missing.png)\n",
+        ).unwrap();
+
+        let files = crate::file_processor::FileProcessor::new().process_directory(repo.path()).unwrap();
+        let options = EpubOptions {
+            image_root: Some(repo.path().to_path_buf()),
+            max_image_bytes: 1024,
+            ..Default::default()
+        };
+        let epub = render(&files, options);
+        let opf = package_document(&epub);
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let readme = archive.file_names()
+            .filter(|name| is_chapter(name))
+            .map(|name| read_entry(&epub, name))
+            .find(|chapter| chapter.contains("Screenshot"))
+            .unwrap();
+        let mut packaged = Vec::new();
+        archive.by_name("OEBPS/images/image1.png").unwrap().read_to_end(&mut packaged).unwrap();
+        assert_eq!(packaged, png);
+        assert!(read_entry(&epub, "OEBPS/images/image2.svg").starts_with("<?xml version=\"1.0\""));
+        assert!(opf.contains(r#"media-type="image/png""#));
+        assert!(opf.contains(r#"media-type="image/svg+xml""#));
+
+        assert!(readme.contains(r#"<img src="images/image1.png" alt="Screenshot" />"#));
+        assert!(readme.contains(r#"<img src="images/image2.svg" alt="Logo" />"#));
+        assert!(readme.contains(r#"<span class="image-alt">Too big</span>"#));
+        assert!(readme.contains(r#"<span class="image-alt">Gone</span>"#));
+        assert!(!readme.contains("docs/"));
+    }
+
+    fn highlighted_block(theme: &str) -> String {
+        let markdown = "```rust\nfn main() {\n    let greeting = \"hello\"; // say hi\n}\n```\n";
+        let metadata = DocumentMetadata { syntax_theme: theme.to_string(), ..Default::default() };
+        let options = EpubOptions { generate_cover: false, ..Default::default() };
+        let epub = EpubRenderer::with_options(options).render_markdown(markdown, &metadata).unwrap();
+        let chapter = read_entry(&epub, "OEBPS/title.xhtml");
+        let start = chapter.find("<pre").unwrap();
+        let end = chapter[start..].find("</pre>").unwrap() + start;
+        chapter[start..end].to_string()
+    }
+
+    fn inline_colors(html: &str) -> Vec<String> {
+        let color = Regex::new(r"color:\s*(#[0-9a-fA-F]{6})").unwrap();
+        let mut colors: Vec<String> = color.captures_iter(html).map(|c| c[1].to_lowercase()).collect();
+        colors.sort();
+        colors.dedup();
+        colors
+    }
+
+    #[test]
+    fn test_code_uses_theme_inline_styles() {
+        let light = highlighted_block("InspiredGitHub");
+        let dark = highlighted_block("base16-ocean.dark");
+
+        assert!(!light.contains("class=\""));
+        assert!(!dark.contains("class=\""));
+        assert!(light.contains("<span style=\""));
+        assert_ne!(inline_colors(&light), inline_colors(&dark));
+
+        // Dark themes carry their background on the block itself
+        assert!(dark.starts_with("<pre style=\"background-color:#2b303b;\">"));
+    }
+
+    #[test]
+    fn test_package_names_title_author_and_language() {
+        let metadata = DocumentMetadata {
+            title: "Zahlungen <v2>".to_string(),
+            author: Some("Zoë Ðurić & 山田".to_string()),
+            language: "de".to_string(),
+            ..Default::default()
+        };
+        let epub = EpubRenderer::new().render_markdown("Some prose.\n", &metadata).unwrap();
+        let opf = package_document(&epub);
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+        let dc = |name: &str| -> Vec<String> {
+            doc.descendants()
+                .filter(|n| n.tag_name().name() == name && n.tag_name().namespace() == Some("http://purl.org/dc/elements/1.1/"))
+                .map(|n| n.text().unwrap_or_default().to_string())
+                .collect()
+        };
+        assert_eq!(dc("title"), vec!["Zahlungen <v2>"]);
+        assert_eq!(dc("creator"), vec!["Zoë Ðurić & 山田"]);
+        assert_eq!(dc("language"), vec!["de"]);
+        // The author isn't the publisher
+        assert!(dc("publisher").is_empty());
+    }
+
+    #[test]
+    fn test_package_metadata() {
+        let markdown = MarkdownGenerator::new(true, true).generate_markdown(&sample_files(), "sample").unwrap();
+        let metadata = DocumentMetadata::builder("sample")
+            .author("Tom & Jerry")
+            .publisher("Acme & Sons")
+            .created_at(chrono::Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap())
+            .description("Adds <numbers> & prints them")
+            .keywords(["rust", "cli & tools"])
+            .build();
+        let renderer = EpubRenderer::new();
+        let epub = renderer.render_markdown(&markdown, &metadata).unwrap();
+        let opf = package_document(&epub);
+
+        let doc = roxmltree::Document::parse(&opf).unwrap();
+        let dc = |name: &str| -> Vec<String> {
+            doc.descendants()
+                .filter(|n| n.tag_name().name() == name && n.tag_name().namespace() == Some("http://purl.org/dc/elements/1.1/"))
+                .map(|n| n.text().unwrap_or_default().to_string())
+                .collect()
+        };
+        assert_eq!(dc("description"), vec!["Adds <numbers> & prints them"]);
+        assert_eq!(dc("publisher"), vec!["Acme & Sons"]);
+        assert_eq!(dc("subject"), vec!["rust", "cli & tools"]);
+        assert!(dc("date")[0].starts_with("2024-03-01"));
+        let generator = doc.descendants()
+            .find(|n| n.attribute("name") == Some("generator"))
+            .and_then(|n| n.attribute("content"))
+            .unwrap();
+        assert_eq!(generator, format!("scrollcast {}", env!("CARGO_PKG_VERSION")));
+
+        // Identical content shares an identifier, different content does not
+        let identifier = dc("identifier")[0].clone();
+        assert!(identifier.starts_with("urn:uuid:"));
+        assert_eq!(identifier.as_bytes()[23], b'5');
+        let again = package_document(&renderer.render_markdown(&markdown, &metadata).unwrap());
+        assert!(again.contains(&identifier));
+        let other = package_document(&renderer.render_markdown("# other\n", &metadata).unwrap());
+        assert!(!other.contains(&identifier));
+
+        // The rewritten archive still starts with the uncompressed mimetype
+        let mut archive = zip::ZipArchive::new(Cursor::new(&epub)).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+    }
+}
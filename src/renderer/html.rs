@@ -1,7 +1,7 @@
 use anyhow::Result;
-use pulldown_cmark::{Event, html, Tag, TagEnd, CowStr};
-use crate::renderer::{DocumentRenderer, DocumentMetadata};
-use crate::syntax::highlighter::SyntaxHighlighter;
+use pulldown_cmark::{Event, html};
+use crate::i18n;
+use crate::renderer::{file_info_cards, highlight_code_blocks, highlighter_for, DocumentRenderer, DocumentMetadata, CODE_FONT_RATIO};
 
 pub struct HtmlRenderer;
 
@@ -14,58 +14,49 @@ impl HtmlRenderer {
 impl DocumentRenderer for HtmlRenderer {
     fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
         // Initialize syntax highlighter
-        let highlighter = SyntaxHighlighter::new()?;
-        
-        // Process events to add syntax highlighting
-        let mut processed_events = Vec::new();
-        let mut i = 0;
-        
-        while i < events.len() {
-            match &events[i] {
-                Event::Start(Tag::CodeBlock(kind)) => {
-                    // Extract language from code block
-                    let language = match kind {
-                        pulldown_cmark::CodeBlockKind::Fenced(lang) => {
-                            if lang.is_empty() { None } else { Some(lang.as_ref()) }
-                        }
-                        _ => None,
-                    };
-                    
-                    // Find the corresponding text and end events
-                    i += 1;
-                    let mut code_content = String::new();
-                    while i < events.len() {
-                        match &events[i] {
-                            Event::Text(text) => {
-                                code_content.push_str(text);
-                            }
-                            Event::End(TagEnd::CodeBlock) => {
-                                break;
-                            }
-                            _ => {}
-                        }
-                        i += 1;
-                    }
-                    
-                    // Generate highlighted HTML
-                    if language.is_some() {
-                        let highlighted_html = highlighter.highlight_to_html(&code_content, language);
-                        let wrapped_html = format!("<pre>{}</pre>", highlighted_html);
-                        processed_events.push(Event::Html(CowStr::Boxed(wrapped_html.into_boxed_str())));
-                    } else {
-                        // No language specified, use regular code block
-                        processed_events.push(Event::Start(Tag::CodeBlock(kind.clone())));
-                        processed_events.push(Event::Text(CowStr::Boxed(code_content.into_boxed_str())));
-                        processed_events.push(Event::End(TagEnd::CodeBlock));
-                    }
-                }
-                _ => {
-                    processed_events.push(events[i].clone());
-                }
+        let highlighter = highlighter_for(metadata)?;
+        
+        // Highlight every fenced block, including those from embedded markdown
+        let processed_events = highlight_code_blocks(events, &highlighter, &metadata.highlight_policy, &metadata.cancellation)?;
+        let processed_events = file_info_cards(processed_events);
+        
+        // Expose a configured palette to stylesheets as CSS variables
+        let palette_css = match &metadata.palette {
+            Some(palette) => {
+                let variables: String = palette.entries().iter()
+                    .map(|(key, color)| format!("            --color-{}: {};\n", key.replace('_', "-"), color))
+                    .collect();
+                format!("        :root {{\n{}        }}\n        \n", variables)
             }
-            i += 1;
+            None => String::new(),
+        };
+        
+        // Configured text size and spacing override the defaults above
+        let mut typography_css = String::new();
+        if let Some(font_size) = metadata.font_size {
+            typography_css.push_str(&format!(
+                "        body {{ font-size: {}pt; }}\n        pre code {{ font-size: {}em; }}\n",
+                font_size, CODE_FONT_RATIO
+            ));
+        }
+        if let Some(line_height) = metadata.line_height {
+            typography_css.push_str(&format!(
+                "        body, pre {{ line-height: {}; }}\n",
+                line_height
+            ));
         }
         
+        // Larger text and always-underlined links for low-vision readers
+        let contrast_css = if metadata.enforce_contrast {
+            "        body { font-size: 1.25rem; color: #000; }\n        a { text-decoration: underline; }\n"
+        } else {
+            ""
+        };
+        
+        let author_meta = metadata.author.as_deref()
+            .map(|author| format!("    <meta name=\"author\" content=\"{}\">\n", escape_html(author)))
+            .unwrap_or_default();
+        
         // Convert processed events to HTML
         let mut body_html = String::new();
         html::push_html(&mut body_html, processed_events.into_iter());
@@ -82,8 +73,8 @@ impl DocumentRenderer for HtmlRenderer {
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <title>{}</title>
-    <style>
-        body {{
+{}    <style>
+{}        body {{
             font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
             line-height: 1.6;
             color: #333;
@@ -172,18 +163,43 @@ impl DocumentRenderer for HtmlRenderer {
             border-bottom: 1px solid #e1e4e8;
         }}
         
-        /* Syntect syntax highlighting styles */
-        .source {{ background-color: transparent; }}
-        [class*="comment"] {{ color: #6a737d; font-style: italic; }}
-        [class*="keyword"] {{ color: #d73a49; font-weight: bold; }}
-        [class*="storage"] {{ color: #d73a49; font-weight: bold; }}
-        [class*="string"] {{ color: #032f62; }}
-        [class*="constant"] {{ color: #005cc5; }}
-        [class*="entity"] {{ color: #6f42c1; }}
-        [class*="support"] {{ color: #005cc5; }}
-        [class*="variable"] {{ color: #e36209; }}
-        [class*="punctuation"] {{ color: #24292e; }}
-        [class*="meta"] {{ color: #24292e; }}
+        /* Facts about each file, under its heading */
+        .file-info {{
+            display: grid;
+            grid-template-columns: max-content 1fr;
+            gap: 0.15rem 1rem;
+            margin: 0 0 1rem;
+            padding: 0.5rem 0.75rem;
+            font-size: 0.875rem;
+            color: #586069;
+            background-color: #f6f8fa;
+            border: 1px solid #e1e4e8;
+            border-radius: 6px;
+        }}
+        
+        .file-info dt {{
+            font-weight: 600;
+        }}
+        
+        .file-info dd {{
+            margin: 0;
+            overflow-wrap: anywhere;
+        }}
+        
+        /* Lines changed since the --since revision */
+        .line-added,
+        .line-modified {{
+            display: inline-block;
+            min-width: 100%;
+        }}
+        
+        .line-added {{
+            background-color: rgba(46, 160, 67, 0.18);
+        }}
+        
+        .line-modified {{
+            background-color: rgba(210, 153, 34, 0.2);
+        }}
         
         @media (prefers-color-scheme: dark) {{
             body {{
@@ -226,36 +242,35 @@ impl DocumentRenderer for HtmlRenderer {
                 border-bottom-color: #30363d;
             }}
             
-            /* Dark mode syntax highlighting */
-            [class*="comment"] {{ color: #8b949e; }}
-            [class*="keyword"] {{ color: #ff7b72; }}
-            [class*="storage"] {{ color: #ff7b72; }}
-            [class*="string"] {{ color: #a5d6ff; }}
-            [class*="constant"] {{ color: #79c0ff; }}
-            [class*="entity"] {{ color: #d2a8ff; }}
-            [class*="support"] {{ color: #79c0ff; }}
-            [class*="variable"] {{ color: #ffa657; }}
-            [class*="punctuation"] {{ color: #c9d1d9; }}
-            [class*="meta"] {{ color: #c9d1d9; }}
+            .file-info {{
+                color: #8b949e;
+                background-color: #161b22;
+                border-color: #30363d;
+            }}
         }}
-    </style>
+{}{}    </style>
 </head>
 <body>
     <div class="metadata">
         <h1>{}</h1>"#,
-            metadata.language,
-            metadata.title,
-            metadata.title
+            escape_html(&metadata.language),
+            escape_html(&metadata.title),
+            author_meta,
+            palette_css,
+            typography_css,
+            contrast_css,
+            escape_html(&metadata.title)
         );
         
         let mut final_html = html_document;
+        let strings = i18n::strings_for(&metadata.language);
         
         if let Some(author) = &metadata.author {
-            final_html.push_str(&format!("        <p>Author: {}</p>\n", author));
+            final_html.push_str(&format!("        <p>{} {}</p>\n", strings.author, escape_html(author)));
         }
         
-        if let Some(date) = &metadata.date {
-            final_html.push_str(&format!("        <p>Generated: {}</p>\n", date));
+        if let Some(date) = metadata.date_label() {
+            final_html.push_str(&format!("        <p>{} {}</p>\n", strings.generated, date));
         }
         
         final_html.push_str("    </div>\n");
@@ -264,4 +279,11 @@ impl DocumentRenderer for HtmlRenderer {
         
         Ok(final_html.into_bytes())
     }
-}
\ No newline at end of file
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
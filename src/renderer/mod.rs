@@ -1,20 +1,89 @@
-use anyhow::Result;
-use std::path::Path;
-use pulldown_cmark::Event;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use rayon::prelude::*;
+use crate::cancel::CancellationToken;
+use crate::changes::LineChanges;
+use crate::file_header::FILE_INFO_MARKER;
+use crate::fragment_cache::FragmentCache;
+use crate::syntax::highlighter::{escape_html, SyntaxHighlighter, DEFAULT_HIGHLIGHT_TIMEOUT};
+use crate::syntax::policy::HighlightPolicy;
+use crate::theme::{palette_builtin, ColorScheme, FontStyle, MIN_CODE_CONTRAST};
+use crate::warnings::Warnings;
 
+#[cfg(feature = "pdf")]
 pub mod pdf;
+// The EPUB renderer embeds the same fonts, but needs none of the metrics
+#[cfg(any(feature = "pdf", feature = "epub"))]
+#[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+mod pdf_font;
+#[cfg(feature = "epub")]
 pub mod epub;
+#[cfg(feature = "html")]
 pub mod html;
 
-/// Metadata for document generation
+/// Body text size, in points, that `DocumentMetadata::font_size` is
+/// measured against
+pub const DEFAULT_FONT_SIZE: f32 = 10.0;
+
+/// Code is set this much smaller than the body text
+pub const CODE_FONT_RATIO: f32 = 0.9;
+
+/// Metadata for document generation. Outside this crate, create it with
+/// [`DocumentMetadata::builder`] or `Default`; new fields keep being added.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct DocumentMetadata {
     pub title: String,
     pub author: Option<String>,
-    pub date: Option<String>,
+    /// Publisher in the EPUB package; left out when unset
+    pub publisher: Option<String>,
+    /// When the document was created; each format writes it its own way
+    pub date: Option<chrono::DateTime<chrono::Utc>>,
     pub language: String,
     pub include_toc: bool,
     pub syntax_theme: String,
+    /// One-line summary of the repository
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    /// Extra directory of `.sublime-syntax` definitions for highlighting
+    pub syntax_dir: Option<PathBuf>,
+    /// `.tmTheme` file to highlight with instead of `syntax_theme`
+    pub theme_file: Option<PathBuf>,
+    /// Reuse highlighted HTML for identical code blocks
+    pub highlight_cache: bool,
+    /// Time allowed for highlighting one code block
+    pub highlight_timeout: Duration,
+    /// Which code blocks are small enough to highlight
+    pub highlight_policy: HighlightPolicy,
+    /// Colors from the config file, used for code in every format instead
+    /// of the syntax theme's
+    pub palette: Option<ColorScheme>,
+    /// Font styles by token kind, applied along with `palette`
+    pub font_styles: BTreeMap<String, FontStyle>,
+    /// Raise code colors to WCAG AA contrast; HTML also gets larger text
+    /// and underlined links
+    pub enforce_contrast: bool,
+    /// Body text size in points; each renderer keeps its own default when
+    /// unset
+    pub font_size: Option<f32>,
+    /// Line spacing as a multiple of the text size
+    pub line_height: Option<f32>,
+    /// Keep highlighted code on disk, so reruns only highlight what changed
+    pub fragment_cache: Option<FragmentCache>,
+    /// Checked between code blocks and pages; cancelling it stops the
+    /// render with a `Cancelled` error
+    pub cancellation: CancellationToken,
+    /// Where highlighting timeouts and images left out are noted
+    pub warnings: Warnings,
+    /// How many directories deep the table of contents was collapsed to,
+    /// if it was; the EPUB navigation collapses the same way
+    pub toc_depth: Option<usize>,
 }
 
 impl Default for DocumentMetadata {
@@ -22,42 +91,386 @@ impl Default for DocumentMetadata {
         Self {
             title: "Document".to_string(),
             author: None,
-            date: Some(chrono::Utc::now().format("%Y-%m-%d").to_string()),
+            publisher: None,
+            date: Some(chrono::Utc::now()),
             language: "en".to_string(),
             include_toc: true,
             syntax_theme: "InspiredGitHub".to_string(),
+            description: None,
+            keywords: Vec::new(),
+            syntax_dir: None,
+            theme_file: None,
+            highlight_cache: true,
+            highlight_timeout: DEFAULT_HIGHLIGHT_TIMEOUT,
+            highlight_policy: HighlightPolicy::default(),
+            palette: None,
+            font_styles: BTreeMap::new(),
+            enforce_contrast: false,
+            font_size: None,
+            line_height: None,
+            fragment_cache: None,
+            cancellation: CancellationToken::new(),
+            warnings: Warnings::new(),
+            toc_depth: None,
+        }
+    }
+}
+
+impl DocumentMetadata {
+    /// Start building metadata for a document titled `title`; everything
+    /// else keeps its `Default` value unless set
+    ///
+    /// ```
+    /// use scrollcast::DocumentMetadata;
+    ///
+    /// let metadata = DocumentMetadata::builder("Repository Export")
+    ///     .author("Scrollcast")
+    ///     .keywords(["rust", "docs"])
+    ///     .toc(false)
+    ///     .build();
+    /// assert_eq!(metadata.author.as_deref(), Some("Scrollcast"));
+    /// assert!(!metadata.include_toc);
+    /// ```
+    pub fn builder(title: impl Into<String>) -> DocumentMetadataBuilder {
+        DocumentMetadataBuilder {
+            metadata: Self { title: title.into(), ..Self::default() },
         }
     }
+
+    /// The date as `YYYY-MM-DD`, the way the text of each format shows it
+    pub fn date_label(&self) -> Option<String> {
+        self.date.map(|date| date.format("%Y-%m-%d").to_string())
+    }
+}
+
+/// Builds a [`DocumentMetadata`]; see [`DocumentMetadata::builder`]
+#[derive(Debug, Clone)]
+pub struct DocumentMetadataBuilder {
+    metadata: DocumentMetadata,
+}
+
+impl DocumentMetadataBuilder {
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.metadata.author = Some(author.into());
+        self
+    }
+
+    pub fn publisher(mut self, publisher: impl Into<String>) -> Self {
+        self.metadata.publisher = Some(publisher.into());
+        self
+    }
+
+    /// When the document was created, instead of now. `None` leaves it out.
+    pub fn date(mut self, date: impl Into<Option<chrono::DateTime<chrono::Utc>>>) -> Self {
+        self.metadata.date = date.into();
+        self
+    }
+
+    /// Same as `date`
+    pub fn created_at(self, created_at: chrono::DateTime<chrono::Utc>) -> Self {
+        self.date(created_at)
+    }
+
+    /// Language code of the document, such as `en` or `de`
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.metadata.language = language.into();
+        self
+    }
+
+    pub fn keywords<I, S>(mut self, keywords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.keywords = keywords.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.metadata.description = Some(description.into());
+        self
+    }
+
+    /// Include a table of contents
+    pub fn toc(mut self, include_toc: bool) -> Self {
+        self.metadata.include_toc = include_toc;
+        self
+    }
+
+    /// Collapse the EPUB navigation to directories this deep, as the
+    /// table of contents was
+    pub fn toc_depth(mut self, depth: impl Into<Option<usize>>) -> Self {
+        self.metadata.toc_depth = depth.into();
+        self
+    }
+
+    /// Syntax theme to highlight code with
+    pub fn theme(mut self, theme: impl Into<String>) -> Self {
+        self.metadata.syntax_theme = theme.into();
+        self
+    }
+
+    pub fn build(self) -> DocumentMetadata {
+        self.metadata
+    }
 }
 
-/// Trait for document renderers
-pub trait DocumentRenderer {
+/// Trait for document renderers. Several can render the same document on
+/// separate threads at once.
+pub trait DocumentRenderer: Send + Sync {
     /// Render markdown events to the target format
     fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>>;
     
+    /// Render markdown events directly into a writer. Renderers that can emit
+    /// output incrementally override this to avoid buffering the whole document.
+    fn render_to_writer(&self, events: Vec<Event>, metadata: &DocumentMetadata, writer: &mut dyn Write) -> Result<()> {
+        let rendered = self.render(events, metadata)?;
+        writer.write_all(&rendered)?;
+        Ok(())
+    }
+    
     /// Render markdown string to the target format
     fn render_markdown(&self, markdown: &str, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
-        use pulldown_cmark::{Parser, Options};
-        
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_FOOTNOTES);
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TASKLISTS);
-        options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
-        
-        let parser = Parser::new_ext(markdown, options);
-        let events: Vec<Event> = parser.collect();
-        
-        self.render(events, metadata)
+        self.render(parse_markdown(markdown), metadata)
     }
     
-    /// Save rendered document to file
+    /// Save rendered document to file. A render that fails or is cancelled
+    /// removes what it had written, rather than leave half a document.
     fn save_to_file(&self, markdown: &str, metadata: &DocumentMetadata, output_path: &Path) -> Result<()> {
-        let rendered = self.render_markdown(markdown, metadata)?;
-        std::fs::write(output_path, rendered)?;
-        Ok(())
+        let file = std::fs::File::create(output_path)
+            .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let result = self.render_to_writer(parse_markdown(markdown), metadata, &mut writer)
+            .and_then(|()| Ok(writer.flush()?));
+        if result.is_err() {
+            drop(writer);
+            let _ = std::fs::remove_file(output_path);
+        }
+        result
+    }
+}
+
+/// Syntax highlighter set up from the document metadata: extra syntaxes,
+/// then the configured palette, the theme file or the named theme, in that
+/// order of preference
+#[cfg_attr(not(any(feature = "pdf", feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) fn highlighter_for(metadata: &DocumentMetadata) -> Result<SyntaxHighlighter> {
+    let mut highlighter = SyntaxHighlighter::with_syntax_dir(metadata.syntax_dir.as_deref())?;
+    if let Some(palette) = &metadata.palette {
+        highlighter.set_palette(palette, &metadata.font_styles)?;
+    } else if let Some(path) = &metadata.theme_file {
+        let theme = highlighter.load_theme_file(path)?;
+        highlighter.set_theme(&theme)?;
+    } else if let Some(theme) = palette_builtin(&metadata.syntax_theme) {
+        highlighter.set_palette(&theme.colors, &theme.font_styles)?;
+    } else {
+        highlighter.set_theme(&metadata.syntax_theme)?;
+    }
+    if metadata.enforce_contrast {
+        highlighter.enforce_contrast(MIN_CODE_CONTRAST);
     }
+    highlighter.set_cache_enabled(metadata.highlight_cache);
+    highlighter.set_timeout(metadata.highlight_timeout);
+    highlighter.set_warnings(metadata.warnings.clone());
+    if let Some(cache) = &metadata.fragment_cache {
+        highlighter.set_fragment_cache(cache.clone());
+    }
+    Ok(highlighter)
+}
+
+/// Nanoseconds spent highlighting code, summed over every render in the
+/// process
+static HIGHLIGHT_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Run `highlight`, counting its time towards `highlight_time`
+#[cfg_attr(not(any(feature = "pdf", feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) fn timed_highlight<T>(highlight: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = highlight();
+    HIGHLIGHT_NANOS.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+/// Time renderers have spent highlighting code so far, so callers can tell
+/// it apart from the rest of rendering by comparing before and after
+pub fn highlight_time() -> Duration {
+    Duration::from_nanos(HIGHLIGHT_NANOS.load(Ordering::Relaxed))
+}
+
+/// The language named by a fence info string: its first word, so that
+/// "rust,ignore", "rust no_run" and "{.rust}" all name Rust. Attributes
+/// such as "added=3" are not languages.
+#[cfg_attr(not(any(feature = "pdf", feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) fn fence_language(info: &str) -> Option<&str> {
+    info.split(|c: char| c.is_whitespace() || matches!(c, ',' | '{' | '}'))
+        .map(|word| word.trim_start_matches('.'))
+        .find(|word| !word.is_empty())
+        .filter(|word| !word.contains('='))
+}
+
+/// A plain `<pre>` block with each changed line wrapped in a span carrying
+/// its change class
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+fn plain_html_with_changes(code: &str, changes: &LineChanges) -> String {
+    let mut html = String::from("<pre><code>");
+    for (index, line) in code.lines().enumerate() {
+        match changes.get(index + 1) {
+            Some(change) => html.push_str(&format!("<span class=\"{}\">{}</span>", change.css_class(), escape_html(line))),
+            None => html.push_str(&escape_html(line)),
+        }
+        html.push('\n');
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// The start of the HTML `file_info_cards` turns each file header into
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) const FILE_INFO_CARD: &str = "<dl class=\"file-info\">";
+
+/// Replace each file header table, the one after `FILE_INFO_MARKER`, with
+/// a `FILE_INFO_CARD` list of its labels and values
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) fn file_info_cards(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut cards = Vec::with_capacity(events.len());
+    let mut marked = false;
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        match event {
+            Event::Html(html) if html.trim() == FILE_INFO_MARKER => marked = true,
+            Event::End(TagEnd::HtmlBlock) => cards.push(event),
+            Event::Start(Tag::Table(_)) if marked => {
+                marked = false;
+                let mut html = format!("{}\n", FILE_INFO_CARD);
+                let mut cell = String::new();
+                let mut row = Vec::new();
+                for event in events.by_ref() {
+                    match event {
+                        Event::Text(text) | Event::Code(text) => cell.push_str(&text),
+                        Event::End(TagEnd::TableCell) => row.push(std::mem::take(&mut cell)),
+                        Event::End(TagEnd::TableHead) | Event::End(TagEnd::TableRow) => {
+                            if let [label, value, ..] = row.as_slice() {
+                                html.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", escape_html(label), escape_html(value)));
+                            }
+                            row.clear();
+                        }
+                        Event::End(TagEnd::Table) => break,
+                        _ => {}
+                    }
+                }
+                html.push_str("</dl>\n");
+                cards.push(Event::Html(CowStr::Boxed(html.into_boxed_str())));
+            }
+            event => {
+                marked = false;
+                cards.push(event);
+            }
+        }
+    }
+    cards
+}
+
+/// Part of an event stream split up for highlighting: events passed
+/// through as they are, or the kind and text of one code block
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+enum Segment<'a> {
+    Events(Vec<Event<'a>>),
+    CodeBlock(CodeBlockKind<'a>, String),
+}
+
+/// Replace each fenced code block the policy allows with inline-styled
+/// highlighted HTML. Indented blocks and fences without a language stay
+/// plain code blocks. Blocks are highlighted in parallel on rayon's pool;
+/// the output is the same as highlighting them one by one. Stops before the
+/// next block once `cancellation` is cancelled.
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+pub(crate) fn highlight_code_blocks<'a>(
+    events: Vec<Event<'a>>,
+    highlighter: &SyntaxHighlighter,
+    policy: &HighlightPolicy,
+    cancellation: &CancellationToken,
+) -> Result<Vec<Event<'a>>> {
+    let mut segments = Vec::new();
+    let mut passed = Vec::new();
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::CodeBlock(kind)) = event else {
+            passed.push(event);
+            continue;
+        };
+        let mut code = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => break,
+                _ => {}
+            }
+        }
+        segments.push(Segment::Events(std::mem::take(&mut passed)));
+        segments.push(Segment::CodeBlock(kind, code));
+    }
+    segments.push(Segment::Events(passed));
+
+    // Timed as a whole, so the highlighting time stays wall time however
+    // many threads share the work
+    let highlighted = timed_highlight(|| {
+        segments.into_par_iter()
+            .map(|segment| match segment {
+                Segment::Events(events) => Ok(events),
+                Segment::CodeBlock(kind, code) => {
+                    cancellation.check()?;
+                    Ok(highlight_code_block(kind, code, highlighter, policy))
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    Ok(highlighted.into_iter().flatten().collect())
+}
+
+/// The events standing for one code block: highlighted HTML, or the block
+/// itself if the policy leaves it plain
+#[cfg_attr(not(any(feature = "epub", feature = "html")), allow(dead_code))]
+fn highlight_code_block<'a>(
+    kind: CodeBlockKind<'a>,
+    code: String,
+    highlighter: &SyntaxHighlighter,
+    policy: &HighlightPolicy,
+) -> Vec<Event<'a>> {
+    let (language, changes) = match &kind {
+        CodeBlockKind::Fenced(info) => (fence_language(info), LineChanges::from_fence_info(info)),
+        CodeBlockKind::Indented => (None, None),
+    };
+    if let Some(changes) = changes {
+        let html = if policy.should_highlight(language, &code) {
+            highlighter.highlight_to_inline_html_with_changes(&code, language, &changes)
+        } else {
+            plain_html_with_changes(&code, &changes)
+        };
+        vec![Event::Html(CowStr::Boxed(html.into_boxed_str()))]
+    } else if policy.should_highlight(language, &code) {
+        let html = highlighter.highlight_to_inline_html(&code, language);
+        vec![Event::Html(CowStr::Boxed(html.into_boxed_str()))]
+    } else {
+        vec![
+            Event::Start(Tag::CodeBlock(kind)),
+            Event::Text(CowStr::Boxed(code.into_boxed_str())),
+            Event::End(TagEnd::CodeBlock),
+        ]
+    }
+}
+
+/// Parse markdown into events with the extensions all renderers expect
+pub fn parse_markdown(markdown: &str) -> Vec<Event<'_>> {
+    use pulldown_cmark::{Parser, Options};
+    
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    
+    Parser::new_ext(markdown, options).collect()
 }
 
 /// Output format for documents
@@ -80,14 +493,435 @@ impl OutputFormat {
     }
 }
 
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Html => "html",
+            OutputFormat::Markdown => "markdown",
+        })
+    }
+}
+
+/// The error for a format whose Cargo feature this build leaves out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedFormat(pub String);
+
+impl fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} output isn't supported by this build of scrollcast (enable the \"{0}\" feature)", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedFormat {}
+
+/// Format-specific renderer options
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    #[cfg(feature = "pdf")]
+    pub pdf: pdf::PdfOptions,
+    #[cfg(feature = "epub")]
+    pub epub: epub::EpubOptions,
+}
+
 /// Factory for creating renderers based on output format
 pub fn create_renderer(format: &OutputFormat) -> Result<Box<dyn DocumentRenderer>> {
+    create_renderer_with_options(format, &RenderOptions::default())
+}
+
+/// Factory for creating renderers with format-specific options
+#[cfg_attr(not(any(feature = "pdf", feature = "epub")), allow(unused_variables))]
+pub fn create_renderer_with_options(format: &OutputFormat, options: &RenderOptions) -> Result<Box<dyn DocumentRenderer>> {
     match format {
-        OutputFormat::Pdf => Ok(Box::new(pdf::PdfRenderer::new()?)),
-        OutputFormat::Epub => Ok(Box::new(epub::EpubRenderer::new())),
+        #[cfg(feature = "pdf")]
+        OutputFormat::Pdf => Ok(Box::new(pdf::PdfRenderer::with_options(options.pdf.clone()))),
+        #[cfg(feature = "epub")]
+        OutputFormat::Epub => Ok(Box::new(epub::EpubRenderer::with_options(options.epub.clone()))),
+        #[cfg(feature = "html")]
         OutputFormat::Html => Ok(Box::new(html::HtmlRenderer::new())),
         OutputFormat::Markdown => {
             anyhow::bail!("Markdown output doesn't need a renderer")
         }
+        #[allow(unreachable_patterns)]
+        _ => Err(UnsupportedFormat(format.to_string()).into()),
+    }
+}
+#[cfg(test)]
+mod tests {
+    // Most of these render through several formats; with some disabled the
+    // shared helpers go unused.
+    #![cfg_attr(not(all(feature = "pdf", feature = "epub", feature = "html")), allow(unused_imports, dead_code))]
+
+    use super::*;
+    use crate::syntax::tokens::TokenKind;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+    use std::io::{Cursor, Read};
+
+    const SNIPPET: &str = "fn main() {\n    let greeting = \"hello\"; // say hi\n    println!(\"{}\", 42);\n}\n";
+
+    fn inline_colors(html: &str) -> BTreeSet<String> {
+        let start = html.find("<pre").unwrap();
+        let end = html[start..].find("</pre>").unwrap() + start;
+        Regex::new(r"color:(#[0-9a-f]{6})").unwrap()
+            .captures_iter(&html[start..end])
+            .map(|caps| caps[1].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_builder_keeps_defaults_for_unset_fields() {
+        let metadata = DocumentMetadata::builder("Export").build();
+        let defaults = DocumentMetadata::default();
+        assert_eq!(metadata.title, "Export");
+        assert_eq!(metadata.author, defaults.author);
+        assert_eq!(metadata.language, defaults.language);
+        assert_eq!(metadata.include_toc, defaults.include_toc);
+        assert_eq!(metadata.syntax_theme, defaults.syntax_theme);
+        assert_eq!(metadata.date_label(), defaults.date_label());
+
+        let created = chrono::DateTime::parse_from_rfc3339("2024-03-01T23:30:00Z").unwrap().to_utc();
+        let metadata = DocumentMetadata::builder("Export")
+            .author("Ada")
+            .created_at(created)
+            .language("de")
+            .keywords(["rust"])
+            .description("A listing")
+            .toc(false)
+            .theme("base16-ocean.dark")
+            .build();
+        assert_eq!(metadata.author.as_deref(), Some("Ada"));
+        assert_eq!(metadata.date, Some(created));
+        assert_eq!(metadata.date_label().as_deref(), Some("2024-03-01"));
+        assert_eq!(metadata.language, "de");
+        assert_eq!(metadata.keywords, ["rust"]);
+        assert_eq!(metadata.description.as_deref(), Some("A listing"));
+        assert!(!metadata.include_toc);
+        assert_eq!(metadata.syntax_theme, "base16-ocean.dark");
+        assert_eq!(DocumentMetadata::builder("Export").date(None).build().date, None);
+    }
+
+    #[test]
+    #[cfg(all(feature = "pdf", feature = "epub", feature = "html"))]
+    fn test_code_colors_match_across_formats() {
+        let markdown = format!("```rust\n{}```\n", SNIPPET);
+        let metadata = DocumentMetadata { syntax_theme: "base16-ocean.dark".to_string(), ..Default::default() };
+        let expected: BTreeSet<String> = highlighter_for(&metadata).unwrap()
+            .highlight_spans(SNIPPET, Some("rust"))
+            .iter()
+            .flatten()
+            .filter(|span| !span.text.trim().is_empty())
+            .map(|span| span.fg_hex())
+            .collect();
+        assert!(expected.len() > 3, "{:?}", expected);
+
+        let html = html::HtmlRenderer::new().render_markdown(&markdown, &metadata).unwrap();
+        let html_colors = inline_colors(&String::from_utf8(html).unwrap());
+
+        let options = epub::EpubOptions { generate_cover: false, ..Default::default() };
+        let epub = epub::EpubRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap();
+        let mut chapter = String::new();
+        zip::ZipArchive::new(Cursor::new(epub)).unwrap()
+            .by_name("OEBPS/title.xhtml").unwrap()
+            .read_to_string(&mut chapter).unwrap();
+        let epub_colors = inline_colors(&chapter);
+
+        let options = pdf::PdfOptions { compress: false, ..Default::default() };
+        let pdf = pdf::PdfRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap();
+        let pdf = String::from_utf8_lossy(&pdf);
+        // Code runs are drawn as "r g b rg" followed by their text
+        let run = Regex::new(r"([\d.]+) ([\d.]+) ([\d.]+) rg\n\((.*)\) Tj").unwrap();
+        let pdf_colors: BTreeSet<String> = run.captures_iter(&pdf)
+            .filter(|caps| !caps[4].trim().is_empty() && !caps[4].starts_with("---"))
+            .filter(|caps| SNIPPET.contains(caps[4].replace('\\', "").as_str()))
+            .map(|caps| {
+                let channel = |i: usize| (caps[i].parse::<f32>().unwrap() * 255.0).round() as u8;
+                format!("#{:02x}{:02x}{:02x}", channel(1), channel(2), channel(3))
+            })
+            .collect();
+
+        // Whitespace-only runs may carry their own color in HTML
+        assert!(expected.is_subset(&html_colors), "{:?} vs {:?}", expected, html_colors);
+        assert_eq!(html_colors, epub_colors);
+        assert_eq!(pdf_colors, expected);
+    }
+
+    #[test]
+    fn test_disabled_formats_are_reported() {
+        let formats = [
+            (OutputFormat::Pdf, cfg!(feature = "pdf")),
+            (OutputFormat::Epub, cfg!(feature = "epub")),
+            (OutputFormat::Html, cfg!(feature = "html")),
+        ];
+        for (format, enabled) in formats {
+            match create_renderer(&format) {
+                Ok(_) => assert!(enabled, "{} renderer built without its feature", format),
+                Err(e) => {
+                    assert!(!enabled, "{}: {}", format, e);
+                    let unsupported = e.downcast_ref::<UnsupportedFormat>().unwrap();
+                    assert_eq!(unsupported.0, format.to_string());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fence_language_takes_first_word() {
+        assert_eq!(fence_language("rust"), Some("rust"));
+        assert_eq!(fence_language("rust,ignore"), Some("rust"));
+        assert_eq!(fence_language("rust no_run"), Some("rust"));
+        assert_eq!(fence_language("{.python}"), Some("python"));
+        assert_eq!(fence_language("  "), None);
+        assert_eq!(fence_language("added=1-3"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(all(feature = "epub", feature = "html"))]
+    fn test_embedded_readme_fences_are_highlighted() {
+        use crate::markdown_generator::{FileInfo, MarkdownGenerator};
+
+        let readme = "# Demo\n\n```rust,ignore\nfn main() {}\n```\n\nIndented:\n\n    fn plain() {}\n";
+        let file = FileInfo {
+            path: "README.md".to_string(),
+            content: readme.into(),
+            language: MarkdownGenerator::detect_language("README.md"),
+            size: readme.len(),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        };
+        let markdown = MarkdownGenerator::new(false, false).generate_markdown(&[file], "demo").unwrap();
+
+        let metadata = DocumentMetadata::default();
+        let keyword = highlighter_for(&metadata).unwrap()
+            .highlight_spans("fn main() {}\n", Some("rust"))
+            .into_iter()
+            .flatten()
+            .find(|span| span.text == "fn")
+            .unwrap();
+        assert_eq!(keyword.token_kind, TokenKind::Keyword);
+        let keyword_span = Regex::new(&format!(r#"<span style="color:{};[^"]*">fn</span>"#, keyword.fg_hex())).unwrap();
+
+        let html = html::HtmlRenderer::new().render_markdown(&markdown, &metadata).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(keyword_span.is_match(&html), "{}", html);
+        assert!(html.contains("<pre><code>fn plain() {}"), "{}", html);
+
+        let options = epub::EpubOptions { generate_cover: false, ..Default::default() };
+        let epub = epub::EpubRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let names: Vec<String> = archive.file_names().filter(|name| name.ends_with(".xhtml")).map(str::to_string).collect();
+        let mut chapters = String::new();
+        for name in names {
+            archive.by_name(&name).unwrap().read_to_string(&mut chapters).unwrap();
+        }
+        assert!(keyword_span.is_match(&chapters), "{}", chapters);
+        assert!(chapters.contains("<pre><code>fn plain() {}"), "{}", chapters);
+    }
+
+    /// Markdown with `count` distinct Rust code blocks, a few with changed
+    /// lines and a few without a language
+    fn many_code_blocks(count: usize) -> String {
+        let mut markdown = String::from("# blocks\n\n");
+        for i in 0..count {
+            let body: String = (0..40).map(|line| format!("    let value_{line} = compute({i}, {line}); // step {line}\n")).collect();
+            let fence = match i % 10 {
+                0 => "rust added=2",
+                5 => "",
+                _ => "rust",
+            };
+            markdown.push_str(&format!("### file_{i}.rs\n\n```{fence}\nfn block_{i}() {{\n{body}}}\n```\n\n"));
+        }
+        markdown
+    }
+
+    /// Render on a rayon pool of `threads`, where a pool of one highlights
+    /// the code blocks one after another, or on the global pool
+    #[cfg(feature = "html")]
+    fn render_html_on(threads: Option<usize>, markdown: &str, metadata: &DocumentMetadata) -> Vec<u8> {
+        let render = || html::HtmlRenderer::new().render_markdown(markdown, metadata).unwrap();
+        match threads {
+            Some(threads) => rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap().install(render),
+            None => render(),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "epub", feature = "html"))]
+    fn test_parallel_highlighting_matches_serial() {
+        let markdown = many_code_blocks(60);
+        let metadata = DocumentMetadata { highlight_cache: false, ..Default::default() };
+        let serial = render_html_on(Some(1), &markdown, &metadata);
+        assert!(render_html_on(None, &markdown, &metadata) == serial, "parallel HTML differs from serial");
+        assert!(String::from_utf8_lossy(&serial).contains("class=\"line-added\""));
+
+        // The archive records when it was written, so compare the chapters
+        let chapters_on = |threads| {
+            let options = epub::EpubOptions { generate_cover: false, ..Default::default() };
+            let epub = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap()
+                .install(|| epub::EpubRenderer::with_options(options).render_markdown(&markdown, &metadata).unwrap());
+            let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+            let names: Vec<String> = archive.file_names().filter(|name| name.ends_with(".xhtml")).map(str::to_string).collect();
+            names.into_iter()
+                .map(|name| {
+                    let mut chapter = String::new();
+                    archive.by_name(&name).unwrap().read_to_string(&mut chapter).unwrap();
+                    (name, chapter)
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert!(chapters_on(1) == chapters_on(4), "parallel EPUB chapters differ from serial");
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_concurrent_renders_match_serial() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let markdown = many_code_blocks(30);
+        // The shared in-memory and on-disk caches are where threads meet
+        let metadata = DocumentMetadata {
+            fragment_cache: Some(FragmentCache::new(temp_dir.path())),
+            ..Default::default()
+        };
+        let uncached = DocumentMetadata { highlight_cache: false, fragment_cache: None, ..metadata.clone() };
+        let serial = render_html_on(Some(1), &markdown, &uncached);
+
+        let rendered: Vec<Vec<u8>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| render_html_on(None, &markdown, &metadata)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        for html in rendered {
+            assert!(html == serial, "a concurrent render differs from the serial one");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    #[ignore = "wall-time comparison, run with --ignored on a multi-core machine"]
+    fn test_parallel_highlighting_is_faster() {
+        let markdown = many_code_blocks(400);
+        let metadata = DocumentMetadata { highlight_cache: false, ..Default::default() };
+        let time = |threads| {
+            let started = Instant::now();
+            render_html_on(threads, &markdown, &metadata);
+            started.elapsed()
+        };
+        let serial = time(Some(1));
+        let parallel = time(None);
+        println!("serial {:?}, parallel {:?} on {} threads", serial, parallel, rayon::current_num_threads());
+        if rayon::current_num_threads() < 2 {
+            return;
+        }
+        assert!(parallel * 3 < serial * 2, "{:?} vs {:?}", parallel, serial);
+    }
+
+    #[test]
+    #[cfg(all(feature = "epub", feature = "html"))]
+    fn test_large_json_is_left_plain() {
+        let records: Vec<String> = (0..3000)
+            .map(|i| format!("  {{\"id\": {}, \"name\": \"item-{}\", \"tags\": [\"a\", \"b\"]}}", i, i))
+            .collect();
+        let json = format!("[\n{}\n]\n", records.join(",\n"));
+        assert!(json.len() > 100 * 1024);
+        let markdown = format!("### data.json\n\n```json\n{}```\n", json);
+
+        let render = |metadata: &DocumentMetadata| {
+            String::from_utf8(html::HtmlRenderer::new().render_markdown(&markdown, metadata).unwrap()).unwrap()
+        };
+        let plain = render(&DocumentMetadata { highlight_cache: false, ..Default::default() });
+        assert!(!plain.contains("<span style="));
+        assert!(plain.contains(r#"{"id": 2999, "name": "item-2999""#));
+
+        let unlimited = HighlightPolicy { language_max_bytes: Default::default(), ..Default::default() };
+        let metadata = DocumentMetadata { highlight_cache: false, highlight_policy: unlimited, ..Default::default() };
+        let highlighted = render(&metadata);
+        assert!(highlighted.contains("<span style="));
+
+        let options = epub::EpubOptions { generate_cover: false, ..Default::default() };
+        let epub = epub::EpubRenderer::with_options(options).render_markdown(&markdown, &DocumentMetadata::default()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        for name in names.iter().filter(|name| name.ends_with(".xhtml")) {
+            let mut chapter = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut chapter).unwrap();
+            assert!(!chapter.contains("<span style="), "{}", name);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_html_uses_configured_font_size_and_line_height() {
+        let render = |metadata: &DocumentMetadata| {
+            String::from_utf8(html::HtmlRenderer::new().render_markdown("Some prose.\n", metadata).unwrap()).unwrap()
+        };
+
+        let html = render(&DocumentMetadata::default());
+        assert!(!html.contains("pt; }"));
+
+        let metadata = DocumentMetadata { font_size: Some(12.0), line_height: Some(1.4), ..Default::default() };
+        let html = render(&metadata);
+        assert!(html.contains("body { font-size: 12pt; }"), "{}", html);
+        assert!(html.contains("pre code { font-size: 0.9em; }"), "{}", html);
+        assert!(html.contains("body, pre { line-height: 1.4; }"), "{}", html);
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_html_empty_and_blank_files_match_snapshot() {
+        let markdown = include_str!("../../testfiles/snapshots/chunked_empty_project.md");
+        let html = String::from_utf8(html::HtmlRenderer::new().render_markdown(markdown, &DocumentMetadata::default()).unwrap()).unwrap();
+        let files = &html[html.find("<h2 id=\"file-contents\">").unwrap()..];
+        assert_eq!(files, include_str!("../../testfiles/snapshots/empty_project_files.html"));
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_html_metadata_block_names_title_author_and_language() {
+        let metadata = DocumentMetadata {
+            title: "Payments <v2>".to_string(),
+            author: Some("Zoë Ðurić & Co".to_string()),
+            language: "de".to_string(),
+            ..Default::default()
+        };
+        let html = String::from_utf8(html::HtmlRenderer::new().render_markdown("Some prose.\n", &metadata).unwrap()).unwrap();
+        assert!(html.contains("<html lang=\"de\">"), "{}", html);
+        assert!(html.contains("<title>Payments &lt;v2&gt;</title>"), "{}", html);
+        assert!(html.contains("<meta name=\"author\" content=\"Zoë Ðurić &amp; Co\">"), "{}", html);
+        assert!(html.contains("<h1>Payments &lt;v2&gt;</h1>"), "{}", html);
+        assert!(html.contains("<p>Autor: Zoë Ðurić &amp; Co</p>"), "{}", html);
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_high_contrast_and_enforced_contrast() {
+        let markdown = format!("[docs](https://example.com)\n\n```rust\n{}```\n", SNIPPET);
+        let render = |metadata: &DocumentMetadata| {
+            String::from_utf8(html::HtmlRenderer::new().render_markdown(&markdown, metadata).unwrap()).unwrap()
+        };
+
+        let metadata = DocumentMetadata { syntax_theme: "high-contrast".to_string(), ..Default::default() };
+        let html = render(&metadata);
+        assert!(html.contains(r#"<span style="color:#00008b;font-weight:bold;">fn</span>"#), "{}", html);
+        assert!(html.contains(r#"<span style="color:#4a4a4a;font-style:italic;">// say hi"#), "{}", html);
+        assert!(!html.contains("a { text-decoration: underline; }"));
+
+        // base16-ocean.light comments (#a7adba) fall well short of 4.5:1
+        let metadata = DocumentMetadata {
+            syntax_theme: "pygments".to_string(),
+            enforce_contrast: true,
+            ..Default::default()
+        };
+        let html = render(&metadata);
+        assert!(html.contains("a { text-decoration: underline; }"));
+        let background = crate::theme::parse_hex_color("#eff1f5").unwrap();
+        let colors = Regex::new(r#"<span style="color:(#[0-9a-f]{6});"#).unwrap();
+        for color in colors.captures_iter(&html) {
+            let rgb = crate::theme::parse_hex_color(&color[1]).unwrap();
+            assert!(crate::theme::contrast_ratio(rgb, background) >= MIN_CODE_CONTRAST, "{}", &color[1]);
+        }
+    }
+}
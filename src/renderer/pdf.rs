@@ -1,359 +1,2044 @@
-use anyhow::Result;
-use pulldown_cmark::Event;
-use crate::renderer::{DocumentRenderer, DocumentMetadata};
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hasher;
+use std::io::Write;
+use crate::cancel::CancellationToken;
+use crate::changes::{LineChange, LineChanges};
+use crate::file_header::FILE_INFO_MARKER;
+use crate::i18n;
+use crate::renderer::{fence_language, highlighter_for, timed_highlight, DocumentRenderer, DocumentMetadata, DEFAULT_FONT_SIZE};
+use crate::renderer::pdf_font::{EMBEDDED_FONTS, FIRST_CHAR, LAST_CHAR, SRGB_ICC_PROFILE, win_ansi_code};
+use crate::syntax::highlighter::SyntaxHighlighter;
+use crate::syntax::policy::HighlightPolicy;
+use crate::syntax::tokens::StyledSpan;
+use crate::theme::{Theme, MIN_CODE_CONTRAST};
+use crate::warnings::WarningCategory;
 
-pub struct PdfRenderer;
+/// Page geometry in points (US Letter, portrait)
+const PAGE_WIDTH: f32 = 612.0;
+const PAGE_HEIGHT: f32 = 792.0;
+const MARGIN_LEFT: f32 = 50.0;
+const MARGIN_RIGHT: f32 = 50.0;
+const MARGIN_TOP: f32 = 72.0;
+const MARGIN_BOTTOM: f32 = 72.0;
+/// Height of a file header's rule above the next line's baseline, leaving
+/// room below the header's descenders
+const HEADER_RULE_DROP: f32 = 14.0;
+const COLUMN_GUTTER: f32 = 24.0;
+
+/// Space kept below a heading so it is never stranded at the bottom of a column
+const KEEP_WITH_NEXT: f32 = 45.0;
+
+/// Code listing font sizes for one- and two-column layouts
+const CODE_FONT_SIZE: f32 = 9.0;
+const DENSE_CODE_FONT_SIZE: f32 = 8.0;
+
+/// Gutter bars marking changed code lines
+const CHANGE_BAR_WIDTH: f32 = 3.0;
+const CHANGE_BAR_OFFSET: f32 = 8.0;
+const CHANGE_ADDED_COLOR: &str = "#2ea043";
+const CHANGE_MODIFIED_COLOR: &str = "#d29922";
+
+/// Table cell layout
+const TABLE_FONT_SIZE: f32 = 9.0;
+const TABLE_LEADING: f32 = 11.0;
+const TABLE_CELL_PADDING: f32 = 4.0;
+
+/// Options controlling PDF output
+#[derive(Debug, Clone)]
+pub struct PdfOptions {
+    /// Color theme for page background and text. When `None`, the theme is
+    /// derived from the syntax theme in the document metadata.
+    pub theme: Option<Theme>,
+    /// Number of text columns per page (1 or 2)
+    pub columns: u8,
+    /// Use landscape instead of portrait pages
+    pub landscape: bool,
+    /// Code listing font size; defaults to 0.9 of the body text, or 0.8
+    /// with two columns
+    pub code_font_size: Option<f32>,
+    /// Produce PDF/A-2b output: embedded fonts, sRGB output intent, XMP
+    /// metadata and a document ID
+    pub pdfa: bool,
+    /// Flate-compress content streams and embedded font programs
+    pub compress: bool,
+    /// Most pages to write. A document that would run longer ends with a
+    /// page saying how many files were left out to stay within it.
+    pub max_pages: Option<usize>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            columns: 1,
+            landscape: false,
+            code_font_size: None,
+            pdfa: false,
+            compress: true,
+            max_pages: None,
+        }
+    }
+}
+
+impl PdfOptions {
+    fn code_font_size(&self, text_size: f32) -> f32 {
+        let default = if self.columns >= 2 { DENSE_CODE_FONT_SIZE } else { CODE_FONT_SIZE };
+        self.code_font_size.unwrap_or(default * text_size / DEFAULT_FONT_SIZE)
+    }
+}
+
+/// Page size and column boxes for the configured layout
+#[derive(Debug, Clone, Copy)]
+struct PageGeometry {
+    width: f32,
+    height: f32,
+    columns: usize,
+}
+
+impl PageGeometry {
+    fn from_options(options: &PdfOptions) -> Self {
+        let (width, height) = if options.landscape {
+            (PAGE_HEIGHT, PAGE_WIDTH)
+        } else {
+            (PAGE_WIDTH, PAGE_HEIGHT)
+        };
+        Self {
+            width,
+            height,
+            columns: options.columns.clamp(1, 2) as usize,
+        }
+    }
+
+    fn top_y(&self) -> f32 {
+        self.height - MARGIN_TOP
+    }
+
+    fn bottom_y(&self) -> f32 {
+        MARGIN_BOTTOM
+    }
+
+    fn content_width(&self) -> f32 {
+        self.width - MARGIN_LEFT - MARGIN_RIGHT
+    }
+
+    fn column_width(&self) -> f32 {
+        (self.content_width() - COLUMN_GUTTER * (self.columns - 1) as f32) / self.columns as f32
+    }
+
+    fn column_x(&self, column: usize) -> f32 {
+        MARGIN_LEFT + column as f32 * (self.column_width() + COLUMN_GUTTER)
+    }
+}
+
+/// Font sizes, line spacing and characters per line of body text and code
+#[derive(Debug, Clone, Copy)]
+struct TextMetrics {
+    text_size: f32,
+    code_size: f32,
+    text_leading: f32,
+    item_leading: f32,
+    code_leading: f32,
+    code_chars: usize,
+    text_chars: usize,
+}
+
+impl TextMetrics {
+    fn new(options: &PdfOptions, geometry: PageGeometry, font_size: Option<f32>, line_height: Option<f32>) -> Self {
+        let text_size = font_size.unwrap_or(DEFAULT_FONT_SIZE);
+        let code_size = options.code_font_size(text_size);
+        let (text_leading, item_leading, code_leading) = match line_height {
+            Some(line_height) => (text_size * line_height, text_size * line_height, code_size * line_height),
+            None => (15.0, 12.0, if geometry.columns > 1 { code_size + 2.0 } else { code_size + 6.0 }),
+        };
+        Self {
+            text_size,
+            code_size,
+            text_leading,
+            item_leading,
+            code_leading,
+            // Courier glyphs are 0.6em wide; Helvetica averages a little over 0.5em
+            code_chars: (geometry.column_width() / (code_size * 0.6)) as usize,
+            text_chars: (geometry.column_width() / (text_size * 0.55)) as usize,
+        }
+    }
+}
+
+/// How much code fits in the layout, for estimating page counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeCapacity {
+    /// Code lines on a full page, over all its columns
+    pub lines_per_page: usize,
+    /// Characters on a code line before it wraps
+    pub chars_per_line: usize,
+}
+
+/// The code capacity of a page laid out with `options`, body text at
+/// `font_size` and lines `line_height` apart, as `--font-size` and
+/// `--line-height` set them
+pub fn code_capacity(options: &PdfOptions, font_size: Option<f32>, line_height: Option<f32>) -> CodeCapacity {
+    let geometry = PageGeometry::from_options(options);
+    let metrics = TextMetrics::new(options, geometry, font_size, line_height);
+    let column_lines = ((geometry.top_y() - geometry.bottom_y()) / metrics.code_leading) as usize;
+    CodeCapacity {
+        lines_per_page: (column_lines * geometry.columns).max(1),
+        chars_per_line: metrics.code_chars.max(1),
+    }
+}
+
+pub struct PdfRenderer {
+    options: PdfOptions,
+}
 
 impl PdfRenderer {
     pub fn new() -> Result<Self> {
-        Ok(Self)
+        Ok(Self::with_options(PdfOptions::default()))
+    }
+
+    pub fn with_options(options: PdfOptions) -> Self {
+        Self { options }
+    }
+
+    /// Resolve the color theme, deriving it from the syntax theme unless one was given
+    fn resolve_theme(&self, highlighter: &SyntaxHighlighter, metadata: &DocumentMetadata) -> Result<Theme> {
+        Ok(match &self.options.theme {
+            Some(theme) if metadata.enforce_contrast => theme.with_min_contrast(MIN_CODE_CONTRAST),
+            Some(theme) => theme.clone(),
+            None => Theme::from_syntect(highlighter.current_theme()),
+        })
     }
 }
 
-impl DocumentRenderer for PdfRenderer {
-    fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
-        // Process markdown events to create structured content like HTML
-        let mut pdf_content_lines = Vec::new();
-        let mut current_text = String::new();
-        let mut in_heading = false;
-        let mut heading_level = 1;
-        let mut in_code_block = false;
-        
-        for event in events {
-            match event {
-                Event::Start(pulldown_cmark::Tag::Heading { level, .. }) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
-                    in_heading = true;
-                    heading_level = level as u32;
-                }
-                Event::End(pulldown_cmark::TagEnd::Heading(_)) => {
-                    if !current_text.is_empty() {
-                        let heading_marker = match heading_level {
-                            1 => "H1",
-                            2 => "H2", 
-                            3 => "H3",
-                            _ => "H4",
-                        };
-                        pdf_content_lines.push(format!("{}: {}", heading_marker, current_text.trim()));
-                        current_text.clear();
-                    }
-                    in_heading = false;
+/// PDF fill color operator for a hex color
+fn fill_color(hex: &str) -> String {
+    let (r, g, b) = Theme::hex_to_rgb(hex);
+    format!("{:.3} {:.3} {:.3} rg", r, g, b)
+}
+
+/// PDF stroke color operator for a hex color
+fn stroke_color(hex: &str) -> String {
+    let (r, g, b) = Theme::hex_to_rgb(hex);
+    format!("{:.3} {:.3} {:.3} RG", r, g, b)
+}
+
+/// Encode text as WinAnsi for use inside a PDF string literal. Non-ASCII
+/// codes are written as octal escapes; characters outside WinAnsi become `?`.
+fn escape_pdf_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '(' => escaped.push_str("\\("),
+            ')' => escaped.push_str("\\)"),
+            '\t' => escaped.push(' '),
+            _ => match win_ansi_code(ch) {
+                Some(code) if code.is_ascii() => escaped.push(code as char),
+                Some(code) => escaped.push_str(&format!("\\{:03o}", code)),
+                None if ch.is_control() => {}
+                None => escaped.push('?'),
+            },
+        }
+    }
+    escaped
+}
+
+/// Layout blocks extracted from the markdown event stream
+#[derive(Debug, Clone)]
+enum Block {
+    /// Level, text and the anchor links to it go to
+    Heading(u32, String, Option<String>),
+    Text(String),
+    /// Text and the anchor of the first same-document link in it
+    ListItem(String, Option<String>),
+    CodeStart,
+    /// A line of code and how it changed since the base revision
+    CodeLine(Vec<StyledSpan>, Option<LineChange>),
+    CodeEnd,
+    Table(TableBlock),
+    PageBreak,
+}
+
+/// A markdown table with its cell contents flattened to plain text
+#[derive(Debug, Clone, Default)]
+struct TableBlock {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl TableBlock {
+    fn column_count(&self) -> usize {
+        self.rows.iter()
+            .map(|row| row.len())
+            .chain(std::iter::once(self.header.len()))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The path of the file whose section `block` starts: file sections open
+/// with a level three heading carrying the file's anchor
+fn file_heading(block: &Block) -> Option<&str> {
+    match block {
+        Block::Heading(3, path, Some(_)) => Some(path),
+        _ => None,
+    }
+}
+
+/// Layout blocks read from the markdown event stream as the layout asks for
+/// them. Each code block the policy allows is highlighted when it's reached,
+/// so only the blocks of the page being laid out are held at once.
+struct Blocks<'h, I> {
+    events: I,
+    highlighter: &'h SyntaxHighlighter,
+    policy: &'h HighlightPolicy,
+    cancellation: &'h CancellationToken,
+    /// Read from the events but not taken yet
+    pending: VecDeque<Block>,
+    /// The events ran out and the text left over was flushed
+    done: bool,
+    /// Pass over code blocks instead of highlighting them
+    skip_code: bool,
+    current_text: String,
+    in_heading: bool,
+    heading_level: u32,
+    heading_id: Option<String>,
+    in_item: usize,
+    item_link: Option<String>,
+    in_code_block: bool,
+    code_language: Option<String>,
+    code_changes: Option<LineChanges>,
+    table: Option<TableBlock>,
+    current_row: Vec<String>,
+    /// The next table is a file header, drawn as label and value lines
+    file_info: bool,
+}
+
+impl<'h, 'e, I: Iterator<Item = Event<'e>>> Blocks<'h, I> {
+    fn new(
+        events: I,
+        highlighter: &'h SyntaxHighlighter,
+        policy: &'h HighlightPolicy,
+        cancellation: &'h CancellationToken,
+    ) -> Self {
+        Self {
+            events,
+            highlighter,
+            policy,
+            cancellation,
+            pending: VecDeque::new(),
+            done: false,
+            skip_code: false,
+            current_text: String::new(),
+            in_heading: false,
+            heading_level: 1,
+            heading_id: None,
+            in_item: 0,
+            item_link: None,
+            in_code_block: false,
+            code_language: None,
+            code_changes: None,
+            table: None,
+            current_row: Vec::new(),
+            file_info: false,
+        }
+    }
+
+    /// The paths of the files whose sections are still to come, read
+    /// without highlighting their code
+    fn remaining_file_headings(mut self) -> Result<Vec<String>> {
+        self.skip_code = true;
+        let mut paths = Vec::new();
+        for block in self {
+            if let Some(path) = file_heading(&block?) {
+                paths.push(path.to_string());
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Queue the text gathered so far as a paragraph
+    fn flush_text(&mut self) {
+        if !self.current_text.is_empty() {
+            self.pending.push_back(Block::Text(self.current_text.trim().to_string()));
+            self.current_text.clear();
+        }
+    }
+
+    fn read(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                self.flush_text();
+                self.in_heading = true;
+                self.heading_level = level as u32;
+                self.heading_id = id.map(|id| id.to_string());
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if !self.current_text.is_empty() {
+                    self.pending.push_back(Block::Heading(self.heading_level.min(4), self.current_text.trim().to_string(), self.heading_id.take()));
+                    self.current_text.clear();
                 }
-                Event::Start(pulldown_cmark::Tag::CodeBlock(_)) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
-                    in_code_block = true;
+                self.in_heading = false;
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                if let Some(anchor) = dest_url.strip_prefix('#').filter(|_| self.in_item > 0 && self.item_link.is_none()) {
+                    self.item_link = Some(anchor.to_string());
                 }
-                Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
-                    if !current_text.is_empty() {
-                        // Split code into multiple lines for better formatting
-                        let code_lines: Vec<&str> = current_text.lines().collect();
-                        pdf_content_lines.push("CODE_START".to_string());
-                        for line in code_lines.iter().take(10) { // Limit code lines
-                            pdf_content_lines.push(format!("CODE_LINE: {}", line.trim()));
-                        }
-                        if code_lines.len() > 10 {
-                            pdf_content_lines.push("CODE_LINE: ... [more code]".to_string());
-                        }
-                        pdf_content_lines.push("CODE_END".to_string());
-                        current_text.clear();
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                self.flush_text();
+                self.in_code_block = true;
+                (self.code_language, self.code_changes) = match kind {
+                    CodeBlockKind::Fenced(info) => (fence_language(&info).map(str::to_string), LineChanges::from_fence_info(&info)),
+                    CodeBlockKind::Indented => (None, None),
+                };
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if !self.current_text.is_empty() && !self.skip_code {
+                    self.cancellation.check()?;
+                    // Split code into lines, keeping indentation
+                    self.pending.push_back(Block::CodeStart);
+                    let code_language = self.code_language.as_deref();
+                    let language = code_language
+                        .filter(|_| self.policy.should_highlight(code_language, &self.current_text));
+                    let lines = timed_highlight(|| self.highlighter.highlight_spans(&self.current_text, language));
+                    for (index, spans) in lines.into_iter().enumerate() {
+                        let change = self.code_changes.as_ref().and_then(|changes| changes.get(index + 1));
+                        self.pending.push_back(Block::CodeLine(trim_line_end(spans), change));
                     }
-                    in_code_block = false;
+                    self.pending.push_back(Block::CodeEnd);
                 }
-                Event::Start(pulldown_cmark::Tag::List(_)) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
+                self.current_text.clear();
+                self.in_code_block = false;
+            }
+            Event::Start(Tag::List(_)) | Event::Start(Tag::Item) => {
+                self.flush_text();
+                if matches!(event, Event::Start(Tag::Item)) {
+                    self.in_item += 1;
+                    self.item_link = None;
                 }
-                Event::Start(pulldown_cmark::Tag::Item) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
+            }
+            Event::End(TagEnd::Item) => {
+                if !self.current_text.is_empty() {
+                    self.pending.push_back(Block::ListItem(format!("• {}", self.current_text.trim()), self.item_link.take()));
+                    self.current_text.clear();
                 }
-                Event::End(pulldown_cmark::TagEnd::Item) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("LIST_ITEM: • {}", current_text.trim()));
-                        current_text.clear();
+                self.in_item = self.in_item.saturating_sub(1);
+            }
+            Event::Start(Tag::Table(_)) => {
+                self.flush_text();
+                self.table = Some(TableBlock::default());
+            }
+            Event::Html(html) if html.trim() == FILE_INFO_MARKER => self.file_info = true,
+            Event::End(TagEnd::Table) => {
+                match self.table.take() {
+                    Some(table) if std::mem::take(&mut self.file_info) => {
+                        for row in std::iter::once(&table.header).chain(&table.rows) {
+                            self.pending.push_back(Block::Text(row.join(" ")));
+                        }
                     }
+                    Some(table) => self.pending.push_back(Block::Table(table)),
+                    None => {}
                 }
-                Event::Start(pulldown_cmark::Tag::Table(_)) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
-                    pdf_content_lines.push("TABLE_START".to_string());
+            }
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                self.current_row.clear();
+            }
+            Event::End(TagEnd::TableHead) => {
+                if let Some(table) = self.table.as_mut() {
+                    table.header = std::mem::take(&mut self.current_row);
                 }
-                Event::End(pulldown_cmark::TagEnd::Table) => {
-                    pdf_content_lines.push("TABLE_END".to_string());
+            }
+            Event::End(TagEnd::TableRow) => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push(std::mem::take(&mut self.current_row));
                 }
-                Event::Start(pulldown_cmark::Tag::TableHead) => {
-                    // Table header start
+            }
+            Event::Start(Tag::TableCell) => {
+                self.current_text.clear();
+            }
+            Event::End(TagEnd::TableCell) => {
+                // Inline formatting inside cells is flattened to plain text
+                self.current_row.push(self.current_text.trim().to_string());
+                self.current_text.clear();
+            }
+            Event::End(TagEnd::Paragraph) if !self.in_heading && !self.in_code_block => self.flush_text(),
+            Event::Text(text) => {
+                // Page break markers only count outside code blocks; source
+                // files may legitimately contain the literal text
+                if !self.in_code_block && text.contains("\\newpage") {
+                    let parts: Vec<&str> = text.split("\\newpage").collect();
+                    for (i, part) in parts.iter().enumerate() {
+                        if !part.is_empty() {
+                            self.current_text.push_str(part);
+                        }
+                        if i < parts.len() - 1 {
+                            self.flush_text();
+                            self.pending.push_back(Block::PageBreak);
+                        }
+                    }
+                } else {
+                    self.current_text.push_str(&text);
                 }
-                Event::End(pulldown_cmark::TagEnd::TableHead) => {
-                    // Table header end
+            }
+            Event::Code(code) => {
+                if self.table.is_some() {
+                    self.current_text.push_str(&code);
+                } else {
+                    self.current_text.push_str(&format!("`{}`", code));
                 }
-                Event::Start(pulldown_cmark::Tag::TableRow) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
-                    }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                self.current_text.push(' ');
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Queue any text left when the events run out
+    fn finish(&mut self) {
+        if self.current_text.is_empty() {
+            return;
+        }
+        if self.in_code_block {
+            if !self.skip_code {
+                for spans in timed_highlight(|| self.highlighter.highlight_spans(self.current_text.trim(), self.code_language.as_deref())) {
+                    self.pending.push_back(Block::CodeLine(trim_line_end(spans), None));
                 }
-                Event::End(pulldown_cmark::TagEnd::TableRow) => {
-                    if !current_text.is_empty() {
-                        pdf_content_lines.push(format!("TABLE_ROW: {}", current_text.trim()));
-                        current_text.clear();
+            }
+            self.current_text.clear();
+        } else {
+            self.flush_text();
+        }
+    }
+}
+
+impl<'e, I: Iterator<Item = Event<'e>>> Iterator for Blocks<'_, I> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Result<Block>> {
+        while self.pending.is_empty() && !self.done {
+            match self.events.next() {
+                Some(event) => {
+                    if let Err(error) = self.read(event) {
+                        return Some(Err(error));
                     }
                 }
-                Event::Start(pulldown_cmark::Tag::TableCell) => {
-                    // Cell start
+                None => {
+                    self.finish();
+                    self.done = true;
                 }
-                Event::End(pulldown_cmark::TagEnd::TableCell) => {
-                    current_text.push_str(" | ");
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Drop trailing whitespace, including the newline, from a line of spans
+fn trim_line_end(mut spans: Vec<StyledSpan>) -> Vec<StyledSpan> {
+    while let Some(last) = spans.last_mut() {
+        let trimmed = last.text.trim_end().len();
+        if trimmed > 0 {
+            last.text.truncate(trimmed);
+            break;
+        }
+        spans.pop();
+    }
+    spans
+}
+
+/// Fill and stroke colors derived from the theme
+struct Palette {
+    background: String,
+    text: String,
+    header: String,
+    muted: String,
+    rule: String,
+    header_rule: String,
+}
+
+impl Palette {
+    fn from_theme(theme: &Theme) -> Self {
+        Self {
+            background: fill_color(&theme.colors.background),
+            text: fill_color(&theme.colors.text),
+            header: fill_color(&theme.colors.header),
+            muted: fill_color(&theme.colors.line_numbers),
+            rule: stroke_color(&theme.colors.line_numbers),
+            header_rule: stroke_color(&theme.colors.header),
+        }
+    }
+}
+
+/// A laid out page: its content stream and the links placed on it
+struct LaidOutPage {
+    content: String,
+    /// Clickable rectangles and the named destinations they go to
+    links: Vec<([f32; 4], String)>,
+}
+
+/// A heading links and the outline can go to
+struct Destination {
+    name: String,
+    /// Index of the page the heading is on
+    page: usize,
+    /// Top of the heading
+    y: f32,
+    level: u32,
+    title: String,
+}
+
+/// Lays out content top-to-bottom onto pages using absolute positioning.
+/// Flowing content fills the columns of a region in turn; spanning content
+/// (headings, tables) starts a new region below everything placed so far.
+/// Finished pages are queued until the caller drains them with `take_completed_before`.
+struct PageLayout<'a> {
+    palette: &'a Palette,
+    geometry: PageGeometry,
+    pages: Vec<LaidOutPage>,
+    current: String,
+    links: Vec<([f32; 4], String)>,
+    /// Pages finished so far, the index of the current one
+    page_count: usize,
+    destinations: Vec<Destination>,
+    y: f32,
+    column: usize,
+    region_top: f32,
+    region_low: f32,
+}
+
+impl<'a> PageLayout<'a> {
+    fn new(palette: &'a Palette, geometry: PageGeometry) -> Self {
+        let top = geometry.top_y();
+        Self {
+            palette,
+            geometry,
+            pages: Vec::new(),
+            current: String::new(),
+            links: Vec::new(),
+            page_count: 0,
+            destinations: Vec::new(),
+            y: top,
+            column: 0,
+            region_top: top,
+            region_low: top,
+        }
+    }
+
+    fn new_page(&mut self) {
+        if !self.current.is_empty() {
+            self.pages.push(LaidOutPage {
+                content: std::mem::take(&mut self.current),
+                links: std::mem::take(&mut self.links),
+            });
+            self.page_count += 1;
+        }
+        self.y = self.geometry.top_y();
+        self.column = 0;
+        self.region_top = self.y;
+        self.region_low = self.y;
+    }
+
+    /// Move to the next column of the current region, or to a new page
+    fn next_column(&mut self) {
+        if self.column + 1 < self.geometry.columns {
+            self.region_low = self.region_low.min(self.y);
+            self.column += 1;
+            self.y = self.region_top;
+        } else {
+            self.new_page();
+        }
+    }
+
+    /// Move to the next column unless `height` points fit above the bottom margin
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < self.geometry.bottom_y() {
+            self.next_column();
+        }
+    }
+
+    /// Position the cursor for content spanning all columns, below anything
+    /// already placed in the current region
+    fn begin_span(&mut self, height: f32) {
+        self.y = self.y.min(self.region_low);
+        if self.y - height < self.geometry.bottom_y() {
+            self.new_page();
+        }
+    }
+
+    /// Start a new column region below spanning content
+    fn end_span(&mut self) {
+        self.column = 0;
+        self.region_top = self.y;
+        self.region_low = self.y;
+    }
+
+    /// Make the spanning line about to be drawn at the cursor a destination
+    /// named `name`
+    fn destination(&mut self, name: &str, level: u32, title: &str, size: f32) {
+        self.destinations.push(Destination {
+            name: name.to_string(),
+            page: self.page_count,
+            y: self.y + size,
+            level,
+            title: title.to_string(),
+        });
+    }
+
+    /// Make the line of `chars` characters about to be drawn in the current
+    /// column a link to the destination `name`
+    fn link_line(&mut self, name: &str, chars: usize, size: f32) {
+        let x = self.geometry.column_x(self.column);
+        let width = (chars as f32 * size * 0.5).min(self.geometry.column_width());
+        self.links.push(([x, self.y - 2.0, x + width, self.y + size], name.to_string()));
+    }
+
+    /// Draw a line of text with its baseline at `y`
+    fn text_at(&mut self, font: &str, size: f32, color: &str, x: f32, y: f32, text: &str) {
+        self.current.push_str(&format!(
+            "BT\n/{} {} Tf\n{}\n{:.2} {:.2} Td\n({}) Tj\nET\n",
+            font, size, color, x, y, escape_pdf_text(text)
+        ));
+    }
+
+    /// Draw a line of text in the current column and move down by `spacing`
+    fn text_line(&mut self, font: &str, size: f32, color: &str, text: &str, spacing: f32) {
+        let (x, y) = (self.geometry.column_x(self.column), self.y);
+        self.text_at(font, size, color, x, y, text);
+        self.y -= spacing;
+        self.region_low = self.region_low.min(self.y);
+    }
+
+    /// Draw a line of differently styled runs of code in the current
+    /// column and move down by `spacing`
+    fn runs_line(&mut self, size: f32, runs: &[CodeRun], spacing: f32) {
+        let (x, y) = (self.geometry.column_x(self.column), self.y);
+        let mut font = runs.first().map_or(CODE_FONT, |run| run.font);
+        self.current.push_str(&format!("BT\n/{} {} Tf\n{:.2} {:.2} Td\n", font, size, x, y));
+        for run in runs {
+            if run.font != font {
+                font = run.font;
+                self.current.push_str(&format!("/{} {} Tf\n", font, size));
+            }
+            self.current.push_str(&format!("{}\n({}) Tj\n", run.color, escape_pdf_text(&run.text)));
+        }
+        self.current.push_str("ET\n");
+        self.y -= spacing;
+        self.region_low = self.region_low.min(self.y);
+    }
+
+    /// Draw a line of text spanning all columns
+    fn span_line(&mut self, font: &str, size: f32, color: &str, text: &str, spacing: f32) {
+        self.begin_span(spacing);
+        let y = self.y;
+        self.text_at(font, size, color, MARGIN_LEFT, y, text);
+        self.y -= spacing;
+        self.end_span();
+    }
+
+    /// Draw a colored bar in the margin left of the current line, marking
+    /// it as added or modified
+    fn change_bar(&mut self, change: LineChange, size: f32, leading: f32) {
+        let color = match change {
+            LineChange::Added => CHANGE_ADDED_COLOR,
+            LineChange::Modified => CHANGE_MODIFIED_COLOR,
+        };
+        // Cover the line from below its descenders up to the next line
+        let x = self.geometry.column_x(self.column) - CHANGE_BAR_OFFSET;
+        let bottom = self.y - (leading - size).max(size * 0.25);
+        self.current.push_str(&format!(
+            "{}\n{:.2} {:.2} {:.2} {:.2} re\nf\n",
+            fill_color(color), x, bottom, CHANGE_BAR_WIDTH, leading
+        ));
+    }
+
+    /// Draw a thin rule between two points
+    fn rule(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let color = self.palette.rule.clone();
+        self.stroke_line(&color, x1, y1, x2, y2);
+    }
+
+    /// Underline the spanning line just drawn with a rule in the header
+    /// color across the full content width
+    fn header_rule(&mut self) {
+        let y = self.y + HEADER_RULE_DROP;
+        let color = self.palette.header_rule.clone();
+        self.stroke_line(&color, MARGIN_LEFT, y, MARGIN_LEFT + self.geometry.content_width(), y);
+    }
+
+    /// Stroke a 0.5pt line between two points
+    fn stroke_line(&mut self, color: &str, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.current.push_str(&format!(
+            "{}\n0.5 w\n{:.2} {:.2} m\n{:.2} {:.2} l\nS\n",
+            color, x1, y1, x2, y2
+        ));
+    }
+
+    fn layout_table(&mut self, table: &TableBlock) {
+        let columns = table.column_count();
+        if columns == 0 {
+            return;
+        }
+
+        let widths = table_column_widths(table, columns, self.geometry.content_width());
+        let table_width: f32 = widths.iter().sum();
+
+        // Start the table on a fresh page if not even the header and one row fit
+        let first_rows_height = table_row_height(&table.header, &widths)
+            + table.rows.first().map_or(0.0, |row| table_row_height(row, &widths));
+        self.begin_span(first_rows_height);
+        self.rule(MARGIN_LEFT, self.y, MARGIN_LEFT + table_width, self.y);
+        if !table.header.is_empty() {
+            self.table_row(&table.header, &widths, true);
+        }
+
+        for row in &table.rows {
+            let height = table_row_height(row, &widths);
+            if self.y - height < self.geometry.bottom_y() {
+                // Continue on the next page, repeating the header row
+                self.new_page();
+                self.rule(MARGIN_LEFT, self.y, MARGIN_LEFT + table_width, self.y);
+                if !table.header.is_empty() {
+                    self.table_row(&table.header, &widths, true);
                 }
-                Event::End(pulldown_cmark::TagEnd::Paragraph) => {
-                    if !current_text.is_empty() && !in_heading && !in_code_block {
-                        pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                        current_text.clear();
+            }
+            self.table_row(row, &widths, false);
+        }
+
+        self.y -= 8.0;
+        self.end_span();
+    }
+
+    fn table_row(&mut self, cells: &[String], widths: &[f32], is_header: bool) {
+        let font = if is_header { "F2" } else { "F1" };
+        let color = if is_header { self.palette.header.clone() } else { self.palette.text.clone() };
+        let top = self.y;
+        let height = table_row_height(cells, widths);
+
+        let mut x = MARGIN_LEFT;
+        for (column, width) in widths.iter().enumerate() {
+            let cell = cells.get(column).map(String::as_str).unwrap_or("");
+            for (i, line) in wrap_cell_text(cell, *width).iter().enumerate() {
+                let baseline = top - TABLE_CELL_PADDING - TABLE_FONT_SIZE - i as f32 * TABLE_LEADING;
+                self.text_at(font, TABLE_FONT_SIZE, &color, x + TABLE_CELL_PADDING, baseline, line);
+            }
+            x += width;
+        }
+
+        let bottom = top - height;
+        let right = MARGIN_LEFT + widths.iter().sum::<f32>();
+        self.rule(MARGIN_LEFT, bottom, right, bottom);
+        let mut x = MARGIN_LEFT;
+        self.rule(x, top, x, bottom);
+        for width in widths {
+            x += width;
+            self.rule(x, top, x, bottom);
+        }
+
+        self.y = bottom;
+    }
+
+    /// Take the pages completed so far that come before page `end`,
+    /// keeping the later ones queued
+    fn take_completed_before(&mut self, end: usize) -> Vec<LaidOutPage> {
+        let first = self.page_count - self.pages.len();
+        let rest = self.pages.split_off(end.saturating_sub(first).min(self.pages.len()));
+        std::mem::replace(&mut self.pages, rest)
+    }
+
+    /// Throw away everything laid out from page `end` on and carry on at
+    /// the top of a blank page `end`. Destinations on the pages thrown
+    /// away move to the top of it.
+    fn discard_from(&mut self, end: usize) {
+        let first = self.page_count - self.pages.len();
+        self.pages.truncate(end.saturating_sub(first));
+        self.page_count = first + self.pages.len();
+        self.current.clear();
+        self.links.clear();
+        self.new_page();
+        let top = self.geometry.top_y();
+        for destination in self.destinations.iter_mut().filter(|destination| destination.page >= end) {
+            destination.page = self.page_count;
+            destination.y = top;
+        }
+    }
+
+    /// The remaining pages and every destination placed
+    fn finish(mut self) -> (Vec<LaidOutPage>, Vec<Destination>) {
+        if !self.current.is_empty() {
+            self.pages.push(LaidOutPage { content: self.current, links: self.links });
+        }
+        (self.pages, self.destinations)
+    }
+}
+
+/// Approximate Helvetica glyph width at the table font size
+fn table_char_width() -> f32 {
+    TABLE_FONT_SIZE * 0.55
+}
+
+/// Column widths sized to content, scaled down to fit the available width
+fn table_column_widths(table: &TableBlock, columns: usize, available: f32) -> Vec<f32> {
+    let mut widths = vec![0.0f32; columns];
+    for row in std::iter::once(&table.header).chain(table.rows.iter()) {
+        for (column, cell) in row.iter().enumerate() {
+            let width = cell.chars().count() as f32 * table_char_width() + 2.0 * TABLE_CELL_PADDING;
+            widths[column] = widths[column].max(width);
+        }
+    }
+
+    let min_width = 2.0 * TABLE_CELL_PADDING + 3.0 * table_char_width();
+    for width in widths.iter_mut() {
+        *width = width.max(min_width);
+    }
+
+    let total: f32 = widths.iter().sum();
+    if total > available {
+        let scale = available / total;
+        for width in widths.iter_mut() {
+            *width *= scale;
+        }
+    }
+
+    widths
+}
+
+/// Wrap cell text on word boundaries to fit a column
+fn wrap_cell_text(text: &str, width: f32) -> Vec<String> {
+    let max_chars = ((width - 2.0 * TABLE_CELL_PADDING) / table_char_width()) as usize;
+    wrap_words(text, max_chars)
+}
+
+/// Wrap text on word boundaries, hard-breaking words longer than a line
+fn wrap_words(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > max_chars {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..max_chars).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if word.is_empty() {
+            continue;
+        }
+
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// A run of code text in one font and color
+#[derive(Debug, Clone)]
+struct CodeRun {
+    font: &'static str,
+    /// Fill color operator
+    color: String,
+    text: String,
+}
+
+/// Hard-wrap a line of styled runs at a fixed character count,
+/// preserving whitespace
+fn wrap_runs(runs: &[CodeRun], max_chars: usize) -> Vec<Vec<CodeRun>> {
+    let max_chars = max_chars.max(1);
+    let mut lines = vec![Vec::new()];
+    let mut used = 0;
+    for run in runs {
+        let mut chars = run.text.chars().peekable();
+        while chars.peek().is_some() {
+            if used == max_chars {
+                lines.push(Vec::new());
+                used = 0;
+            }
+            let piece: String = chars.by_ref().take(max_chars - used).collect();
+            used += piece.chars().count();
+            if let Some(line) = lines.last_mut() {
+                line.push(CodeRun { text: piece, ..run.clone() });
+            }
+        }
+    }
+    lines
+}
+
+fn table_row_height(cells: &[String], widths: &[f32]) -> f32 {
+    let line_count = widths.iter()
+        .enumerate()
+        .map(|(column, width)| {
+            let cell = cells.get(column).map(String::as_str).unwrap_or("");
+            wrap_cell_text(cell, *width).len()
+        })
+        .max()
+        .unwrap_or(1);
+    2.0 * TABLE_CELL_PADDING + line_count as f32 * TABLE_LEADING
+}
+
+/// Object numbers reserved before any page is written
+const CATALOG_OBJ: usize = 1;
+const PAGES_OBJ: usize = 2;
+const FONT_OBJS: [(usize, &str); 4] = [
+    (3, "Helvetica"),
+    (4, "Helvetica-Bold"),
+    (5, "Courier"),
+    (6, "Courier-Bold"),
+];
+const FIRST_PAGE_OBJ: usize = 7;
+
+/// Page resource names of the regular and bold code fonts. Italic and
+/// underlined tokens are set upright: the builtin fonts have no matching
+/// monospace faces embedded for PDF/A.
+const CODE_FONT: &str = "F3";
+const CODE_BOLD_FONT: &str = "F4";
+
+/// Document information recorded in the Info dictionary and catalog, and
+/// in the XMP packet of PDF/A output
+struct DocumentInfo {
+    title: String,
+    author: Option<String>,
+    language: String,
+    created: String,
+}
+
+/// Streams PDF objects to the output as they are produced, keeping only the
+/// byte offsets and page object numbers needed for the page tree and xref table
+struct PdfWriter<'w> {
+    out: &'w mut dyn Write,
+    position: usize,
+    offsets: Vec<usize>,
+    page_objs: Vec<usize>,
+    next_obj: usize,
+    background: String,
+    geometry: PageGeometry,
+    info: DocumentInfo,
+    pdfa: bool,
+    compress: bool,
+    digest: [DefaultHasher; 2],
+}
+
+impl<'w> PdfWriter<'w> {
+    fn new(
+        out: &'w mut dyn Write,
+        background: String,
+        geometry: PageGeometry,
+        info: DocumentInfo,
+        pdfa: bool,
+        compress: bool,
+    ) -> Result<Self> {
+        let mut second = DefaultHasher::new();
+        second.write_u8(0xA5);
+        let mut writer = Self {
+            out,
+            position: 0,
+            offsets: vec![0; FIRST_PAGE_OBJ],
+            page_objs: Vec::new(),
+            next_obj: FIRST_PAGE_OBJ,
+            background,
+            geometry,
+            info,
+            pdfa,
+            compress,
+            digest: [DefaultHasher::new(), second],
+        };
+        if writer.pdfa {
+            // PDF/A requires a binary comment right after the header
+            writer.write_raw(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n")?;
+            writer.write_embedded_fonts()?;
+        } else {
+            writer.write_raw(b"%PDF-1.4\n")?;
+            for (obj_num, base_font) in FONT_OBJS {
+                writer.write_object(obj_num, &format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    base_font
+                ))?;
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Write TrueType font programs in place of the builtin Type1 fonts
+    fn write_embedded_fonts(&mut self) -> Result<()> {
+        for ((obj_num, _), font) in FONT_OBJS.iter().zip(EMBEDDED_FONTS.iter()) {
+            let metrics = font.metrics()?;
+
+            let file_obj = self.allocate_object();
+            self.write_filtered_stream(
+                file_obj,
+                &format!("/Length1 {}", font.data.len()),
+                font.data,
+            )?;
+
+            let descriptor_obj = self.allocate_object();
+            let [x_min, y_min, x_max, y_max] = metrics.bbox;
+            self.write_object(descriptor_obj, &format!(
+                "<< /Type /FontDescriptor /FontName /{} /Flags {} /FontBBox [{} {} {} {}]\n\
+                 /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} /StemV {} /FontFile2 {} 0 R >>",
+                font.base_font, metrics.flags, x_min, y_min, x_max, y_max,
+                metrics.italic_angle, metrics.ascent, metrics.descent, metrics.cap_height,
+                metrics.stem_v, file_obj
+            ))?;
+
+            let widths: Vec<String> = metrics.widths.iter().map(|w| w.to_string()).collect();
+            self.write_object(*obj_num, &format!(
+                "<< /Type /Font /Subtype /TrueType /BaseFont /{} /Encoding /WinAnsiEncoding\n\
+                 /FirstChar {} /LastChar {} /Widths [{}]\n/FontDescriptor {} 0 R >>",
+                font.base_font, FIRST_CHAR, LAST_CHAR, widths.join(" "), descriptor_obj
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes).context("Failed to write PDF output")?;
+        self.position += bytes.len();
+        if self.pdfa {
+            for hasher in &mut self.digest {
+                hasher.write(bytes);
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate_object(&mut self) -> usize {
+        let obj_num = self.next_obj;
+        self.next_obj += 1;
+        self.offsets.push(0);
+        obj_num
+    }
+
+    fn write_object(&mut self, obj_num: usize, body: &str) -> Result<()> {
+        self.offsets[obj_num] = self.position;
+        self.write_raw(format!("{} 0 obj\n{}\nendobj\n", obj_num, body).as_bytes())
+    }
+
+    /// Write a stream object; `entries` are extra dictionary entries besides `/Length`
+    fn write_stream(&mut self, obj_num: usize, entries: &str, data: &[u8]) -> Result<()> {
+        self.offsets[obj_num] = self.position;
+        let separator = if entries.is_empty() { "" } else { " " };
+        self.write_raw(format!(
+            "{} 0 obj\n<< /Length {}{}{} >>\nstream\n",
+            obj_num, data.len(), separator, entries
+        ).as_bytes())?;
+        self.write_raw(data)?;
+        self.write_raw(b"\nendstream\nendobj\n")
+    }
+
+    /// Write a stream object, Flate-compressed unless compression is disabled
+    fn write_filtered_stream(&mut self, obj_num: usize, entries: &str, data: &[u8]) -> Result<()> {
+        if !self.compress {
+            return self.write_stream(obj_num, entries, data);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).context("Failed to compress PDF stream")?;
+        let compressed = encoder.finish().context("Failed to compress PDF stream")?;
+        let entries = format!("/Filter /FlateDecode {}", entries);
+        self.write_stream(obj_num, entries.trim_end(), &compressed)
+    }
+
+    /// Write a finished page: its content stream (page background fill, then
+    /// the page content), its link annotations and the page object
+    fn add_page(&mut self, page: &LaidOutPage) -> Result<()> {
+        let content_obj = self.allocate_object();
+        let page_obj = self.allocate_object();
+
+        let stream = format!(
+            "q\n{}\n0 0 {} {} re\nf\nQ\n{}",
+            self.background, self.geometry.width, self.geometry.height, page.content
+        );
+        self.write_filtered_stream(content_obj, "", stream.as_bytes())?;
+
+        let mut annots = Vec::new();
+        for ([x1, y1, x2, y2], name) in &page.links {
+            let annot_obj = self.allocate_object();
+            self.write_object(annot_obj, &format!(
+                "<< /Type /Annot /Subtype /Link /Rect [{:.2} {:.2} {:.2} {:.2}] /Border [0 0 0] /F 4 /Dest {} >>",
+                x1, y1, x2, y2, pdf_name(name)
+            ))?;
+            annots.push(format!("{} 0 R", annot_obj));
+        }
+        let annots = if annots.is_empty() { String::new() } else { format!("\n/Annots [{}]", annots.join(" ")) };
+
+        let fonts: Vec<String> = FONT_OBJS.iter()
+            .enumerate()
+            .map(|(i, (obj_num, _))| format!("/F{} {} 0 R", i + 1, obj_num))
+            .collect();
+        self.write_object(page_obj, &format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}]\n/Resources << /Font << {} >> >>\n/Contents {} 0 R{} >>",
+            PAGES_OBJ, self.geometry.width, self.geometry.height, fonts.join(" "), content_obj, annots
+        ))?;
+
+        self.page_objs.push(page_obj);
+        Ok(())
+    }
+
+    /// Write the XMP metadata and sRGB output intent objects, returning the
+    /// extra catalog entries that reference them
+    fn write_pdfa_objects(&mut self) -> Result<String> {
+        if !self.pdfa {
+            return Ok(String::new());
+        }
+        let xmp = xmp_packet(&self.info);
+
+        let metadata_obj = self.allocate_object();
+        self.write_stream(metadata_obj, "/Type /Metadata /Subtype /XML", xmp.as_bytes())?;
+
+        let profile_obj = self.allocate_object();
+        self.write_stream(profile_obj, "/N 3", SRGB_ICC_PROFILE)?;
+
+        Ok(format!(
+            " /Metadata {} 0 R\n/OutputIntents [<< /Type /OutputIntent /S /GTS_PDFA1 \
+             /OutputConditionIdentifier (sRGB IEC61966-2.1) /Info (sRGB IEC61966-2.1) \
+             /DestOutputProfile {} 0 R >>]",
+            metadata_obj, profile_obj
+        ))
+    }
+
+    /// Write the named destinations and the outline of the headings among
+    /// them, returning the extra catalog entries that reference them
+    fn write_navigation(&mut self, destinations: &[Destination]) -> Result<String> {
+        if destinations.is_empty() {
+            return Ok(String::new());
+        }
+        let target = |dest: &Destination, page_objs: &[usize]| {
+            format!("[{} 0 R /XYZ 0 {:.2} null]", page_objs[dest.page], dest.y)
+        };
+
+        // The first heading with a name is the one links go to
+        let mut named = HashSet::new();
+        let dests: Vec<String> = destinations.iter()
+            .filter(|dest| named.insert(dest.name.as_str()))
+            .map(|dest| format!("{} {}", pdf_name(&dest.name), target(dest, &self.page_objs)))
+            .collect();
+        let dests_obj = self.allocate_object();
+        self.write_object(dests_obj, &format!("<< {} >>", dests.join("\n")))?;
+
+        // Second level headings are the outline's top, deeper ones nest
+        // under the one before them
+        let mut tree: Vec<(&Destination, Vec<&Destination>)> = Vec::new();
+        for dest in destinations {
+            match tree.last_mut() {
+                Some((_, children)) if dest.level > 2 => children.push(dest),
+                _ => tree.push((dest, Vec::new())),
+            }
+        }
+        let outlines_obj = self.allocate_object();
+        let top_objs: Vec<usize> = tree.iter().map(|_| self.allocate_object()).collect();
+        let total: usize = tree.iter().map(|(_, children)| 1 + children.len()).sum();
+        self.write_object(outlines_obj, &format!(
+            "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+            top_objs[0], top_objs[top_objs.len() - 1], total
+        ))?;
+        for (i, (dest, children)) in tree.iter().enumerate() {
+            let child_objs: Vec<usize> = children.iter().map(|_| self.allocate_object()).collect();
+            let mut entries = outline_links(&top_objs, i, outlines_obj);
+            if let (Some(first), Some(last)) = (child_objs.first(), child_objs.last()) {
+                entries.push_str(&format!(" /First {} 0 R /Last {} 0 R /Count {}", first, last, child_objs.len()));
+            }
+            self.write_object(top_objs[i], &format!(
+                "<< /Title {} /Dest {}{} >>",
+                pdf_text_string(&dest.title), target(dest, &self.page_objs), entries
+            ))?;
+            for (j, child) in children.iter().enumerate() {
+                self.write_object(child_objs[j], &format!(
+                    "<< /Title {} /Dest {}{} >>",
+                    pdf_text_string(&child.title), target(child, &self.page_objs),
+                    outline_links(&child_objs, j, top_objs[i])
+                ))?;
+            }
+        }
+
+        Ok(format!(" /Dests {} 0 R /Outlines {} 0 R", dests_obj, outlines_obj))
+    }
+
+    /// Write the page tree, catalog, xref table, and trailer
+    fn finish(mut self, destinations: &[Destination]) -> Result<()> {
+        let kids: Vec<String> = self.page_objs.iter()
+            .map(|obj_num| format!("{} 0 R", obj_num))
+            .collect();
+        self.write_object(PAGES_OBJ, &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "), self.page_objs.len()
+        ))?;
+        let navigation_entries = self.write_navigation(destinations)?;
+        let pdfa_entries = self.write_pdfa_objects()?;
+        self.write_object(CATALOG_OBJ, &format!(
+            "<< /Type /Catalog /Pages {} 0 R /Lang {}{}{} >>",
+            PAGES_OBJ, pdf_text_string(&self.info.language), navigation_entries, pdfa_entries
+        ))?;
+
+        let info_obj = self.allocate_object();
+        let author = self.info.author.as_deref()
+            .map(|author| format!(" /Author {}", pdf_text_string(author)))
+            .unwrap_or_default();
+        self.write_object(info_obj, &format!(
+            "<< /Title {}{} /Producer (scrollcast) >>",
+            pdf_text_string(&self.info.title), author
+        ))?;
+
+        // The document ID is a digest of everything written so far
+        let id = if self.pdfa {
+            let digest = format!("{:016x}{:016x}", self.digest[0].finish(), self.digest[1].finish());
+            format!(" /ID [<{}> <{}>]", digest, digest)
+        } else {
+            String::new()
+        };
+
+        let xref_offset = self.position;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in &self.offsets[1..] {
+            xref.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R{} >>\nstartxref\n{}\n%%EOF",
+            self.offsets.len(), CATALOG_OBJ, info_obj, id, xref_offset
+        ));
+        self.write_raw(xref.as_bytes())?;
+        self.out.flush().context("Failed to flush PDF output")?;
+        Ok(())
+    }
+}
+
+/// The `/Parent`, `/Prev` and `/Next` entries of the `i`th of the sibling
+/// outline items `siblings`
+fn outline_links(siblings: &[usize], i: usize, parent: usize) -> String {
+    let mut entries = format!(" /Parent {} 0 R", parent);
+    if i > 0 {
+        entries.push_str(&format!(" /Prev {} 0 R", siblings[i - 1]));
+    }
+    if let Some(next) = siblings.get(i + 1) {
+        entries.push_str(&format!(" /Next {} 0 R", next));
+    }
+    entries
+}
+
+/// `name` as a PDF name object, characters other than letters, digits,
+/// `-` and `_` written as `#` and their hex bytes
+fn pdf_name(name: &str) -> String {
+    let mut escaped = String::from("/");
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("#{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// `text` as a PDF text string: UTF-16BE with a byte order mark, written in
+/// hex so any character survives without escaping
+fn pdf_text_string(text: &str) -> String {
+    let units: String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+    format!("<FEFF{}>", units)
+}
+
+/// XMP packet declaring PDF/A-2b conformance along with the document title,
+/// author, language and creation date
+fn xmp_packet(info: &DocumentInfo) -> String {
+    let creator = info.author.as_deref()
+        .map(|author| format!(
+            "\n   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            escape_xml(author)
+        ))
+        .unwrap_or_default();
+    format!(
+        "<?xpacket begin=\"{}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+  <rdf:Description rdf:about=\"\"\n\
+    xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"\n\
+    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n\
+    xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\n\
+   <pdfaid:part>2</pdfaid:part>\n\
+   <pdfaid:conformance>B</pdfaid:conformance>\n\
+   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>{}\n\
+   <dc:language><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></dc:language>\n\
+   <xmp:CreateDate>{}</xmp:CreateDate>\n\
+   <pdf:Producer>scrollcast</pdf:Producer>\n\
+  </rdf:Description>\n\
+ </rdf:RDF>\n\
+</x:xmpmeta>\n\
+<?xpacket end=\"w\"?>",
+        '\u{feff}', escape_xml(&info.title), creator, escape_xml(&info.language), escape_xml(&info.created)
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl DocumentRenderer for PdfRenderer {
+    fn render(&self, events: Vec<Event>, metadata: &DocumentMetadata) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.render_to_writer(events, metadata, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn render_to_writer(&self, events: Vec<Event>, metadata: &DocumentMetadata, writer: &mut dyn Write) -> Result<()> {
+        let highlighter = highlighter_for(metadata)?;
+        let theme = self.resolve_theme(&highlighter, metadata)?;
+        let palette = Palette::from_theme(&theme);
+        let mut blocks = Blocks::new(events.into_iter(), &highlighter, &metadata.highlight_policy, &metadata.cancellation);
+        // Code takes the syntax theme's colors and weights, or the token
+        // styles of an explicitly chosen theme so they suit its background
+        let code_run = |span: &StyledSpan| {
+            let (color, bold) = if self.options.theme.is_some() {
+                (
+                    fill_color(span.token_kind.color(&theme.colors)),
+                    theme.font_style(span.token_kind.style_key()).bold,
+                )
+            } else {
+                (fill_color(&span.fg_hex()), span.bold)
+            };
+            CodeRun {
+                font: if bold { CODE_BOLD_FONT } else { CODE_FONT },
+                color,
+                text: span.text.clone(),
+            }
+        };
+
+        let geometry = PageGeometry::from_options(&self.options);
+        let TextMetrics { text_size, code_size, text_leading, item_leading, code_leading, code_chars, text_chars } =
+            TextMetrics::new(&self.options, geometry, metadata.font_size, metadata.line_height);
+
+        let info = DocumentInfo {
+            title: metadata.title.clone(),
+            author: metadata.author.clone(),
+            language: metadata.language.clone(),
+            created: metadata.date_label()
+                .unwrap_or_else(|| chrono::Utc::now().format("%Y-%m-%d").to_string()),
+        };
+        let mut pdf = PdfWriter::new(
+            writer,
+            palette.background.clone(),
+            geometry,
+            info,
+            self.options.pdfa,
+            self.options.compress,
+        )?;
+        let mut layout = PageLayout::new(&palette, geometry);
+
+        // First page - Title and metadata
+        layout.span_line("F2", 18.0, &palette.header, &metadata.title, 30.0);
+        let strings = i18n::strings_for(&metadata.language);
+        let date = format!("{} {}", strings.generated, metadata.date_label().as_deref().unwrap_or(strings.today));
+        layout.span_line("F1", 10.0, &palette.muted, &date, 25.0);
+
+        // With a page limit, the last page allowed is held back until it's
+        // clear whether the rest fits or it makes way for the notice
+        let max_pages = self.options.max_pages.map(|pages| pages.max(1));
+        let notice_page = max_pages.map_or(usize::MAX, |pages| pages - 1);
+        let over_limit = |layout: &PageLayout| {
+            max_pages.is_some_and(|pages| layout.page_count > pages || (layout.page_count == pages && !layout.current.is_empty()))
+        };
+        // Path and page of each file heading laid out so far
+        let mut file_pages: Vec<(String, usize)> = Vec::new();
+        // Files still to come when the page limit was reached
+        let mut stopped: Option<Vec<String>> = None;
+
+        // Blocks are read and laid out a page at a time
+        while let Some(block) = blocks.next().transpose()? {
+            if over_limit(&layout) {
+                let mut rest: Vec<String> = file_heading(&block).map(str::to_string).into_iter().collect();
+                rest.extend(blocks.remaining_file_headings()?);
+                stopped = Some(rest);
+                break;
+            }
+            // Emit finished pages right away so only the current page is buffered
+            for page in layout.take_completed_before(notice_page) {
+                metadata.cancellation.check()?;
+                pdf.add_page(&page)?;
+            }
+            if let Some(path) = file_heading(&block) {
+                file_pages.push((path.to_string(), layout.page_count));
+            }
+
+            match &block {
+                Block::PageBreak => layout.new_page(),
+                Block::Table(table) => layout.layout_table(table),
+                Block::Heading(level, text, id) => {
+                    let size = match level {
+                        1 => 16.0,
+                        2 => 14.0,
+                        _ => 12.0,
+                    };
+                    // Headings span all columns and keep a few lines below them
+                    layout.begin_span(20.0 + KEEP_WITH_NEXT);
+                    if let Some(id) = id {
+                        layout.destination(id, *level, text, size);
+                    }
+                    layout.span_line("F2", size, &palette.header, text, 20.0);
+                    // Level three headings name files; rule them off from the code
+                    if *level == 3 {
+                        layout.header_rule();
                     }
                 }
-                Event::Text(text) => {
-                    // Handle page breaks
-                    if text.contains("\\newpage") {
-                        let parts: Vec<&str> = text.split("\\newpage").collect();
-                        for (i, part) in parts.iter().enumerate() {
-                            if !part.is_empty() {
-                                current_text.push_str(part);
-                            }
-                            if i < parts.len() - 1 {
-                                if !current_text.is_empty() {
-                                    pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-                                    current_text.clear();
-                                }
-                                pdf_content_lines.push("PAGE_BREAK".to_string());
-                            }
+                Block::CodeLine(spans, change) => {
+                    let runs: Vec<CodeRun> = spans.iter().map(code_run).collect();
+                    for line in wrap_runs(&runs, code_chars) {
+                        layout.ensure_space(code_leading);
+                        if let Some(change) = change {
+                            layout.change_bar(*change, code_size, code_leading);
                         }
-                    } else {
-                        current_text.push_str(&text);
+                        layout.runs_line(code_size, &line, code_leading);
                     }
                 }
-                Event::Code(code) => {
-                    current_text.push_str(&format!("`{}`", code));
+                Block::CodeStart | Block::CodeEnd => {
+                    let marker = if matches!(block, Block::CodeStart) { "--- Code Block ---" } else { "--- End Code ---" };
+                    layout.ensure_space(15.0);
+                    layout.text_line("F1", 10.0, &palette.muted, marker, 15.0);
                 }
-                Event::SoftBreak | Event::HardBreak => {
-                    current_text.push(' ');
+                Block::ListItem(text, _) | Block::Text(text) => {
+                    let (spacing, link) = match &block {
+                        Block::ListItem(_, link) => (item_leading, link.as_deref()),
+                        _ => (text_leading, None),
+                    };
+                    for line in wrap_words(text, text_chars) {
+                        layout.ensure_space(spacing);
+                        if let Some(name) = link {
+                            layout.link_line(name, line.chars().count(), text_size);
+                        }
+                        layout.text_line("F1", text_size, &palette.text, &line, spacing);
+                    }
                 }
-                _ => {}
             }
         }
-        
-        // Add any remaining text
-        if !current_text.is_empty() {
-            if in_code_block {
-                pdf_content_lines.push(format!("CODE: {}", current_text.trim()));
-            } else {
-                pdf_content_lines.push(format!("TEXT: {}", current_text.trim()));
-            }
-        }
-        
-        // Create multi-page PDF with proper formatting
-        let mut pages = Vec::new();
-        let mut current_page_content = String::new();
-        let mut y_pos = 720;
-        let mut page_num = 1;
-        
-        // Helper function to start a new page
-        let mut start_new_page = |content: &mut String, y: &mut i32, num: &mut i32| {
-            if !content.is_empty() {
-                pages.push(content.clone());
-                content.clear();
-            }
-            *y = 720;
-            *num += 1;
+
+        if stopped.is_none() && over_limit(&layout) {
+            stopped = Some(Vec::new());
+        }
+        if let (Some(rest), Some(max_pages)) = (stopped, max_pages) {
+            // Files starting on the pages given up to the notice are left out
+            // too, and the last file kept is cut short unless one of them
+            // starts at the top of the notice page
+            let (kept, dropped): (Vec<_>, Vec<_>) = file_pages.iter().partition(|(_, page)| *page < notice_page);
+            let omitted: Vec<&str> = dropped.iter().map(|(path, _)| path.as_str())
+                .chain(rest.iter().map(String::as_str))
+                .collect();
+            if let Some((path, _)) = kept.last().filter(|_| !dropped.iter().any(|(_, page)| *page == notice_page)) {
+                metadata.warnings.push(
+                    WarningCategory::PageLimit,
+                    Some(path),
+                    format!("Cut short at the {} page limit", max_pages),
+                );
+            }
+            for path in &omitted {
+                metadata.warnings.push(
+                    WarningCategory::PageLimit,
+                    Some(path),
+                    format!("Left out at the {} page limit", max_pages),
+                );
+            }
+
+            layout.discard_from(notice_page);
+            layout.span_line("F2", 16.0, &palette.header, strings.page_limit, 30.0);
+            for line in wrap_words(&strings.files_left_out(omitted.len(), max_pages), text_chars) {
+                layout.text_line("F1", text_size, &palette.text, &line, text_leading);
+            }
+        }
+
+        let (pages, destinations) = layout.finish();
+        for page in pages {
+            pdf.add_page(&page)?;
+        }
+        pdf.finish(&destinations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    const SAMPLE: &str = "# Title\n\nSome prose.\n\n```rust\nfn main() {}\n```\n";
+
+    /// Options with compression off so tests can inspect content streams
+    fn uncompressed() -> PdfOptions {
+        PdfOptions { compress: false, ..Default::default() }
+    }
+
+    fn render_with(theme: Option<Theme>) -> String {
+        let renderer = PdfRenderer::with_options(PdfOptions { theme, ..uncompressed() });
+        let bytes = renderer.render_markdown(SAMPLE, &DocumentMetadata::default()).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Split rendered PDF output into its page content streams
+    fn content_streams(pdf: &str) -> Vec<&str> {
+        pdf.split("stream\n")
+            .skip(1)
+            .step_by(2)
+            .map(|stream| stream.split("endstream").next().unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn test_dark_theme_background_and_text_colors() {
+        let pdf = render_with(Some(Theme::dark()));
+
+        // #2d2d2d background rectangle and #f0f0f0 text
+        assert!(pdf.contains("0.176 0.176 0.176 rg\n0 0 612 792 re\nf"));
+        assert!(pdf.contains("0.941 0.941 0.941 rg\n50.00 645.00 Td\n(Some prose.) Tj"));
+    }
+
+    #[test]
+    fn test_dark_background_on_every_page_and_file_header_rules() {
+        let code: String = (1..=150).map(|i| format!("let value_{} = {};\n", i, i)).collect();
+        let markdown = format!("### src/main.rs\n\n```rust\n{}```\n\n### src/lib.rs\n\n```rust\nfn lib() {{}}\n```\n", code);
+        let renderer = PdfRenderer::with_options(PdfOptions { theme: Some(Theme::dark()), ..uncompressed() });
+        let bytes = renderer.render_markdown(&markdown, &DocumentMetadata::default()).unwrap();
+        let pdf = String::from_utf8(bytes).unwrap();
+
+        let streams = content_streams(&pdf);
+        assert!(streams.len() > 2);
+        for stream in &streams {
+            assert!(stream.starts_with("q\n0.176 0.176 0.176 rg\n0 0 612 792 re\nf\nQ\n"), "{}", stream);
+        }
+        // A white 0.5pt rule across the content width under each file header
+        let rule = Regex::new(r"1\.000 1\.000 1\.000 RG\n0\.5 w\n50\.00 (\d+\.\d+) m\n562\.00 (\d+\.\d+) l\nS").unwrap();
+        let rules: Vec<_> = rule.captures_iter(&pdf).collect();
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|rule| rule[1] == rule[2]));
+    }
+
+    #[test]
+    fn test_syntax_theme_sets_page_colors() {
+        let renderer = PdfRenderer::with_options(uncompressed());
+        let metadata = DocumentMetadata {
+            syntax_theme: "breezedark".to_string(),
+            ..Default::default()
         };
-        
-        // First page - Title and metadata
-        current_page_content.push_str(&format!(
-            "/F2 18 Tf\n50 {} Td\n({}) Tj\n0 -30 Td\n",
-            y_pos,
-            metadata.title.replace("(", "\\(").replace(")", "\\)")
-        ));
-        y_pos -= 30;
-        
-        current_page_content.push_str(&format!(
-            "/F1 10 Tf\n(Generated: {}) Tj\n0 -25 Td\n",
-            metadata.date.as_ref().unwrap_or(&"Today".to_string())
-        ));
-        y_pos -= 25;
-        
-        // Process content with page breaks
-        for line in pdf_content_lines.iter() {
-            // Check for file boundaries (start new page)
-            let is_file_header = line.starts_with("H3:") && (
-                line.contains("README.md") || 
-                line.contains("main.rs") || 
-                line.contains(".rs") ||
-                line.contains(".md")
-            );
-            
-            // Force page break on main.rs or explicit page breaks
-            let is_main_rs = line.contains("main.rs") && line.starts_with("H3:");
-            
-            if line == "PAGE_BREAK" || is_main_rs {
-                start_new_page(&mut current_page_content, &mut y_pos, &mut page_num);
-            }
-            
-            // Skip processing PAGE_BREAK lines (they just trigger page breaks)
-            if line == "PAGE_BREAK" {
-                continue;
-            }
-            
-            // Check if we need a new page before adding content
-            if y_pos < 150 { // More generous space check
-                start_new_page(&mut current_page_content, &mut y_pos, &mut page_num);
-            }
-            
-            let (font_cmd, text_content) = if line.starts_with("H1:") {
-                ("/F2 16 Tf", &line[3..])
-            } else if line.starts_with("H2:") {
-                ("/F2 14 Tf", &line[3..])
-            } else if line.starts_with("H3:") || line.starts_with("H4:") {
-                ("/F2 12 Tf", &line[3..])
-            } else if line.starts_with("CODE_LINE:") {
-                ("/F3 9 Tf", &line[10..])
-            } else if line.starts_with("CODE_START") {
-                ("/F1 10 Tf", "--- Code Block ---")
-            } else if line.starts_with("CODE_END") {
-                ("/F1 10 Tf", "--- End Code ---")
-            } else if line.starts_with("LIST_ITEM:") {
-                ("/F1 10 Tf", &line[10..])
-            } else if line.starts_with("TABLE_ROW:") {
-                ("/F1 9 Tf", &line[10..])
-            } else if line == "TABLE_START" {
-                ("/F1 10 Tf", "--- Table ---")
-            } else if line == "TABLE_END" {
-                ("/F1 10 Tf", "--- End Table ---")
-            } else if line.starts_with("TEXT:") {
-                ("/F1 10 Tf", &line[5..])
-            } else {
-                ("/F1 10 Tf", line.as_str())
-            };
-            
-            let escaped_text = text_content
-                .replace("(", "\\(")
-                .replace(")", "\\)")
-                .replace("\\", "\\\\");
-            
-            let line_spacing = if line.starts_with("H") { 
-                20 
-            } else if line.starts_with("LIST_ITEM:") || line.starts_with("TABLE_ROW:") { 
-                12 
-            } else { 
-                15 
-            };
-            
-            current_page_content.push_str(&format!(
-                "{}\n({}) Tj\n0 -{} Td\n",
-                font_cmd,
-                escaped_text,
-                line_spacing
-            ));
-            y_pos -= line_spacing;
-        }
-        
-        // Add final page
-        if !current_page_content.is_empty() {
-            pages.push(current_page_content);
-        }
-        
-        let num_pages = pages.len();
-        
-        // Generate multi-page PDF structure
-        let mut pdf_content = String::from("%PDF-1.4\n");
-        
-        // Catalog
-        pdf_content.push_str("1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n");
-        
-        // Pages object with kids array
-        let mut kids_array = String::new();
-        for i in 0..num_pages {
-            if i > 0 { kids_array.push(' '); }
-            kids_array.push_str(&format!("{} 0 R", 3 + i));
-        }
-        pdf_content.push_str(&format!(
-            "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
-            kids_array, num_pages
-        ));
-        
-        // Page objects and content streams
-        let mut content_objects = Vec::new();
-        for (i, page_content) in pages.iter().enumerate() {
-            let page_obj_num = 3 + i;
-            let content_obj_num = 3 + num_pages + 3 + i; // After pages, fonts, then content
-            
-            // Page object
-            pdf_content.push_str(&format!(
-                "{} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792]\n/Resources << /Font << /F1 {} 0 R /F2 {} 0 R /F3 {} 0 R >> >>\n/Contents {} 0 R >>\nendobj\n",
-                page_obj_num,
-                3 + num_pages,     // F1 font object
-                3 + num_pages + 1, // F2 font object  
-                3 + num_pages + 2, // F3 font object
-                content_obj_num
-            ));
-            
-            content_objects.push((content_obj_num, page_content));
-        }
-        
-        // Font objects
-        let font1_obj = 3 + num_pages;
-        let font2_obj = 3 + num_pages + 1;
-        let font3_obj = 3 + num_pages + 2;
-        
-        pdf_content.push_str(&format!(
-            "{} 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>\nendobj\n",
-            font1_obj
-        ));
-        pdf_content.push_str(&format!(
-            "{} 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold >>\nendobj\n",
-            font2_obj
-        ));
-        pdf_content.push_str(&format!(
-            "{} 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>\nendobj\n",
-            font3_obj
-        ));
-        
-        // Content stream objects
-        for (obj_num, content) in content_objects {
-            let content_length = content.len();
-            pdf_content.push_str(&format!(
-                "{} 0 obj\n<< /Length {} >>\nstream\nBT\n{}ET\nendstream\nendobj\n",
-                obj_num, content_length, content
+        let bytes = renderer.render_markdown(SAMPLE, &metadata).unwrap();
+        let pdf = String::from_utf8(bytes).unwrap();
+
+        // base16-ocean.dark's #2b303b background and #c0c5ce text
+        assert!(pdf.contains("0.169 0.188 0.231 rg\n0 0 612 792 re\nf"), "{}", pdf);
+        assert!(pdf.contains("0.753 0.773 0.808 rg\n50.00 645.00 Td\n(Some prose.) Tj"));
+    }
+
+    #[test]
+    fn test_light_theme_keeps_white_background() {
+        let pdf = render_with(Some(Theme::light()));
+
+        assert!(pdf.contains("1.000 1.000 1.000 rg\n0 0 612 792 re\nf"));
+        assert!(!pdf.contains("0.176 0.176 0.176 rg"));
+    }
+
+    #[test]
+    fn test_table_spanning_pages_repeats_header_and_draws_rules() {
+        let mut markdown = String::from("# Fixture README\n\n| Column A | Column B | Column C | Column D | Column E |\n|---|---|---|---|---|\n");
+        for row in 1..=60 {
+            markdown.push_str(&format!(
+                "| row-{:02} | `value` | **bold** text | a somewhat longer cell that needs wrapping inside its column | {} |\n",
+                row, row * 10
             ));
         }
-        
-        // Calculate total objects
-        let total_objects = 3 + num_pages + 3 + num_pages; // catalog + pages + page_objs + fonts + content_objs
-        
-        // xref table 
-        pdf_content.push_str(&format!("xref\n0 {}\n", total_objects));
-        pdf_content.push_str("0000000000 65535 f\n");
-        for i in 1..total_objects {
-            pdf_content.push_str(&format!("{:010} 00000 n\n", 9 + i * 50)); // Rough offset
-        }
-        
-        // Trailer
-        pdf_content.push_str(&format!(
-            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
-            total_objects,
-            pdf_content.len() - 100
-        ));
-        
-        Ok(pdf_content.into_bytes())
+
+        let renderer = PdfRenderer::with_options(PdfOptions { theme: Some(Theme::light()), ..uncompressed() });
+        let bytes = renderer.render_markdown(&markdown, &DocumentMetadata::default()).unwrap();
+        let pdf = String::from_utf8(bytes).unwrap();
+        let streams = content_streams(&pdf);
+
+        // The header row is repeated on every page the table spans
+        let header_pages = streams.iter().filter(|s| s.contains("(Column A) Tj")).count();
+        assert!(header_pages >= 2, "table should span at least two pages");
+        assert!(pdf.contains("/F2 9 Tf\n0.000 0.000 0.000 rg\n54.00"));
+
+        // Rows appear in order
+        let positions: Vec<usize> = (1..=60)
+            .map(|row| pdf.find(&format!("(row-{:02}) Tj", row)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        // One top rule per page segment, plus a bottom rule and six column rules per row
+        let rules = pdf.matches(" l\nS\n").count();
+        assert_eq!(rules, header_pages + (60 + header_pages) * 7);
+    }
+
+    #[test]
+    fn test_xref_offsets_point_at_objects() {
+        let pdf = render_with(Some(Theme::light()));
+
+        let startxref: usize = pdf.rsplit("startxref\n").next().unwrap()
+            .lines().next().unwrap()
+            .parse().unwrap();
+        assert!(pdf[startxref..].starts_with("xref\n"));
+
+        let entries: Vec<&str> = pdf[startxref..].lines().skip(3).take_while(|l| !l.starts_with("trailer")).collect();
+        assert!(!entries.is_empty());
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)), "bad offset for object {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn test_render_to_writer_matches_render() {
+        let renderer = PdfRenderer::with_options(PdfOptions { theme: Some(Theme::dark()), ..uncompressed() });
+        let metadata = DocumentMetadata::default();
+
+        let rendered = renderer.render_markdown(SAMPLE, &metadata).unwrap();
+        let mut streamed = Vec::new();
+        renderer.render_to_writer(crate::renderer::parse_markdown(SAMPLE), &metadata, &mut streamed).unwrap();
+
+        assert_eq!(rendered, streamed);
+    }
+
+    #[test]
+    #[ignore = "coarse memory measurement, run with --ignored"]
+    fn test_streaming_memory_stays_flat() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use sysinfo::{ProcessesToUpdate, System};
+
+        fn resident_bytes(sys: &mut System) -> u64 {
+            let pid = sysinfo::get_current_pid().unwrap();
+            sys.refresh_processes(ProcessesToUpdate::Some(&[pid]));
+            sys.process(pid).map(|p| p.memory()).unwrap_or(0)
+        }
+
+        /// A synthetic repository of `files` files, each a page of prose and
+        /// a highlighted listing
+        fn repository(files: usize) -> String {
+            let mut markdown = String::from("# synthetic\n\n");
+            for file in 0..files {
+                markdown.push_str(&format!("\n### src/file_{}.rs\n\n", file));
+                for paragraph in 0..20 {
+                    markdown.push_str(&format!("Paragraph {} of file {} with some filler text to take up room.\n\n", paragraph, file));
+                }
+                markdown.push_str("```rust\n");
+                for line in 0..60 {
+                    markdown.push_str(&format!("    let value_{} = compute({}, \"text\"); // note {}\n", line, line, file));
+                }
+                markdown.push_str("```\n");
+            }
+            markdown
+        }
+
+        /// How far resident memory rose above where it started while
+        /// rendering `files` files, sampled from another thread
+        fn peak_growth(files: usize) -> u64 {
+            let renderer = PdfRenderer::with_options(PdfOptions { theme: Some(Theme::light()), ..uncompressed() });
+            let metadata = DocumentMetadata::default();
+            let markdown = repository(files);
+            let events = crate::renderer::parse_markdown(&markdown);
+            let before = resident_bytes(&mut System::new());
+            let rendering = AtomicBool::new(true);
+            let peak = std::thread::scope(|scope| {
+                let sampler = scope.spawn(|| {
+                    let mut sys = System::new();
+                    let mut peak = 0;
+                    while rendering.load(Ordering::Relaxed) {
+                        peak = peak.max(resident_bytes(&mut sys));
+                        std::thread::sleep(std::time::Duration::from_millis(2));
+                    }
+                    peak.max(resident_bytes(&mut sys))
+                });
+                renderer.render_to_writer(events, &metadata, &mut std::io::sink()).unwrap();
+                rendering.store(false, Ordering::Relaxed);
+                sampler.join().unwrap()
+            });
+            peak.saturating_sub(before)
+        }
+
+        // Loads the syntaxes and fonts, which later renders share
+        peak_growth(1);
+        let small = peak_growth(50);
+        let large = peak_growth(500);
+        println!("peak growth: {} KB for 50 files, {} KB for 500 files", small / 1024, large / 1024);
+        // Ten times the files holds at most a few pages and a little
+        // bookkeeping per page more, not ten times the highlighted code
+        assert!(large < small * 2 + 4 * 1024 * 1024, "{} KB for 50 files, {} KB for 500", small / 1024, large / 1024);
+    }
+
+    #[test]
+    fn test_wrap_cell_text_fits_column() {
+        let lines = wrap_cell_text("alpha beta gamma delta", 2.0 * TABLE_CELL_PADDING + 11.0 * table_char_width());
+        assert_eq!(lines, vec!["alpha beta", "gamma delta"]);
+
+        let lines = wrap_cell_text("abcdefghij", 2.0 * TABLE_CELL_PADDING + 4.0 * table_char_width());
+        assert_eq!(lines, vec!["abcd", "efgh", "ij"]);
+    }
+
+    fn render_listing(options: PdfOptions, markdown: &str) -> String {
+        let renderer = PdfRenderer::with_options(options);
+        let bytes = renderer.render_markdown(markdown, &DocumentMetadata::default()).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn long_listing(lines: usize) -> String {
+        let code: Vec<String> = (0..lines).map(|i| format!("    let value_{} = {};", i, i)).collect();
+        format!("### src/lib.rs\n\n```rust\n{}\n```\n", code.join("\n"))
+    }
+
+    #[test]
+    fn test_two_columns_fit_listing_in_fewer_pages() {
+        let markdown = long_listing(200);
+        let one = render_listing(uncompressed(), &markdown);
+        let two = render_listing(PdfOptions { columns: 2, ..uncompressed() }, &markdown);
+
+        let one_pages = one.matches("/Type /Page ").count();
+        let two_pages = two.matches("/Type /Page ").count();
+        assert!(two_pages < one_pages, "two columns: {} pages, one column: {} pages", two_pages, one_pages);
+
+        // Code is set in the denser 8pt font and flows into the second column
+        assert!(two.contains("/F3 8 Tf"));
+        let second_column_x = PageGeometry::from_options(&PdfOptions { columns: 2, ..uncompressed() }).column_x(1);
+        assert!(two.contains(&format!("{:.2} ", second_column_x)));
+    }
+
+    #[test]
+    fn test_font_size_and_line_height_change_lines_per_page() {
+        let markdown = long_listing(200);
+        let render = |font_size: f32, line_height: f32| {
+            let metadata = DocumentMetadata {
+                font_size: Some(font_size),
+                line_height: Some(line_height),
+                ..Default::default()
+            };
+            let bytes = PdfRenderer::with_options(uncompressed()).render_markdown(&markdown, &metadata).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+        let regular = render(10.0, 1.2);
+        let large = render(14.0, 1.5);
+
+        let regular_pages = regular.matches("/Type /Page ").count();
+        let large_pages = large.matches("/Type /Page ").count();
+        assert!(large_pages > regular_pages, "large: {} pages, regular: {} pages", large_pages, regular_pages);
+
+        // Code follows the body size at the usual ratio
+        assert!(regular.contains("/F3 9 Tf"));
+        assert!(large.contains("/F3 12.6 Tf"));
+    }
+
+    #[test]
+    fn test_heading_spans_columns_below_flowed_code() {
+        let markdown = format!("{}\n### src/main.rs\n\nAfter.\n", long_listing(80));
+        let pdf = render_listing(PdfOptions { columns: 2, ..uncompressed() }, &markdown);
+        let geometry = PageGeometry::from_options(&PdfOptions { columns: 2, ..uncompressed() });
+
+        let page = content_streams(&pdf)
+            .into_iter()
+            .find(|stream| stream.contains("(src/main.rs) Tj"))
+            .expect("heading page");
+        let heading = page.split("(src/main.rs) Tj").next().unwrap();
+        let td = heading.rsplit("Td").nth(1).unwrap().lines().last().unwrap();
+        let coords: Vec<f32> = td.split_whitespace().map(|v| v.parse().unwrap()).collect();
+
+        assert_eq!(coords[0], MARGIN_LEFT);
+        assert!(coords[1] >= geometry.bottom_y() + KEEP_WITH_NEXT);
+    }
+
+    #[test]
+    fn test_changed_lines_get_gutter_bars() {
+        let markdown = "```rust added=2 modified=3\nfn main() {\n    start();\n    stop();\n}\n```\n";
+        let pdf = render_listing(uncompressed(), markdown);
+        let bar_x = format!("{:.2} ", MARGIN_LEFT - CHANGE_BAR_OFFSET);
+        let bars: Vec<&str> = pdf.lines().filter(|line| line.starts_with(&bar_x) && line.ends_with(" re")).collect();
+        assert_eq!(bars.len(), 2, "{}", pdf);
+        assert!(pdf.contains(&format!("{}\n{}", fill_color(CHANGE_ADDED_COLOR), bars[0])));
+        assert!(pdf.contains(&format!("{}\n{}", fill_color(CHANGE_MODIFIED_COLOR), bars[1])));
+
+        // Each bar is drawn just before its line's text
+        let position = |text: &str| pdf.find(text).unwrap();
+        assert!(position(bars[0]) < position("start") && position("start") < position(bars[1]));
+        assert!(position(bars[1]) < position("stop"));
+    }
+
+    #[test]
+    fn test_landscape_swaps_page_size() {
+        let pdf = render_listing(PdfOptions { landscape: true, ..uncompressed() }, SAMPLE);
+        assert!(pdf.contains("/MediaBox [0 0 792 612]"));
+        assert!(pdf.contains("0 0 792 612 re"));
+    }
+
+    #[test]
+    fn test_short_main_rs_does_not_force_page_break() {
+        let markdown = "# Project\n\n### src/main.rs\n\n```rust\nfn main() {\n    run();\n}\n```\n";
+        let pdf = render_listing(uncompressed(), markdown);
+        assert_eq!(pdf.matches("/Type /Page ").count(), 1);
+    }
+
+    #[test]
+    fn test_newpage_inside_code_fence_does_not_break_listing() {
+        let markdown = "### docs/build.tex\n\n```latex\nIntro text\n\\newpage\nMore text\n```\n";
+        let pdf = render_listing(uncompressed(), markdown);
+
+        assert_eq!(pdf.matches("/Type /Page ").count(), 1);
+        assert!(pdf.contains("(\\\\newpage) Tj"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_newpage_marker_outside_code_breaks_page() {
+        let markdown = "### a.rs\n\n```rust\nfn a() {}\n```\n\n\\newpage\n\n### b.rs\n\n```rust\nfn b() {}\n```\n";
+        let pdf = render_listing(uncompressed(), markdown);
+        assert_eq!(pdf.matches("/Type /Page ").count(), 2);
+    }
+
+    #[test]
+    fn test_pdfa_structures_present_only_when_enabled() {
+        let pdfa = PdfRenderer::with_options(PdfOptions { pdfa: true, ..uncompressed() })
+            .render_markdown(SAMPLE, &DocumentMetadata::default())
+            .unwrap();
+        let document = lopdf::Document::load_mem(&pdfa).unwrap();
+        assert!(document.extract_text(&[1]).unwrap().contains("Some prose."));
+        let pdfa = String::from_utf8_lossy(&pdfa);
+
+        assert!(pdfa.starts_with("%PDF-1.7\n"));
+        assert!(pdfa.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(pdfa.contains("<pdfaid:conformance>B</pdfaid:conformance>"));
+        assert!(pdfa.contains("/Type /Metadata /Subtype /XML"));
+        assert!(pdfa.contains("/OutputIntents [<< /Type /OutputIntent /S /GTS_PDFA1"));
+        assert!(pdfa.contains("/FontFile2 "));
+        assert!(!pdfa.contains("/Subtype /Type1"));
+        assert!(!pdfa.contains("/Encrypt"));
+        assert!(pdfa.contains(" /ID [<"));
+
+        let plain = render_with(None);
+        assert!(!plain.contains("pdfaid"));
+        assert!(!plain.contains("/OutputIntents"));
+        assert!(!plain.contains("/FontFile2"));
+        assert!(!plain.contains("/Encrypt"));
+    }
+
+    #[test]
+    fn test_info_dictionary_names_title_author_and_language() {
+        let metadata = DocumentMetadata {
+            title: "Payments (v2)".to_string(),
+            author: Some("Zoë Ðurić 山田".to_string()),
+            language: "de".to_string(),
+            ..Default::default()
+        };
+        let pdf = PdfRenderer::with_options(uncompressed()).render_markdown(SAMPLE, &metadata).unwrap();
+        let document = lopdf::Document::load_mem(&pdf).unwrap();
+        let text = |dictionary: &lopdf::Dictionary, key: &[u8]| -> String {
+            lopdf::decode_text_string(dictionary.get(key).unwrap()).unwrap()
+        };
+        let info = document.trailer.get_deref(b"Info", &document).unwrap().as_dict().unwrap();
+        assert_eq!(text(info, b"Title"), "Payments (v2)");
+        assert_eq!(text(info, b"Author"), "Zoë Ðurić 山田");
+        assert_eq!(text(document.catalog().unwrap(), b"Lang"), "de");
+        assert_eq!(pdf_text_string("é"), "<FEFF00E9>");
+    }
+
+    #[test]
+    fn test_text_is_encoded_as_win_ansi() {
+        assert_eq!(escape_pdf_text("(a\\b)"), "\\(a\\\\b\\)");
+        assert_eq!(escape_pdf_text("• café →"), "\\225 caf\\351 ?");
+    }
+
+    #[test]
+    fn test_compressed_output_is_smaller_and_parseable() {
+        let markdown = long_listing(600);
+        let compressed = PdfRenderer::new().unwrap()
+            .render_markdown(&markdown, &DocumentMetadata::default())
+            .unwrap();
+        let plain = render_listing(uncompressed(), &markdown);
+
+        assert!(
+            compressed.len() * 10 < plain.len() * 6,
+            "compressed {} bytes, uncompressed {} bytes", compressed.len(), plain.len()
+        );
+
+        let document = lopdf::Document::load_mem(&compressed).unwrap();
+        let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+        assert!(pages.len() > 1);
+        for page in &pages {
+            let text = document.extract_text(&[*page]).unwrap();
+            assert!(text.contains("let value_"), "page {} text: {:?}", page, text);
+        }
+    }
+
+    #[test]
+    fn test_toc_links_go_to_named_destinations_and_outline() {
+        let markdown = "# repo\n\n## Table of Contents {#table-of-contents}\n\n\
+            - [src/main.rs](#src-main-rs)\n- [src/lib.rs](#src-lib-rs)\n\n\
+            ## File Contents {#file-contents}\n\n\
+            ### src/main.rs {#src-main-rs}\n\n```rust\nfn main() {}\n```\n\n\
+            ### src/lib.rs {#src-lib-rs}\n\n```rust\npub fn lib() {}\n```\n";
+        let pdf = PdfRenderer::with_options(uncompressed())
+            .render_markdown(markdown, &DocumentMetadata::default())
+            .unwrap();
+        let document = lopdf::Document::load_mem(&pdf).unwrap();
+        let catalog = document.catalog().unwrap();
+
+        let dests = catalog.get_deref(b"Dests", &document).unwrap().as_dict().unwrap();
+        let names: Vec<&[u8]> = dests.iter().map(|(name, _)| name.as_slice()).collect();
+        assert_eq!(names, [&b"table-of-contents"[..], b"file-contents", b"src-main-rs", b"src-lib-rs"]);
+
+        let mut targets = Vec::new();
+        for page in document.get_pages().values() {
+            for annot in document.get_page_annotations(*page).unwrap() {
+                assert_eq!(annot.get(b"Subtype").unwrap().as_name().unwrap(), b"Link");
+                targets.push(annot.get(b"Dest").unwrap().as_name().unwrap().to_vec());
+            }
+        }
+        assert_eq!(targets, [b"src-main-rs".to_vec(), b"src-lib-rs".to_vec()]);
+
+        // Files nest under the section before them
+        let outlines = catalog.get_deref(b"Outlines", &document).unwrap().as_dict().unwrap();
+        assert_eq!(outlines.get(b"Count").unwrap().as_i64().unwrap(), 4);
+        let title = |item: &lopdf::Dictionary| lopdf::decode_text_string(item.get(b"Title").unwrap()).unwrap();
+        let first = outlines.get_deref(b"First", &document).unwrap().as_dict().unwrap();
+        assert_eq!(title(first), "Table of Contents");
+        let last = outlines.get_deref(b"Last", &document).unwrap().as_dict().unwrap();
+        assert_eq!(title(last), "File Contents");
+        let child = last.get_deref(b"First", &document).unwrap().as_dict().unwrap();
+        assert_eq!(title(child), "src/main.rs");
+        assert_eq!(title(child.get_deref(b"Next", &document).unwrap().as_dict().unwrap()), "src/lib.rs");
+
+        assert_eq!(pdf_name("a b#c"), "/a#20b#23c");
+    }
+
+    #[test]
+    fn test_page_budget_ends_with_notice_of_files_left_out() {
+        // Six files, the first already longer than the budget
+        let markdown: String = (1..=6)
+            .map(|i| long_listing(300).replace("### src/lib.rs", &format!("### src/file{0}.rs {{#src-file{0}-rs}}", i)) + "\n\\newpage\n\n")
+            .collect();
+        let metadata = DocumentMetadata::default();
+        let pdf = PdfRenderer::with_options(PdfOptions { max_pages: Some(4), ..uncompressed() })
+            .render_markdown(&markdown, &metadata)
+            .unwrap();
+        let document = lopdf::Document::load_mem(&pdf).unwrap();
+        let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+        assert_eq!(pages.len(), 4);
+        let notice = document.extract_text(&pages[3..]).unwrap();
+        assert!(notice.contains("Page Limit Reached"), "{}", notice);
+        assert!(notice.contains("within 4 pages: 5"), "{}", notice);
+
+        let warnings: Vec<(String, String)> = metadata.warnings.entries().into_iter()
+            .map(|warning| (warning.path.unwrap(), warning.message))
+            .collect();
+        assert_eq!(warnings.len(), 6, "{:?}", warnings);
+        assert_eq!(warnings[0], ("src/file1.rs".to_string(), "Cut short at the 4 page limit".to_string()));
+        assert!(warnings[1..].iter().all(|(_, message)| message == "Left out at the 4 page limit"), "{:?}", warnings);
+
+        // Within the budget nothing changes
+        let unlimited = render_listing(uncompressed(), &markdown);
+        let roomy = render_listing(PdfOptions { max_pages: Some(1000), ..uncompressed() }, &markdown);
+        assert_eq!(unlimited, roomy);
+        assert!(!roomy.contains("Page Limit Reached"));
+    }
+}
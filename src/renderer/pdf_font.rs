@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+
+/// A TrueType font bundled with the binary for embedding in PDF output
+pub struct EmbeddedFont {
+    pub base_font: &'static str,
+    pub data: &'static [u8],
+}
+
+/// Embedded replacements for the builtin Helvetica, Helvetica-Bold, Courier
+/// and Courier-Bold
+pub const EMBEDDED_FONTS: [EmbeddedFont; 4] = [
+    EmbeddedFont {
+        base_font: "DejaVuSans",
+        data: include_bytes!("../../assets/fonts/DejaVuSans.ttf"),
+    },
+    EmbeddedFont {
+        base_font: "DejaVuSans-Bold",
+        data: include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"),
+    },
+    EmbeddedFont {
+        base_font: "DejaVuSansMono",
+        data: include_bytes!("../../assets/fonts/DejaVuSansMono.ttf"),
+    },
+    EmbeddedFont {
+        base_font: "DejaVuSansMono-Bold",
+        data: include_bytes!("../../assets/fonts/DejaVuSansMono-Bold.ttf"),
+    },
+];
+
+/// sRGB color profile used as the PDF/A output intent
+pub const SRGB_ICC_PROFILE: &[u8] = include_bytes!("../../assets/icc/sRGB-v4.icc");
+
+/// First and last WinAnsi codes given widths in the font dictionary
+pub const FIRST_CHAR: u8 = 32;
+pub const LAST_CHAR: u8 = 255;
+
+/// Font metrics needed for the PDF font dictionary and descriptor
+pub struct FontMetrics {
+    pub widths: Vec<u32>,
+    pub bbox: [i32; 4],
+    pub ascent: i32,
+    pub descent: i32,
+    pub cap_height: i32,
+    pub italic_angle: f32,
+    pub flags: u32,
+    pub stem_v: u32,
+}
+
+impl EmbeddedFont {
+    /// Read glyph widths and descriptor metrics, scaled to 1000 units per em
+    pub fn metrics(&self) -> Result<FontMetrics> {
+        let face = ttf_parser::Face::parse(self.data, 0)
+            .map_err(|e| anyhow!("Failed to parse font {}: {}", self.base_font, e))?;
+        let scale = 1000.0 / face.units_per_em() as f32;
+        let scaled = |value: i16| (value as f32 * scale).round() as i32;
+
+        let missing_width = face.glyph_index(' ')
+            .and_then(|glyph| face.glyph_hor_advance(glyph))
+            .unwrap_or(0);
+        let widths = (FIRST_CHAR..=LAST_CHAR)
+            .map(|code| {
+                let advance = win_ansi_char(code)
+                    .and_then(|ch| face.glyph_index(ch))
+                    .and_then(|glyph| face.glyph_hor_advance(glyph))
+                    .unwrap_or(missing_width);
+                (advance as f32 * scale).round() as u32
+            })
+            .collect();
+
+        let bbox = face.global_bounding_box();
+        // Nonsymbolic, plus fixed pitch for monospace faces
+        let flags = if face.is_monospaced() { 32 | 1 } else { 32 };
+
+        Ok(FontMetrics {
+            widths,
+            bbox: [scaled(bbox.x_min), scaled(bbox.y_min), scaled(bbox.x_max), scaled(bbox.y_max)],
+            ascent: scaled(face.ascender()),
+            descent: scaled(face.descender()),
+            cap_height: scaled(face.capital_height().unwrap_or(face.ascender())),
+            italic_angle: face.italic_angle(),
+            flags,
+            stem_v: if face.is_bold() { 120 } else { 80 },
+        })
+    }
+}
+
+/// WinAnsi codes 0x80-0x9F that differ from Latin-1
+const WIN_ANSI_HIGH: [(u8, char); 27] = [
+    (0x80, '€'), (0x82, '‚'), (0x83, 'ƒ'), (0x84, '„'), (0x85, '…'), (0x86, '†'),
+    (0x87, '‡'), (0x88, 'ˆ'), (0x89, '‰'), (0x8A, 'Š'), (0x8B, '‹'), (0x8C, 'Œ'),
+    (0x8E, 'Ž'), (0x91, '‘'), (0x92, '’'), (0x93, '“'), (0x94, '”'), (0x95, '•'),
+    (0x96, '–'), (0x97, '—'), (0x98, '˜'), (0x99, '™'), (0x9A, 'š'), (0x9B, '›'),
+    (0x9C, 'œ'), (0x9E, 'ž'), (0x9F, 'Ÿ'),
+];
+
+/// The character a WinAnsi code maps to, if any
+fn win_ansi_char(code: u8) -> Option<char> {
+    match code {
+        0x20..=0x7E | 0xA0..=0xFF => Some(code as char),
+        _ => WIN_ANSI_HIGH.iter().find(|(c, _)| *c == code).map(|(_, ch)| *ch),
+    }
+}
+
+/// The WinAnsi code for a character, if it has one
+pub fn win_ansi_code(ch: char) -> Option<u8> {
+    match ch as u32 {
+        0x20..=0x7E | 0xA0..=0xFF => Some(ch as u8),
+        _ => WIN_ANSI_HIGH.iter().find(|(_, c)| *c == ch).map(|(code, _)| *code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win_ansi_round_trip() {
+        assert_eq!(win_ansi_code('A'), Some(b'A'));
+        assert_eq!(win_ansi_code('é'), Some(0xE9));
+        assert_eq!(win_ansi_code('•'), Some(0x95));
+        assert_eq!(win_ansi_code('→'), None);
+        assert_eq!(win_ansi_char(0x95), Some('•'));
+        assert_eq!(win_ansi_char(0x81), None);
+    }
+
+    #[test]
+    fn test_embedded_font_metrics() {
+        for font in &EMBEDDED_FONTS {
+            let metrics = font.metrics().unwrap();
+            assert_eq!(metrics.widths.len(), (LAST_CHAR - FIRST_CHAR) as usize + 1);
+            assert!(metrics.widths.iter().all(|w| *w > 0));
+            assert!(metrics.ascent > 0 && metrics.descent < 0);
+        }
+
+        let mono = EMBEDDED_FONTS[2].metrics().unwrap();
+        assert_eq!(mono.flags & 1, 1);
+        assert!(mono.widths.iter().all(|w| *w == mono.widths[0]));
+    }
+}
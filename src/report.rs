@@ -0,0 +1,347 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cancel::Cancelled;
+use crate::estimate::SizeEstimate;
+use crate::fragment_cache::FragmentStats;
+use crate::markdown_generator::MarkdownGenerator;
+use crate::warnings::Warning;
+
+/// What kind of problem ended a run. Attached to errors with
+/// `FailureContext::failure` so the command line can pick an exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Bad options, environment variables or configuration
+    InvalidArguments,
+    /// The input couldn't be found or read
+    Input,
+    /// Generating or writing the document failed
+    Render,
+    /// Some of several formats were written and the others failed
+    PartialSuccess,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::InvalidArguments => 2,
+            Failure::Input => 3,
+            Failure::Render => 4,
+            Failure::PartialSuccess => 5,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::InvalidArguments => "Invalid arguments",
+            Failure::Input => "Input error",
+            Failure::Render => "Render failed",
+            Failure::PartialSuccess => "Some formats failed",
+        })
+    }
+}
+
+/// An error with the `Failure` it belongs to. It shows as the error it
+/// wraps, so the first line of the message says what went wrong rather
+/// than what kind of problem it was.
+#[derive(Debug)]
+pub struct Failed {
+    pub failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Failed {
+    /// The causes of the wrapped error, which `Display` already shows
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Attach a `Failure` to an error, or to the error of a result
+pub trait FailureContext {
+    type Output;
+
+    fn failure(self, failure: Failure) -> Self::Output;
+}
+
+impl FailureContext for anyhow::Error {
+    type Output = anyhow::Error;
+
+    /// A cancelled run stays recognizable as one, as cancelling decides
+    /// the exit code whatever failed
+    fn failure(self, failure: Failure) -> anyhow::Error {
+        if self.is::<Cancelled>() {
+            return self;
+        }
+        anyhow::Error::new(Failed { failure, error: self })
+    }
+}
+
+impl<T, E: Into<anyhow::Error>> FailureContext for std::result::Result<T, E> {
+    type Output = Result<T>;
+
+    fn failure(self, failure: Failure) -> Result<T> {
+        self.map_err(|error| error.into().failure(failure))
+    }
+}
+
+/// Exit code of a cancelled run, the one shells give a process stopped by
+/// Ctrl-C
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// The exit code for `error`: `CANCELLED_EXIT_CODE` if the run was
+/// cancelled, else the code of its `Failure`, or 1 when it has none
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if error.is::<Cancelled>() {
+        return CANCELLED_EXIT_CODE;
+    }
+    error.downcast_ref::<Failed>().map_or(1, |failed| failed.failure.exit_code())
+}
+
+/// A document written by the run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputReport {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// How many of the discovered files made it into the document
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileCounts {
+    pub included: usize,
+    /// Found but left out, e.g. unchanged since the `--since` revision
+    pub skipped: usize,
+    /// Included, but cut short by `--max-file-size`
+    pub truncated: usize,
+}
+
+/// Wall time spent in one phase of the run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Phase {
+    pub name: String,
+    pub seconds: f64,
+    /// Bytes the phase read or wrote, where that's cheap to count
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Seconds into the run the phase began, for phases that ran alongside
+    /// others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<f64>,
+}
+
+/// Wall time, and bytes where cheap to count, for each phase of a run in
+/// the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct PhaseTimer {
+    phases: Vec<Phase>,
+}
+
+impl PhaseTimer {
+    pub fn record(&mut self, name: &str, elapsed: Duration, bytes: Option<u64>) {
+        self.phases.push(Phase {
+            name: name.to_string(),
+            seconds: elapsed.as_secs_f64(),
+            bytes,
+            started: None,
+        });
+    }
+
+    /// Record a phase that began `started` into the run and may overlap
+    /// other phases recorded this way
+    pub fn record_at(&mut self, name: &str, started: Duration, elapsed: Duration, bytes: Option<u64>) {
+        self.phases.push(Phase {
+            name: name.to_string(),
+            seconds: elapsed.as_secs_f64(),
+            bytes,
+            started: Some(started.as_secs_f64()),
+        });
+    }
+
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /// Wall time of all phases: overlapping ones count from the first start
+    /// to the last finish
+    pub fn total(&self) -> Duration {
+        let sequential: f64 = self.phases.iter()
+            .filter(|phase| phase.started.is_none())
+            .map(|phase| phase.seconds)
+            .sum();
+        let overlapping = self.phases.iter()
+            .filter_map(|phase| phase.started.map(|started| (started, started + phase.seconds)));
+        let (first, last) = overlapping.fold((f64::MAX, 0.0_f64), |(first, last), (start, end)| (first.min(start), last.max(end)));
+        Duration::from_secs_f64(sequential + (last - first).max(0.0))
+    }
+}
+
+impl fmt::Display for PhaseTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        let width = self.phases.iter().map(|phase| phase.name.len()).max().unwrap_or(0).max(10);
+        writeln!(f, "  {:<width$}  {:>9}  {:>6}  {:>10}", "Phase", "Time", "Share", "Bytes")?;
+        for phase in &self.phases {
+            let share = if total > 0.0 { phase.seconds / total * 100.0 } else { 0.0 };
+            let bytes = phase.bytes.map(|bytes| MarkdownGenerator::format_file_size(bytes as usize)).unwrap_or_default();
+            writeln!(f, "  {:<width$}  {:>8.3}s  {:>5.1}%  {:>10}", phase.name, phase.seconds, share, bytes)?;
+        }
+        writeln!(f, "  {:<width$}  {:>8.3}s", "total", total)
+    }
+}
+
+/// Machine-readable summary of a run, written by `--report`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunReport {
+    pub success: bool,
+    pub exit_code: i32,
+    /// The error that ended the run, with its causes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub input: PathBuf,
+    pub formats: Vec<String>,
+    pub outputs: Vec<OutputReport>,
+    pub files: FileCounts,
+    pub warnings: Vec<String>,
+    /// Files and code blocks that didn't come out as they should have
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub generation_warnings: Vec<Warning>,
+    pub phases: PhaseTimer,
+    /// Fragments reused from `--cache-dir` and generated into it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<FragmentStats>,
+    /// The document's projected size, from `--dry-run`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<SizeEstimate>,
+}
+
+impl RunReport {
+    pub fn new(input: &Path) -> Self {
+        Self { input: input.to_path_buf(), ..Default::default() }
+    }
+
+    pub fn warn(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Record a written document and its size
+    pub fn output(&mut self, path: &Path) {
+        let bytes = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        self.outputs.push(OutputReport { path: path.to_path_buf(), bytes });
+    }
+
+    /// Record how the run ended
+    pub fn finish<T>(&mut self, result: &Result<T>) {
+        self.success = result.is_ok();
+        self.exit_code = result.as_ref().err().map_or(0, exit_code);
+        self.error = result.as_ref().err().map(|error| format!("{:#}", error));
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Write the report to `target`, a file path or `-` for stdout
+    pub fn write_to(&self, target: &str) -> Result<()> {
+        let json = self.to_json()?;
+        if target == "-" {
+            println!("{}", json);
+            return Ok(());
+        }
+        fs::write(target, json + "\n")
+            .with_context(|| format!("Failed to write the run report to {}", target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_exit_codes_follow_failure_context() {
+        let missing = Err::<(), _>(anyhow!("Input path does not exist: nowhere")).failure(Failure::Input);
+        let error = missing.unwrap_err();
+        assert_eq!(exit_code(&error), 3);
+        // The cause comes first, not the category
+        assert_eq!(format!("{:#}", error), "Input path does not exist: nowhere");
+        let nested = anyhow!("permission denied").context("Failed to read src").failure(Failure::Input);
+        assert_eq!(format!("{:#}", nested), "Failed to read src: permission denied");
+        assert!(format!("{:?}", nested).starts_with("Failed to read src\n\nCaused by:\n    permission denied"), "{:?}", nested);
+
+        // Context added on top keeps the failure reachable
+        let error = error.context("Run failed");
+        assert_eq!(exit_code(&error), 3);
+        assert_eq!(exit_code(&anyhow!("something else")), 1);
+        assert_eq!(Failure::InvalidArguments.exit_code(), 2);
+        assert_eq!(Failure::Render.exit_code(), 4);
+        assert_eq!(Failure::PartialSuccess.exit_code(), 5);
+
+        // However it failed, a cancelled run says so
+        let cancelled = anyhow::Error::new(Cancelled).context("Failed to render pdf").failure(Failure::Render);
+        assert_eq!(exit_code(&cancelled), 130);
+    }
+
+    #[test]
+    fn test_report_json_schema() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let output = temp_dir.path().join("book.html");
+        fs::write(&output, "<html></html>")?;
+
+        let mut report = RunReport::new(Path::new("repo"));
+        report.formats.push("html".to_string());
+        report.output(&output);
+        report.files = FileCounts { included: 3, skipped: 1, truncated: 0 };
+        report.warn("Found 1 file larger than 10MB");
+        report.phases.record("discovery", Duration::from_millis(250), None);
+        report.phases.record("read", Duration::from_millis(750), Some(2022));
+        report.finish(&Ok(()));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["exit_code"], 0);
+        assert!(json.get("error").is_none());
+        assert!(json.get("cache").is_none());
+        assert!(json.get("estimate").is_none());
+        assert_eq!(json["input"], "repo");
+        assert_eq!(json["formats"], serde_json::json!(["html"]));
+        assert_eq!(json["outputs"][0]["bytes"], 13);
+        assert_eq!(json["files"], serde_json::json!({"included": 3, "skipped": 1, "truncated": 0}));
+        assert_eq!(json["warnings"][0], "Found 1 file larger than 10MB");
+        assert_eq!(json["phases"][0], serde_json::json!({"name": "discovery", "seconds": 0.25}));
+        assert_eq!(json["phases"][1]["bytes"], 2022);
+
+        let table = report.phases.to_string();
+        assert!(table.contains("  read           0.750s   75.0%      2.0 KB\n"), "{}", table);
+        assert!(table.ends_with("  total          1.000s\n"), "{}", table);
+
+        // Phases that ran side by side count once towards the total
+        report.phases.record_at("render html", Duration::from_millis(1000), Duration::from_millis(500), None);
+        report.phases.record_at("render markdown", Duration::from_millis(1100), Duration::from_millis(100), None);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["phases"][2]["started"], 1.0);
+        assert_eq!(report.phases.total(), Duration::from_millis(1500));
+        assert!(report.phases.to_string().contains("  render markdown     0.100s    6.7%  "), "{}", report.phases);
+
+        let path = temp_dir.path().join("report.json");
+        report.finish(&Err::<(), _>(anyhow!("disk full").failure(Failure::Render)));
+        report.write_to(path.to_str().unwrap())?;
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["exit_code"], 4);
+        assert_eq!(json["error"], "disk full");
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use dialoguer::{Input, MultiSelect};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::markdown_generator::FileInfo;
+
+/// First line of a saved selection
+const MANIFEST_HEADER: &str = "# scrollcast file selection: one path per line, relative to the input";
+
+/// Checklist rows shown at a time; the rest scroll
+const PAGE_SIZE: usize = 20;
+
+/// Name used for files at the top of the input
+const ROOT_DIRECTORY: &str = ".";
+
+/// Paths listed in a manifest: one per line, skipping blank lines and
+/// `#` comments
+pub fn parse_manifest(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.replace('\\', "/"))
+        .collect()
+}
+
+/// A manifest listing `paths`, readable by `parse_manifest`
+pub fn format_manifest<'a>(paths: impl IntoIterator<Item = &'a str>) -> String {
+    let mut manifest = format!("{}\n", MANIFEST_HEADER);
+    for path in paths {
+        manifest.push_str(path);
+        manifest.push('\n');
+    }
+    manifest
+}
+
+pub fn read_manifest(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read file list {}", path.display()))?;
+    Ok(parse_manifest(&text))
+}
+
+pub fn write_manifest(path: &Path, files: &[FileInfo]) -> Result<()> {
+    fs::write(path, format_manifest(files.iter().map(|file| file.path.as_str())))
+        .with_context(|| format!("Failed to save the file selection to {}", path.display()))
+}
+
+/// Keep only the files `listed`, in their usual order. Returns the listed
+/// paths that weren't among the files.
+pub fn retain_listed(files: &mut Vec<FileInfo>, listed: &[String]) -> Vec<String> {
+    let listed: BTreeSet<&str> = listed.iter().map(String::as_str).collect();
+    let found: BTreeSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    let missing = listed.difference(&found).map(|path| path.to_string()).collect();
+    files.retain(|file| listed.contains(file.path.as_str()));
+    missing
+}
+
+/// One row of the checklist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    /// A directory and the indices of the files directly in it
+    Directory(String, Vec<usize>),
+    File(usize),
+}
+
+/// Checklist rows for `paths`: each directory followed by its files, in
+/// path order
+pub fn checklist(paths: &[&str]) -> Vec<Row> {
+    let mut order: Vec<usize> = (0..paths.len()).collect();
+    order.sort_by_key(|&index| (directory_of(paths[index]), paths[index]));
+
+    let mut rows = Vec::new();
+    let mut current: Option<(&str, Vec<usize>)> = None;
+    for index in order {
+        let directory = directory_of(paths[index]);
+        if current.as_ref().is_none_or(|(name, _)| *name != directory) {
+            if let Some((name, files)) = current.take() {
+                push_group(&mut rows, name, files);
+            }
+            current = Some((directory, Vec::new()));
+        }
+        if let Some((_, files)) = current.as_mut() {
+            files.push(index);
+        }
+    }
+    if let Some((name, files)) = current {
+        push_group(&mut rows, name, files);
+    }
+    rows
+}
+
+fn push_group(rows: &mut Vec<Row>, name: &str, files: Vec<usize>) {
+    rows.push(Row::Directory(name.to_string(), files.clone()));
+    rows.extend(files.into_iter().map(Row::File));
+}
+
+fn directory_of(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(end) => &path[..end],
+        None => ROOT_DIRECTORY,
+    }
+}
+
+/// Whether each row starts checked: files as `selected` says, directories
+/// when all their files are selected
+pub fn initial_checks(rows: &[Row], selected: &[bool]) -> Vec<bool> {
+    rows.iter()
+        .map(|row| match row {
+            Row::Directory(_, files) => files.iter().all(|&index| selected[index]),
+            Row::File(index) => selected[*index],
+        })
+        .collect()
+}
+
+/// Apply a confirmed checklist to `selected`: a directory whose box was
+/// toggled takes all its files with it, otherwise each file follows its own
+/// box. Files without a row, such as those filtered out of view, keep
+/// their state.
+pub fn apply_checks(rows: &[Row], before: &[bool], after: &[bool], selected: &mut [bool]) {
+    for (row, (&was, &now)) in rows.iter().zip(before.iter().zip(after)) {
+        if let Row::File(index) = row {
+            if was != now {
+                selected[*index] = now;
+            }
+        }
+    }
+    for (row, (&was, &now)) in rows.iter().zip(before.iter().zip(after)) {
+        if let Row::Directory(_, files) = row {
+            if was != now {
+                for &index in files {
+                    selected[index] = now;
+                }
+            }
+        }
+    }
+}
+
+/// Let the user pick from `files` on the terminal, starting with all of
+/// them checked. An optional filter first narrows the list to paths
+/// containing some text; files outside it stay as they were. Returns
+/// whether each file was kept.
+pub fn select_files(files: &[FileInfo]) -> Result<Vec<bool>> {
+    let paths: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    let mut selected = vec![true; files.len()];
+
+    let filter: String = Input::new()
+        .with_prompt("Only list paths containing (Enter for all)")
+        .allow_empty(true)
+        .interact_text()
+        .context("Failed to read the path filter")?;
+    let rows: Vec<Row> = checklist(&paths)
+        .into_iter()
+        .filter(|row| match row {
+            Row::Directory(name, _) => filter.is_empty() || name.contains(filter.as_str()),
+            Row::File(index) => paths[*index].contains(filter.as_str()),
+        })
+        .collect();
+
+    let labels: Vec<String> = rows.iter()
+        .map(|row| match row {
+            Row::Directory(name, files) => format!("{}/ ({} files)", name, files.len()),
+            Row::File(index) => format!("    {}", paths[*index]),
+        })
+        .collect();
+    let before = initial_checks(&rows, &selected);
+    let chosen = MultiSelect::new()
+        .with_prompt("Files to include (Space toggles, Enter confirms)")
+        .items(&labels)
+        .defaults(&before)
+        .max_length(PAGE_SIZE)
+        .interact()
+        .context("Failed to read the file selection")?;
+
+    let mut after = vec![false; rows.len()];
+    for index in chosen {
+        after[index] = true;
+    }
+    apply_checks(&rows, &before, &after, &mut selected);
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            content: "".into(),
+            language: None,
+            size: 0,
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_round_trip() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let manifest = temp_dir.path().join("selection.txt");
+        let files = vec![file("README.md"), file("src/main.rs"), file("src/util/mod.rs")];
+        write_manifest(&manifest, &files)?;
+        assert!(fs::read_to_string(&manifest)?.starts_with("# scrollcast file selection"));
+
+        let listed = read_manifest(&manifest)?;
+        assert_eq!(listed, vec!["README.md", "src/main.rs", "src/util/mod.rs"]);
+
+        let mut discovered = vec![file("Cargo.toml"), file("src/main.rs"), file("README.md"), file("src/util/mod.rs")];
+        assert!(retain_listed(&mut discovered, &listed).is_empty());
+        let kept: Vec<&str> = discovered.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(kept, vec!["src/main.rs", "README.md", "src/util/mod.rs"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_skips_comments_and_reports_missing_paths() {
+        let listed = parse_manifest("# picked by hand\n\nsrc\\lib.rs\n  gone.rs  \n");
+        assert_eq!(listed, vec!["src/lib.rs", "gone.rs"]);
+        let mut files = vec![file("src/lib.rs"), file("src/main.rs")];
+        assert_eq!(retain_listed(&mut files, &listed), vec!["gone.rs"]);
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn test_directory_rows_toggle_their_files() {
+        let paths = ["src/main.rs", "README.md", "src/lib.rs", "docs/guide.md"];
+        let rows = checklist(&paths);
+        assert_eq!(rows, vec![
+            Row::Directory(".".to_string(), vec![1]),
+            Row::File(1),
+            Row::Directory("docs".to_string(), vec![3]),
+            Row::File(3),
+            Row::Directory("src".to_string(), vec![2, 0]),
+            Row::File(2),
+            Row::File(0),
+        ]);
+
+        let mut selected = vec![true; paths.len()];
+        let before = initial_checks(&rows, &selected);
+        assert!(before.iter().all(|&checked| checked));
+
+        // Unchecking src/ drops both its files; unchecking only README.md drops it
+        let mut after = before.clone();
+        after[4] = false;
+        after[1] = false;
+        apply_checks(&rows, &before, &after, &mut selected);
+        assert_eq!(selected, vec![false, false, false, true]);
+
+        // Checking src/ again brings them back, even if a file box was left unchecked
+        let before = initial_checks(&rows, &selected);
+        let mut after = before.clone();
+        after[4] = true;
+        apply_checks(&rows, &before, &after, &mut selected);
+        assert_eq!(selected, vec![true, false, true, true]);
+    }
+}
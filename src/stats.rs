@@ -0,0 +1,282 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::markdown_generator::{blank_line_count, FileInfo, MarkdownGenerator};
+use crate::todos::{count_by_marker, find_todos};
+
+/// How many of the largest files the report lists
+pub const LARGEST_FILES: usize = 10;
+
+/// Name used for files without a detected language
+const NO_LANGUAGE: &str = "other";
+
+/// Name used for files at the top of the repository
+const ROOT_DIRECTORY: &str = ".";
+
+/// Size and line count of one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileStats {
+    pub path: String,
+    pub bytes: usize,
+    pub lines: usize,
+}
+
+/// Totals for a group of files, such as one language or directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub files: usize,
+    pub bytes: usize,
+    pub lines: usize,
+}
+
+/// File, byte and line counts for a set of processed files, overall and
+/// broken down by language and top-level directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepoStats {
+    pub files: usize,
+    pub bytes: usize,
+    pub lines: usize,
+    /// Files with nothing in them but whitespace
+    pub empty: usize,
+    /// Largest first, at most `LARGEST_FILES`
+    pub largest: Vec<FileStats>,
+    /// Most bytes first
+    pub languages: Vec<GroupStats>,
+    /// Most bytes first
+    pub directories: Vec<GroupStats>,
+    /// Binary files by what they hold, such as "SQLite database", most
+    /// bytes first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub binaries: Vec<GroupStats>,
+    /// How often each review marker appears, with `--todo-index`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub todos: BTreeMap<String, usize>,
+}
+
+impl RepoStats {
+    pub fn from_files(files: &[FileInfo]) -> Self {
+        let stats: Vec<FileStats> = files.iter()
+            .map(|file| FileStats {
+                path: file.path.clone(),
+                bytes: file.size,
+                lines: file.content.lines().count(),
+            })
+            .collect();
+
+        let mut languages: BTreeMap<&str, GroupStats> = BTreeMap::new();
+        let mut directories: BTreeMap<&str, GroupStats> = BTreeMap::new();
+        let mut binaries: BTreeMap<&str, GroupStats> = BTreeMap::new();
+        for (file, stats) in files.iter().zip(&stats) {
+            let language = file.language.as_deref().unwrap_or(NO_LANGUAGE);
+            add_to_group(&mut languages, language, stats);
+            add_to_group(&mut directories, top_level_directory(&file.path), stats);
+            if let Some(kind) = &file.binary_kind {
+                add_to_group(&mut binaries, kind, stats);
+            }
+        }
+
+        let mut largest = stats.clone();
+        largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        largest.truncate(LARGEST_FILES);
+
+        Self {
+            files: stats.len(),
+            bytes: stats.iter().map(|file| file.bytes).sum(),
+            lines: stats.iter().map(|file| file.lines).sum(),
+            empty: files.iter()
+                .filter(|file| file.binary_kind.is_none() && blank_line_count(&file.content).is_some())
+                .count(),
+            largest,
+            languages: by_size(languages),
+            directories: by_size(directories),
+            binaries: by_size(binaries),
+            todos: BTreeMap::new(),
+        }
+    }
+
+    /// Count each of `markers`, such as `TODO`, across `files`
+    pub fn with_todos(mut self, files: &[FileInfo], markers: &[String]) -> Self {
+        let todos: Vec<_> = files.iter().flat_map(|file| find_todos(file, markers)).collect();
+        self.todos = count_by_marker(&todos, markers);
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn add_to_group<'a>(groups: &mut BTreeMap<&'a str, GroupStats>, name: &'a str, file: &FileStats) {
+    let group = groups.entry(name).or_insert_with(|| GroupStats {
+        name: name.to_string(),
+        files: 0,
+        bytes: 0,
+        lines: 0,
+    });
+    group.files += 1;
+    group.bytes += file.bytes;
+    group.lines += file.lines;
+}
+
+/// Groups with the most bytes first, ties by name
+fn by_size(groups: BTreeMap<&str, GroupStats>) -> Vec<GroupStats> {
+    let mut groups: Vec<GroupStats> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
+
+/// The first directory in `path`, or "." for files at the top
+fn top_level_directory(path: &str) -> &str {
+    let mut components = Path::new(path).components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_str().unwrap_or(ROOT_DIRECTORY),
+        _ => ROOT_DIRECTORY,
+    }
+}
+
+impl fmt::Display for RepoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = MarkdownGenerator::format_file_size;
+        writeln!(f, "Files: {}  Size: {}  Lines: {}", self.files, size(self.bytes), self.lines)?;
+        if self.empty > 0 {
+            writeln!(f, "Empty or blank files: {}", self.empty)?;
+        }
+
+        writeln!(f, "\nLargest files")?;
+        let width = self.largest.iter().map(|file| file.path.len()).max().unwrap_or(0);
+        for file in &self.largest {
+            writeln!(f, "  {:<width$}  {:>10}  {:>8} lines", file.path, size(file.bytes), file.lines, width = width)?;
+        }
+
+        // The binary breakdown only when there are binaries
+        let binaries = Some(("Binary", &self.binaries)).filter(|(_, groups)| !groups.is_empty());
+        for (title, groups) in [("Language", &self.languages), ("Directory", &self.directories)].into_iter().chain(binaries) {
+            let width = groups.iter().map(|group| group.name.len()).max().unwrap_or(0).max(title.len());
+            writeln!(f, "\n  {:<width$}  {:>6}  {:>10}  {:>8}", title, "Files", "Size", "Lines", width = width)?;
+            for group in groups {
+                writeln!(f, "  {:<width$}  {:>6}  {:>10}  {:>8}",
+                    group.name, group.files, size(group.bytes), group.lines, width = width)?;
+            }
+        }
+
+        if !self.todos.is_empty() {
+            let width = self.todos.keys().map(String::len).max().unwrap_or(0).max("Marker".len());
+            writeln!(f, "\n  {:<width$}  {:>6}", "Marker", "Count", width = width)?;
+            for (marker, count) in &self.todos {
+                writeln!(f, "  {:<width$}  {:>6}", marker, count, width = width)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_processor::FileProcessor;
+    use anyhow::Result;
+
+    fn test_project_stats() -> Result<RepoStats> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testfiles/test_project");
+        Ok(RepoStats::from_files(&FileProcessor::new().process_directory(root)?))
+    }
+
+    #[test]
+    fn test_test_project_counts() -> Result<()> {
+        let stats = test_project_stats()?;
+        assert_eq!((stats.files, stats.bytes, stats.lines), (3, 2022, 97));
+        let largest: Vec<&str> = stats.largest.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(largest, vec!["Token.sol", "README.md", "main.rs"]);
+        let languages: Vec<(&str, usize)> = stats.languages.iter().map(|group| (group.name.as_str(), group.lines)).collect();
+        assert_eq!(languages, vec![("solidity", 28), ("markdown", 44), ("rust", 25)]);
+        assert_eq!(stats.directories, vec![GroupStats { name: ".".to_string(), files: 3, bytes: 2022, lines: 97 }]);
+
+        let report = stats.to_string();
+        assert!(report.starts_with("Files: 3  Size: 2.0 KB  Lines: 97\n\nLargest files\n"), "{}", report);
+        assert!(report.contains("  Token.sol       857 B        28 lines\n"), "{}", report);
+        assert!(report.contains("  solidity       1       857 B        28\n"), "{}", report);
+
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json()?)?;
+        assert_eq!(json["files"], 3);
+        assert_eq!(json["lines"], 97);
+        assert_eq!(json["largest"][0]["path"], "Token.sol");
+        assert_eq!(json["languages"][2]["name"], "rust");
+        assert_eq!(json["directories"][0]["bytes"], 2022);
+        assert_eq!(json["empty"], 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_counts_empty_and_blank_files() -> Result<()> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testfiles/empty_project");
+        let stats = RepoStats::from_files(&FileProcessor::new().process_directory(root)?);
+        assert_eq!((stats.files, stats.empty), (4, 3));
+        assert!(stats.to_string().starts_with("Files: 4  Size: 19 B  Lines: 5\nEmpty or blank files: 3\n"), "{}", stats);
+        Ok(())
+    }
+
+    #[test]
+    fn test_groups_by_top_level_directory() {
+        let file = |path: &str, language: Option<&str>, content: &str| FileInfo {
+            path: path.to_string(),
+            content: content.into(),
+            language: language.map(str::to_string),
+            size: content.len(),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        };
+        let stats = RepoStats::from_files(&[
+            file("src/main.rs", Some("rust"), "fn main() {}\n"),
+            file("src/util/mod.rs", Some("rust"), "pub mod a;\npub mod b;\n"),
+            file("LICENSE", None, "MIT\n"),
+        ]);
+        let directories: Vec<(&str, usize)> = stats.directories.iter().map(|group| (group.name.as_str(), group.files)).collect();
+        assert_eq!(directories, vec![("src", 2), (".", 1)]);
+        assert_eq!(stats.languages[1].name, "other");
+        assert_eq!(stats.lines, 4);
+        assert!(stats.binaries.is_empty());
+    }
+
+    #[test]
+    fn test_binaries_by_kind() {
+        let file = |path: &str, size: usize, kind: &str| FileInfo {
+            path: path.to_string(),
+            content: format!("[Binary file: {} ({}, {} bytes)]", path, kind, size).into(),
+            language: None,
+            size,
+            changes: None,
+            binary_kind: Some(kind.to_string()),
+            modified: None,
+            commit: None,
+        };
+        let stats = RepoStats::from_files(&[
+            file("data/app.db", 4096, "SQLite database"),
+            file("data/old.db", 2048, "SQLite database"),
+            file("bin/tool", 9000, "ELF 64-bit executable"),
+        ]);
+        let binaries: Vec<(&str, usize, usize)> = stats.binaries.iter()
+            .map(|group| (group.name.as_str(), group.files, group.bytes))
+            .collect();
+        assert_eq!(binaries, vec![("ELF 64-bit executable", 1, 9000), ("SQLite database", 2, 6144)]);
+        assert!(stats.to_string().contains("\n  Binary                  Files        Size     Lines\n"), "{}", stats);
+    }
+
+    #[test]
+    fn test_todo_counts() -> Result<()> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testfiles/todo_project");
+        let files = FileProcessor::new().process_directory(root)?;
+        let stats = RepoStats::from_files(&files);
+        assert!(serde_json::to_value(&stats)?.get("todos").is_none());
+
+        let stats = stats.with_todos(&files, &crate::todos::parse_markers("TODO,FIXME,NOTE"));
+        assert_eq!(stats.todos.iter().map(|(marker, count)| (marker.as_str(), *count)).collect::<Vec<_>>(),
+            vec![("FIXME", 1), ("NOTE", 0), ("TODO", 1)]);
+        assert!(stats.to_string().ends_with("\n  Marker   Count\n  FIXME        1\n  NOTE         0\n  TODO         1\n"), "{}", stats);
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+/// Highlighted HTML kept at most, by entry count and by total size
+const MAX_ENTRIES: usize = 4096;
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Identifies one highlighting result: the code, the syntax and theme used,
+/// and whether the output uses inline styles or classes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    digest: [u8; 20],
+    syntax: String,
+    theme: String,
+    inline: bool,
+}
+
+impl CacheKey {
+    pub fn new(code: &str, syntax: &str, theme: &str, inline: bool) -> Self {
+        Self {
+            digest: Sha1::digest(code.as_bytes()).into(),
+            syntax: syntax.to_string(),
+            theme: theme.to_string(),
+            inline,
+        }
+    }
+}
+
+/// Hit and size counters for verbose output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// Least-recently-used cache of highlighted HTML
+#[derive(Debug)]
+pub struct HighlightCache {
+    max_entries: usize,
+    max_bytes: usize,
+    /// HTML and last-use tick per key
+    entries: HashMap<CacheKey, (String, u64)>,
+    /// Keys by last-use tick, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl HighlightCache {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<String> {
+        self.tick += 1;
+        let Some((html, last_used)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.recency.remove(last_used);
+        *last_used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        self.stats.hits += 1;
+        Some(html.clone())
+    }
+
+    pub fn insert(&mut self, key: CacheKey, html: String) {
+        if html.len() > self.max_bytes {
+            return;
+        }
+        self.tick += 1;
+        self.stats.bytes += html.len();
+        if let Some((old, last_used)) = self.entries.insert(key.clone(), (html, self.tick)) {
+            self.stats.bytes -= old.len();
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, key);
+
+        while self.entries.len() > self.max_entries || self.stats.bytes > self.max_bytes {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.entries.remove(&oldest) {
+                self.stats.bytes -= evicted.len();
+            }
+        }
+        self.stats.entries = self.entries.len();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+/// The cache shared by every highlighter in the process, so repeated
+/// renders (and identical files within one render) highlight once
+pub fn shared_cache() -> &'static Mutex<HighlightCache> {
+    static CACHE: OnceLock<Mutex<HighlightCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HighlightCache::new(MAX_ENTRIES, MAX_BYTES)))
+}
+
+/// Counters of the shared cache
+pub fn shared_cache_stats() -> CacheStats {
+    shared_cache().lock().map(|cache| cache.stats()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: &str) -> CacheKey {
+        CacheKey::new(code, "Rust", "InspiredGitHub", true)
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = HighlightCache::new(2, 1024);
+        cache.insert(key("a"), "<a>".to_string());
+        cache.insert(key("b"), "<b>".to_string());
+        assert_eq!(cache.get(&key("a")), Some("<a>".to_string()));
+
+        cache.insert(key("c"), "<c>".to_string());
+        assert_eq!(cache.get(&key("b")), None);
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1, entries: 2, bytes: 6 });
+    }
+
+    #[test]
+    fn test_bounded_by_total_size() {
+        let mut cache = HighlightCache::new(100, 10);
+        cache.insert(key("a"), "x".repeat(6));
+        cache.insert(key("b"), "y".repeat(6));
+        assert_eq!(cache.get(&key("a")), None);
+        assert_eq!(cache.stats().bytes, 6);
+
+        // Entries larger than the whole cache are never stored
+        cache.insert(key("c"), "z".repeat(11));
+        assert_eq!(cache.get(&key("c")), None);
+        assert!(cache.get(&key("b")).is_some());
+    }
+
+    #[test]
+    fn test_key_covers_theme_and_style() {
+        assert_ne!(key("a"), CacheKey::new("a", "Rust", "base16-ocean.dark", true));
+        assert_ne!(key("a"), CacheKey::new("a", "Rust", "InspiredGitHub", false));
+        assert_eq!(key("a"), CacheKey::new("a", "Rust", "InspiredGitHub", true));
+    }
+}
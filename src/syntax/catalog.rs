@@ -0,0 +1,99 @@
+use anyhow::Result;
+use crate::syntax::highlighter::SyntaxHighlighter;
+
+/// A color theme available for highlighting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeInfo {
+    pub name: String,
+    /// Pandoc style names that select this theme
+    pub pandoc_aliases: Vec<String>,
+    /// Whether the theme's background is dark
+    pub is_dark: bool,
+}
+
+/// A language the highlighter has a syntax for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageInfo {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// All themes: syntect's defaults plus those in the user theme directory
+pub fn available_themes() -> Result<Vec<ThemeInfo>> {
+    Ok(SyntaxHighlighter::new()?.themes())
+}
+
+/// All languages: syntect's defaults, the bundled syntaxes and those in the
+/// user syntax directory
+pub fn available_languages() -> Result<Vec<LanguageInfo>> {
+    Ok(SyntaxHighlighter::new()?.languages())
+}
+
+/// Theme listing for `--list-themes`: one theme per line with the Pandoc
+/// style names that select it
+pub fn theme_table(themes: &[ThemeInfo]) -> String {
+    let width = themes.iter().map(|theme| theme.name.len()).max().unwrap_or(0);
+    themes.iter()
+        .map(|theme| {
+            if theme.pandoc_aliases.is_empty() {
+                format!("  {}\n", theme.name)
+            } else {
+                format!("  {:<width$}  pandoc: {}\n", theme.name, theme.pandoc_aliases.join(", "), width = width)
+            }
+        })
+        .collect()
+}
+
+/// Language listing for `--list-languages`: syntax names in one column
+/// and their file extensions in the next
+pub fn language_table(languages: &[LanguageInfo]) -> String {
+    let width = languages.iter().map(|language| language.name.len()).max().unwrap_or(0);
+    languages.iter()
+        .map(|language| {
+            let line = format!("  {:<width$}  {}", language.name, language.extensions.join(" "), width = width);
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_themes_and_languages_are_listed() {
+        let themes = available_themes().unwrap();
+        let ocean = themes.iter().find(|theme| theme.name == "base16-ocean.dark").unwrap();
+        assert_eq!(ocean.pandoc_aliases, vec!["breezedark"]);
+        assert!(ocean.is_dark);
+        let github = themes.iter().find(|theme| theme.name == "InspiredGitHub").unwrap();
+        assert_eq!(github.pandoc_aliases, vec!["kate", "haddock", "tango"]);
+        assert!(!github.is_dark);
+        assert!(themes.iter().any(|theme| theme.name == "Solarized (dark)" && theme.is_dark));
+
+        let languages = available_languages().unwrap();
+        let rust = languages.iter().find(|language| language.name == "Rust").unwrap();
+        assert!(rust.extensions.contains(&"rs".to_string()));
+        assert!(languages.iter().any(|language| language.name == "Solidity" && language.extensions == vec!["sol"]));
+    }
+
+    #[test]
+    fn test_listings_match_library_results() {
+        let themes = available_themes().unwrap();
+        let table = theme_table(&themes);
+        assert_eq!(table.lines().count(), themes.len());
+        for (line, theme) in table.lines().zip(&themes) {
+            assert!(line.trim_start().starts_with(&theme.name), "{}", line);
+        }
+        assert!(table.lines().any(|line| line.trim_start().starts_with("base16-ocean.dark") && line.ends_with("pandoc: breezedark")));
+
+        let languages = available_languages().unwrap();
+        let table = language_table(&languages);
+        assert_eq!(table.lines().count(), languages.len());
+        for (line, language) in table.lines().zip(&languages) {
+            assert!(line.trim_start().starts_with(&language.name), "{}", line);
+            assert!(line.ends_with(&language.extensions.join(" ")), "{}", line);
+        }
+        assert!(table.lines().any(|line| line.trim_start().starts_with("OCaml") && line.contains(" ml")));
+    }
+}
@@ -1,20 +1,113 @@
-use syntect::parsing::{SyntaxSet, SyntaxReference};
-use syntect::highlighting::{ThemeSet, Style};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxSet, SyntaxReference};
+use syntect::highlighting::{Theme, ThemeSet, Style};
 use syntect::util::LinesWithEndings;
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::changes::LineChanges;
+use crate::fragment_cache::FragmentCache;
+use crate::syntax::cache::{shared_cache, CacheKey};
+use crate::syntax::catalog::{LanguageInfo, ThemeInfo};
+use crate::syntax::tokens::{push_span, StyledSpan, TokenKind, SCOPE_KINDS};
+use crate::warnings::{WarningCategory, Warnings};
+use crate::theme::{is_dark_color, parse_hex_color, syntax_theme_for, with_min_contrast, ColorScheme, FontStyle as AppFontStyle, Theme as AppTheme};
+use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Default time allowed for highlighting one code block
+pub const DEFAULT_HIGHLIGHT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Languages without a bundled grammar and the closest syntax used instead
+const FALLBACK_SYNTAXES: [(&str, &str); 15] = [
+    ("zig", "Rust"),
+    ("nim", "Python"),
+    ("elixir", "Ruby"),
+    ("svelte", "HTML"),
+    ("vue", "HTML"),
+    ("graphql", "Plain Text"),
+    ("protobuf", "C"),
+    ("terraform", "Ruby"),
+    ("cue", "Go"),
+    ("nix", "Plain Text"),
+    ("dart", "Java"),
+    ("cmake", "Plain Text"),
+    ("powershell", "Plain Text"),
+    ("csv", "Plain Text"),
+    ("ini", "Java Properties"),
+];
+
+/// Blocks with a line longer than this are left unhighlighted; such lines
+/// are almost always generated and can make syntect's regexes crawl
+const LONG_LINE_LIMIT: usize = 20_000;
+
+/// Light themes and the dark themes they are read with at night
+const NIGHT_THEMES: [(&str, &str); 2] = [
+    ("Solarized (light)", "Solarized (dark)"),
+    ("base16-ocean.light", "base16-ocean.dark"),
+];
+
+/// The night theme of light themes without a pairing of their own
+const DEFAULT_NIGHT_THEME: &str = "base16-ocean.dark";
+
+/// An RGB color
+type Rgb = (u8, u8, u8);
+
+/// A light theme's colors paired with those of its night theme
+pub struct NightPalette {
+    pub background: Rgb,
+    pub foreground: Rgb,
+    /// Each foreground color of the light theme, with the night theme's
+    /// color for the same scope
+    pub colors: Vec<(Rgb, Rgb)>,
+}
 
 pub struct SyntaxHighlighter {
-    syntax_set: SyntaxSet,
-    theme_set: ThemeSet,
+    /// Shared with every other highlighter unless custom syntaxes were added
+    syntax_set: Arc<SyntaxSet>,
+    /// Shared until a theme file is loaded into this highlighter
+    theme_set: Arc<ThemeSet>,
     current_theme: String,
     language_map: HashMap<String, String>,
+    use_cache: bool,
+    /// On-disk cache of highlighted HTML, with a fingerprint of the theme
+    /// it was set up with
+    fragments: Option<(FragmentCache, String)>,
+    /// Time allowed per code block before the rest is left as plain text
+    timeout: Duration,
+    /// Where blocks cut short by the timeout are noted
+    warnings: Warnings,
 }
 
 impl SyntaxHighlighter {
     pub fn new() -> Result<Self> {
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme_set = ThemeSet::load_defaults();
+        Self::with_syntax_dir(None)
+    }
+    
+    /// Create a highlighter with syntect's default syntaxes plus any
+    /// `.sublime-syntax` definitions found in the user syntax directory and
+    /// in `syntax_dir`
+    pub fn with_syntax_dir(syntax_dir: Option<&Path>) -> Result<Self> {
+        let custom: Vec<SyntaxDefinition> = default_syntax_dir()
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(syntax_dir)
+            .flat_map(load_syntax_definitions)
+            .collect();
+        let custom_names: Vec<(String, Vec<String>)> = custom.iter()
+            .map(|syntax| (syntax.name.clone(), syntax.file_extensions.clone()))
+            .collect();
+        
+        let syntax_set = if custom.is_empty() {
+            shared_syntax_set()
+        } else {
+            let mut builder = SyntaxSet::clone(&shared_syntax_set()).into_builder();
+            for syntax in custom {
+                builder.add(syntax);
+            }
+            Arc::new(builder.build())
+        };
+        let theme_set = shared_theme_set()?;
         
         // Create language mapping for common extensions
         let mut language_map = HashMap::new();
@@ -53,29 +146,104 @@ impl SyntaxHighlighter {
         language_map.insert("dockerfile".to_string(), "Dockerfile".to_string());
         language_map.insert("makefile".to_string(), "Makefile".to_string());
         
-        // Solidity not included by default, would need custom syntax
+        language_map.insert("erlang".to_string(), "Erlang".to_string());
+        language_map.insert("haskell".to_string(), "Haskell".to_string());
+        language_map.insert("ocaml".to_string(), "OCaml".to_string());
+        language_map.insert("gradle".to_string(), "Groovy".to_string());
+        language_map.insert("batch".to_string(), "Batch File".to_string());
+        language_map.insert("properties".to_string(), "Java Properties".to_string());
+        // Stand-ins only for languages the syntax set has no grammar for
+        for (language, syntax) in FALLBACK_SYNTAXES {
+            if syntax_set.find_syntax_by_token(language).is_none() {
+                language_map.insert(language.to_string(), syntax.to_string());
+            }
+        }
+        
+        // Bundled in assets/syntaxes
+        language_map.insert("solidity".to_string(), "Solidity".to_string());
+        
+        // Custom syntaxes are found by name and by their file extensions,
+        // taking precedence over the defaults above
+        for (name, extensions) in custom_names {
+            language_map.insert(name.to_lowercase(), name.clone());
+            for extension in extensions {
+                language_map.insert(extension.to_lowercase(), name.clone());
+            }
+        }
         
         Ok(Self {
             syntax_set,
             theme_set,
             current_theme: "InspiredGitHub".to_string(),
             language_map,
+            use_cache: true,
+            fragments: None,
+            timeout: DEFAULT_HIGHLIGHT_TIMEOUT,
+            warnings: Warnings::new(),
         })
     }
     
-    pub fn set_theme(&mut self, theme_name: &str) -> Result<()> {
-        // Map Pandoc theme names to syntect theme names
-        let syntect_theme = match theme_name {
-            "pygments" => "base16-ocean.light",
-            "kate" => "InspiredGitHub",
-            "monochrome" => "base16-ocean.light",
-            "breezedark" => "base16-ocean.dark",
-            "espresso" => "base16-mocha.dark",
-            "zenburn" => "base16-eighties.dark",
-            "haddock" => "InspiredGitHub",
-            "tango" => "InspiredGitHub",
-            _ => theme_name,
+    /// Load a `.tmTheme` file, making it available to `set_theme` under its
+    /// file stem. Returns that name.
+    pub fn load_theme_file(&mut self, path: &Path) -> Result<String> {
+        let (name, theme) = read_theme(path)?;
+        if let Some(warning) = AppTheme::from_syntect(&theme).contrast_warning() {
+            eprintln!("Warning: {}: {}", path.display(), warning);
+        }
+        self.themes_mut().insert(name.clone(), theme);
+        Ok(name)
+    }
+    
+    /// Themes of this highlighter for adding to, copied first if shared
+    fn themes_mut(&mut self) -> &mut BTreeMap<String, Theme> {
+        if Arc::get_mut(&mut self.theme_set).is_none() {
+            self.theme_set = Arc::new(ThemeSet { themes: self.theme_set.themes.clone() });
+        }
+        &mut Arc::get_mut(&mut self.theme_set).expect("theme set was just copied").themes
+    }
+    
+    /// Highlight with the colors and font styles of a configured palette
+    /// instead of a syntect theme, by registering a theme built from it and
+    /// selecting it
+    pub fn set_palette(&mut self, palette: &ColorScheme, font_styles: &BTreeMap<String, AppFontStyle>) -> Result<()> {
+        palette.validate()?;
+        let (name, theme) = palette_theme(palette, font_styles);
+        self.themes_mut().insert(name.clone(), theme);
+        self.set_theme(&name)
+    }
+    
+    /// Switch to a copy of the current theme whose foreground colors have
+    /// at least `min_ratio` contrast against their backgrounds
+    pub fn enforce_contrast(&mut self, min_ratio: f32) {
+        let mut theme = self.current_theme().clone();
+        let rgb = |color: syntect::highlighting::Color| (color.r, color.g, color.b);
+        let background = theme.settings.background.map_or((255, 255, 255), rgb);
+        let adjust = |color: &mut Option<syntect::highlighting::Color>, background| {
+            if let Some(color) = color {
+                (color.r, color.g, color.b) = with_min_contrast(rgb(*color), background, min_ratio);
+            }
         };
+        adjust(&mut theme.settings.foreground, background);
+        adjust(&mut theme.settings.gutter_foreground, background);
+        for item in &mut theme.scopes {
+            let background = item.style.background.map_or(background, rgb);
+            adjust(&mut item.style.foreground, background);
+        }
+        let name = format!("{}-contrast-{}", self.current_theme, min_ratio);
+        self.themes_mut().insert(name.clone(), theme);
+        self.current_theme = name;
+    }
+    
+    /// Load every `.tmTheme` file in `dir`
+    pub fn load_theme_dir(&mut self, dir: &Path) -> Result<()> {
+        for (name, theme) in read_theme_dir(dir)? {
+            self.themes_mut().insert(name, theme);
+        }
+        Ok(())
+    }
+    
+    pub fn set_theme(&mut self, theme_name: &str) -> Result<()> {
+        let syntect_theme = resolve_theme_name(theme_name);
         
         if self.theme_set.themes.contains_key(syntect_theme) {
             self.current_theme = syntect_theme.to_string();
@@ -87,20 +255,104 @@ impl SyntaxHighlighter {
         }
     }
     
-    pub fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
-        // Try to find syntax by our mapped name first
-        if let Some(syntax_name) = self.language_map.get(language) {
-            self.syntax_set.find_syntax_by_name(syntax_name)
-        } else {
-            // Try direct lookup
-            self.syntax_set.find_syntax_by_name(language)
-                .or_else(|| self.syntax_set.find_syntax_by_extension(language))
-                .or_else(|| {
-                    // Try case-insensitive search
-                    self.syntax_set.syntaxes().iter()
-                        .find(|s| s.name.to_lowercase() == language.to_lowercase())
-                })
+    /// Turn the shared highlight cache on or off for this highlighter
+    pub fn set_cache_enabled(&mut self, enabled: bool) {
+        self.use_cache = enabled;
+    }
+    
+    /// Keep highlighted HTML in `cache` across runs. Set this once the theme
+    /// is chosen: cached HTML is keyed by the theme's colors as they are
+    /// now, so a theme file edited between runs isn't served stale.
+    pub fn set_fragment_cache(&mut self, cache: FragmentCache) {
+        let fingerprint = FragmentCache::key("theme", &[&format!("{:?}", self.current_theme())]);
+        self.fragments = Some((cache, fingerprint));
+    }
+    
+    /// Set the time allowed for highlighting one code block
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    
+    /// Note blocks cut short by the timeout in `warnings`
+    pub fn set_warnings(&mut self, warnings: Warnings) {
+        self.warnings = warnings;
+    }
+    
+    /// Whether highlighting that began at `started` has used up its time,
+    /// warning once when it has
+    fn out_of_time(&self, started: Instant, syntax: &SyntaxReference) -> bool {
+        if started.elapsed() < self.timeout {
+            return false;
         }
+        self.warnings.push(
+            WarningCategory::HighlightTimeout,
+            None,
+            format!(
+                "Highlighting {} code took longer than {:?}; the rest of the block is plain text",
+                syntax.name, self.timeout
+            ),
+        );
+        true
+    }
+    
+    /// Look `code` up in the shared highlight cache, then the fragment
+    /// cache on disk, producing the HTML with `highlight` on a miss. Results
+    /// cut short by the timeout aren't stored.
+    fn cached_html(
+        &self,
+        code: &str,
+        syntax: &SyntaxReference,
+        inline: bool,
+        highlight: impl FnOnce() -> (String, bool),
+    ) -> String {
+        // Classed output doesn't depend on the theme
+        let theme = if inline { self.current_theme.as_str() } else { "" };
+        let key = self.use_cache.then(|| CacheKey::new(code, &syntax.name, theme, inline));
+        if let Some(html) = key.as_ref().and_then(|key| shared_cache().lock().ok()?.get(key)) {
+            return html;
+        }
+        let fragment = self.fragments.as_ref().map(|(cache, fingerprint)| {
+            let theme = if inline { fingerprint.as_str() } else { "" };
+            (cache, FragmentCache::key("html", &[code, &syntax.name, theme, if inline { "inline" } else { "classed" }]))
+        });
+        let (html, complete) = match fragment.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            Some(html) => (html, true),
+            None => {
+                let (html, complete) = highlight();
+                if let (true, Some((cache, key))) = (complete, &fragment) {
+                    cache.insert(key, &html);
+                }
+                (html, complete)
+            }
+        };
+        if let (true, Some(key)) = (complete, key) {
+            if let Ok(mut cache) = shared_cache().lock() {
+                cache.insert(key, html.clone());
+            }
+        }
+        html
+    }
+    
+    /// The stand-in syntax used for `language` when there's no grammar for
+    /// it, unless a custom syntax has since provided one
+    pub fn fallback_for(&self, language: &str) -> Option<&'static str> {
+        FALLBACK_SYNTAXES.iter()
+            .find(|(name, syntax)| *name == language && self.language_map.get(language).is_some_and(|mapped| mapped == syntax))
+            .map(|(_, syntax)| *syntax)
+    }
+    
+    pub fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        // Try to find syntax by our mapped name first, then directly, so a
+        // grammar the mapped name misses in this syntax set is still found
+        self.language_map.get(language)
+            .and_then(|syntax_name| self.syntax_set.find_syntax_by_name(syntax_name))
+            .or_else(|| self.syntax_set.find_syntax_by_name(language))
+            .or_else(|| self.syntax_set.find_syntax_by_extension(language))
+            .or_else(|| {
+                // Try case-insensitive search
+                self.syntax_set.syntaxes().iter()
+                    .find(|s| s.name.to_lowercase() == language.to_lowercase())
+            })
     }
     
     pub fn highlight_lines<'a>(&self, code: &'a str, language: Option<&str>) -> Vec<Vec<(Style, &'a str)>> {
@@ -114,16 +366,25 @@ impl SyntaxHighlighter {
         let mut h = HighlightLines::new(syntax, theme);
         
         let mut highlighted_lines = Vec::new();
+        let started = Instant::now();
+        let mut plain = has_long_line(code);
         
         for line in LinesWithEndings::from(code) {
-            let ranges = h.highlight_line(line, &self.syntax_set)
-                .unwrap_or_else(|_| vec![(Style::default(), line)]);
+            plain = plain || self.out_of_time(started, syntax);
+            let ranges = if plain {
+                vec![(Style::default(), line)]
+            } else {
+                h.highlight_line(line, &self.syntax_set)
+                    .unwrap_or_else(|_| vec![(Style::default(), line)])
+            };
             highlighted_lines.push(ranges);
         }
         
         highlighted_lines
     }
     
+    /// Highlight code as HTML spans carrying syntect's scope classes, for
+    /// use with a stylesheet rather than the theme's colors
     pub fn highlight_to_html(&self, code: &str, language: Option<&str>) -> String {
         use syntect::html::{ClassedHTMLGenerator, ClassStyle};
         
@@ -131,17 +392,169 @@ impl SyntaxHighlighter {
             .and_then(|lang| self.find_syntax(lang))
             .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
         
-        let mut generator = ClassedHTMLGenerator::new_with_class_style(
-            syntax,
-            &self.syntax_set,
-            ClassStyle::Spaced
-        );
+        self.cached_html(code, syntax, false, || {
+            if has_long_line(code) {
+                return (escape_html(code), true);
+            }
+            let mut generator = ClassedHTMLGenerator::new_with_class_style(
+                syntax,
+                &self.syntax_set,
+                ClassStyle::Spaced
+            );
+            
+            let started = Instant::now();
+            let mut done = 0;
+            for line in LinesWithEndings::from(code) {
+                if self.out_of_time(started, syntax) {
+                    break;
+                }
+                let _ = generator.parse_html_for_line_which_includes_newline(line);
+                done += line.len();
+            }
+            
+            let mut html = generator.finalize();
+            html.push_str(&escape_html(&code[done..]));
+            (html, done == code.len())
+        })
+    }
+    
+    /// Highlight code as a `<pre>` block with inline colors from the current
+    /// theme, including the theme's background. Useful where stylesheets with
+    /// syntect classes aren't available, such as EPUB readers.
+    pub fn highlight_to_inline_html(&self, code: &str, language: Option<&str>) -> String {
+        use syntect::html::start_highlighted_html_snippet;
+        
+        let syntax = self.syntax_for(language);
+        let theme = &self.theme_set.themes[&self.current_theme];
+        
+        self.cached_html(code, syntax, true, || {
+            let (spans, complete) = self.spans_for(code, syntax);
+            let (mut html, _) = start_highlighted_html_snippet(theme);
+            for span in spans.iter().flatten() {
+                push_inline_span(&mut html, span, &span.text);
+            }
+            html.push_str("</pre>\n");
+            (html, complete)
+        })
+    }
+    
+    /// Like `highlight_to_inline_html`, with each changed line wrapped in a
+    /// span carrying its change class. Not cached, as the markup depends on
+    /// the changes as well as the code.
+    pub fn highlight_to_inline_html_with_changes(&self, code: &str, language: Option<&str>, changes: &LineChanges) -> String {
+        use syntect::html::start_highlighted_html_snippet;
+        
+        let syntax = self.syntax_for(language);
+        let theme = &self.theme_set.themes[&self.current_theme];
+        let (spans, _) = self.spans_for(code, syntax);
+        let (mut html, _) = start_highlighted_html_snippet(theme);
+        for (index, line) in spans.iter().enumerate() {
+            let change = changes.get(index + 1);
+            if let Some(change) = change {
+                html.push_str(&format!("<span class=\"{}\">", change.css_class()));
+            }
+            // The newline goes after the line's span so its background
+            // covers only the line itself
+            for span in line {
+                let text = span.text.trim_end_matches(['\r', '\n']);
+                if !text.is_empty() {
+                    push_inline_span(&mut html, span, text);
+                }
+            }
+            if change.is_some() {
+                html.push_str("</span>");
+            }
+            html.push('\n');
+        }
+        html.push_str("</pre>\n");
+        html
+    }
+    
+    /// Highlight code into styled spans, one list per line, each line's
+    /// spans concatenating back to that line. Every renderer draws code from
+    /// these so a theme looks the same in all formats.
+    pub fn highlight_spans(&self, code: &str, language: Option<&str>) -> Vec<Vec<StyledSpan>> {
+        self.spans_for(code, self.syntax_for(language)).0
+    }
+    
+    /// Styled spans for `code`, and whether highlighting finished within the
+    /// timeout. Lines after the timeout, and blocks with very long lines,
+    /// come out in the theme's plain foreground.
+    fn spans_for(&self, code: &str, syntax: &SyntaxReference) -> (Vec<Vec<StyledSpan>>, bool) {
+        use syntect::highlighting::{FontStyle, Highlighter};
+        
+        let theme = &self.theme_set.themes[&self.current_theme];
+        let highlighter = Highlighter::new(theme);
+        let styled = |text: &str, style: Style, token_kind: TokenKind| StyledSpan {
+            text: text.to_string(),
+            fg: (style.foreground.r, style.foreground.g, style.foreground.b),
+            bold: style.font_style.contains(FontStyle::BOLD),
+            italic: style.font_style.contains(FontStyle::ITALIC),
+            underline: style.font_style.contains(FontStyle::UNDERLINE),
+            token_kind,
+        };
+        let plain = highlighter.get_default();
+        
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let started = Instant::now();
+        let long_line = has_long_line(code);
+        let mut timed_out = false;
+        let mut lines = Vec::new();
         
         for line in LinesWithEndings::from(code) {
-            let _ = generator.parse_html_for_line_which_includes_newline(line);
+            timed_out = timed_out || (!long_line && self.out_of_time(started, syntax));
+            let mut spans = Vec::new();
+            let ops = if long_line || timed_out {
+                None
+            } else {
+                state.parse_line(line, &self.syntax_set).ok()
+            };
+            let Some(ops) = ops else {
+                push_span(&mut spans, styled(line, plain, TokenKind::Text));
+                lines.push(spans);
+                continue;
+            };
+            
+            let mut start = 0;
+            for (offset, op) in ops {
+                if offset > start {
+                    let style = highlighter.style_for_stack(stack.as_slice());
+                    push_span(&mut spans, styled(&line[start..offset], style, TokenKind::from_scopes(&stack)));
+                    start = offset;
+                }
+                let _ = stack.apply(&op);
+            }
+            let style = highlighter.style_for_stack(stack.as_slice());
+            push_span(&mut spans, styled(&line[start..], style, TokenKind::from_scopes(&stack)));
+            lines.push(spans);
         }
         
-        generator.finalize()
+        (lines, !timed_out)
+    }
+    
+    /// The syntax for a language name, or plain text
+    fn syntax_for(&self, language: Option<&str>) -> &SyntaxReference {
+        language
+            .and_then(|lang| self.find_syntax(lang))
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+    
+    /// Highlight code with 24-bit ANSI color escapes for terminal output
+    pub fn highlight_to_ansi(&self, code: &str, language: Option<&str>) -> String {
+        use syntect::util::as_24_bit_terminal_escaped;
+        
+        let mut output: String = self.highlight_lines(code, language).iter()
+            .map(|ranges| as_24_bit_terminal_escaped(ranges, false))
+            .collect();
+        // Reset so the terminal's own colors resume after the listing
+        output.push_str("\x1b[0m");
+        output
+    }
+    
+    /// Name of the theme in use
+    pub fn theme_name(&self) -> &str {
+        &self.current_theme
     }
     
     pub fn get_theme_background(&self) -> Option<(u8, u8, u8, u8)> {
@@ -157,9 +570,568 @@ impl SyntaxHighlighter {
             (color.r, color.g, color.b, color.a)
         })
     }
+    
+    /// Names of all available themes, built-in and user-loaded, sorted
+    pub fn theme_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.theme_set.themes.keys().map(String::as_str).collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+    
+    /// Every available theme with its Pandoc aliases and brightness, sorted by name
+    pub fn themes(&self) -> Vec<ThemeInfo> {
+        self.theme_names().into_iter()
+            .map(|name| ThemeInfo {
+                name: name.to_string(),
+                pandoc_aliases: pandoc_aliases(name).into_iter().map(str::to_string).collect(),
+                is_dark: is_dark(&self.theme_set.themes[name]),
+            })
+            .collect()
+    }
+    
+    /// Every loaded syntax with the file extensions it claims, sorted by name
+    pub fn languages(&self) -> Vec<LanguageInfo> {
+        let mut languages: Vec<LanguageInfo> = self.syntax_set.syntaxes().iter()
+            .filter(|syntax| !syntax.hidden)
+            .map(|syntax| LanguageInfo {
+                name: syntax.name.clone(),
+                extensions: syntax.file_extensions.clone(),
+            })
+            .collect();
+        languages.sort_by_key(|language| language.name.to_lowercase());
+        languages
+    }
+    
+    /// The syntect theme code is currently highlighted with
+    pub fn current_theme(&self) -> &Theme {
+        &self.theme_set.themes[&self.current_theme]
+    }
+    
+    /// Whether the current theme has a dark background
+    pub fn is_dark_theme(&self) -> bool {
+        is_dark(&self.theme_set.themes[&self.current_theme])
+    }
+    
+    /// The colors of the dark theme the current one is read with at night,
+    /// or `None` when the current theme is dark already
+    pub fn night_palette(&self) -> Option<NightPalette> {
+        use syntect::highlighting::{Color, Highlighter};
+        
+        if self.is_dark_theme() {
+            return None;
+        }
+        // Contrast-adjusted copies pair like the theme they were made from
+        let base = self.current_theme.split("-contrast-").next().unwrap_or_default();
+        let night_name = NIGHT_THEMES.iter()
+            .find(|(light, _)| *light == base)
+            .map_or(DEFAULT_NIGHT_THEME, |(_, dark)| dark);
+        let night = self.theme_set.themes.get(night_name)?;
+        let light = self.current_theme();
+        let rgb = |color: Color| (color.r, color.g, color.b);
+        let foreground = night.settings.foreground.map_or((0xc0, 0xc5, 0xce), rgb);
+        let background = night.settings.background.map_or((0x2b, 0x30, 0x3b), rgb);
+        
+        // A light color used for several scopes takes the night color most
+        // of them have, other than plain text or the background itself
+        let light_highlighter = Highlighter::new(light);
+        let night_highlighter = Highlighter::new(night);
+        let stacks = SCOPE_KINDS.iter()
+            .filter_map(|(scope, _)| Scope::new(scope).ok().map(|scope| vec![scope]))
+            .chain(light.scopes.iter()
+                .flat_map(|item| &item.scope.selectors)
+                .map(|selector| selector.path.as_slice().to_vec()));
+        let mut candidates: Vec<(Rgb, Vec<Rgb>)> = Vec::new();
+        for stack in stacks {
+            let color = rgb(light_highlighter.style_for_stack(&stack).foreground);
+            let night_color = rgb(night_highlighter.style_for_stack(&stack).foreground);
+            match candidates.iter_mut().find(|(light, _)| *light == color) {
+                Some((_, night_colors)) => night_colors.push(night_color),
+                None => candidates.push((color, vec![night_color])),
+            }
+        }
+        let mut colors: Vec<_> = light.settings.foreground.map(|color| (rgb(color), foreground)).into_iter().collect();
+        for (color, night_colors) in candidates {
+            if colors.iter().any(|(light, _)| *light == color) {
+                continue;
+            }
+            let votes = |candidate: &Rgb| night_colors.iter().filter(|c| *c == candidate).count();
+            let night_color = night_colors.iter()
+                .filter(|candidate| **candidate != foreground && **candidate != background)
+                .rev()
+                .max_by_key(|candidate| votes(candidate))
+                .copied()
+                .unwrap_or(foreground);
+            colors.push((color, night_color));
+        }
+        Some(NightPalette { background, foreground, colors })
+    }
+}
+
+/// The precompiled syntax dump embedded in the binary
+const SYNTAX_DUMP: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/syntaxes.packdump"));
+
+/// Syntect's default syntaxes (or two-face's extended set with the
+/// `extra-syntaxes` feature) plus the definitions bundled in
+/// `assets/syntaxes` (Solidity), precompiled by the build script.
+/// Deserialized once per process.
+fn shared_syntax_set() -> Arc<SyntaxSet> {
+    static SYNTAXES: OnceLock<Arc<SyntaxSet>> = OnceLock::new();
+    SYNTAXES.get_or_init(|| Arc::new(syntect::dumps::from_binary(SYNTAX_DUMP))).clone()
+}
+
+/// The syntax set built into the binary and what it costs
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedSyntaxes {
+    /// Built with the `extra-syntaxes` feature
+    pub extended: bool,
+    pub syntaxes: usize,
+    /// Size of the embedded dump
+    pub bytes: usize,
+    /// Size of a dump of syntect's default set alone
+    pub default_bytes: usize,
+}
+
+pub fn embedded_syntaxes() -> EmbeddedSyntaxes {
+    let default_bytes = option_env!("SCROLLCAST_DEFAULT_SYNTAX_BYTES")
+        .and_then(|bytes| bytes.parse().ok())
+        .unwrap_or(SYNTAX_DUMP.len());
+    EmbeddedSyntaxes {
+        extended: cfg!(feature = "extra-syntaxes"),
+        syntaxes: shared_syntax_set().syntaxes().len(),
+        bytes: SYNTAX_DUMP.len(),
+        default_bytes,
+    }
+}
+
+/// Syntect's default themes plus those in the user theme directory,
+/// loaded once per process
+fn shared_theme_set() -> Result<Arc<ThemeSet>> {
+    static THEMES: OnceLock<Arc<ThemeSet>> = OnceLock::new();
+    if let Some(themes) = THEMES.get() {
+        return Ok(themes.clone());
+    }
+    let mut themes = ThemeSet::load_defaults();
+    if let Some(theme_dir) = default_theme_dir() {
+        themes.themes.extend(read_theme_dir(&theme_dir)?);
+    }
+    Ok(THEMES.get_or_init(|| Arc::new(themes)).clone())
+}
+
+/// A syntect theme giving each token kind its color from `palette` and its
+/// font style from `font_styles`, named after both so different palettes
+/// don't share cached results
+fn palette_theme(palette: &ColorScheme, font_styles: &BTreeMap<String, AppFontStyle>) -> (String, Theme) {
+    use std::hash::{Hash, Hasher};
+    use syntect::highlighting::{Color, FontStyle, ScopeSelectors, StyleModifier, ThemeItem, ThemeSettings};
+    
+    let color = |hex: &str| parse_hex_color(hex).map(|(r, g, b)| Color { r, g, b, a: 0xFF });
+    let font_style = |kind: &TokenKind| {
+        let style = font_styles.get(kind.style_key()).copied().unwrap_or_default();
+        let mut font_style = FontStyle::empty();
+        font_style.set(FontStyle::BOLD, style.bold);
+        font_style.set(FontStyle::ITALIC, style.italic);
+        font_style.set(FontStyle::UNDERLINE, style.underline);
+        font_style
+    };
+    let scopes = SCOPE_KINDS.iter()
+        .filter_map(|(scope, kind)| Some(ThemeItem {
+            scope: scope.parse::<ScopeSelectors>().ok()?,
+            style: StyleModifier {
+                foreground: color(kind.color(palette)),
+                background: None,
+                font_style: Some(font_style(kind)),
+            },
+        }))
+        .collect();
+    let theme = Theme {
+        settings: ThemeSettings {
+            foreground: color(&palette.text),
+            background: color(&palette.background),
+            ..Default::default()
+        },
+        scopes,
+        ..Default::default()
+    };
+    
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    palette.entries().hash(&mut hasher);
+    font_styles.hash(&mut hasher);
+    (format!("palette-{:016x}", hasher.finish()), theme)
+}
+
+/// Read a `.tmTheme` file, named after its file stem
+fn read_theme(path: &Path) -> Result<(String, Theme)> {
+    // The plist error in the cause chain carries the failure position
+    let theme = ThemeSet::get_theme(path)
+        .with_context(|| format!("Failed to load theme {}", path.display()))?;
+    let name = path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("Theme file has no name: {}", path.display()))?;
+    Ok((name, theme))
+}
+
+/// Read every `.tmTheme` file in `dir`, in name order
+fn read_theme_dir(dir: &Path) -> Result<Vec<(String, Theme)>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tmTheme"))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| read_theme(path)).collect()
+}
+
+/// User directory for custom syntax definitions
+/// (`~/.local/share/scrollcast/syntaxes` on Linux)
+pub fn default_syntax_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("scrollcast").join("syntaxes"))
+}
+
+/// User directory for custom `.tmTheme` color themes
+/// (`~/.local/share/scrollcast/themes` on Linux)
+pub fn default_theme_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("scrollcast").join("themes"))
+}
+
+/// Load every `.sublime-syntax` file in `dir`, warning about and skipping
+/// definitions that fail to parse
+fn load_syntax_definitions(dir: &Path) -> Vec<SyntaxDefinition> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sublime-syntax"))
+        .collect();
+    paths.sort();
+    
+    paths.into_iter()
+        .filter_map(|path| {
+            let fallback_name = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+            let loaded = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    SyntaxDefinition::load_from_str(&text, true, fallback_name.as_deref())
+                        .map_err(|e| e.to_string())
+                });
+            match loaded {
+                Ok(syntax) => Some(syntax),
+                Err(e) => {
+                    eprintln!("Warning: Failed to load syntax definition {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Pandoc style names and the syntect themes used in their place
+const PANDOC_THEME_ALIASES: [(&str, &str); 8] = [
+    ("pygments", "base16-ocean.light"),
+    ("kate", "InspiredGitHub"),
+    ("monochrome", "base16-ocean.light"),
+    ("breezedark", "base16-ocean.dark"),
+    ("espresso", "base16-mocha.dark"),
+    ("zenburn", "base16-eighties.dark"),
+    ("haddock", "InspiredGitHub"),
+    ("tango", "InspiredGitHub"),
+];
+
+/// Map Pandoc and built-in app theme names to syntect theme names, passing
+/// unknown names through
+pub fn resolve_theme_name(theme_name: &str) -> &str {
+    PANDOC_THEME_ALIASES.iter()
+        .find(|(alias, _)| *alias == theme_name)
+        .map(|(_, theme)| *theme)
+        .or_else(|| syntax_theme_for(theme_name))
+        .unwrap_or(theme_name)
+}
+
+/// Every Pandoc style name `--theme` accepts
+pub fn pandoc_theme_names() -> Vec<&'static str> {
+    PANDOC_THEME_ALIASES.iter().map(|(alias, _)| *alias).collect()
+}
+
+/// Pandoc style names that resolve to the syntect theme `theme`
+pub fn pandoc_aliases(theme: &str) -> Vec<&'static str> {
+    PANDOC_THEME_ALIASES.iter()
+        .filter(|(_, target)| *target == theme)
+        .map(|(alias, _)| *alias)
+        .collect()
+}
+
+/// Whether a theme's background is dark (relative luminance below 0.5)
+fn is_dark(theme: &Theme) -> bool {
+    theme.settings.background.is_some_and(is_dark_color)
+}
+
+/// Whether `code` has a line too long to be worth highlighting
+fn has_long_line(code: &str) -> bool {
+    code.lines().any(|line| line.len() > LONG_LINE_LIMIT)
+}
+
+/// Append `text` as a span colored and styled like `span`
+fn push_inline_span(html: &mut String, span: &StyledSpan, text: &str) {
+    html.push_str(&format!(
+        "<span style=\"color:{};{}{}{}\">{}</span>",
+        span.fg_hex(),
+        if span.bold { "font-weight:bold;" } else { "" },
+        if span.italic { "font-style:italic;" } else { "" },
+        if span.underline { "text-decoration:underline;" } else { "" },
+        escape_html(text)
+    ));
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 /// Helper function to determine if syntax highlighting should be applied
 pub fn should_highlight(language: Option<&str>) -> bool {
     language.is_some() && language != Some("text") && language != Some("plain")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    const FAKE_SYNTAX: &str = r#"%YAML 1.2
+---
+name: FakeLang
+file_extensions: [fk]
+scope: source.fake
+contexts:
+  main:
+    - match: '\b(frob|twiddle)\b'
+      scope: keyword.control.fake
+    - match: '"[^"]*"'
+      scope: string.quoted.double.fake
+"#;
+
+    #[test]
+    fn test_bundled_solidity_syntax() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        assert_eq!(highlighter.find_syntax("solidity").map(|s| s.name.as_str()), Some("Solidity"));
+        assert_eq!(highlighter.find_syntax("sol").map(|s| s.name.as_str()), Some("Solidity"));
+
+        let source = include_str!("../../testfiles/test_project/Token.sol");
+        let html = highlighter.highlight_to_html(source, Some("solidity"));
+        assert!(html.contains(r#"<span class="storage type contract solidity">contract</span>"#), "{}", html);
+        assert!(html.contains(r#"<span class="keyword control solidity">return</span>"#));
+        assert!(html.contains(r#"<span class="string quoted double solidity">"#));
+        assert!(html.contains(r#"<span class="storage modifier solidity">public</span>"#));
+    }
+
+    const CORPORATE_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Corporate</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#102030</string>
+                <key>foreground</key>
+                <string>#E0E4E8</string>
+            </dict>
+        </dict>
+        <dict>
+            <key>scope</key>
+            <string>keyword</string>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#FFAA00</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn test_custom_theme_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corporate.tmTheme");
+        fs::write(&path, CORPORATE_THEME).unwrap();
+
+        let mut highlighter = SyntaxHighlighter::new().unwrap();
+        assert_eq!(highlighter.load_theme_file(&path).unwrap(), "corporate");
+        highlighter.set_theme("corporate").unwrap();
+        assert_eq!(highlighter.get_theme_background(), Some((0x10, 0x20, 0x30, 0xff)));
+        assert!(highlighter.is_dark_theme());
+
+        let broken = dir.path().join("broken.tmTheme");
+        fs::write(&broken, "<plist><dict><key>name</key></plist>").unwrap();
+        let error = format!("{:#}", highlighter.load_theme_file(&broken).unwrap_err());
+        assert!(error.contains("broken.tmTheme"), "{}", error);
+        assert!(error.contains("offset"), "{}", error);
+    }
+
+    #[test]
+    fn test_custom_syntax_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("fake.sublime-syntax"), FAKE_SYNTAX).unwrap();
+        fs::write(dir.path().join("broken.sublime-syntax"), "name: [unclosed").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a syntax").unwrap();
+
+        let highlighter = SyntaxHighlighter::with_syntax_dir(Some(dir.path())).unwrap();
+        assert_eq!(highlighter.find_syntax("fk").map(|s| s.name.as_str()), Some("FakeLang"));
+        assert_eq!(highlighter.find_syntax("fakelang").map(|s| s.name.as_str()), Some("FakeLang"));
+        // The defaults are still there
+        assert!(highlighter.find_syntax("rust").is_some());
+
+        let html = highlighter.highlight_to_html("frob \"x\"\n", Some("fk"));
+        assert!(html.contains(r#"<span class="keyword control fake">frob</span>"#), "{}", html);
+        assert!(html.contains("string quoted double fake"), "{}", html);
+    }
+
+    #[test]
+    fn test_identical_blocks_hit_the_cache() {
+        use crate::syntax::cache::shared_cache_stats;
+
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        // Unique so other tests sharing the cache can't have stored it
+        let code = "fn cached_block_7f3a() -> u32 { 42 }\n";
+        let first = highlighter.highlight_to_inline_html(code, Some("rust"));
+        let hits = shared_cache_stats().hits;
+        let second = highlighter.highlight_to_inline_html(code, Some("rust"));
+        assert_eq!(first, second);
+        assert!(shared_cache_stats().hits > hits);
+
+        let mut uncached = SyntaxHighlighter::new().unwrap();
+        uncached.set_cache_enabled(false);
+        assert_eq!(uncached.highlight_to_inline_html(code, Some("rust")), first);
+    }
+
+    #[test]
+    fn test_highlighters_share_loaded_sets() {
+        let first = SyntaxHighlighter::new().unwrap();
+        let mut second = SyntaxHighlighter::new().unwrap();
+        assert!(Arc::ptr_eq(&first.syntax_set, &second.syntax_set));
+        assert!(Arc::ptr_eq(&first.theme_set, &second.theme_set));
+
+        // Loading a theme file copies the themes rather than changing the shared ones
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shared-copy.tmTheme");
+        fs::write(&path, CORPORATE_THEME).unwrap();
+        second.load_theme_file(&path).unwrap();
+        assert!(!Arc::ptr_eq(&first.theme_set, &second.theme_set));
+        assert!(!first.theme_names().contains(&"shared-copy"));
+    }
+
+    #[test]
+    fn test_spans_cover_line_exactly() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let line = "    let total: Sum = add(1, 2); // sum \"x\"\n";
+        let lines = highlighter.highlight_spans(line, Some("rust"));
+        assert_eq!(lines.len(), 1);
+
+        let spans = &lines[0];
+        let joined: String = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(joined, line);
+
+        let kind_of = |text: &str| spans.iter().find(|span| span.text.trim() == text).map(|span| span.token_kind);
+        assert_eq!(kind_of("let"), Some(TokenKind::Keyword));
+        assert_eq!(kind_of("add"), Some(TokenKind::Function));
+        assert!(spans.iter().any(|span| span.token_kind == TokenKind::Comment && span.text.contains("sum \"x\"")));
+        assert!(spans.iter().any(|span| span.token_kind == TokenKind::Number && span.text == "1"));
+    }
+
+    #[test]
+    fn test_spans_keep_state_across_lines() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let code = "/* open\nstill comment */ fn f() {}\n";
+        let lines = highlighter.highlight_spans(code, Some("rust"));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1][0].token_kind, TokenKind::Comment);
+        assert!(lines[1][0].text.starts_with("still comment"), "{:?}", lines[1]);
+        for (spans, line) in lines.iter().zip(LinesWithEndings::from(code)) {
+            assert_eq!(spans.iter().map(|span| span.text.as_str()).collect::<String>(), line);
+        }
+    }
+
+    #[test]
+    fn test_timeout_leaves_rest_as_plain_text() {
+        let mut highlighter = SyntaxHighlighter::new().unwrap();
+        highlighter.set_timeout(Duration::ZERO);
+        let code = "fn timed_out() -> Vec<u8> {\n    a && b\n}\n";
+
+        let classed = highlighter.highlight_to_html(code, Some("rust"));
+        assert_eq!(classed, "fn timed_out() -&gt; Vec&lt;u8&gt; {\n    a &amp;&amp; b\n}\n");
+
+        let inline = highlighter.highlight_to_inline_html(code, Some("rust"));
+        assert!(inline.starts_with("<pre style="), "{}", inline);
+        let tags = Regex::new("<[^>]*>").unwrap();
+        assert_eq!(tags.replace_all(&inline, ""), "\nfn timed_out() -&gt; Vec&lt;u8&gt; {\n    a &amp;&amp; b\n}\n\n");
+
+        let lines = highlighter.highlight_lines(code, Some("rust"));
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|ranges| ranges.len() == 1));
+
+        // Cut-short results aren't cached, so a normal budget highlights fully
+        highlighter.set_timeout(DEFAULT_HIGHLIGHT_TIMEOUT);
+        assert!(highlighter.highlight_to_html(code, Some("rust")).contains("<span"));
+    }
+
+    #[test]
+    fn test_long_lines_are_not_highlighted() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let code = format!("let generated = [{}];\n", "1, ".repeat(LONG_LINE_LIMIT / 3));
+        let html = highlighter.highlight_to_html(&code, Some("rust"));
+        assert_eq!(html, code);
+        assert!(highlighter.highlight_to_html("let short = 1;\n", Some("rust")).contains("<span"));
+    }
+
+    #[test]
+    fn test_modern_languages_resolve() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let with_grammar = [
+            "zig", "nim", "elixir", "erlang", "haskell", "ocaml", "svelte", "vue", "protobuf",
+            "terraform", "cue", "dart", "gradle", "batch", "ini", "properties",
+        ];
+        for language in with_grammar {
+            let syntax = highlighter.find_syntax(language).map(|s| s.name.as_str());
+            assert!(syntax.is_some_and(|name| name != "Plain Text"), "{}: {:?}", language, syntax);
+        }
+        assert_eq!(highlighter.fallback_for("haskell"), None);
+        assert_eq!(highlighter.fallback_for("rust"), None);
+
+        // The extended set has real grammars for these
+        if cfg!(feature = "extra-syntaxes") {
+            return;
+        }
+        for language in ["graphql", "nix", "cmake", "powershell", "csv"] {
+            assert_eq!(highlighter.find_syntax(language).map(|s| s.name.as_str()), Some("Plain Text"));
+        }
+        assert_eq!(highlighter.fallback_for("vue"), Some("HTML"));
+        assert_eq!(highlighter.fallback_for("nix"), Some("Plain Text"));
+    }
+
+    #[cfg(feature = "extra-syntaxes")]
+    #[test]
+    fn test_extended_syntaxes_replace_fallbacks() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let syntax = |language: &str| highlighter.find_syntax(language).map(|s| s.name.clone());
+        assert_eq!(syntax("tsx").as_deref(), Some("TypeScriptReact"));
+        assert_eq!(syntax("typescript").as_deref(), Some("TypeScript"));
+        assert_eq!(syntax("toml").as_deref(), Some("TOML"));
+        assert_eq!(syntax("dockerfile").as_deref(), Some("Dockerfile"));
+        assert_eq!(syntax("zig").as_deref(), Some("Zig"));
+        assert_eq!(highlighter.fallback_for("zig"), None);
+        assert_eq!(syntax("solidity").as_deref(), Some("Solidity"));
+
+        let embedded = embedded_syntaxes();
+        assert!(embedded.extended);
+        assert!(embedded.bytes > embedded.default_bytes);
+    }
+}
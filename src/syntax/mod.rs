@@ -1 +1,9 @@
-pub mod highlighter;
\ No newline at end of file
+pub mod cache;
+pub mod catalog;
+pub mod highlighter;
+pub mod policy;
+pub mod preview;
+pub mod tokens;
+
+#[allow(unused_imports)]
+pub use catalog::{available_languages, available_themes, LanguageInfo, ThemeInfo};
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use crate::syntax::highlighter::should_highlight;
+
+/// Highlight nothing larger than this by default
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+/// ... or longer than this many lines
+pub const DEFAULT_MAX_LINES: usize = 20_000;
+
+/// Decides which code blocks are worth highlighting. Very large blocks,
+/// typically generated data, are left as plain text: highlighting them
+/// dominates render time for little benefit.
+#[derive(Debug, Clone)]
+pub struct HighlightPolicy {
+    pub max_bytes: usize,
+    pub max_lines: usize,
+    /// Lower size limits for particular languages
+    pub language_max_bytes: HashMap<String, usize>,
+}
+
+impl Default for HighlightPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_lines: DEFAULT_MAX_LINES,
+            language_max_bytes: HashMap::from([("json".to_string(), 100 * 1024)]),
+        }
+    }
+}
+
+impl HighlightPolicy {
+    /// Whether a block of `code` in `language` should be highlighted
+    pub fn should_highlight(&self, language: Option<&str>, code: &str) -> bool {
+        should_highlight(language) && self.skip_reason(language, code).is_none()
+    }
+
+    /// Why a block is too large to highlight, if it is
+    pub fn skip_reason(&self, language: Option<&str>, code: &str) -> Option<String> {
+        let language_limit = language.and_then(|lang| self.language_max_bytes.get(lang).copied());
+        if let Some(limit) = language_limit.filter(|limit| code.len() > *limit) {
+            return Some(format!("{} code over {}", language.unwrap_or_default(), size_label(limit)));
+        }
+        if code.len() > self.max_bytes {
+            return Some(format!("over {}", size_label(self.max_bytes)));
+        }
+        let lines = code.lines().count();
+        if lines > self.max_lines {
+            return Some(format!("{} lines (limit {})", lines, self.max_lines));
+        }
+        None
+    }
+}
+
+fn size_label(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 && b % (1024 * 1024) == 0 => format!("{} MB", b / (1024 * 1024)),
+        b if b >= 1024 => format!("{} KB", b / 1024),
+        b => format!("{} bytes", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_by_size_lines_and_language() {
+        let policy = HighlightPolicy::default();
+        assert!(policy.should_highlight(Some("rust"), "fn main() {}\n"));
+        assert!(!policy.should_highlight(None, "fn main() {}\n"));
+        assert!(!policy.should_highlight(Some("text"), "notes\n"));
+
+        let json = format!("[{}]", "1,".repeat(60 * 1024));
+        assert_eq!(policy.skip_reason(Some("json"), &json).as_deref(), Some("json code over 100 KB"));
+        assert!(policy.should_highlight(Some("javascript"), &json));
+
+        let many_lines = "x\n".repeat(DEFAULT_MAX_LINES + 1);
+        assert_eq!(policy.skip_reason(Some("python"), &many_lines), Some(format!("{} lines (limit 20000)", DEFAULT_MAX_LINES + 1)));
+
+        let small = HighlightPolicy { max_bytes: 10, ..Default::default() };
+        assert_eq!(small.skip_reason(Some("rust"), "fn main() {}\n").as_deref(), Some("over 10 bytes"));
+        assert_eq!(policy.skip_reason(Some("rust"), &"x".repeat(DEFAULT_MAX_BYTES + 1)).as_deref(), Some("over 1 MB"));
+    }
+}
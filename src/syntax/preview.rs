@@ -0,0 +1,52 @@
+use crate::markdown_generator::MarkdownGenerator;
+use crate::syntax::highlighter::SyntaxHighlighter;
+
+/// Show how a file will be classified and highlighted: a banner naming the
+/// detected language, syntax and theme, then the file as 24-bit ANSI text,
+/// or as the highlighted HTML block the HTML renderer would embed
+pub fn preview(highlighter: &SyntaxHighlighter, path: &str, content: &str, html: bool) -> String {
+    let language = MarkdownGenerator::detect_language_with_content(path, content);
+    let syntax = language.as_deref()
+        .and_then(|lang| highlighter.find_syntax(lang))
+        .map_or("Plain Text", |syntax| syntax.name.as_str());
+    let banner = format!(
+        "{} · language: {} ({}) · theme: {}",
+        path,
+        language.as_deref().unwrap_or("none"),
+        syntax,
+        highlighter.theme_name()
+    );
+
+    if html {
+        let block = highlighter.highlight_to_inline_html(content, language.as_deref());
+        format!("<!-- {} -->\n{}", banner.replace("--", "- -"), block)
+    } else {
+        format!("── {} ──\n{}\n", banner, highlighter.highlight_to_ansi(content, language.as_deref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_preview_has_banner_and_colors() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let output = preview(&highlighter, "src/lib.rs", "fn main() {}\n", false);
+        let banner = output.lines().next().unwrap();
+        assert_eq!(banner, "── src/lib.rs · language: rust (Rust) · theme: InspiredGitHub ──");
+        assert!(output.contains("\x1b[38;2;"), "{:?}", output);
+        assert!(output.contains("main"));
+        assert!(output.trim_end().ends_with("\x1b[0m"));
+    }
+
+    #[test]
+    fn test_html_preview_is_inline_styled_block() {
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let output = preview(&highlighter, "run", "#!/usr/bin/env python3\nprint(1)\n", true);
+        assert!(output.starts_with("<!-- run · language: python (Python) · theme: InspiredGitHub -->\n"));
+        assert!(output.contains("<pre style=\"background-color:#ffffff;\">"), "{}", output);
+        assert!(output.contains("<span style=\"color:#"), "{}", output);
+        assert!(!output.contains('\x1b'));
+    }
+}
@@ -0,0 +1,117 @@
+use syntect::parsing::ScopeStack;
+use crate::theme::ColorScheme;
+
+/// Broad token categories, matching the colors of a `ColorScheme`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Function,
+    Type,
+    Operator,
+    Text,
+}
+
+/// Scope prefixes and the token kind they imply, most specific first
+pub(crate) const SCOPE_KINDS: [(&str, TokenKind); 14] = [
+    ("comment", TokenKind::Comment),
+    ("string", TokenKind::String),
+    ("constant.character", TokenKind::String),
+    ("constant.numeric", TokenKind::Number),
+    ("constant.language", TokenKind::Keyword),
+    ("keyword.operator", TokenKind::Operator),
+    ("keyword", TokenKind::Keyword),
+    ("storage.modifier", TokenKind::Keyword),
+    ("storage.type", TokenKind::Keyword),
+    ("entity.name.function", TokenKind::Function),
+    ("support.function", TokenKind::Function),
+    ("variable.function", TokenKind::Function),
+    ("entity.name", TokenKind::Type),
+    ("support.type", TokenKind::Type),
+];
+
+impl TokenKind {
+    /// Classify text by the innermost scope on `stack` that names a category
+    pub fn from_scopes(stack: &ScopeStack) -> Self {
+        stack.as_slice().iter().rev()
+            .find_map(|scope| {
+                let name = scope.build_string();
+                SCOPE_KINDS.iter()
+                    .find(|(prefix, _)| {
+                        name.strip_prefix(prefix)
+                            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                    })
+                    .map(|(_, kind)| *kind)
+            })
+            .unwrap_or(TokenKind::Text)
+    }
+
+    /// The color for this kind of token in `colors`
+    pub fn color<'a>(&self, colors: &'a ColorScheme) -> &'a str {
+        match self {
+            TokenKind::Keyword => &colors.keywords,
+            TokenKind::String => &colors.strings,
+            TokenKind::Comment => &colors.comments,
+            TokenKind::Number => &colors.numbers,
+            TokenKind::Function => &colors.functions,
+            TokenKind::Type => &colors.types,
+            TokenKind::Operator => &colors.operators,
+            TokenKind::Text => &colors.text,
+        }
+    }
+
+    /// The key for this kind of token in a theme's font styles
+    pub fn style_key(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "keywords",
+            TokenKind::String => "strings",
+            TokenKind::Comment => "comments",
+            TokenKind::Number => "numbers",
+            TokenKind::Function => "functions",
+            TokenKind::Type => "types",
+            TokenKind::Operator => "operators",
+            TokenKind::Text => "text",
+        }
+    }
+}
+
+/// A run of source text with its theme style and token kind, the common
+/// form every renderer draws highlighted code from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub fg: (u8, u8, u8),
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub token_kind: TokenKind,
+}
+
+impl StyledSpan {
+    /// Foreground as a `#rrggbb` color
+    pub fn fg_hex(&self) -> String {
+        let (r, g, b) = self.fg;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    fn same_style(&self, other: &StyledSpan) -> bool {
+        self.fg == other.fg
+            && self.bold == other.bold
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.token_kind == other.token_kind
+    }
+}
+
+/// Append `span` to `spans`, extending the last span if it has the same style
+pub(crate) fn push_span(spans: &mut Vec<StyledSpan>, span: StyledSpan) {
+    if span.text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.same_style(&span) => last.text.push_str(&span.text),
+        _ => spans.push(span),
+    }
+}
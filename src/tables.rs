@@ -0,0 +1,118 @@
+//! CSV and TSV files shown as tables instead of code. Only files small
+//! enough to read as a table qualify; anything else keeps its code block,
+//! with a `Fallback` saying why.
+
+use std::fmt;
+
+/// Which delimited files become tables, and how wide their cells get
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableLimits {
+    pub max_bytes: usize,
+    /// Rows after the header
+    pub max_rows: usize,
+    /// Longer cells are cut short, ending in `…`
+    pub max_cell_chars: usize,
+}
+
+impl Default for TableLimits {
+    fn default() -> Self {
+        Self { max_bytes: 100 * 1024, max_rows: 200, max_cell_chars: 60 }
+    }
+}
+
+/// Why a delimited file stays a code block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+    TooLarge { bytes: usize, limit: usize },
+    TooManyRows { limit: usize },
+    Empty,
+    /// The parser's complaint, such as rows of different lengths
+    Unparsable(String),
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fallback::TooLarge { bytes, limit } => write!(f, "{} bytes is over the {} byte limit", bytes, limit),
+            Fallback::TooManyRows { limit } => write!(f, "more than {} rows", limit),
+            Fallback::Empty => f.write_str("no rows"),
+            Fallback::Unparsable(error) => write!(f, "couldn't parse it: {}", error),
+        }
+    }
+}
+
+/// The delimiter of `path` going by its extension: commas for `.csv`, tabs
+/// for `.tsv`, and `None` for other files
+pub fn delimiter(path: &str) -> Option<u8> {
+    let extension = std::path::Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "csv" => Some(b','),
+        "tsv" => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// The rows of `content`, the header first, with every cell at most
+/// `max_cell_chars` long. Quoted cells may hold delimiters, quotes and line
+/// breaks.
+pub fn parse(content: &str, delimiter: u8, limits: &TableLimits) -> Result<Vec<Vec<String>>, Fallback> {
+    if content.len() > limits.max_bytes {
+        return Err(Fallback::TooLarge { bytes: content.len(), limit: limits.max_bytes });
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .from_reader(content.as_bytes());
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|error| Fallback::Unparsable(error.to_string()))?;
+        if rows.len() > limits.max_rows {
+            return Err(Fallback::TooManyRows { limit: limits.max_rows });
+        }
+        rows.push(record.iter().map(|cell| shorten(cell, limits.max_cell_chars)).collect());
+    }
+    if rows.is_empty() {
+        return Err(Fallback::Empty);
+    }
+    Ok(rows)
+}
+
+/// `cell` on one line, cut to `max_chars` characters with `…` at the end
+fn shorten(cell: &str, max_chars: usize) -> String {
+    let cell = cell.replace("\r\n", "\n");
+    if cell.chars().count() <= max_chars {
+        return cell;
+    }
+    let mut short: String = cell.chars().take(max_chars.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quoted_cells() {
+        let content = "name,notes\n\"Smith, Jane\",\"said \"\"hi\"\"\nthen left\"\nplain,a|b\n";
+        let rows = parse(content, b',', &TableLimits::default()).unwrap();
+        assert_eq!(rows, vec![
+            vec!["name".to_string(), "notes".to_string()],
+            vec!["Smith, Jane".to_string(), "said \"hi\"\nthen left".to_string()],
+            vec!["plain".to_string(), "a|b".to_string()],
+        ]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = TableLimits { max_bytes: 1000, max_rows: 2, max_cell_chars: 5 };
+        assert_eq!(parse("h\n1\n2\n", b',', &limits).unwrap().len(), 3);
+        assert_eq!(parse("h\n1\n2\n3\n", b',', &limits), Err(Fallback::TooManyRows { limit: 2 }));
+        assert_eq!(parse(&"x".repeat(1001), b',', &limits), Err(Fallback::TooLarge { bytes: 1001, limit: 1000 }));
+        assert_eq!(parse("a\tbcdefgh\n", b'\t', &limits).unwrap(), vec![vec!["a".to_string(), "bcde…".to_string()]]);
+        assert!(matches!(parse("a,b\n1\n", b',', &limits), Err(Fallback::Unparsable(_))));
+        assert_eq!(parse("", b',', &limits), Err(Fallback::Empty));
+        assert_eq!(delimiter("data/Matrix.TSV"), Some(b'\t'));
+        assert_eq!(delimiter("main.rs"), None);
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Intermediate files of one run, such as the generated markdown. They
+/// hold the repository's source, so they're removed when this is dropped,
+/// on error paths too, unless kept for debugging with `--keep-temp`.
+#[derive(Debug, Default)]
+pub struct TempFiles {
+    keep: bool,
+    paths: Vec<PathBuf>,
+}
+
+impl TempFiles {
+    pub fn new(keep: bool) -> Self {
+        Self { keep, paths: Vec::new() }
+    }
+
+    /// Create an empty file in the system temp directory, named `prefix`,
+    /// a random part and `suffix`, so concurrent runs never share one
+    pub fn create(&mut self, prefix: &str, suffix: &str) -> Result<PathBuf> {
+        let (_, path) = tempfile::Builder::new()
+            .prefix(prefix)
+            .suffix(suffix)
+            .tempfile()
+            .and_then(|file| file.keep().map_err(|error| error.error))
+            .context("Failed to create a temporary file")?;
+        self.paths.push(path.clone());
+        Ok(path)
+    }
+
+    /// Create an empty directory in the system temp directory, named like
+    /// `create`'s files, removed along with everything put in it
+    pub fn create_dir(&mut self, prefix: &str) -> Result<PathBuf> {
+        let path = tempfile::Builder::new()
+            .prefix(prefix)
+            .tempdir()
+            .map(tempfile::TempDir::keep)
+            .context("Failed to create a temporary directory")?;
+        self.paths.push(path.clone());
+        Ok(path)
+    }
+
+    /// The files left behind for `--keep-temp`; empty otherwise
+    pub fn kept(&self) -> &[PathBuf] {
+        if self.keep { &self.paths } else { &[] }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.paths.iter().map(PathBuf::as_path)
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        if !self.keep {
+            for path in &self.paths {
+                let _ = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_go_away_unless_kept() -> Result<()> {
+        let mut temp = TempFiles::new(false);
+        let first = temp.create("scrollcast-repo-", ".md")?;
+        let second = temp.create("scrollcast-repo-", ".md")?;
+        assert_ne!(first, second);
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("scrollcast-repo-"));
+        assert!(temp.kept().is_empty());
+        drop(temp);
+        assert!(!first.exists() && !second.exists());
+
+        let mut temp = TempFiles::new(true);
+        let kept = temp.create("scrollcast-repo-", ".md")?;
+        assert_eq!(temp.kept(), std::slice::from_ref(&kept));
+        drop(temp);
+        assert!(kept.exists());
+        fs::remove_file(kept)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_directories_go_away_with_their_contents() -> Result<()> {
+        let mut temp = TempFiles::new(false);
+        let dir = temp.create_dir("scrollcast-snapshot-")?;
+        fs::create_dir(dir.join("src"))?;
+        fs::write(dir.join("src/lib.rs"), "pub fn lib() {}\n")?;
+        drop(temp);
+        assert!(!dir.exists());
+        Ok(())
+    }
+}
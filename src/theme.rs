@@ -1,5 +1,8 @@
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use syntect::highlighting::{Color, Highlighter, ScopeSelectors};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ThemeMode {
@@ -13,7 +16,7 @@ impl Default for ThemeMode {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ColorScheme {
     pub background: String,
     pub text: String,
@@ -28,16 +31,210 @@ pub struct ColorScheme {
     pub header: String,
 }
 
+impl ColorScheme {
+    /// Every color with its configuration key
+    pub fn entries(&self) -> [(&'static str, &str); 11] {
+        [
+            ("background", &self.background),
+            ("text", &self.text),
+            ("keywords", &self.keywords),
+            ("strings", &self.strings),
+            ("comments", &self.comments),
+            ("numbers", &self.numbers),
+            ("functions", &self.functions),
+            ("types", &self.types),
+            ("operators", &self.operators),
+            ("line_numbers", &self.line_numbers),
+            ("header", &self.header),
+        ]
+    }
+
+    /// Every color that is not a `#rgb` or `#rrggbb` hex value
+    pub fn invalid_colors(&self) -> Vec<ThemeError> {
+        self.entries().into_iter()
+            .filter(|(_, value)| parse_hex_color(value).is_none())
+            .map(|(field, value)| ThemeError { field, value: value.to_string() })
+            .collect()
+    }
+
+    /// Check that every color is a hex value, naming each one that isn't
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.invalid_colors();
+        if !errors.is_empty() {
+            bail!("{}", ThemeError::list(&errors));
+        }
+        Ok(())
+    }
+}
+
+/// Token kinds that can be given a font style, named like their colors
+pub const TOKEN_STYLE_KEYS: [&str; 8] = [
+    "keywords", "strings", "comments", "numbers", "functions", "types", "operators", "text",
+];
+
+/// Font style of one kind of token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl From<syntect::highlighting::FontStyle> for FontStyle {
+    fn from(style: syntect::highlighting::FontStyle) -> Self {
+        use syntect::highlighting::FontStyle as Syntect;
+        FontStyle {
+            bold: style.contains(Syntect::BOLD),
+            italic: style.contains(Syntect::ITALIC),
+            underline: style.contains(Syntect::UNDERLINE),
+        }
+    }
+}
+
+/// Color and font style of one kind of token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenStyle {
+    pub color: (f32, f32, f32),
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+/// A theme color that is not a valid hex value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeError {
+    /// The `ColorScheme` field, e.g. "keywords"
+    pub field: &'static str,
+    pub value: String,
+}
+
+impl ThemeError {
+    /// All errors, one per line
+    pub fn list(errors: &[ThemeError]) -> String {
+        errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid color for {}: {:?} (expected #rgb or #rrggbb)", self.field, self.value)
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+/// Parse a `#rrggbb` or shorthand `#rgb` color into its channels
+pub fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// WCAG contrast ratio between two colors, from 1 (none) to 21 (black on white)
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    let luminance = |(r, g, b): (u8, u8, u8)| {
+        let linear = |channel: u8| {
+            let c = channel as f32 / 255.0;
+            if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+    };
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Contrast below which text is hard to read on the background
+const MIN_TEXT_CONTRAST: f32 = 3.0;
+
+/// Whether a color is dark (relative luminance below 0.5)
+pub fn is_dark_color(color: Color) -> bool {
+    let luminance = (0.2126 * color.r as f32 + 0.7152 * color.g as f32 + 0.0722 * color.b as f32) / 255.0;
+    luminance < 0.5
+}
+
+/// Name, constructor and closest syntect theme of a built-in theme
+type BuiltinTheme = (&'static str, fn() -> Theme, Option<&'static str>);
+
+/// Built-in themes by name, with the syntect theme closest to each. A
+/// built-in theme colors PDF output; HTML and EPUB code is highlighted with
+/// the syntect theme, or with the theme's own colors if none is close.
+const BUILTIN_THEMES: [BuiltinTheme; 8] = [
+    ("light", Theme::light, Some("InspiredGitHub")),
+    ("dark", Theme::dark, Some("base16-ocean.dark")),
+    ("solarized-light", Theme::solarized_light, Some("Solarized (light)")),
+    ("solarized-dark", Theme::solarized_dark, Some("Solarized (dark)")),
+    ("dracula", Theme::dracula, Some("base16-eighties.dark")),
+    ("nord", Theme::nord, Some("base16-ocean.dark")),
+    ("gruvbox-dark", Theme::gruvbox_dark, Some("base16-mocha.dark")),
+    ("high-contrast", Theme::high_contrast, None),
+];
+
+/// WCAG AA contrast for normal text, enforced by `--enforce-contrast`
+pub const MIN_CODE_CONTRAST: f32 = 4.5;
+
+/// Names of the built-in themes
+pub fn builtin_theme_names() -> Vec<&'static str> {
+    BUILTIN_THEMES.iter().map(|(name, _, _)| *name).collect()
+}
+
+/// The syntect theme used for code when the built-in theme `name` is selected
+pub fn syntax_theme_for(name: &str) -> Option<&'static str> {
+    BUILTIN_THEMES.iter()
+        .find(|(theme, _, _)| *theme == name)
+        .and_then(|(_, _, syntax_theme)| *syntax_theme)
+}
+
+/// The built-in theme `name` if it has no close syntect theme, so every
+/// format highlights with its own colors
+pub fn palette_builtin(name: &str) -> Option<Theme> {
+    BUILTIN_THEMES.iter()
+        .find(|(theme, _, syntax_theme)| *theme == name && syntax_theme.is_none())
+        .map(|(_, theme, _)| theme())
+}
+
+/// `color` darkened or lightened just enough to reach `min_ratio` contrast
+/// against `background`, moving toward whichever of black and white
+/// contrasts more with the background
+pub fn with_min_contrast(color: (u8, u8, u8), background: (u8, u8, u8), min_ratio: f32) -> (u8, u8, u8) {
+    if contrast_ratio(color, background) >= min_ratio {
+        return color;
+    }
+    let target = if contrast_ratio((0, 0, 0), background) >= contrast_ratio((255, 255, 255), background) {
+        0.0
+    } else {
+        255.0
+    };
+    let mix = |amount: f32| {
+        let channel = |c: u8| (c as f32 + (target - c as f32) * amount).round() as u8;
+        (channel(color.0), channel(color.1), channel(color.2))
+    };
+    (1..=20)
+        .map(|step| mix(step as f32 / 20.0))
+        .find(|candidate| contrast_ratio(*candidate, background) >= min_ratio)
+        .unwrap_or_else(|| mix(1.0))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Theme {
     pub mode: ThemeMode,
     pub colors: ColorScheme,
     pub font_size: f32,
     pub line_height: f32,
+    /// Font styles by token kind, keyed like the token colors ("comments")
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub font_styles: BTreeMap<String, FontStyle>,
 }
 
 impl Theme {
-    #[allow(dead_code)]
     pub fn light() -> Self {
         Theme {
             mode: ThemeMode::Light,
@@ -56,10 +253,10 @@ impl Theme {
             },
             font_size: 10.0,
             line_height: 1.2,
+            font_styles: BTreeMap::new(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn dark() -> Self {
         Theme {
             mode: ThemeMode::Dark,
@@ -78,10 +275,154 @@ impl Theme {
             },
             font_size: 10.0,
             line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// Ethan Schoonover's Solarized on its light base (base3)
+    pub fn solarized_light() -> Self {
+        Theme {
+            mode: ThemeMode::Light,
+            colors: ColorScheme {
+                background: "#fdf6e3".to_string(),
+                text: "#657b83".to_string(),
+                keywords: "#859900".to_string(),
+                strings: "#2aa198".to_string(),
+                comments: "#93a1a1".to_string(),
+                numbers: "#d33682".to_string(),
+                functions: "#268bd2".to_string(),
+                types: "#b58900".to_string(),
+                operators: "#cb4b16".to_string(),
+                line_numbers: "#93a1a1".to_string(),
+                header: "#586e75".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// Solarized on its dark base (base03)
+    pub fn solarized_dark() -> Self {
+        Theme {
+            mode: ThemeMode::Dark,
+            colors: ColorScheme {
+                background: "#002b36".to_string(),
+                text: "#839496".to_string(),
+                keywords: "#859900".to_string(),
+                strings: "#2aa198".to_string(),
+                comments: "#586e75".to_string(),
+                numbers: "#d33682".to_string(),
+                functions: "#268bd2".to_string(),
+                types: "#b58900".to_string(),
+                operators: "#cb4b16".to_string(),
+                line_numbers: "#586e75".to_string(),
+                header: "#93a1a1".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// The Dracula palette
+    pub fn dracula() -> Self {
+        Theme {
+            mode: ThemeMode::Dark,
+            colors: ColorScheme {
+                background: "#282a36".to_string(),
+                text: "#f8f8f2".to_string(),
+                keywords: "#ff79c6".to_string(),
+                strings: "#f1fa8c".to_string(),
+                comments: "#6272a4".to_string(),
+                numbers: "#bd93f9".to_string(),
+                functions: "#50fa7b".to_string(),
+                types: "#8be9fd".to_string(),
+                operators: "#ff79c6".to_string(),
+                line_numbers: "#6272a4".to_string(),
+                header: "#bd93f9".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// Arctic Ice Studio's Nord: Polar Night background, Snow Storm text, Frost and Aurora accents
+    pub fn nord() -> Self {
+        Theme {
+            mode: ThemeMode::Dark,
+            colors: ColorScheme {
+                background: "#2e3440".to_string(),
+                text: "#d8dee9".to_string(),
+                keywords: "#81a1c1".to_string(),
+                strings: "#a3be8c".to_string(),
+                comments: "#616e88".to_string(),
+                numbers: "#b48ead".to_string(),
+                functions: "#88c0d0".to_string(),
+                types: "#8fbcbb".to_string(),
+                operators: "#81a1c1".to_string(),
+                line_numbers: "#4c566a".to_string(),
+                header: "#eceff4".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// Gruvbox's dark mode with medium contrast
+    pub fn gruvbox_dark() -> Self {
+        Theme {
+            mode: ThemeMode::Dark,
+            colors: ColorScheme {
+                background: "#282828".to_string(),
+                text: "#ebdbb2".to_string(),
+                keywords: "#fb4934".to_string(),
+                strings: "#b8bb26".to_string(),
+                comments: "#928374".to_string(),
+                numbers: "#d3869b".to_string(),
+                functions: "#b8bb26".to_string(),
+                types: "#fabd2f".to_string(),
+                operators: "#fe8019".to_string(),
+                line_numbers: "#7c6f64".to_string(),
+                header: "#fabd2f".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::new(),
+        }
+    }
+
+    /// Black on white with dark, saturated token colors, each above 7:1
+    /// contrast (WCAG AAA). Keywords are bold and comments italic so
+    /// tokens differ by more than color.
+    pub fn high_contrast() -> Self {
+        let emphasis = |bold, italic| FontStyle { bold, italic, underline: false };
+        Theme {
+            mode: ThemeMode::Light,
+            colors: ColorScheme {
+                background: "#ffffff".to_string(),
+                text: "#000000".to_string(),
+                keywords: "#00008b".to_string(),
+                strings: "#005000".to_string(),
+                comments: "#4a4a4a".to_string(),
+                numbers: "#6a006a".to_string(),
+                functions: "#5c2e00".to_string(),
+                types: "#004d4d".to_string(),
+                operators: "#8b0000".to_string(),
+                line_numbers: "#333333".to_string(),
+                header: "#000000".to_string(),
+            },
+            font_size: 10.0,
+            line_height: 1.2,
+            font_styles: BTreeMap::from([
+                ("keywords".to_string(), emphasis(true, false)),
+                ("comments".to_string(), emphasis(false, true)),
+            ]),
         }
     }
 
-    #[allow(dead_code)]
     pub fn from_mode(mode: ThemeMode) -> Self {
         match mode {
             ThemeMode::Light => Self::light(),
@@ -89,36 +430,132 @@ impl Theme {
         }
     }
 
-    /// Convert hex color to RGB values (0.0-1.0 range for PDF)
-    #[allow(dead_code)]
-    pub fn hex_to_rgb(hex: &str) -> (f32, f32, f32) {
-        let hex = hex.trim_start_matches('#');
-        if hex.len() != 6 {
-            return (0.0, 0.0, 0.0); // Default to black for invalid colors
+    /// The theme matching a syntect theme: its background, foreground and
+    /// gutter colors, and the color and font style it gives each kind of
+    /// token. Anything
+    /// the syntect theme leaves to its foreground color is taken from the
+    /// light or dark built-in matching its background, so tokens stay
+    /// distinguishable.
+    pub fn from_syntect(theme: &syntect::highlighting::Theme) -> Self {
+        let settings = &theme.settings;
+        let mode = match settings.background {
+            Some(color) if is_dark_color(color) => ThemeMode::Dark,
+            _ => ThemeMode::Light,
+        };
+        let mut derived = Self::from_mode(mode);
+        let hex = |color: Color| format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+        if let Some(background) = settings.background {
+            derived.colors.background = hex(background);
+        }
+        if let Some(foreground) = settings.foreground {
+            derived.colors.text = hex(foreground);
+        }
+        if let Some(gutter) = settings.gutter_foreground {
+            derived.colors.line_numbers = hex(gutter);
         }
 
-        let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0) as f32 / 255.0;
-        let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0) as f32 / 255.0;
-        let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0) as f32 / 255.0;
+        let highlighter = Highlighter::new(theme);
+        let probe = |scopes: &[&str]| {
+            scopes.iter().find_map(|scope| {
+                let selector = scope.parse::<ScopeSelectors>().ok()?;
+                let stack = selector.selectors.first()?.path.as_slice().to_vec();
+                let style = highlighter.style_for_stack(&stack);
+                (Some(style.foreground) != settings.foreground).then_some(style)
+            })
+        };
+        // Scopes probed for each color, most specific first
+        let colors = &mut derived.colors;
+        let probes: [(&str, &mut String, &[&str]); 8] = [
+            ("keywords", &mut colors.keywords, &["keyword.control", "keyword", "storage.modifier"]),
+            ("strings", &mut colors.strings, &["string.quoted.double", "string"]),
+            ("comments", &mut colors.comments, &["comment.line", "comment"]),
+            ("numbers", &mut colors.numbers, &["constant.numeric"]),
+            ("functions", &mut colors.functions, &["entity.name.function", "support.function", "variable.function"]),
+            ("types", &mut colors.types, &["entity.name.type", "support.type", "storage.type"]),
+            ("operators", &mut colors.operators, &["keyword.operator"]),
+            ("header", &mut colors.header, &["markup.heading", "entity.name.section"]),
+        ];
+        for (key, field, scopes) in probes {
+            let Some(style) = probe(scopes) else { continue };
+            *field = hex(style.foreground);
+            let font_style = FontStyle::from(style.font_style);
+            if font_style != FontStyle::default() && TOKEN_STYLE_KEYS.contains(&key) {
+                derived.font_styles.insert(key.to_string(), font_style);
+            }
+        }
+        derived
+    }
 
-        (r, g, b)
+    /// The built-in theme called `name`, such as "dracula"
+    pub fn by_name(name: &str) -> Option<Self> {
+        BUILTIN_THEMES.iter()
+            .find(|(theme, _, _)| *theme == name)
+            .map(|(_, theme, _)| theme())
     }
 
-    /// Get color mapping for syntax highlighting
-    #[allow(dead_code)]
-    pub fn get_color_map(&self) -> HashMap<String, (f32, f32, f32)> {
-        let mut map = HashMap::new();
-        
-        map.insert("keyword".to_string(), Self::hex_to_rgb(&self.colors.keywords));
-        map.insert("string".to_string(), Self::hex_to_rgb(&self.colors.strings));
-        map.insert("comment".to_string(), Self::hex_to_rgb(&self.colors.comments));
-        map.insert("number".to_string(), Self::hex_to_rgb(&self.colors.numbers));
-        map.insert("function".to_string(), Self::hex_to_rgb(&self.colors.functions));
-        map.insert("type".to_string(), Self::hex_to_rgb(&self.colors.types));
-        map.insert("operator".to_string(), Self::hex_to_rgb(&self.colors.operators));
-        map.insert("text".to_string(), Self::hex_to_rgb(&self.colors.text));
-        
-        map
+    /// Check every color, reporting each field that is not valid hex
+    pub fn validate(&self) -> std::result::Result<(), Vec<ThemeError>> {
+        let errors = self.colors.invalid_colors();
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// A warning if the text is hard to read on the background
+    pub fn contrast_warning(&self) -> Option<String> {
+        let text = parse_hex_color(&self.colors.text)?;
+        let background = parse_hex_color(&self.colors.background)?;
+        let ratio = contrast_ratio(text, background);
+        (ratio < MIN_TEXT_CONTRAST).then(|| format!(
+            "Text color {} on background {} has a contrast ratio of {:.1}:1, below {}:1",
+            self.colors.text, self.colors.background, ratio, MIN_TEXT_CONTRAST
+        ))
+    }
+
+    /// Convert hex color to RGB values (0.0-1.0 range for PDF), black if
+    /// the color is invalid
+    pub fn hex_to_rgb(hex: &str) -> (f32, f32, f32) {
+        let (r, g, b) = parse_hex_color(hex).unwrap_or((0, 0, 0));
+        (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    /// The same theme with every foreground color adjusted to at least
+    /// `min_ratio` contrast against the background
+    pub fn with_min_contrast(&self, min_ratio: f32) -> Theme {
+        let mut theme = self.clone();
+        let Some(background) = parse_hex_color(&self.colors.background) else { return theme };
+        let colors = &mut theme.colors;
+        for color in [
+            &mut colors.text, &mut colors.keywords, &mut colors.strings, &mut colors.comments,
+            &mut colors.numbers, &mut colors.functions, &mut colors.types, &mut colors.operators,
+            &mut colors.line_numbers, &mut colors.header,
+        ] {
+            if let Some(rgb) = parse_hex_color(color) {
+                let (r, g, b) = with_min_contrast(rgb, background, min_ratio);
+                *color = format!("#{:02x}{:02x}{:02x}", r, g, b);
+            }
+        }
+        theme
+    }
+
+    /// Font style of a token kind such as "comments", plain if unset
+    pub fn font_style(&self, key: &str) -> FontStyle {
+        self.font_styles.get(key).copied().unwrap_or_default()
+    }
+
+    /// Get color and font style mapping for syntax highlighting
+    pub fn get_style_map(&self) -> HashMap<String, TokenStyle> {
+        let colors: HashMap<&str, &str> = self.colors.entries().into_iter().collect();
+        TOKEN_STYLE_KEYS.iter()
+            .map(|key| {
+                let font_style = self.font_style(key);
+                let style = TokenStyle {
+                    color: Self::hex_to_rgb(colors[key]),
+                    bold: font_style.bold,
+                    italic: font_style.italic,
+                    underline: font_style.underline,
+                };
+                (key.to_string(), style)
+            })
+            .collect()
     }
 }
 
@@ -133,6 +570,124 @@ mod tests {
         assert_eq!(Theme::hex_to_rgb("#FF0000"), (1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn test_validate_names_bad_color() {
+        assert!(Theme::light().colors.validate().is_ok());
+        assert_eq!(parse_hex_color("#ff00aa"), Some((255, 0, 170)));
+        assert_eq!(parse_hex_color("ff00aa"), None);
+
+        let mut colors = Theme::dark().colors;
+        colors.strings = "#12345g".to_string();
+        let error = colors.validate().unwrap_err().to_string();
+        assert_eq!(error, "Invalid color for strings: \"#12345g\" (expected #rgb or #rrggbb)");
+    }
+
+    #[test]
+    fn test_shorthand_hex_expands() {
+        assert_eq!(parse_hex_color("#f0a"), Some((255, 0, 170)));
+        assert_eq!(Theme::hex_to_rgb("#fff"), (1.0, 1.0, 1.0));
+        assert_eq!(parse_hex_color("#f0"), None);
+        assert_eq!(parse_hex_color("#GGG000"), None);
+    }
+
+    #[test]
+    fn test_theme_validate_reports_every_bad_field() {
+        let mut theme = Theme::light();
+        theme.colors.background = "#fff".to_string();
+        assert_eq!(theme.validate(), Ok(()));
+
+        theme.colors.keywords = "#GGG000".to_string();
+        theme.colors.header = "black".to_string();
+        let errors = theme.validate().unwrap_err();
+        assert_eq!(errors, vec![
+            ThemeError { field: "keywords", value: "#GGG000".to_string() },
+            ThemeError { field: "header", value: "black".to_string() },
+        ]);
+        assert_eq!(errors[0].to_string(), "Invalid color for keywords: \"#GGG000\" (expected #rgb or #rrggbb)");
+    }
+
+    #[test]
+    fn test_low_contrast_warning() {
+        assert!((contrast_ratio((0, 0, 0), (255, 255, 255)) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio((255, 255, 255), (255, 255, 255)) - 1.0).abs() < 0.01);
+        assert!(Theme::light().contrast_warning().is_none());
+        assert!(Theme::dark().contrast_warning().is_none());
+
+        let mut theme = Theme::light();
+        theme.colors.text = "#bbb".to_string();
+        let warning = theme.contrast_warning().unwrap();
+        assert!(warning.starts_with("Text color #bbb on background #FFFFFF has a contrast ratio of 1.9:1"), "{}", warning);
+    }
+
+    #[test]
+    fn test_theme_from_syntect() {
+        let themes = syntect::highlighting::ThemeSet::load_defaults();
+        let ocean = Theme::from_syntect(&themes.themes["base16-ocean.dark"]);
+        assert!(matches!(ocean.mode, ThemeMode::Dark));
+        assert_eq!(ocean.colors.background, "#2b303b");
+        assert_ne!(ocean.colors.keywords, ocean.colors.strings);
+        assert_ne!(ocean.colors.keywords, ocean.colors.text);
+        ocean.colors.validate().unwrap();
+
+        let github = Theme::from_syntect(&themes.themes["InspiredGitHub"]);
+        assert!(matches!(github.mode, ThemeMode::Light));
+        assert_eq!(github.colors.background, "#ffffff");
+    }
+
+    #[test]
+    fn test_builtin_themes_by_name() {
+        let dracula = Theme::by_name("dracula").unwrap();
+        assert_eq!(dracula.colors.background, "#282a36");
+        assert_eq!(Theme::by_name("solarized-light").unwrap().colors.background, "#fdf6e3");
+        assert_eq!(Theme::by_name("nord").unwrap().colors.text, "#d8dee9");
+        assert!(Theme::by_name("kate").is_none());
+        assert_eq!(syntax_theme_for("solarized-dark"), Some("Solarized (dark)"));
+        assert_eq!(syntax_theme_for("InspiredGitHub"), None);
+        assert_eq!(syntax_theme_for("high-contrast"), None);
+        assert!(palette_builtin("high-contrast").is_some());
+        assert!(palette_builtin("dracula").is_none());
+
+        for name in builtin_theme_names() {
+            let theme = Theme::by_name(name).unwrap();
+            theme.colors.validate().unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let light = matches!(name, "light" | "solarized-light" | "high-contrast");
+            assert_eq!(matches!(theme.mode, ThemeMode::Dark), !light, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_high_contrast_tokens_exceed_aaa() {
+        let theme = Theme::high_contrast();
+        let white = parse_hex_color(&theme.colors.background).unwrap();
+        for (key, color) in theme.colors.entries().into_iter().filter(|(key, _)| *key != "background") {
+            let ratio = contrast_ratio(parse_hex_color(color).unwrap(), white);
+            assert!(ratio > 7.0, "{} {} is only {:.2}:1", key, color, ratio);
+        }
+    }
+
+    #[test]
+    fn test_min_contrast_adjustment() {
+        let white = (255, 255, 255);
+        // #777777 is 4.48:1 on white, just short of AA
+        let adjusted = with_min_contrast((0x77, 0x77, 0x77), white, MIN_CODE_CONTRAST);
+        assert!(contrast_ratio(adjusted, white) >= MIN_CODE_CONTRAST);
+        assert!(adjusted.0 < 0x77);
+        assert_eq!(with_min_contrast((0, 0, 0x8b), white, MIN_CODE_CONTRAST), (0, 0, 0x8b));
+
+        // On a dark background colors are lightened instead
+        let dark = (0x28, 0x28, 0x28);
+        let lightened = with_min_contrast((0x50, 0x50, 0x50), dark, MIN_CODE_CONTRAST);
+        assert!(lightened.0 > 0x50);
+        assert!(contrast_ratio(lightened, dark) >= MIN_CODE_CONTRAST);
+
+        let mut theme = Theme::light();
+        theme.colors.comments = "#cccccc".to_string();
+        let enforced = theme.with_min_contrast(MIN_CODE_CONTRAST);
+        let comments = parse_hex_color(&enforced.colors.comments).unwrap();
+        assert!(contrast_ratio(comments, white) >= MIN_CODE_CONTRAST);
+        assert_eq!(enforced.colors.keywords, "#0066cc");
+    }
+
     #[test]
     fn test_theme_creation() {
         let light = Theme::light();
@@ -143,12 +698,15 @@ mod tests {
     }
 
     #[test]
-    fn test_color_map() {
-        let theme = Theme::light();
-        let color_map = theme.get_color_map();
+    fn test_style_map() {
+        let mut theme = Theme::light();
+        theme.font_styles.insert("comments".to_string(), FontStyle { italic: true, ..Default::default() });
+        let style_map = theme.get_style_map();
         
-        assert!(color_map.contains_key("keyword"));
-        assert!(color_map.contains_key("string"));
-        assert!(color_map.contains_key("comment"));
+        assert!(style_map.contains_key("keywords"));
+        assert!(style_map.contains_key("strings"));
+        assert_eq!(style_map["comments"].color, Theme::hex_to_rgb("#808080"));
+        assert!(style_map["comments"].italic);
+        assert!(!style_map["keywords"].bold);
     }
 }
\ No newline at end of file
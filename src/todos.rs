@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::comments::outside_literals;
+use crate::markdown_generator::FileInfo;
+
+/// Markers `--todo-index` looks for when none are given
+pub const DEFAULT_MARKERS: [&str; 4] = ["TODO", "FIXME", "HACK", "XXX"];
+
+/// A marker such as `TODO` found in a file, with the rest of its line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoItem {
+    pub path: String,
+    /// 1-based, counting the lines of the file as it appears in the document
+    pub line: usize,
+    pub marker: String,
+    pub text: String,
+}
+
+/// The markers named in a `--todo-index` value, separated by commas, or
+/// `DEFAULT_MARKERS` for an empty value
+pub fn parse_markers(value: &str) -> Vec<String> {
+    let markers: Vec<String> = value.split(',')
+        .map(str::trim)
+        .filter(|marker| !marker.is_empty())
+        .map(str::to_string)
+        .collect();
+    if markers.is_empty() {
+        DEFAULT_MARKERS.iter().map(|marker| marker.to_string()).collect()
+    } else {
+        markers
+    }
+}
+
+/// Every one of `markers` in `file`, in order, skipping those inside the
+/// string literals of languages whose literals `comments` knows. A marker
+/// only counts as a whole word, so `TODOS` and `XXXL` don't match `TODO`
+/// and `XXX`. Its note runs to the end of the line or of its comment.
+pub fn find_todos(file: &FileInfo, markers: &[String]) -> Vec<TodoItem> {
+    let content = &*file.content;
+    // Where each marker starts, where its comment or code ends and the marker
+    let mut found: Vec<(usize, usize, &str)> = Vec::new();
+    for range in outside_literals(content, file.language.as_deref()) {
+        let text = &content[range.clone()];
+        for marker in markers.iter().filter(|marker| !marker.is_empty()) {
+            for (offset, _) in text.match_indices(marker.as_str()) {
+                let before = text[..offset].chars().next_back();
+                let after = text[offset + marker.len()..].chars().next();
+                if !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char) {
+                    found.push((range.start + offset, range.end, marker));
+                }
+            }
+        }
+    }
+    found.sort_unstable();
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    found.into_iter()
+        .map(|(offset, end, marker)| {
+            let line = line_starts.partition_point(|&start| start <= offset);
+            let end = content[offset..end].find('\n').map_or(end, |line_end| offset + line_end);
+            let text = content[offset..end].trim_end();
+            let text = text.strip_suffix("*/").or_else(|| text.strip_suffix("-->")).unwrap_or(text);
+            TodoItem {
+                path: file.path.clone(),
+                line,
+                marker: marker.to_string(),
+                text: text.trim_end().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// How many of each of `markers` `todos` has, zero for markers not found
+pub fn count_by_marker(todos: &[TodoItem], markers: &[String]) -> BTreeMap<String, usize> {
+    let mut counts: BTreeMap<String, usize> = markers.iter().map(|marker| (marker.clone(), 0)).collect();
+    for todo in todos {
+        *counts.entry(todo.marker.clone()).or_default() += 1;
+    }
+    counts
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, language: &str, content: &str) -> FileInfo {
+        FileInfo {
+            path: path.to_string(),
+            content: content.into(),
+            language: Some(language.to_string()),
+            size: content.len(),
+            changes: None,
+            binary_kind: None,
+            modified: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn test_markers_in_code_and_comments() {
+        let markers = parse_markers("");
+        let source = "fn main() {\n    // TODO: handle errors\n    let s = \"TODO not this one\";\n    /* FIXME later */ run(); // XXX\n    let todos = TODOS;\n}\n";
+        let todos = find_todos(&file("src/main.rs", "rust", source), &markers);
+        let found: Vec<(usize, &str, &str)> = todos.iter()
+            .map(|todo| (todo.line, todo.marker.as_str(), todo.text.as_str()))
+            .collect();
+        assert_eq!(found, vec![
+            (2, "TODO", "TODO: handle errors"),
+            (4, "FIXME", "FIXME later"),
+            (4, "XXX", "XXX"),
+        ]);
+        let counts = count_by_marker(&todos, &markers);
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![
+            ("FIXME".to_string(), 1),
+            ("HACK".to_string(), 0),
+            ("TODO".to_string(), 1),
+            ("XXX".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn test_json_strings_hide_markers() {
+        let markers = parse_markers("TODO, NOTE");
+        assert_eq!(markers, ["TODO", "NOTE"]);
+        let json = "{\n  \"status\": \"TODO\",\n  \"NOTE\": 1\n}\n";
+        assert!(find_todos(&file("data.json", "json", json), &markers).is_empty());
+        // Without a language the whole text is searched
+        let text = FileInfo { language: None, ..file("notes.txt", "", "TODO: \"write\" the NOTE\n") };
+        assert_eq!(find_todos(&text, &markers).len(), 2);
+    }
+}
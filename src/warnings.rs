@@ -0,0 +1,179 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::i18n::Strings;
+use crate::markdown_generator::table_cell;
+
+/// What a warning is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningCategory {
+    /// A file that was found but couldn't be read
+    Unreadable,
+    /// A file deleted between being found and being read
+    Vanished,
+    /// A file whose size changed between being found and being read
+    Changed,
+    /// Binary content left out, or text that isn't UTF-8
+    Encoding,
+    /// A file cut down to a preview
+    Truncated,
+    /// Lines too long for the page, broken up or kept whole
+    LongLines,
+    /// A code block left partly unhighlighted because it took too long
+    HighlightTimeout,
+    /// An image the EPUB keeps only the alt text of
+    Image,
+    /// A file left out of the PDF, or cut short, to stay within `--max-pages`
+    PageLimit,
+}
+
+impl fmt::Display for WarningCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WarningCategory::Unreadable => "unreadable",
+            WarningCategory::Vanished => "vanished",
+            WarningCategory::Changed => "changed",
+            WarningCategory::Encoding => "encoding",
+            WarningCategory::Truncated => "truncated",
+            WarningCategory::LongLines => "long-lines",
+            WarningCategory::HighlightTimeout => "highlight-timeout",
+            WarningCategory::Image => "image",
+            WarningCategory::PageLimit => "page-limit",
+        })
+    }
+}
+
+/// One warning, with how many times it came up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub category: WarningCategory,
+    /// The file it concerns, relative to the input, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub message: String,
+    pub count: usize,
+}
+
+/// Collects what went wrong along the way without stopping the run, so it
+/// can be shown at the end, written to the run report and listed in the
+/// document. Clones share one list, like `CancellationToken`, so a caller
+/// can hand a clone to the file processor, the generator and the renderers
+/// and read them all back. The same warning twice is kept once, counted.
+#[derive(Debug, Clone, Default)]
+pub struct Warnings(Arc<Mutex<Vec<Warning>>>);
+
+impl Warnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, category: WarningCategory, path: Option<&str>, message: impl Into<String>) {
+        let message = message.into();
+        let Ok(mut warnings) = self.0.lock() else {
+            return;
+        };
+        let existing = warnings.iter_mut().find(|warning| {
+            warning.category == category && warning.path.as_deref() == path && warning.message == message
+        });
+        match existing {
+            Some(warning) => warning.count += 1,
+            None => warnings.push(Warning { category, path: path.map(str::to_string), message, count: 1 }),
+        }
+    }
+
+    /// Every warning so far, by category, then file, then message
+    pub fn entries(&self) -> Vec<Warning> {
+        let mut warnings = self.0.lock().map(|warnings| warnings.clone()).unwrap_or_default();
+        warnings.sort_by(|a, b| (a.category, &a.path, &a.message).cmp(&(b.category, &b.path, &b.message)));
+        warnings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().map_or(true, |warnings| warnings.is_empty())
+    }
+
+    /// One line per warning, for the console
+    pub fn summary(&self) -> String {
+        let warnings = self.entries();
+        let mut summary = format!("⚠️  {} generation warning{}:\n", warnings.len(), if warnings.len() == 1 { "" } else { "s" });
+        for warning in &warnings {
+            summary.push_str(&format!("   [{}] ", warning.category));
+            if let Some(path) = &warning.path {
+                summary.push_str(&format!("{}: ", path));
+            }
+            summary.push_str(&warning.message);
+            if warning.count > 1 {
+                summary.push_str(&format!(" (×{})", warning.count));
+            }
+            summary.push('\n');
+        }
+        summary
+    }
+
+    /// A "Generation Warnings" section listing every warning in a table, to
+    /// append to the document's markdown, headed in the language of `strings`
+    pub fn appendix(&self, strings: &Strings) -> String {
+        let mut markdown = format!("\n\\newpage\n\n## {}\n\n", strings.generation_warnings);
+        markdown.push_str(&format!(
+            "| {} | {} | {} | {} |\n|---|---|---|---|\n",
+            strings.category, strings.file, strings.message, strings.count
+        ));
+        for warning in self.entries() {
+            markdown.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                warning.category,
+                warning.path.as_deref().map(table_cell).unwrap_or_default(),
+                table_cell(&warning.message),
+                warning.count
+            ));
+        }
+        markdown.push('\n');
+        markdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_and_collapse_duplicates() {
+        let warnings = Warnings::new();
+        let clone = warnings.clone();
+        assert!(warnings.is_empty());
+        clone.push(WarningCategory::Truncated, Some("big.rs"), "Cut down to a preview");
+        warnings.push(WarningCategory::Truncated, Some("big.rs"), "Cut down to a preview");
+        warnings.push(WarningCategory::Encoding, Some("a.txt"), "Not UTF-8");
+        warnings.push(WarningCategory::HighlightTimeout, None, "Rust took too long");
+
+        let entries = warnings.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].category, WarningCategory::Encoding);
+        assert_eq!(entries[1], Warning {
+            category: WarningCategory::Truncated,
+            path: Some("big.rs".to_string()),
+            message: "Cut down to a preview".to_string(),
+            count: 2,
+        });
+        assert_eq!(
+            warnings.summary(),
+            "⚠️  3 generation warnings:\n   [encoding] a.txt: Not UTF-8\n   [truncated] big.rs: Cut down to a preview (×2)\n   [highlight-timeout] Rust took too long\n"
+        );
+        assert_eq!(serde_json::to_value(&entries[2]).unwrap(), serde_json::json!({
+            "category": "highlight-timeout",
+            "message": "Rust took too long",
+            "count": 1,
+        }));
+    }
+
+    #[test]
+    fn test_appendix_escapes_table_cells() {
+        let warnings = Warnings::new();
+        warnings.push(WarningCategory::LongLines, Some("a|b.rs"), "2 lines\nbroken up");
+        let appendix = warnings.appendix(&crate::i18n::ENGLISH);
+        assert!(appendix.starts_with("\n\\newpage\n\n## Generation Warnings\n\n"));
+        assert!(appendix.ends_with("| long-lines | a\\|b.rs | 2 lines broken up | 1 |\n\n"), "{}", appendix);
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::file_processor::FileProcessor;
+
+/// Quiet time after the last change before rebuilding, so saving several
+/// files at once triggers a single rebuild
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// `path` with its directory resolved, so it compares equal to the paths
+/// the watcher reports even when the file doesn't exist yet
+fn resolve(path: &Path) -> PathBuf {
+    match (path.parent().and_then(|dir| dir.canonicalize().ok()), path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Call `rebuild` whenever a file `processor` would pick up under `root`
+/// changes, until `stop` completes. Files the builds write, starting with
+/// `outputs`, don't count as changes. A failed rebuild is reported and the
+/// watch goes on.
+pub async fn watch<F, Fut, S>(
+    root: &Path,
+    processor: &FileProcessor,
+    outputs: Vec<PathBuf>,
+    mut rebuild: F,
+    stop: S,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Vec<PathBuf>>>,
+    S: Future,
+{
+    let root = root.canonicalize()
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+    let rules = processor.root_ignore_rules(&root);
+    let mut outputs: Vec<PathBuf> = outputs.iter().map(|path| resolve(path)).collect();
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    }).context("Failed to start the file watcher")?;
+    watcher.watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+    tokio::pin!(stop);
+    loop {
+        let event = tokio::select! {
+            _ = &mut stop => return Ok(()),
+            event = receiver.recv() => event,
+        };
+        let changed = match event {
+            Some(Ok(event)) => !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|path| {
+                    !outputs.contains(path) && processor.would_process(&root, &rules, path)
+                }),
+            Some(Err(error)) => {
+                eprintln!("⚠️  Watch error: {}", error);
+                false
+            }
+            None => return Ok(()),
+        };
+        if !changed {
+            continue;
+        }
+
+        // Let a burst of changes settle before rebuilding once
+        while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {}
+
+        let started = Instant::now();
+        let time = chrono::Local::now().format("%H:%M:%S");
+        match rebuild().await {
+            Ok(written) => {
+                // Output names with {datetime} change on every build
+                for output in written.iter().map(|path| resolve(path)) {
+                    if !outputs.contains(&output) {
+                        outputs.push(output);
+                    }
+                }
+                println!("[{}] 🔁 Rebuilt in {:.2?}", time, started.elapsed());
+            }
+            Err(error) => eprintln!("[{}] ❌ Rebuild failed: {:#}", time, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    /// Poll `done` until it holds or ten seconds pass
+    async fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for a rebuild");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_changes_rebuild_and_failures_keep_watching() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(root.join("target"))?;
+        fs::write(root.join("src/main.rs"), "fn main() {}\n")?;
+        let output = root.join("book.html");
+        fs::write(&output, "initial build")?;
+        let modified = || fs::metadata(&output).and_then(|metadata| metadata.modified()).unwrap();
+
+        let builds = AtomicUsize::new(0);
+        let rebuild = || {
+            let build = builds.fetch_add(1, Ordering::SeqCst) + 1;
+            let output = output.clone();
+            async move {
+                anyhow::ensure!(build > 1, "first rebuild fails");
+                fs::write(&output, format!("build {}", build))?;
+                Ok(vec![output])
+            }
+        };
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let processor = FileProcessor::new();
+        let watching = watch(root, &processor, vec![output.clone()], rebuild, stopped);
+        let edit = async {
+            // Give the watcher a moment to start
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            // Build output under an excluded directory doesn't trigger anything
+            fs::write(root.join("target/app.d"), "noise")?;
+            fs::write(root.join("src/main.rs"), "fn main() { }\n")?;
+            wait_for(|| builds.load(Ordering::SeqCst) == 1).await;
+
+            let before = modified();
+            fs::write(root.join("src/lib.rs"), "pub fn lib() {}\n")?;
+            wait_for(|| modified() > before).await;
+
+            // The rebuild's own write was not taken for a change
+            tokio::time::sleep(DEBOUNCE * 2).await;
+            assert_eq!(builds.load(Ordering::SeqCst), 2);
+            let _ = stop.send(());
+            Ok::<_, anyhow::Error>(())
+        };
+
+        let (watched, edited) = tokio::join!(watching, edit);
+        watched?;
+        edited?;
+        assert_eq!(fs::read_to_string(&output)?, "build 2");
+        Ok(())
+    }
+}
@@ -0,0 +1 @@
+pub const ROUTES: &[&str] = &["/v2/health"];
@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// @title A minimal token used to check Solidity highlighting
+contract Token {
+    string public name = "Scrollcast Token";
+    uint256 public totalSupply;
+    mapping(address => uint256) private balances;
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+
+    constructor(uint256 supply) {
+        totalSupply = supply;
+        balances[msg.sender] = supply;
+    }
+
+    function balanceOf(address owner) external view returns (uint256) {
+        return balances[owner];
+    }
+
+    function transfer(address to, uint256 value) external returns (bool) {
+        require(balances[msg.sender] >= value, "insufficient balance");
+        balances[msg.sender] -= value;
+        balances[to] += value;
+        emit Transfer(msg.sender, to, value);
+        return true;
+    }
+}
@@ -0,0 +1,11 @@
+fn main() {
+    // TODO: read the port from the environment
+    let port = 8080;
+    let banner = "TODO: this is a string, not a task";
+    /* FIXME: handle a busy port */
+    serve(port, banner);
+}
+
+fn serve(port: u16, banner: &str) {
+    println!("{} on {}", banner, port); // HACK: no logging yet
+}
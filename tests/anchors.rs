@@ -0,0 +1,92 @@
+#![cfg(feature = "cli")]
+
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::process::Command;
+
+/// A project whose paths slugify alike, and alike to a section's anchor
+fn colliding_project(dir: &Path) {
+    std::fs::create_dir(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+    std::fs::write(dir.join("src-main.rs"), "fn other() {}\n").unwrap();
+    std::fs::write(dir.join("table-of-contents"), "not the section\n").unwrap();
+    std::fs::write(dir.join("文档.md"), "# 文档\n").unwrap();
+}
+
+fn convert(project: &Path, format: &str, output: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .arg(project)
+        .args(["-f", format, "-y", "-q", "-o"])
+        .arg(output)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+}
+
+fn captures(pattern: &str, text: &str) -> Vec<String> {
+    Regex::new(pattern).unwrap().captures_iter(text).map(|c| c[1].to_string()).collect()
+}
+
+#[test]
+fn test_every_link_resolves_in_every_format() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    std::fs::create_dir(&project).unwrap();
+    colliding_project(&project);
+
+    // HTML: each same-document link has a matching id, and ids are unique
+    let html_path = temp_dir.path().join("out.html");
+    convert(&project, "html", &html_path);
+    let html = std::fs::read_to_string(&html_path).unwrap();
+    let ids = captures(r#" id="([^"]+)""#, &html);
+    let unique: HashSet<&String> = ids.iter().collect();
+    assert_eq!(unique.len(), ids.len(), "{:?}", ids);
+    let links = captures(r##"href="#([^"]+)""##, &html);
+    assert_eq!(links.len(), 4, "{:?}", links);
+    for link in &links {
+        assert!(unique.contains(link), "no id for #{}", link);
+    }
+
+    // EPUB: each chapter link names a chapter holding the id
+    let epub_path = temp_dir.path().join("out.epub");
+    convert(&project, "epub", &epub_path);
+    let mut archive = zip::ZipArchive::new(Cursor::new(std::fs::read(&epub_path).unwrap())).unwrap();
+    let mut chapters = std::collections::HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        if entry.name().ends_with(".xhtml") {
+            let name = entry.name().trim_start_matches("OEBPS/").to_string();
+            let mut text = String::new();
+            entry.read_to_string(&mut text).unwrap();
+            chapters.insert(name, text);
+        }
+    }
+    let mut checked = 0;
+    for text in chapters.values() {
+        for link in captures(r##"href="([^"#]+\.xhtml#[^"]+)""##, text) {
+            let (chapter, id) = link.split_once('#').unwrap();
+            let target = chapters.get(chapter).unwrap_or_else(|| panic!("no chapter {}", chapter));
+            assert!(target.contains(&format!(r#"id="{}""#, id)), "no id {} in {}", id, chapter);
+            checked += 1;
+        }
+    }
+    assert!(checked >= 4, "{}", checked);
+
+    // PDF: each link annotation goes to a named destination
+    let pdf_path = temp_dir.path().join("out.pdf");
+    convert(&project, "pdf", &pdf_path);
+    let document = lopdf::Document::load(&pdf_path).unwrap();
+    let dests = document.catalog().unwrap().get_deref(b"Dests", &document).unwrap().as_dict().unwrap();
+    let mut targets = Vec::new();
+    for page in document.get_pages().values() {
+        for annot in document.get_page_annotations(*page).unwrap() {
+            targets.push(annot.get(b"Dest").unwrap().as_name().unwrap().to_vec());
+        }
+    }
+    assert_eq!(targets.len(), 4);
+    for target in &targets {
+        assert!(dests.has(target), "no destination {}", String::from_utf8_lossy(target));
+    }
+}
@@ -0,0 +1,47 @@
+#![cfg(all(unix, feature = "cli"))]
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// A repository big enough that rendering it takes a while
+fn large_repo(root: &Path) {
+    let body: String = (0..100).map(|i| format!("    let value_{i} = compute({i}) * {i};\n")).collect();
+    for i in 0..2000 {
+        let dir = root.join(format!("module_{}", i / 100));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("file_{i}.rs")), format!("fn f{i}() {{\n{body}}}\n")).unwrap();
+    }
+}
+
+#[test]
+fn test_ctrl_c_cleans_up_and_exits_130() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let repo = temp_dir.path().join("repo");
+    let tmp = temp_dir.path().join("tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    large_repo(&repo);
+    let output = temp_dir.path().join("repo.pdf");
+
+    let mut run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .env("TMPDIR", &tmp)
+        .arg(&repo)
+        .args(["-f", "pdf", "-y", "-q", "-o"])
+        .arg(&output)
+        .spawn()
+        .expect("failed to run scrollcast");
+
+    // The intermediate markdown shows the run is past startup and into the work
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while std::fs::read_dir(&tmp).unwrap().next().is_none() {
+        assert!(Instant::now() < deadline, "no temp file appeared");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let interrupted = Command::new("kill").args(["-INT", &run.id().to_string()]).status().unwrap();
+    assert!(interrupted.success());
+
+    let status = run.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 0);
+    assert!(!output.exists());
+}
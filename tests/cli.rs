@@ -0,0 +1,38 @@
+#![cfg(feature = "cli")]
+
+use std::process::{Command, Output};
+
+fn scrollcast(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn test_convert_is_the_default_command() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let read = |name: &str| -> Vec<String> {
+        std::fs::read_to_string(temp_dir.path().join(name)).unwrap().lines()
+            .filter(|line| !line.contains("Generated on"))
+            .map(str::to_string)
+            .collect()
+    };
+    for (name, command) in [("bare.md", None), ("convert.md", Some("convert"))] {
+        let output = temp_dir.path().join(name);
+        let output = output.to_str().unwrap();
+        let mut args: Vec<&str> = command.into_iter().collect();
+        args.extend(["testfiles/test_project", "-f", "markdown", "-y", "-q", "-o", output]);
+        scrollcast(&args);
+    }
+    assert_eq!(read("bare.md"), read("convert.md"));
+}
+
+#[test]
+fn test_legacy_flags_match_subcommands() {
+    assert_eq!(scrollcast(&["--list-themes"]).stdout, scrollcast(&["list", "themes"]).stdout);
+    assert_eq!(scrollcast(&["--list-languages"]).stdout, scrollcast(&["list", "languages"]).stdout);
+}
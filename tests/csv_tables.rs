@@ -0,0 +1,38 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+fn convert(extra: &[&str]) -> (String, String) {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("csv_project.md");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/csv_project", "-f", "markdown", "-y", "--verbose", "--csv-tables", "-o"])
+        .arg(&output)
+        .args(extra)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    (std::fs::read_to_string(&output).unwrap(), String::from_utf8_lossy(&run.stdout).into_owned())
+}
+
+#[test]
+fn test_quoted_fields_become_table_cells() {
+    let (markdown, _) = convert(&[]);
+    assert!(markdown.contains("| **name** | **notes** | **score** |"), "{}", markdown);
+    assert!(markdown.contains("| Smith, Jane | said \"hi\" then left | 10 |"), "{}", markdown);
+    assert!(markdown.contains("| plain | a\\|b | 7 |"), "{}", markdown);
+    assert!(markdown.contains("| **os** | **arch** | **supported** |"), "{}", markdown);
+    assert!(!markdown.contains("```csv\nname,notes"), "{}", markdown);
+}
+
+#[test]
+fn test_large_files_stay_code_with_the_reason() {
+    let (markdown, console) = convert(&[]);
+    assert!(markdown.contains("```csv\nid,value\n0,0\n"), "{}", markdown);
+    assert!(console.contains("large.csv stays code: more than 200 rows"), "{}", console);
+
+    let (markdown, console) = convert(&["--csv-max-size", "0"]);
+    assert!(markdown.contains("```csv\nname,notes,score\n"), "{}", markdown);
+    assert!(console.contains("people.csv stays code"), "{}", console);
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn scrollcast(args: &[&str], paths: &[&Path]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(args)
+        .args(paths)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn test_changes_since_an_emitted_model() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    std::fs::create_dir(&project).unwrap();
+    std::fs::write(project.join("main.rs"), "fn main() {\n    a();\n}\n").unwrap();
+    std::fs::write(project.join("old.py"), "x = 1\n").unwrap();
+    std::fs::write(project.join("same.txt"), "same\n").unwrap();
+    let ast = temp_dir.path().join("old.json");
+    let output = temp_dir.path().join("old.md");
+    scrollcast(&["-f", "markdown", "-y", "-q", "--emit-ast"], &[&ast, &project, Path::new("-o"), &output]);
+
+    std::fs::write(project.join("main.rs"), "fn main() {\n    b();\n    c();\n}\n").unwrap();
+    std::fs::remove_file(project.join("old.py")).unwrap();
+    std::fs::write(project.join("new.js"), "let a;\n").unwrap();
+
+    let report = String::from_utf8(scrollcast(&["diff"], &[&ast, &project]).stdout).unwrap();
+    assert!(report.contains("# Changes in project\n"), "{}", report);
+    assert!(report.contains("**Added:** 1 · **Removed:** 1 · **Modified:** 1 · **Unchanged:** 1\n"), "{}", report);
+    assert!(report.contains("| modified | main.rs | +2 | -1 |\n| added | new.js | +1 | -0 |\n| removed | old.py | +0 | -1 |\n"), "{}", report);
+
+    let annotated = temp_dir.path().join("annotated.html");
+    scrollcast(&["diff", "--annotate", "-f", "html", "-o"], &[&annotated, &ast, &project]);
+    let html = std::fs::read_to_string(&annotated).unwrap();
+    assert!(html.contains(r#"<h3 id="main-rs">main.rs <em>(modified)</em></h3>"#), "{}", html);
+    assert!(html.contains(r#"<h3 id="new-js">new.js <em>(new)</em></h3>"#), "{}", html);
+    assert!(html.contains(r#"<h3 id="same-txt">same.txt</h3>"#), "{}", html);
+    assert!(!html.contains("old.py"), "{}", html);
+}
+
+#[test]
+fn test_unreadable_model_fails() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let ast = temp_dir.path().join("old.json");
+    std::fs::write(&ast, r#"{"schema_version": 99, "metadata": {}, "anchors": {}, "sections": []}"#).unwrap();
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["diff"])
+        .arg(&ast)
+        .arg("testfiles/test_project")
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(!run.status.success());
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(stderr.contains("Failed to load the document model"), "{}", stderr);
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+#[test]
+fn test_german_document_has_no_english_headings() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("test_project.md");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "--doc-language", "de", "-o"])
+        .arg(&output)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+
+    let markdown = std::fs::read_to_string(&output).unwrap();
+    assert!(markdown.contains("## Inhaltsverzeichnis"), "{}", markdown);
+    assert!(markdown.contains("**Größe:**"), "{}", markdown);
+    for english in ["Table of Contents", "File Structure", "File Contents", "Size:", "Generated on:"] {
+        assert!(!markdown.contains(english), "{:?} left in:\n{}", english, markdown);
+    }
+}
+
+#[test]
+fn test_unknown_language_falls_back_to_english_with_a_warning() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("test_project.md");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "--doc-language", "eo", "-o"])
+        .arg(&output)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+
+    let console = String::from_utf8_lossy(&run.stdout);
+    assert!(console.contains("No translation for eo; headings and labels stay in English"), "{}", console);
+    assert!(std::fs::read_to_string(&output).unwrap().contains("## Table of Contents"));
+}
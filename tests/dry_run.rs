@@ -0,0 +1,38 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::Command;
+
+/// Run scrollcast on the test project, returning the report it prints
+fn report(output: &Path, extra_args: &[&str]) -> serde_json::Value {
+    let result = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["testfiles/test_project", "-y", "--report", "-", "-o"])
+        .arg(output)
+        .args(extra_args)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(result.status.success());
+    serde_json::from_slice(&result.stdout).unwrap()
+}
+
+#[test]
+fn test_dry_run_estimates_without_writing() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("docs/book.pdf");
+    let dry_run = report(&output, &["-f", "pdf,markdown", "--dry-run"]);
+    assert!(!temp_dir.path().join("docs").exists());
+    assert_eq!(dry_run["exit_code"], 0);
+    assert_eq!(dry_run["outputs"], serde_json::json!([]));
+    let estimate = &dry_run["estimate"];
+    assert_eq!(estimate["files"], 3);
+    let pages = &estimate["pdf_pages"];
+    assert!(pages["min"].as_u64().unwrap() <= pages["max"].as_u64().unwrap(), "{}", estimate);
+
+    // The markdown phase's bytes are the size of the generated markdown
+    let run = report(&output, &["-f", "markdown"]);
+    let phases = run["phases"].as_array().unwrap();
+    let generated = phases.iter().find(|phase| phase["name"] == "markdown").unwrap()["bytes"].as_f64().unwrap();
+    let estimated = estimate["markdown_bytes"].as_f64().unwrap();
+    assert!((estimated - generated).abs() / generated < 0.1, "estimated {} bytes, generated {}", estimated, generated);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "cli")]
+
+use scrollcast::document_model::{DocumentModel, SCHEMA_VERSION};
+use scrollcast::MarkdownGenerator;
+use std::process::Command;
+
+#[test]
+fn test_emitted_model_renders_the_same_markdown() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("test_project.md");
+    let ast = temp_dir.path().join("test_project.json");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "--emit-ast"])
+        .arg(&ast)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+
+    let model = DocumentModel::from_json(&std::fs::read_to_string(&ast).unwrap()).unwrap();
+    assert_eq!(model.schema_version, SCHEMA_VERSION);
+    assert_eq!(model.metadata.title, "test_project");
+    let paths: Vec<&str> = model.files().iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(paths.len(), 3);
+    assert!(paths.contains(&"main.rs"), "{:?}", paths);
+
+    let rendered = MarkdownGenerator::new(true, true).render_markdown(&model).unwrap();
+    let markdown = std::fs::read_to_string(&output).unwrap();
+    assert!(markdown.contains(&rendered), "{}\n---\n{}", rendered, markdown);
+}
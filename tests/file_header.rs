@@ -0,0 +1,67 @@
+#![cfg(feature = "cli")]
+
+use regex::Regex;
+use std::process::Command;
+
+const FILES: usize = 3;
+const LABELS: [&str; 7] = ["Size:", "Language:", "Lines:", "Modified:", "Commit:", "SHA-1:", "Truncated:"];
+
+fn convert(format: &str, fields: &str) -> Vec<u8> {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join(format!("test_project.{}", format.replace("markdown", "md")));
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", format, "-y", "-q", "--file-header-fields", fields, "-o"])
+        .arg(&output)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    std::fs::read(&output).unwrap()
+}
+
+#[test]
+fn test_selected_fields_appear_once_per_file() {
+    let markdown = String::from_utf8(convert("markdown", "lines,size,commit")).unwrap();
+    for label in LABELS {
+        let expected = if ["Size:", "Lines:", "Commit:"].contains(&label) { FILES } else { 0 };
+        assert_eq!(markdown.matches(&format!("| **{}** |", label)).count(), expected, "{}", label);
+    }
+    // In the order given, the first as the table's header row
+    assert!(markdown.contains("<!-- file-info -->\n| **Lines:** | 25 |\n|---|---|\n| **Size:** | 425 B |\n"), "{}", markdown);
+    // The fixtures are committed, so each has a commit
+    let commit = Regex::new(r"\| \*\*Commit:\*\* \| [0-9a-f]{7} \|").unwrap();
+    assert_eq!(commit.find_iter(&markdown).count(), FILES, "{}", markdown);
+
+    let html = String::from_utf8(convert("html", "hash,language")).unwrap();
+    assert_eq!(html.matches(r#"<dl class="file-info">"#).count(), FILES, "{}", html);
+    for label in LABELS {
+        let expected = if ["SHA-1:", "Language:"].contains(&label) { FILES } else { 0 };
+        assert_eq!(html.matches(&format!("<dt>{}</dt>", label)).count(), expected, "{}", label);
+    }
+    assert!(html.contains("<dt>Language:</dt><dd>rust</dd>"), "{}", html);
+    assert!(!html.contains("file-info -->"), "{}", html);
+
+    let pdf = lopdf::Document::load_mem(&convert("pdf", "lines,truncated")).unwrap();
+    let pages: Vec<u32> = pdf.get_pages().keys().copied().collect();
+    let text = pdf.extract_text(&pages).unwrap();
+    for label in LABELS {
+        let expected = if ["Lines:", "Truncated:"].contains(&label) { FILES } else { 0 };
+        assert_eq!(text.matches(label).count(), expected, "{}: {}", label, text);
+    }
+    assert!(text.contains("Truncated: no"), "{}", text);
+}
+
+#[test]
+fn test_empty_list_leaves_headers_out_and_unknown_fields_fail() {
+    let markdown = String::from_utf8(convert("markdown", "")).unwrap();
+    assert!(!markdown.contains("file-info"), "{}", markdown);
+    assert!(!markdown.contains("Size:"), "{}", markdown);
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "--dry-run", "-o", "out.md", "--file-header-fields", "size,owner"])
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(!run.status.success());
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(stderr.contains("Unknown file header field 'owner'"), "{}", stderr);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Run scrollcast on the test project with stdin from /dev/null, failing
+/// if it's still running after a minute
+fn run_without_terminal(output: &Path, extra_args: &[&str]) -> std::process::ExitStatus {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["testfiles/test_project", "-f", "markdown", "-q", "-o"])
+        .arg(output)
+        .args(extra_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start scrollcast");
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        if let Some(status) = child.try_wait().expect("failed to wait for scrollcast") {
+            return status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("scrollcast waited for input without a terminal");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_skips_confirmation_without_a_terminal() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("test_project.md");
+    assert!(run_without_terminal(&output, &[]).success());
+    assert!(output.exists());
+}
+
+#[test]
+fn test_no_input_fails_instead_of_prompting() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("test_project.md");
+    assert_eq!(run_without_terminal(&output, &["--no-input"]).code(), Some(2));
+    assert!(!output.exists());
+
+    assert!(run_without_terminal(&output, &["--no-input", "--yes"]).success());
+}
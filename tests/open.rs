@@ -0,0 +1,55 @@
+#![cfg(all(unix, feature = "cli"))]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Write a launcher that records the path it was asked to open in `log`
+fn recording_opener(dir: &Path) -> (PathBuf, PathBuf) {
+    let log = dir.join("opened.log");
+    let script = dir.join("opener.sh");
+    std::fs::write(&script, format!("#!/bin/sh\necho \"$1\" >> '{}'\n", log.display())).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    (script, log)
+}
+
+/// Render the test project to `output` with `opener` as the launcher
+fn run(opener: &Path, output: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("SCROLLCAST_OPENER", opener)
+        .args(["testfiles/test_project", "-y", "-q", "-o"])
+        .arg(output)
+        .args(extra_args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run scrollcast")
+}
+
+#[test]
+fn test_open_launches_the_chosen_document() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let (opener, log) = recording_opener(temp_dir.path());
+    let output = temp_dir.path().join("book");
+
+    assert!(run(&opener, &output, &["-f", "html,markdown", "--open"]).status.success());
+    assert!(run(&opener, &output, &["-f", "html,markdown", "--open=markdown", "--force"]).status.success());
+    assert!(run(&opener, &output, &["-f", "markdown", "--force"]).status.success());
+    let opened = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(opened, format!("{}\n{}\n", output.with_extension("html").display(), output.with_extension("md").display()));
+}
+
+#[test]
+fn test_open_failures_only_leave_a_note() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("book.md");
+    let result = run(Path::new("false"), &output, &["-f", "markdown", "--open"]);
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Couldn't open"), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(output.exists());
+
+    let (opener, log) = recording_opener(temp_dir.path());
+    let result = run(&opener, &temp_dir.path().join("other.md"), &["-f", "markdown", "--open=epub"]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(!log.exists());
+}
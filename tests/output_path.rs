@@ -0,0 +1,59 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Render the test project to `output` as markdown without a terminal
+fn run(output: &Path, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "-o"])
+        .arg(output)
+        .args(extra_args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run scrollcast")
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_only_directory_fails_before_any_work() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let read_only = temp_dir.path().join("read_only");
+    std::fs::create_dir(&read_only).unwrap();
+    std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+    // Permissions don't stop root, so there's nothing to check there
+    if tempfile::tempfile_in(&read_only).is_ok() {
+        return;
+    }
+
+    let result = run(&read_only.join("book.md"), &["--report", "-"]);
+    assert_eq!(result.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    assert!(report["error"].as_str().unwrap().contains("Can't write to the output directory"), "{}", report);
+    assert!(report["phases"].as_array().unwrap().is_empty(), "{}", report);
+}
+
+#[test]
+fn test_existing_output_needs_force_without_input() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let output = temp_dir.path().join("book.md");
+    std::fs::write(&output, "keep me").unwrap();
+
+    let result = run(&output, &["--no-input"]);
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("already exists; pass --force"));
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "keep me");
+
+    assert!(run(&output, &["--no-input", "--force"]).status.success());
+    assert!(std::fs::read_to_string(&output).unwrap().contains("test_project"));
+}
+
+#[test]
+fn test_directory_output_is_named_after_the_input() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    assert!(run(temp_dir.path(), &[]).status.success());
+    assert!(temp_dir.path().join("test_project.md").exists());
+}
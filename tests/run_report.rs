@@ -0,0 +1,133 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::Command;
+
+/// Render the test project to `output` as HTML and return the run report
+fn run(output: &Path, extra_args: &[&str]) -> serde_json::Value {
+    let report = output.with_extension("json");
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["testfiles/test_project", "-f", "html", "-y", "-q", "-o"])
+        .arg(output)
+        .arg("--report")
+        .arg(&report)
+        .args(extra_args)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+    serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap()
+}
+
+#[test]
+fn test_report_times_every_phase() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let report = run(&temp_dir.path().join("test_project.html"), &[]);
+    assert_eq!(report["success"], true);
+    let phases = report["phases"].as_array().unwrap();
+    let names: Vec<&str> = phases.iter().map(|phase| phase["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["setup", "discovery", "read", "markdown", "highlight", "render"]);
+    for phase in phases {
+        assert!(phase["seconds"].as_f64().unwrap() > 0.0, "{}", phase);
+    }
+    assert_eq!(phases[2]["bytes"], 2022);
+    assert_eq!(phases[5]["bytes"], report["outputs"][0]["bytes"]);
+    assert!(report.get("cache").is_none());
+}
+
+#[test]
+fn test_rerun_reuses_cached_fragments() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cache = format!("--cache-dir={}", temp_dir.path().join("cache").display());
+    let first = temp_dir.path().join("first.html");
+    let second = temp_dir.path().join("second.html");
+
+    let report = run(&first, &[&cache]);
+    assert_eq!(report["cache"]["hits"], 0);
+    let generated = report["cache"]["misses"].as_u64().unwrap();
+    assert!(generated > 0);
+
+    let report = run(&second, &[&cache]);
+    assert_eq!(report["cache"]["hits"], generated);
+    assert_eq!(report["cache"]["misses"], 0);
+
+    // Apart from the generation time, the document is the same
+    let read = |path: &Path| -> Vec<String> {
+        std::fs::read_to_string(path).unwrap().lines()
+            .filter(|line| !line.contains("Generated on"))
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(read(&first), read(&second));
+}
+
+#[test]
+fn test_formats_render_side_by_side() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    // Added to the -f html that `run` passes, which is only rendered once
+    let report = run(&temp_dir.path().join("book.pdf"), &["-f", "pdf,html,markdown"]);
+    assert_eq!(report["formats"], serde_json::json!(["html", "pdf", "markdown"]));
+    let outputs: Vec<&str> = report["outputs"].as_array().unwrap().iter()
+        .map(|output| output["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(outputs.len(), 3);
+    for extension in ["pdf", "html", "md"] {
+        let path = temp_dir.path().join("book").with_extension(extension);
+        assert!(path.exists(), "{} missing", path.display());
+        assert!(outputs.contains(&path.to_str().unwrap()), "{:?}", outputs);
+    }
+
+    // Each format has its own render phase, and they ran at the same time
+    let phases = report["phases"].as_array().unwrap();
+    let span = |name: &str| -> (f64, f64) {
+        let phase = phases.iter().find(|phase| phase["name"] == name).unwrap_or_else(|| panic!("no {} phase", name));
+        let started = phase["started"].as_f64().unwrap();
+        (started, started + phase["seconds"].as_f64().unwrap())
+    };
+    let (pdf, html) = (span("render pdf"), span("render html"));
+    assert!(pdf.0 < html.1 && html.0 < pdf.1, "pdf {:?} and html {:?} didn't overlap", pdf, html);
+    span("render markdown");
+}
+
+#[test]
+fn test_missing_input_exits_with_input_error() {
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["/nonexistent/scrollcast-input", "-o", "out.pdf", "-y", "--report", "-"])
+        .output()
+        .expect("failed to run scrollcast");
+    assert_eq!(run.status.code(), Some(3));
+
+    // The cause comes first, not the kind of failure
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    assert!(stderr.starts_with("Error: Input path does not exist: /nonexistent/scrollcast-input"), "{}", stderr);
+
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let report: serde_json::Value = serde_json::from_str(&stdout[stdout.find('{').unwrap()..]).unwrap();
+    assert_eq!(report["success"], false);
+    assert_eq!(report["exit_code"], 3);
+    assert_eq!(report["error"], "Input path does not exist: /nonexistent/scrollcast-input");
+}
+
+#[test]
+fn test_one_failed_format_exits_with_partial_success() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    // A directory where the PDF should go fails only that format
+    std::fs::create_dir(temp_dir.path().join("book.pdf")).unwrap();
+    std::fs::write(temp_dir.path().join("book.pdf/keep"), "").unwrap();
+    let report = temp_dir.path().join("report.json");
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["testfiles/test_project", "-f", "pdf,html", "-y", "-q", "--force", "-o"])
+        .arg(temp_dir.path().join("book.html"))
+        .arg("--report")
+        .arg(&report)
+        .status()
+        .expect("failed to run scrollcast");
+    assert_eq!(status.code(), Some(5));
+    assert!(temp_dir.path().join("book.html").exists());
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["success"], false);
+    assert_eq!(report["exit_code"], 5);
+    assert_eq!(report["outputs"].as_array().unwrap().len(), 1);
+}
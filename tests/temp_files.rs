@@ -0,0 +1,52 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Child, Command};
+
+/// Start rendering the test project to `output` with `tmp` as the temp directory
+fn spawn(tmp: &Path, output: &Path, extra_args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("TMPDIR", tmp)
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "-o"])
+        .arg(output)
+        .args(extra_args)
+        .spawn()
+        .expect("failed to run scrollcast")
+}
+
+fn temp_entries(tmp: &Path) -> Vec<String> {
+    std::fs::read_dir(tmp).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn test_concurrent_runs_clean_up_after_themselves() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let tmp = temp_dir.path().join("tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    let outputs = [temp_dir.path().join("first.md"), temp_dir.path().join("second.md")];
+    let runs: Vec<Child> = outputs.iter().map(|output| spawn(&tmp, output, &[])).collect();
+    for mut run in runs {
+        assert!(run.wait().unwrap().success());
+    }
+
+    let first = std::fs::read_to_string(&outputs[0]).unwrap();
+    assert!(first.contains("test_project"));
+    assert_eq!(first, std::fs::read_to_string(&outputs[1]).unwrap());
+    assert!(temp_entries(&tmp).is_empty(), "{:?}", temp_entries(&tmp));
+}
+
+#[test]
+fn test_keep_temp_leaves_the_intermediate_markdown() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let tmp = temp_dir.path().join("tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    let output = temp_dir.path().join("book.md");
+    assert!(spawn(&tmp, &output, &["--keep-temp"]).wait().unwrap().success());
+
+    let kept = temp_entries(&tmp);
+    assert_eq!(kept.len(), 1, "{:?}", kept);
+    assert!(kept[0].starts_with("scrollcast-test_project-") && kept[0].ends_with(".md"), "{:?}", kept);
+}
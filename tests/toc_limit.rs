@@ -0,0 +1,59 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::Command;
+
+/// `docs/` with two files, `src/api/` and `src/db/` with one each
+fn nested_project(dir: &Path) {
+    for sub in ["docs", "src/api", "src/db"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    std::fs::write(dir.join("docs/guide.md"), "# Guide\n").unwrap();
+    std::fs::write(dir.join("docs/faq.md"), "# FAQ\n").unwrap();
+    std::fs::write(dir.join("src/api/routes.rs"), "pub fn routes() {}\n").unwrap();
+    std::fs::write(dir.join("src/db/pool.rs"), "pub fn pool() {}\n").unwrap();
+}
+
+fn convert(project: &Path, flags: &[&str]) -> String {
+    let output = project.with_extension("md");
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .arg(project)
+        .args(["-f", "markdown", "-y", "-q", "-o"])
+        .arg(&output)
+        .args(flags)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+    std::fs::read_to_string(&output).unwrap()
+}
+
+fn toc(markdown: &str) -> &str {
+    let (_, toc) = markdown.split_once("## Table of Contents {#table-of-contents}\n\n").unwrap();
+    toc.split_once("\n## ").unwrap().0
+}
+
+#[test]
+fn test_toc_limit_depth_and_appendix() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let project = temp_dir.path().join("project");
+    nested_project(&project);
+
+    // At the limit the files are still listed
+    let flat = convert(&project, &["--toc-limit", "4"]);
+    assert_eq!(toc(&flat).lines().filter(|line| line.starts_with("- [")).count(), 4, "{}", flat);
+
+    let collapsed = convert(&project, &["--toc-limit", "3"]);
+    assert_eq!(toc(&collapsed), "- [docs/](#docs-faq-md) (2 files)\n- [src/](#src-api-routes-rs) (2 files)\n");
+    assert!(!collapsed.contains("File Index"));
+
+    let deeper = convert(&project, &["--toc-limit", "3", "--toc-depth", "2", "--toc-appendix"]);
+    assert!(toc(&deeper).contains("- [src/api/](#src-api-routes-rs) (1 file)\n- [src/db/](#src-db-pool-rs) (1 file)\n"), "{}", deeper);
+    assert!(deeper.ends_with("## File Index {#file-index}\n\n- [docs/faq.md](#docs-faq-md)\n- [docs/guide.md](#docs-guide-md)\n\
+                              - [src/api/routes.rs](#src-api-routes-rs)\n- [src/db/pool.rs](#src-db-pool-rs)\n\n"), "{}", deeper);
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .args(["testfiles/test_project", "-f", "markdown", "-y", "-q", "--dry-run", "-o", "out.md", "--toc-limit", "0"])
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(!run.status.success());
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "cli")]
+
+use scrollcast::{FileProcessor, MarkdownGenerator, Warnings};
+use std::path::Path;
+use std::process::Command;
+
+fn small_repo(root: &Path) {
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/main.rs"), "fn main() {\n    println!(\"hello\");\n}\n").unwrap();
+    std::fs::write(root.join("src/build_output.rs"), "pub const GENERATED: u32 = 1;\n").unwrap();
+    std::fs::write(root.join("README.md"), "# Small repo\n").unwrap();
+}
+
+#[test]
+fn test_file_deleted_before_reading_ends_up_in_the_appendix() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    small_repo(temp_dir.path());
+    let warnings = Warnings::new();
+    let processor = FileProcessor::new().with_warnings(warnings.clone());
+
+    let found = processor.discover_paths(temp_dir.path()).unwrap();
+    assert_eq!(found.len(), 3);
+    std::fs::remove_file(temp_dir.path().join("src/build_output.rs")).unwrap();
+    let files = processor.read_discovered(temp_dir.path(), &found, |_, _| {}).unwrap();
+    assert_eq!(files.len(), 2);
+
+    let generator = MarkdownGenerator::new(true, true).with_warnings(warnings.clone());
+    let markdown = generator.generate_markdown(&files, "small").unwrap() + &warnings.appendix(&scrollcast::i18n::ENGLISH);
+    assert!(!markdown.contains("GENERATED"));
+    let (_, appendix) = markdown.split_once("## Generation Warnings").unwrap();
+    assert!(appendix.contains("| vanished | src/build_output.rs | Deleted after it was found; left out | 1 |"), "{}", appendix);
+}
+
+#[test]
+fn test_snapshot_run_cleans_up_its_copies() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let repo = temp_dir.path().join("repo");
+    let tmp = temp_dir.path().join("tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    small_repo(&repo);
+    let output = temp_dir.path().join("repo.md");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .env("TMPDIR", &tmp)
+        .arg(&repo)
+        .args(["-f", "markdown", "-y", "-q", "--snapshot", "-o"])
+        .arg(&output)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+    let markdown = std::fs::read_to_string(&output).unwrap();
+    assert!(markdown.contains("println!(\"hello\");"));
+    assert!(markdown.contains("GENERATED"));
+    assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 0);
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::Command;
+
+/// A repository with a file over 1 MB, a line too long for the page and a
+/// UTF-16 file
+fn troubled_repo(root: &Path) {
+    std::fs::create_dir_all(root).unwrap();
+    let big: String = (0..80_000).map(|i| format!("let value_{i} = {i};\n")).collect();
+    std::fs::write(root.join("big.rs"), big).unwrap();
+    std::fs::write(root.join("wide.py"), format!("x = \"{}\"\n", "a".repeat(150))).unwrap();
+    let mut utf16 = vec![0xFF, 0xFE];
+    utf16.extend("hello from utf-16\n".encode_utf16().flat_map(u16::to_le_bytes));
+    std::fs::write(root.join("notes.txt"), utf16).unwrap();
+}
+
+#[test]
+fn test_warnings_reach_console_report_and_document() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let repo = temp_dir.path().join("repo");
+    troubled_repo(&repo);
+    let output = temp_dir.path().join("repo.md");
+    let report = temp_dir.path().join("report.json");
+
+    let run = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .arg(&repo)
+        .args(["-f", "markdown", "-y", "--max-file-size", "1", "--chunk-size", "1", "--warnings-in-document", "-o"])
+        .arg(&output)
+        .arg("--report")
+        .arg(&report)
+        .output()
+        .expect("failed to run scrollcast");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+
+    let console = String::from_utf8_lossy(&run.stdout);
+    assert!(console.contains("3 generation warnings:"), "{}", console);
+    assert!(console.contains("[encoding] notes.txt: UTF-16LE text isn't decoded"), "{}", console);
+    assert!(console.contains("[truncated] big.rs: Cut down from"), "{}", console);
+    assert!(console.contains("[long-lines] wide.py: 1 line over 100 characters kept whole"), "{}", console);
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+    let warnings = report["generation_warnings"].as_array().unwrap();
+    let categories: Vec<(&str, &str)> = warnings.iter()
+        .map(|warning| (warning["category"].as_str().unwrap(), warning["path"].as_str().unwrap()))
+        .collect();
+    assert_eq!(categories, vec![("encoding", "notes.txt"), ("truncated", "big.rs"), ("long-lines", "wide.py")]);
+
+    let document = std::fs::read_to_string(&output).unwrap();
+    let (_, appendix) = document.split_once("## Generation Warnings").expect("no warnings section");
+    assert!(appendix.contains("| encoding | notes.txt | UTF-16LE text isn't decoded and may show garbled | 1 |"), "{}", appendix);
+    assert!(appendix.contains("| truncated | big.rs | Cut down from"), "{}", appendix);
+    assert!(appendix.contains("| long-lines | wide.py | 1 line over 100 characters kept whole | 1 |"), "{}", appendix);
+}
+
+#[test]
+fn test_document_has_no_warnings_section_without_the_flag() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let repo = temp_dir.path().join("repo");
+    troubled_repo(&repo);
+    let output = temp_dir.path().join("repo.md");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_scrollcast"))
+        .arg(&repo)
+        .args(["-f", "markdown", "-y", "-q", "-o"])
+        .arg(&output)
+        .status()
+        .expect("failed to run scrollcast");
+    assert!(status.success());
+    assert!(!std::fs::read_to_string(&output).unwrap().contains("## Generation Warnings"));
+}